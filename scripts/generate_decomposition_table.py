@@ -0,0 +1,86 @@
+#!/usr/bin/env python3
+"""Generates `src/name/decomposition_table.rs` from the Unicode Character Database.
+
+Usage: scripts/generate_decomposition_table.py [UnicodeData.txt] > src/name/decomposition_table.rs
+
+Without an argument, `UnicodeData.txt` of the pinned UCD version is downloaded.
+"""
+
+import sys
+import urllib.request
+
+UCD_VERSION = "14.0.0"
+UCD_URL = f"https://www.unicode.org/Public/{UCD_VERSION}/ucd/UnicodeData.txt"
+
+
+def read_unicode_data(lines):
+    """Maps code points to their canonical combining class and canonical decomposition mapping."""
+    classes = {}
+    mappings = {}
+    for line in lines:
+        fields = line.strip().split(";")
+        if len(fields) < 6:
+            continue
+        code = int(fields[0], 16)
+        if int(fields[3]):
+            classes[code] = int(fields[3])
+        # compatibility mappings start with a `<tag>`
+        if fields[5] and not fields[5].startswith("<"):
+            mappings[code] = [int(c, 16) for c in fields[5].split()]
+    return classes, mappings
+
+
+def full_decomposition(code, mappings):
+    if code not in mappings:
+        return [code]
+    return [d for c in mappings[code] for d in full_decomposition(c, mappings)]
+
+
+def class_ranges(classes):
+    ranges = []
+    for code in sorted(classes):
+        last = ranges[-1] if ranges else None
+        if last and last[1] + 1 == code and last[2] == classes[code]:
+            last[1] = code
+        else:
+            ranges.append([code, code, classes[code]])
+    return ranges
+
+
+def main():
+    if len(sys.argv) > 1:
+        with open(sys.argv[1], encoding="utf-8") as f:
+            lines = f.readlines()
+    else:
+        with urllib.request.urlopen(UCD_URL) as response:
+            lines = response.read().decode("utf-8").splitlines()
+
+    classes, mappings = read_unicode_data(lines)
+    ranges = class_ranges(classes)
+
+    out = [
+        f"// Generated from the Unicode Character Database (version {UCD_VERSION}) by",
+        "// `scripts/generate_decomposition_table.py`. Do not edit manually.",
+        "",
+        "/// Full canonical decompositions, sorted by code point. Hangul syllables are decomposed",
+        "/// algorithmically and therefore not part of this table.",
+        "pub(super) static CANONICAL_DECOMPOSITIONS: "
+        f"[(u32, &[u32]); {len(mappings)}] = [",
+    ]
+    for code in sorted(mappings):
+        decomposition = ", ".join(f"0x{c:04X}" for c in full_decomposition(code, mappings))
+        out.append(f"    (0x{code:04X}, &[{decomposition}]),")
+    out += [
+        "];",
+        "",
+        "/// Ranges `(first, last, class)` of code points with a non-zero canonical combining class.",
+        f"pub(super) static COMBINING_CLASSES: [(u32, u32, u8); {len(ranges)}] = [",
+    ]
+    for first, last, class_ in ranges:
+        out.append(f"    (0x{first:04X}, 0x{last:04X}, {class_}),")
+    out.append("];")
+    print("\n".join(out))
+
+
+if __name__ == "__main__":
+    main()
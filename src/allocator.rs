@@ -0,0 +1,248 @@
+use core::ops::Range;
+
+use alloc::vec::Vec;
+
+use crate::{FIRST_USABLE_CLUSTER_INDEX, bitmap::AllocationBitmap};
+
+/// Read-only view of the allocation bitmap of a volume, handed to [`ClusterAllocator`]s.
+#[derive(Copy, Clone, Debug)]
+pub struct FreeClusters<'a> {
+    bitmap: &'a AllocationBitmap,
+}
+
+impl<'a> FreeClusters<'a> {
+    pub(crate) fn new(bitmap: &'a AllocationBitmap) -> FreeClusters<'a> {
+        FreeClusters { bitmap }
+    }
+
+    /// Clusters of the cluster heap.
+    pub fn clusters(&self) -> Range<u32> {
+        FIRST_USABLE_CLUSTER_INDEX..FIRST_USABLE_CLUSTER_INDEX + self.bitmap.cluster_count()
+    }
+
+    /// Whether the cluster is part of the heap and not in use.
+    pub fn is_free(&self, cluster: u32) -> bool {
+        !self.bitmap.is_allocated(cluster)
+    }
+
+    /// First cluster of the first run of `count` free clusters at or after `start`.
+    pub fn find_run(&self, start: u32, count: u32) -> Option<u32> {
+        let mut run = 0;
+        for cluster in start.max(self.clusters().start)..self.clusters().end {
+            if self.is_free(cluster) {
+                run += 1;
+                if run == count {
+                    return Some(cluster + 1 - count);
+                }
+            } else {
+                run = 0;
+            }
+        }
+        None
+    }
+
+    /// The first `count` free clusters at or after `start`, continuing at the start of the heap.
+    fn scattered(&self, start: u32, count: u32) -> Option<Vec<u32>> {
+        let heap = self.clusters();
+        let start = start.clamp(heap.start, heap.end);
+        let clusters: Vec<u32> = (start..heap.end)
+            .chain(heap.start..start)
+            .filter(|cluster| self.is_free(*cluster))
+            .take(count as usize)
+            .collect();
+        (clusters.len() == count as usize).then_some(clusters)
+    }
+}
+
+/// Decides which free clusters are used when files and directories grow. Allocators only pick
+/// clusters; marking them as allocated is up to the caller.
+pub trait ClusterAllocator {
+    /// Picks `count` free clusters, in the order they are appended to a cluster chain ending at
+    /// `after` (`None` for new chains). `None` if there are not enough free clusters.
+    fn allocate(
+        &mut self,
+        free: &FreeClusters<'_>,
+        count: u32,
+        after: Option<u32>,
+    ) -> Option<Vec<u32>>;
+
+    /// Picks a run of `count` free contiguous clusters and returns its first cluster.
+    fn allocate_contiguous(&mut self, free: &FreeClusters<'_>, count: u32) -> Option<u32>;
+}
+
+/// Always uses the free clusters closest to the start of the heap.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FirstFit;
+
+impl ClusterAllocator for FirstFit {
+    fn allocate(
+        &mut self,
+        free: &FreeClusters<'_>,
+        count: u32,
+        _: Option<u32>,
+    ) -> Option<Vec<u32>> {
+        free.scattered(free.clusters().start, count)
+    }
+
+    fn allocate_contiguous(&mut self, free: &FreeClusters<'_>, count: u32) -> Option<u32> {
+        free.find_run(free.clusters().start, count)
+    }
+}
+
+/// Continues searching where the previous allocation ended, wrapping around at the end of the
+/// heap. Spreads writes across the heap, which suits flash media without wear leveling.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NextFit {
+    /// Cluster following the last allocated one.
+    rotor: u32,
+}
+
+impl ClusterAllocator for NextFit {
+    fn allocate(
+        &mut self,
+        free: &FreeClusters<'_>,
+        count: u32,
+        _: Option<u32>,
+    ) -> Option<Vec<u32>> {
+        let clusters = free.scattered(self.rotor, count)?;
+        if let Some(last) = clusters.last() {
+            self.rotor = last + 1;
+        }
+        Some(clusters)
+    }
+
+    fn allocate_contiguous(&mut self, free: &FreeClusters<'_>, count: u32) -> Option<u32> {
+        let first = free
+            .find_run(self.rotor, count)
+            .or_else(|| free.find_run(free.clusters().start, count))?;
+        self.rotor = first + count;
+        Some(first)
+    }
+}
+
+/// Keeps chains contiguous: continues them with the clusters right after their end if these are
+/// free, otherwise uses the first run of free clusters big enough. Only falls back to scattered
+/// clusters if there is no such run.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ContiguousPreferred;
+
+impl ClusterAllocator for ContiguousPreferred {
+    fn allocate(
+        &mut self,
+        free: &FreeClusters<'_>,
+        count: u32,
+        after: Option<u32>,
+    ) -> Option<Vec<u32>> {
+        let first = after
+            .map(|last| last + 1)
+            .filter(|next| free.find_run(*next, count) == Some(*next))
+            .or_else(|| self.allocate_contiguous(free, count));
+        match first {
+            Some(first) => Some((first..first + count).collect()),
+            None => free.scattered(free.clusters().start, count),
+        }
+    }
+
+    fn allocate_contiguous(&mut self, free: &FreeClusters<'_>, count: u32) -> Option<u32> {
+        free.find_run(free.clusters().start, count)
+    }
+}
+
+/// The [`ClusterAllocator`] used by handles opened with the given
+/// [`OpenOptions`](crate::root::OpenOptions).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AllocationStrategy {
+    /// See [`FirstFit`].
+    FirstFit,
+    /// See [`NextFit`].
+    NextFit,
+    /// See [`ContiguousPreferred`].
+    #[default]
+    ContiguousPreferred,
+}
+
+/// The allocator of a handle, as selected by its [`AllocationStrategy`].
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Allocator {
+    FirstFit(FirstFit),
+    NextFit(NextFit),
+    ContiguousPreferred(ContiguousPreferred),
+}
+
+impl From<AllocationStrategy> for Allocator {
+    fn from(strategy: AllocationStrategy) -> Allocator {
+        match strategy {
+            AllocationStrategy::FirstFit => Allocator::FirstFit(FirstFit),
+            AllocationStrategy::NextFit => Allocator::NextFit(NextFit::default()),
+            AllocationStrategy::ContiguousPreferred => {
+                Allocator::ContiguousPreferred(ContiguousPreferred)
+            }
+        }
+    }
+}
+
+impl Allocator {
+    fn inner(&mut self) -> &mut dyn ClusterAllocator {
+        match self {
+            Allocator::FirstFit(allocator) => allocator,
+            Allocator::NextFit(allocator) => allocator,
+            Allocator::ContiguousPreferred(allocator) => allocator,
+        }
+    }
+}
+
+impl ClusterAllocator for Allocator {
+    fn allocate(
+        &mut self,
+        free: &FreeClusters<'_>,
+        count: u32,
+        after: Option<u32>,
+    ) -> Option<Vec<u32>> {
+        self.inner().allocate(free, count, after)
+    }
+
+    fn allocate_contiguous(&mut self, free: &FreeClusters<'_>, count: u32) -> Option<u32> {
+        self.inner().allocate_contiguous(free, count)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn allocation_strategies() {
+    let boot = crate::test_utils::TestImage::new().boot;
+    let mut bitmap = AllocationBitmap::empty(boot.cluster_count);
+    // clusters 2 to 9 and 12 are in use, 10 and 11 are free
+    bitmap.set(2, 8, true);
+    bitmap.set(12, 1, true);
+    let free = FreeClusters::new(&bitmap);
+
+    assert_eq!(
+        FirstFit.allocate(&free, 3, Some(12)),
+        Some(vec![10, 11, 13])
+    );
+    assert_eq!(FirstFit.allocate_contiguous(&free, 3), Some(13));
+
+    let mut next_fit = NextFit::default();
+    assert_eq!(next_fit.allocate(&free, 1, None), Some(vec![10]));
+    assert_eq!(next_fit.allocate(&free, 1, None), Some(vec![11]));
+    assert_eq!(next_fit.allocate_contiguous(&free, 2), Some(13));
+    assert_eq!(next_fit.allocate_contiguous(&free, 2), Some(15));
+    // wraps around at the end of the heap
+    let last = free.clusters().end - 1;
+    next_fit.rotor = last;
+    assert_eq!(next_fit.allocate(&free, 2, None), Some(vec![last, 10]));
+
+    // chains continue right after their end
+    assert_eq!(
+        ContiguousPreferred.allocate(&free, 2, Some(12)),
+        Some(vec![13, 14])
+    );
+    assert_eq!(
+        ContiguousPreferred.allocate(&free, 3, Some(9)),
+        Some(vec![13, 14, 15])
+    );
+    assert_eq!(
+        ContiguousPreferred.allocate(&free, boot.cluster_count, None),
+        None
+    );
+}
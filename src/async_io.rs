@@ -0,0 +1,618 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{future::Future, marker::PhantomData};
+
+use crate::{
+    Label,
+    cache::Lock,
+    disk::{PartitionError, ReadOffset, SeekFrom},
+    error::{AsyncError, DirectoryError, RootError},
+    fs::{EntryLocation, FileAttributes, FsElement, directory::Directory, file::File},
+    root::{OpenOptions, Root},
+    timestamp::Timestamps,
+};
+
+/// Size of the blocks in which metadata is fetched from asynchronous devices.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Amount of fetched chunks kept in memory once a parse completed. Chunks needed by a parse are
+/// kept until it completes, so that it can make progress.
+const MAX_CHUNKS: usize = 64;
+
+/// Asynchronous counterpart of [`ReadOffset`], e.g. implemented on top of `tokio`'s positional
+/// reads.
+pub trait AsyncReadOffset {
+    type Err: PartitionError + 'static;
+
+    fn read_at(
+        &self,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> impl Future<Output = Result<usize, Self::Err>>;
+
+    fn read_exact(
+        &self,
+        mut offset: u64,
+        mut buffer: &mut [u8],
+    ) -> impl Future<Output = Result<(), Self::Err>> {
+        async move {
+            while !buffer.is_empty() {
+                match self.read_at(offset, buffer).await? {
+                    0 => break,
+                    n => {
+                        buffer = &mut buffer[n..];
+                        offset = offset
+                            .checked_add(n as u64)
+                            .ok_or(PartitionError::unexpected_eop())?;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Asynchronous counterpart of [`WriteSeek`](crate::disk::WriteSeek).
+pub trait AsyncWriteSeek {
+    type Err;
+
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = Result<usize, Self::Err>>;
+
+    fn failed_to_write(&self) -> Self::Err;
+
+    fn write_all(&mut self, mut buf: &[u8]) -> impl Future<Output = Result<(), Self::Err>> {
+        async move {
+            while !buf.is_empty() {
+                match self.write(buf).await? {
+                    0 => return Err(self.failed_to_write()),
+                    n => buf = &buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> impl Future<Output = Result<u64, Self::Err>>;
+
+    fn stream_position(&mut self) -> impl Future<Output = Result<u64, Self::Err>> {
+        self.seek(SeekFrom::Current(0))
+    }
+}
+
+/// The parts of an asynchronous device fetched recently, from which the volume structures are
+/// parsed synchronously. Parts that have not been fetched read as zeroes and are recorded, so that
+/// a parse runs on and reports everything it is missing at once; [`AsyncRoot`] fetches all of it
+/// before parsing again. Once a parse completed, all but the most recently used chunks are
+/// dropped.
+pub struct Prefetched<E> {
+    chunks: Arc<Lock<Chunks>>,
+    _err: PhantomData<fn() -> E>,
+}
+
+#[derive(Debug, Default)]
+struct Chunks {
+    /// Time of the last use and contents of the fetched chunks by index; the last chunk of the
+    /// device may be shorter.
+    fetched: BTreeMap<u64, (u64, Vec<u8>)>,
+    /// Chunks that were read before being fetched.
+    missing: BTreeSet<u64>,
+    /// Incremented on every read.
+    clock: u64,
+}
+
+impl Chunks {
+    /// Drops the least recently used chunks until at most [`MAX_CHUNKS`] are left.
+    fn trim(&mut self) {
+        while self.fetched.len() > MAX_CHUNKS {
+            let least_recent = self
+                .fetched
+                .iter()
+                .min_by_key(|(_, (last_use, _))| *last_use)
+                .map(|(index, _)| *index);
+            if let Some(index) = least_recent {
+                self.fetched.remove(&index);
+            }
+        }
+    }
+}
+
+// not derived, as the chunks are shared instead of cloned
+impl<E> Clone for Prefetched<E> {
+    fn clone(&self) -> Self {
+        Prefetched {
+            chunks: Arc::clone(&self.chunks),
+            _err: PhantomData,
+        }
+    }
+}
+
+impl<E> core::fmt::Debug for Prefetched<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Prefetched").finish_non_exhaustive()
+    }
+}
+
+impl<E> Prefetched<E> {
+    fn new() -> Prefetched<E> {
+        Prefetched {
+            chunks: Arc::new(Lock::new(Chunks::default())),
+            _err: PhantomData,
+        }
+    }
+
+    /// Fetches the chunks recorded as missing from `device`. Returns whether there were any.
+    async fn fetch<D: AsyncReadOffset<Err = E>>(&self, device: &D) -> Result<bool, E> {
+        let missing = core::mem::take(&mut self.chunks.lock().missing);
+        for index in &missing {
+            let mut chunk = vec![0u8; CHUNK_SIZE as usize];
+            let mut read = 0;
+            while read < chunk.len() {
+                match device
+                    .read_at(index * CHUNK_SIZE + read as u64, &mut chunk[read..])
+                    .await?
+                {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+            chunk.truncate(read);
+            let mut chunks = self.chunks.lock();
+            let clock = chunks.clock;
+            chunks.fetched.insert(*index, (clock, chunk));
+        }
+        Ok(!missing.is_empty())
+    }
+}
+
+impl<E: PartitionError + 'static> ReadOffset for Prefetched<E> {
+    type Err = E;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let index = offset / CHUNK_SIZE;
+        let mut chunks = self.chunks.lock();
+        chunks.clock += 1;
+        let clock = chunks.clock;
+        match chunks.fetched.get_mut(&index) {
+            Some((last_use, chunk)) => {
+                *last_use = clock;
+                let skip = (offset - index * CHUNK_SIZE) as usize;
+                let amount = chunk.len().saturating_sub(skip).min(buf.len());
+                buf[..amount].copy_from_slice(&chunk[skip..skip + amount]);
+                Ok(amount)
+            }
+            None => {
+                // record the whole read, so that it succeeds once fetched
+                let last = offset.saturating_add(buf.len() as u64 - 1) / CHUNK_SIZE;
+                for index in index..=last {
+                    if !chunks.fetched.contains_key(&index) {
+                        chunks.missing.insert(index);
+                    }
+                }
+                // the parse is repeated anyway, so it continues on zeroes to find all other
+                // missing parts
+                let amount = ((index + 1) * CHUNK_SIZE - offset).min(buf.len() as u64) as usize;
+                buf[..amount].fill(0);
+                Ok(amount)
+            }
+        }
+    }
+}
+
+/// An element of a directory read through an [`AsyncRoot`].
+pub enum AsyncElement<E: PartitionError + 'static> {
+    F(AsyncFile<E>),
+    D(AsyncDirectory<E>),
+}
+
+impl<E: PartitionError + 'static> AsyncElement<E> {
+    fn new(element: FsElement<Prefetched<E>>) -> AsyncElement<E> {
+        match element {
+            FsElement::F(file) => AsyncElement::F(AsyncFile::new(file)),
+            FsElement::D(dir) => AsyncElement::D(AsyncDirectory(dir)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            AsyncElement::F(file) => file.name(),
+            AsyncElement::D(dir) => dir.name(),
+        }
+    }
+
+    pub fn location(&self) -> EntryLocation {
+        match self {
+            AsyncElement::F(file) => file.location(),
+            AsyncElement::D(dir) => dir.location(),
+        }
+    }
+}
+
+/// A file read through an [`AsyncRoot`], see [`AsyncRoot::read_at`].
+pub struct AsyncFile<E: PartitionError + 'static> {
+    file: File<Prefetched<E>>,
+    /// Clusters of the file in order, followed on the first read.
+    chain: Lock<Option<Arc<Vec<u32>>>>,
+}
+
+impl<E: PartitionError + 'static> AsyncFile<E> {
+    fn new(file: File<Prefetched<E>>) -> AsyncFile<E> {
+        AsyncFile {
+            file,
+            chain: Lock::new(None),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.file.name()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file.is_empty()
+    }
+
+    /// Length of the contents of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.file.len()
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        self.file.timestamps()
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.file.attributes()
+    }
+
+    pub fn location(&self) -> EntryLocation {
+        self.file.location()
+    }
+}
+
+/// A directory read through an [`AsyncRoot`], see [`AsyncRoot::read_dir`].
+pub struct AsyncDirectory<E: PartitionError + 'static>(Directory<Prefetched<E>>);
+
+impl<E: PartitionError + 'static> AsyncDirectory<E> {
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        self.0.timestamps()
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.0.attributes()
+    }
+
+    pub fn location(&self) -> EntryLocation {
+        self.0.location()
+    }
+}
+
+/// The root directory of a volume on an asynchronous device. The volume structures are parsed by
+/// the synchronous parser, which is run again after fetching the chunks of 64 KiB it was missing
+/// until it completes; each run therefore costs a parse of the structures read so far. Only the
+/// most recently used chunks are kept in memory, and the contents of files are read from the
+/// device directly.
+pub struct AsyncRoot<D: AsyncReadOffset> {
+    device: D,
+    prefetched: Prefetched<D::Err>,
+    root: Root<Prefetched<D::Err>>,
+    items: Vec<AsyncElement<D::Err>>,
+}
+
+impl<D: AsyncReadOffset> AsyncRoot<D> {
+    pub async fn open(device: D) -> Result<Self, AsyncError<D::Err>> {
+        Self::open_with(device, OpenOptions::default()).await
+    }
+
+    /// Opens the volume like [`Root::open_with`]. The FAT is never loaded entirely, regardless
+    /// of [`OpenOptionsBuilder::defer_fat`](crate::root::OpenOptionsBuilder::defer_fat); only the
+    /// chunks holding the chains that are followed are fetched.
+    pub async fn open_with(
+        device: D,
+        mut options: OpenOptions,
+    ) -> Result<Self, AsyncError<D::Err>> {
+        options.defer_fat = true;
+        let prefetched = Prefetched::new();
+        let (root, items) = retry(&device, &prefetched, || {
+            let mut root = Root::open_with(prefetched.clone(), options)?;
+            // the chains of the files are followed as part of the parse
+            let items = root.items.drain(..).map(AsyncElement::new).collect();
+            Ok::<_, RootError<_>>((root, items))
+        })
+        .await?;
+        Ok(AsyncRoot {
+            device,
+            prefetched,
+            root,
+            items,
+        })
+    }
+
+    pub fn label(&self) -> Option<&Label> {
+        self.root.label()
+    }
+
+    /// Serial number stored in the boot sector.
+    pub fn volume_serial_number(&self) -> u32 {
+        self.root.volume_serial_number()
+    }
+
+    /// Elements of the root directory.
+    pub fn items(&self) -> &[AsyncElement<D::Err>] {
+        &self.items
+    }
+
+    /// Reads the elements of a directory of the volume.
+    pub async fn read_dir(
+        &self,
+        dir: &AsyncDirectory<D::Err>,
+    ) -> Result<Vec<AsyncElement<D::Err>>, AsyncError<D::Err>> {
+        retry(&self.device, &self.prefetched, || {
+            let items = dir.0.open()?;
+            Ok::<_, DirectoryError<_>>(items.into_iter().map(AsyncElement::new).collect())
+        })
+        .await
+    }
+
+    /// Reads the contents of a file starting at `offset`, coalescing reads of adjacent clusters.
+    /// Returns the amount of bytes read, which is only less than the length of `buf` at the end
+    /// of the file.
+    pub async fn read_at(
+        &self,
+        file: &AsyncFile<D::Err>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, D::Err> {
+        let bytes_per_cluster = self.root.boot.bytes_per_cluster() as u64;
+        let end = file.len().min(offset.saturating_add(buf.len() as u64));
+        if offset >= end {
+            return Ok(0);
+        }
+        let chain = self.chain(file).await?;
+        let mut position = offset;
+        while position < end {
+            let index = (position / bytes_per_cluster) as usize;
+            let first = *chain.get(index).ok_or(PartitionError::unexpected_eop())?;
+            let mut run = 1;
+            while chain.get(index + run) == Some(&(first + run as u32)) {
+                run += 1;
+            }
+
+            let run_end = ((index + run) as u64 * bytes_per_cluster).min(end);
+            let device_offset = self
+                .root
+                .boot
+                .cluster_offset(first)
+                .ok_or(PartitionError::cluster_not_found(first))?
+                + position % bytes_per_cluster;
+            let start = (position - offset) as usize;
+            let amount = (run_end - position) as usize;
+            self.device
+                .read_exact(device_offset, &mut buf[start..start + amount])
+                .await?;
+            position = run_end;
+        }
+        Ok(end.saturating_sub(offset) as usize)
+    }
+
+    /// Clusters of `file` in order, followed through the FAT on the first read of the file.
+    async fn chain(&self, file: &AsyncFile<D::Err>) -> Result<Arc<Vec<u32>>, D::Err> {
+        if let Some(chain) = &*file.chain.lock() {
+            return Ok(Arc::clone(chain));
+        }
+        // like `retry`, for a parse that cannot fail
+        let chain = loop {
+            let (chain, _) = file.file.cluster_chain();
+            if !self.prefetched.fetch(&self.device).await? {
+                break Arc::new(chain);
+            }
+        };
+        self.prefetched.chunks.lock().trim();
+        *file.chain.lock() = Some(Arc::clone(&chain));
+        Ok(chain)
+    }
+
+    /// Appends the contents of a file to `buf`, one cluster at a time, so that `buf` only grows
+    /// as data arrives. Use [`AsyncRoot::copy_to`] to process files without keeping them in
+    /// memory. Returns the amount of bytes read.
+    pub async fn read_to_end(
+        &self,
+        file: &AsyncFile<D::Err>,
+        buf: &mut Vec<u8>,
+    ) -> Result<usize, D::Err> {
+        let bytes_per_cluster = self.root.boot.bytes_per_cluster() as u64;
+        let mut offset = 0;
+        while offset < file.len() {
+            let start = buf.len();
+            let amount = (file.len() - offset).min(bytes_per_cluster) as usize;
+            buf.resize(start + amount, 0);
+            let read = self.read_at(file, offset, &mut buf[start..]).await;
+            match read {
+                Ok(read) => buf.truncate(start + read),
+                Err(err) => {
+                    buf.truncate(start);
+                    return Err(err);
+                }
+            }
+            offset += amount as u64;
+        }
+        Ok(offset as usize)
+    }
+
+    /// Writes the contents of a file to `out` at its current position, one run of adjacent
+    /// clusters at a time. Returns the amount of bytes written.
+    pub async fn copy_to<W: AsyncWriteSeek<Err = D::Err>>(
+        &self,
+        file: &AsyncFile<D::Err>,
+        out: &mut W,
+    ) -> Result<u64, D::Err> {
+        let bytes_per_cluster = self.root.boot.bytes_per_cluster() as usize;
+        let mut buf = vec![0u8; bytes_per_cluster];
+        let mut offset = 0;
+        while offset < file.len() {
+            let read = self.read_at(file, offset, &mut buf).await?;
+            out.write_all(&buf[..read]).await?;
+            offset += read as u64;
+        }
+        Ok(offset)
+    }
+}
+
+/// Runs `parse` until it no longer reads parts of the device that have not been fetched yet,
+/// fetching them in between. Afterwards, the cache of fetched chunks is trimmed.
+async fn retry<D: AsyncReadOffset, T, P>(
+    device: &D,
+    prefetched: &Prefetched<D::Err>,
+    mut parse: impl FnMut() -> Result<T, P>,
+) -> Result<T, AsyncError<D::Err>>
+where
+    AsyncError<D::Err>: From<P>,
+{
+    loop {
+        let result = parse();
+        // errors may be tolerated by the parser, so successful results are checked as well
+        let fetched = prefetched.fetch(device).await;
+        if !matches!(fetched, Ok(true)) {
+            prefetched.chunks.lock().trim();
+            fetched.map_err(AsyncError::Io)?;
+            return result.map_err(AsyncError::from);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn async_reads() {
+    use crate::test_utils::TestImage;
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+    use std::{cell::Cell, io::Cursor};
+
+    /// Yields once before completing, like a device waiting for I/O.
+    struct Pending<T>(Option<T>, bool);
+
+    impl<T: Unpin> Future for Pending<T> {
+        type Output = T;
+
+        fn poll(mut self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+            if core::mem::replace(&mut self.1, true) {
+                Poll::Ready(self.0.take().unwrap())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    struct Device(Cursor<Vec<u8>>, Cell<usize>);
+
+    impl AsyncReadOffset for Device {
+        type Err = std::io::Error;
+
+        fn read_at(
+            &self,
+            offset: u64,
+            buffer: &mut [u8],
+        ) -> impl Future<Output = Result<usize, Self::Err>> {
+            self.1.set(self.1.get() + 1);
+            Pending(Some(ReadOffset::read_at(&self.0, offset, buffer)), false)
+        }
+    }
+
+    struct Sink(Vec<u8>);
+
+    impl AsyncWriteSeek for Sink {
+        type Err = std::io::Error;
+
+        fn write(&mut self, buf: &[u8]) -> impl Future<Output = Result<usize, Self::Err>> {
+            self.0.extend_from_slice(buf);
+            Pending(Some(Ok(buf.len())), false)
+        }
+
+        fn failed_to_write(&self) -> Self::Err {
+            std::io::Error::from(std::io::ErrorKind::WriteZero)
+        }
+
+        fn seek(&mut self, _: SeekFrom) -> impl Future<Output = Result<u64, Self::Err>> {
+            Pending(Some(Ok(self.0.len() as u64)), false)
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    let image = TestImage::sample().cursor();
+    let mut expected = Root::open(image.clone()).unwrap();
+    let root = block_on(AsyncRoot::open(Device(image, Cell::new(0)))).unwrap();
+    // the metadata is fetched in chunks instead of being read piecemeal
+    assert!(root.device.1.get() < 10);
+    assert_eq!(root.volume_serial_number(), expected.volume_serial_number());
+
+    let names: Vec<&str> = root.items().iter().map(AsyncElement::name).collect();
+    assert_eq!(names, ["hello.txt", "dir"]);
+    let AsyncElement::F(hello) = &root.items()[0] else {
+        panic!("hello.txt is not a file");
+    };
+    let FsElement::F(expected_hello) = &expected.items()[0] else {
+        panic!("hello.txt is not a file");
+    };
+    let mut contents = Vec::new();
+    let read = block_on(root.read_to_end(hello, &mut contents)).unwrap();
+    assert_eq!(contents, expected_hello.contents().unwrap());
+    assert_eq!(read, contents.len());
+    let mut buf = [0u8; 64];
+    let read = block_on(root.read_at(hello, 2, &mut buf)).unwrap();
+    assert_eq!(buf[..read], contents[2..]);
+    let mut out = Sink(Vec::new());
+    assert_eq!(
+        block_on(root.copy_to(hello, &mut out)).unwrap(),
+        contents.len() as u64
+    );
+    assert_eq!(out.0, contents);
+
+    let AsyncElement::D(dir) = &root.items()[1] else {
+        panic!("dir is not a directory");
+    };
+    let items = block_on(root.read_dir(dir)).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name(), "nested.txt");
+
+    // parts that have not been fetched read as zeroes, and all of them are recorded
+    let prefetched = Prefetched::<std::io::Error>::new();
+    let mut buf = [1u8; 4];
+    assert_eq!(prefetched.read_at(CHUNK_SIZE - 2, &mut buf).unwrap(), 2);
+    assert_eq!(buf, [0, 0, 1, 1]);
+    prefetched.read_at(5 * CHUNK_SIZE, &mut buf).unwrap();
+    assert_eq!(prefetched.chunks.lock().missing, BTreeSet::from([0, 1, 5]));
+
+    // only the most recently used chunks are kept
+    let mut chunks = Chunks::default();
+    let len = MAX_CHUNKS as u64 + 2;
+    for index in 0..len {
+        chunks
+            .fetched
+            .insert(index, ((index + 2) % len, Vec::new()));
+    }
+    chunks.trim();
+    assert_eq!(chunks.fetched.len(), MAX_CHUNKS);
+    assert!(!chunks.fetched.contains_key(&(len - 2)));
+    assert!(!chunks.fetched.contains_key(&(len - 1)));
+}
@@ -0,0 +1,186 @@
+use std::{format, string::String};
+
+use derive_builder::Builder;
+
+use crate::{
+    disk::{ReadOffset, WriteSeek},
+    error::ImageBuilderError,
+    format::{FormatVolumeOptions, ImageBuilder},
+    timestamp::Clock,
+};
+
+/// Shape of a synthetic volume created by [`generate`]. Every directory, including the root
+/// directory, holds the same amount of files and subdirectories. It implements the
+/// [`derive_builder::Builder`] pattern.
+#[derive(Builder, Copy, Clone, Debug)]
+#[builder(no_std)]
+pub struct SyntheticTree {
+    /// Subdirectories of each directory above the deepest level. Defaults to `4`.
+    #[builder(default = 4)]
+    width: u32,
+    /// Levels of subdirectories below the root directory. Defaults to `2`.
+    #[builder(default = 2)]
+    depth: u32,
+    /// Files in each directory. Defaults to `16`.
+    #[builder(default = 16)]
+    files_per_directory: u32,
+    /// Size of each file in bytes. Defaults to `4096`.
+    #[builder(default = 4096)]
+    file_size: u64,
+}
+
+/// What [`generate`] wrote onto the device.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyntheticSummary {
+    /// Directories, excluding the root directory.
+    pub directories: u64,
+    pub files: u64,
+    /// Combined size of all files.
+    pub bytes: u64,
+}
+
+/// Deterministic contents of a synthetic file, which differ between files with different seeds.
+#[derive(Copy, Clone, Debug)]
+pub struct SyntheticData {
+    seed: u64,
+}
+
+impl SyntheticData {
+    pub fn new(seed: u64) -> SyntheticData {
+        SyntheticData { seed }
+    }
+
+    /// Byte at the given offset of the file.
+    pub fn byte_at(&self, offset: u64) -> u8 {
+        (offset.wrapping_add(self.seed.wrapping_mul(31)) % 251) as u8
+    }
+}
+
+impl ReadOffset for SyntheticData {
+    type Err = std::io::Error;
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize, Self::Err> {
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.byte_at(offset + i as u64);
+        }
+        Ok(buffer.len())
+    }
+}
+
+/// Formats the device and fills it with the tree described by `tree`. Directories are named
+/// `dir0`, `dir1`, ... and files `file0.bin`, `file1.bin`, ...; the contents of the n-th file
+/// (counting in creation order, starting at `0`) are [`SyntheticData::new(n)`](SyntheticData::new).
+/// As with [`ImageBuilder`], the device length must match the `dev_size` of `format_options`.
+pub fn generate<T: Clock, O: WriteSeek>(
+    format_options: FormatVolumeOptions,
+    tree: SyntheticTree,
+    device: &mut O,
+) -> Result<SyntheticSummary, ImageBuilderError<T, O, SyntheticData>>
+where
+    T::Err: core::fmt::Debug,
+{
+    let mut builder = ImageBuilder::new(format_options);
+    let mut summary = SyntheticSummary::default();
+    populate(&mut builder, &tree, "", 0, &mut summary);
+    builder.write::<T, O>(device)?;
+    Ok(summary)
+}
+
+/// Adds the files and subdirectories of the directory at `path`, which is `level` levels below
+/// the root directory.
+fn populate(
+    builder: &mut ImageBuilder<SyntheticData>,
+    tree: &SyntheticTree,
+    path: &str,
+    level: u32,
+    summary: &mut SyntheticSummary,
+) {
+    let child = |name: String| match path {
+        "" => name,
+        _ => format!("{path}/{name}"),
+    };
+
+    for i in 0..tree.files_per_directory {
+        let data = SyntheticData::new(summary.files);
+        builder
+            .add_file(&child(format!("file{i}.bin")), data, tree.file_size)
+            .expect("synthetic names are valid");
+        summary.files += 1;
+        summary.bytes += tree.file_size;
+    }
+
+    if level == tree.depth {
+        return;
+    }
+    for i in 0..tree.width {
+        let directory = child(format!("dir{i}"));
+        builder
+            .add_dir(&directory)
+            .expect("synthetic names are valid");
+        summary.directories += 1;
+        populate(builder, tree, &directory, level + 1, summary);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn generate_tree() {
+    use crate::{
+        format::FormatVolumeOptionsBuilder, fs::FsElement, name::Normalization, root::Root,
+        test_utils::IMAGE_SIZE,
+    };
+    use std::{
+        io::{Cursor, Read},
+        time::SystemTime,
+        vec::Vec,
+    };
+
+    let format_options = FormatVolumeOptionsBuilder::default()
+        .dev_size(IMAGE_SIZE)
+        .bytes_per_sector(512)
+        .build()
+        .unwrap();
+    let tree = SyntheticTreeBuilder::default()
+        .width(3)
+        .depth(2)
+        .files_per_directory(5)
+        .file_size(5000)
+        .build()
+        .unwrap();
+
+    let mut device = Cursor::new(vec![0u8; IMAGE_SIZE as usize]);
+    let summary = generate::<SystemTime, _>(format_options, tree, &mut device).unwrap();
+    // 1 + 3 + 9 directories with 5 files each
+    assert_eq!(summary.directories, 12);
+    assert_eq!(summary.files, 65);
+    assert_eq!(summary.bytes, 65 * 5000);
+
+    let mut root = Root::open(device).unwrap();
+    assert_eq!(root.items().len(), 8);
+    let Some(FsElement::D(dir)) = root.find("dir2", Normalization::None) else {
+        panic!("dir2 not found");
+    };
+    let Some(FsElement::D(dir)) = dir.find("dir0", Normalization::None).unwrap() else {
+        panic!("dir2/dir0 not found");
+    };
+    let Some(FsElement::F(mut file)) = dir.find("file1.bin", Normalization::None).unwrap() else {
+        panic!("dir2/dir0/file1.bin not found");
+    };
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).unwrap();
+    let expected = SyntheticData::new(file_index(2, 0, 1));
+    assert_eq!(contents.len(), 5000);
+    assert!(
+        contents
+            .iter()
+            .enumerate()
+            .all(|(offset, byte)| *byte == expected.byte_at(offset as u64))
+    );
+
+    /// Creation order index of file `file` in `dir{outer}/dir{inner}`.
+    fn file_index(outer: u64, inner: u64, file: u64) -> u64 {
+        // root files, then each top-level directory with its 3 subdirectories
+        5 + outer * 20 + 5 + inner * 5 + file
+    }
+}
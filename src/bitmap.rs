@@ -0,0 +1,207 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::{
+    FIRST_USABLE_CLUSTER_INDEX,
+    boot_sector::{BootSector, VolumeFlags},
+    cluster::{ClusterChainOptions, reader::ClusterChainReader},
+    disk::{PartitionError, ReadOffset, WriteOffset},
+    entry::BitmapEntry,
+    error::{ClusterChainError, RootError, WriteError},
+    fat::Fat,
+};
+
+/// In-memory copy of an allocation bitmap, tracking which clusters of the heap are in use.
+#[derive(Clone, Debug)]
+pub(crate) struct AllocationBitmap {
+    bits: Vec<u8>,
+    cluster_count: u32,
+    /// Clusters holding the bitmap on disk, followed by the clusters of each mirror (the second
+    /// allocation bitmap of TexFAT volumes).
+    chains: Vec<Vec<u32>>,
+}
+
+impl AllocationBitmap {
+    pub(crate) fn load<O: ReadOffset>(
+        disk: &Arc<O>,
+        boot: &Arc<BootSector>,
+        fat: &Fat,
+        entry: &BitmapEntry,
+    ) -> Result<AllocationBitmap, WriteError<O>> {
+        let mut reader = Self::reader(disk, boot, fat, entry)?;
+
+        let mut bits = vec![0u8; boot.cluster_count.div_ceil(8) as usize];
+        reader.read_exact(&mut bits).map_err(WriteError::Io)?;
+
+        Ok(AllocationBitmap {
+            bits,
+            cluster_count: boot.cluster_count,
+            chains: vec![reader.chain().to_vec()],
+        })
+    }
+
+    /// Loads the bitmap of the active FAT among the allocation bitmaps of a volume and mirrors all
+    /// updates into the other one (on TexFAT volumes).
+    pub(crate) fn load_active<O: ReadOffset>(
+        disk: &Arc<O>,
+        boot: &Arc<BootSector>,
+        fat: &Fat,
+        entries: &[BitmapEntry],
+    ) -> Result<AllocationBitmap, WriteError<O>> {
+        if entries.is_empty() {
+            return Err(WriteError::Reopen(
+                RootError::InvalidNumberOfAllocationBitmaps,
+            ));
+        }
+        let active = Self::active_index(boot, entries.len());
+        let mut bitmap = AllocationBitmap::load(disk, boot, fat, &entries[active])?;
+        for (index, entry) in entries.iter().enumerate() {
+            if index != active {
+                bitmap.mirror(disk, boot, fat, entry)?;
+            }
+        }
+        Ok(bitmap)
+    }
+
+    /// Index of the bitmap of the active FAT among the `count` allocation bitmaps of a volume,
+    /// which is the second one on TexFAT volumes using their second FAT.
+    pub(crate) fn active_index(boot: &BootSector, count: usize) -> usize {
+        let flags = VolumeFlags::from_bits_truncate(boot.volume_flags);
+        match flags.contains(VolumeFlags::ACTIVE_FAT) {
+            true => count.saturating_sub(1),
+            false => 0,
+        }
+    }
+
+    /// Mirrors all further flushes into the bitmap described by `entry`.
+    pub(crate) fn mirror<O: ReadOffset>(
+        &mut self,
+        disk: &Arc<O>,
+        boot: &Arc<BootSector>,
+        fat: &Fat,
+        entry: &BitmapEntry,
+    ) -> Result<(), WriteError<O>> {
+        let reader = Self::reader(disk, boot, fat, entry)?;
+        // flushes write the same bytes into the mirror as into the bitmap
+        if reader.data_length() < self.bits.len() as u64 {
+            return Err(WriteError::ClusterChain(
+                ClusterChainError::InvalidDataLength,
+            ));
+        }
+        self.chains.push(reader.chain().to_vec());
+        Ok(())
+    }
+
+    fn reader<O: ReadOffset>(
+        disk: &Arc<O>,
+        boot: &Arc<BootSector>,
+        fat: &Fat,
+        entry: &BitmapEntry,
+    ) -> Result<ClusterChainReader<Arc<O>, Arc<BootSector>>, WriteError<O>> {
+        Ok(ClusterChainReader::try_new(
+            Arc::clone(boot),
+            fat,
+            entry.first_cluster,
+            ClusterChainOptions::Fat {
+                data_length: Some(entry.data_len),
+            },
+            Arc::clone(disk),
+        )?)
+    }
+
+    /// A bitmap with all clusters free, which is not stored on disk.
+    #[cfg(test)]
+    pub(crate) fn empty(cluster_count: u32) -> AllocationBitmap {
+        AllocationBitmap {
+            bits: vec![0u8; cluster_count.div_ceil(8) as usize],
+            cluster_count,
+            chains: Vec::new(),
+        }
+    }
+
+    /// Amount of clusters covered by the bitmap.
+    pub(crate) fn cluster_count(&self) -> u32 {
+        self.cluster_count
+    }
+
+    /// Whether the given cluster is marked as allocated. Clusters outside of the heap are
+    /// reported as allocated.
+    pub(crate) fn is_allocated(&self, cluster: u32) -> bool {
+        let Some(index) = self.index(cluster) else {
+            return true;
+        };
+        self.bits[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// Amount of clusters marked as allocated.
+    pub(crate) fn allocated_count(&self) -> u64 {
+        let mut count: u64 = self.bits.iter().map(|b| b.count_ones() as u64).sum();
+
+        // bits beyond the last cluster are not part of the bitmap
+        let remaining_bits = self.cluster_count % 8;
+        if remaining_bits != 0
+            && let Some(last) = self.bits.last()
+        {
+            count -= (last >> remaining_bits).count_ones() as u64;
+        }
+        count
+    }
+
+    /// Marks `count` clusters starting at `first` as allocated or free.
+    pub(crate) fn set(&mut self, first: u32, count: u32, allocated: bool) {
+        for cluster in first..first + count {
+            let Some(index) = self.index(cluster) else {
+                continue;
+            };
+            if allocated {
+                self.bits[index / 8] |= 1 << (index % 8);
+            } else {
+                self.bits[index / 8] &= !(1 << (index % 8));
+            }
+        }
+    }
+
+    /// Writes the part of the bitmap covering the given clusters back to disk, into the bitmap
+    /// and all of its mirrors.
+    pub(crate) fn flush<O: WriteOffset>(
+        &self,
+        disk: &O,
+        boot: &BootSector,
+        first: u32,
+        count: u32,
+    ) -> Result<(), O::Err> {
+        if count == 0 {
+            return Ok(());
+        }
+        let last = first.checked_add(count - 1);
+        let (Some(start), Some(end)) = (self.index(first), last.and_then(|last| self.index(last)))
+        else {
+            return Err(O::Err::cluster_not_found(first));
+        };
+
+        let bytes_per_cluster = boot.bytes_per_cluster() as usize;
+        let (start, end) = (start / 8, end / 8 + 1);
+
+        for chain in &self.chains {
+            // the written range may span several clusters of the bitmap
+            let mut byte = start;
+            while byte < end {
+                let cluster = chain[byte / bytes_per_cluster];
+                let cluster_end = (byte / bytes_per_cluster + 1) * bytes_per_cluster;
+                let amount = end.min(cluster_end) - byte;
+
+                let offset = boot
+                    .cluster_offset(cluster)
+                    .ok_or(O::Err::cluster_not_found(cluster))?
+                    + (byte % bytes_per_cluster) as u64;
+                disk.write_all_at(offset, &self.bits[byte..byte + amount])?;
+                byte += amount;
+            }
+        }
+        Ok(())
+    }
+
+    fn index(&self, cluster: u32) -> Option<usize> {
+        let index = cluster.checked_sub(FIRST_USABLE_CLUSTER_INDEX)?;
+        (index < self.cluster_count).then_some(index as usize)
+    }
+}
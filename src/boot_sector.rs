@@ -135,6 +135,66 @@ impl BootSector {
     }
 }
 
+/// Checksum over the first 11 sectors of a boot region.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Checksum {
+    inner: u32,
+    sector_size_in_bytes: u16,
+}
+
+impl Checksum {
+    pub(crate) fn new(sector_size_in_bytes: u16) -> Checksum {
+        Self {
+            inner: 0,
+            sector_size_in_bytes,
+        }
+    }
+}
+
+impl Checksum {
+    /// Updates the checksum according to one entirely empty sector.
+    pub(crate) fn zero_sector(&mut self) {
+        for _ in 0..self.sector_size_in_bytes {
+            self.inner = (self.inner & 1) * 0x80000000 + (self.inner >> 1);
+        }
+    }
+
+    /// Updates the checksum according to a boot sector.
+    pub(crate) fn boot_sector(&mut self, sector: &[u8]) {
+        assert_eq!(sector.len(), self.sector_size_in_bytes as usize);
+        for i in 0..self.sector_size_in_bytes {
+            if i == 106 || i == 107 || i == 112 {
+                continue;
+            }
+
+            self.inner = ((self.inner & 1) * 0x80000000 + (self.inner >> 1))
+                .wrapping_add(sector[i as usize] as u32);
+        }
+    }
+
+    /// Updates the checksum according to a set of extended boot sectors.
+    pub(crate) fn extended_boot_sector(&mut self, sector: &[u8], amount: u64) {
+        for _ in 0..amount {
+            self.sector(sector);
+        }
+    }
+
+    /// Updates the checksum according to a sector whose bytes are all covered, e.g. the OEM
+    /// parameters.
+    pub(crate) fn sector(&mut self, sector: &[u8]) {
+        assert_eq!(sector.len(), self.sector_size_in_bytes as usize);
+        for byte in sector {
+            self.inner =
+                ((self.inner & 1) * 0x80000000 + (self.inner >> 1)).wrapping_add(*byte as u32);
+        }
+    }
+
+    /// Returns a copy of the current state of the checksum in little-endian format.
+    pub(crate) fn get(&self) -> u32 {
+        self.inner.to_le()
+    }
+}
+
 bitflags! {
     /// A set of flags that indicate file system status.
     #[derive(Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq)]
@@ -159,37 +219,60 @@ pub(crate) struct FileSystemRevision {
     /// Major version of the exFAT file system (high-order byte).
     vermaj: u8,
 }
-impl Default for FileSystemRevision {
-    fn default() -> Self {
+impl FileSystemRevision {
+    pub(crate) fn new(major: u8, minor: u8) -> Self {
         Self {
-            vermin: 0,
-            vermaj: 1,
+            vermin: minor,
+            vermaj: major,
         }
     }
+
+    pub(crate) fn major(&self) -> u8 {
+        self.vermaj
+    }
+
+    pub(crate) fn minor(&self) -> u8 {
+        self.vermin
+    }
+}
+
+impl Default for FileSystemRevision {
+    fn default() -> Self {
+        Self::new(1, 0)
+    }
 }
 
-/// Structure representing the unique volume serial number.
+/// Structure representing the unique volume serial number. The number is always kept in its
+/// on-disk (little-endian) byte order, also in boot sectors converted to native byte order.
 #[repr(transparent)]
-#[derive(Copy, Clone, Debug, Pod, Zeroable, Endify)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub(crate) struct VolumeSerialNumber(u32);
 
 impl VolumeSerialNumber {
-    pub(crate) fn try_new<U: UnixEpochDuration>() -> Result<VolumeSerialNumber, U::Err> {
-        Ok(VolumeSerialNumber((U::as_secs()? as u32).to_le()))
+    pub(crate) fn new(serial: u32) -> VolumeSerialNumber {
+        VolumeSerialNumber(serial.to_le())
     }
-}
 
-pub trait UnixEpochDuration {
-    type Err;
-    fn as_secs() -> Result<u64, Self::Err>;
+    pub(crate) fn get(&self) -> u32 {
+        u32::from_le(self.0)
+    }
 }
 
-#[cfg(feature = "std")]
-impl UnixEpochDuration for std::time::SystemTime {
-    type Err = std::time::SystemTimeError;
+// the byte order is only converted by `new` and `get`
+impl Endify for VolumeSerialNumber {
+    fn to_le(self) -> Self {
+        self
+    }
+
+    fn to_be(self) -> Self {
+        self
+    }
+
+    fn from_le(self) -> Self {
+        self
+    }
 
-    fn as_secs() -> Result<u64, Self::Err> {
-        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
-        Ok(now.as_secs())
+    fn from_be(self) -> Self {
+        self
     }
 }
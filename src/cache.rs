@@ -0,0 +1,193 @@
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::ops::Range;
+
+use crate::disk::ReadOffset;
+
+/// Blocks of a device kept in memory, evicting the least recently used block once `capacity`
+/// blocks are cached. Shared between handles, which bypass the cache while another one uses it.
+#[derive(Debug)]
+pub(crate) struct BlockCache {
+    block_size: u64,
+    capacity: usize,
+    blocks: Lock<Blocks>,
+}
+
+#[derive(Debug, Default)]
+struct Blocks {
+    /// Time of the last use and contents of each cached block, by block index. Blocks at the end
+    /// of the device may be shorter than the block size.
+    blocks: BTreeMap<u64, (u64, Vec<u8>)>,
+    /// Incremented on every use of the cache.
+    clock: u64,
+}
+
+impl BlockCache {
+    pub(crate) fn new(block_size: u64, capacity: usize) -> BlockCache {
+        BlockCache {
+            block_size,
+            capacity,
+            blocks: Lock::new(Blocks::default()),
+        }
+    }
+
+    /// An empty cache of the same size.
+    pub(crate) fn empty_copy(&self) -> BlockCache {
+        BlockCache::new(self.block_size, self.capacity)
+    }
+
+    pub(crate) fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Reads from the block containing `offset`, reading the block from `device` if it is not
+    /// cached. Returns the amount of bytes read, which ends at the end of the block, or `None` if
+    /// the cache is in use by another handle.
+    pub(crate) fn read<R: ReadOffset>(
+        &self,
+        device: &R,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Option<Result<usize, R::Err>> {
+        let mut cached = self.blocks.try_lock()?;
+        let index = offset / self.block_size;
+        cached.clock += 1;
+        let clock = cached.clock;
+
+        if !cached.blocks.contains_key(&index) {
+            let block = match self.read_block(device, index) {
+                Ok(block) => block,
+                Err(err) => return Some(Err(err)),
+            };
+            if cached.blocks.len() >= self.capacity {
+                let least_recent = cached
+                    .blocks
+                    .iter()
+                    .min_by_key(|(_, (last_use, _))| *last_use)
+                    .map(|(index, _)| *index);
+                cached.blocks.remove(&least_recent?);
+            }
+            cached.blocks.insert(index, (clock, block));
+        }
+
+        let (last_use, block) = cached.blocks.get_mut(&index)?;
+        *last_use = clock;
+        let skip = (offset - index * self.block_size) as usize;
+        let amount = block.len().saturating_sub(skip).min(buf.len());
+        buf[..amount].copy_from_slice(&block[skip..skip + amount]);
+        Some(Ok(amount))
+    }
+
+    /// Drops all cached blocks overlapping `range`, waiting for other handles to finish using
+    /// the cache.
+    pub(crate) fn invalidate(&self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+        let blocks = range.start / self.block_size..range.end.div_ceil(self.block_size);
+        let mut cached = self.blocks.lock();
+        cached.blocks.retain(|index, _| !blocks.contains(index));
+    }
+
+    /// Drops all cached blocks.
+    pub(crate) fn clear(&self) {
+        self.blocks.lock().blocks.clear();
+    }
+
+    /// Reads a whole block, which is shorter at the end of the device.
+    fn read_block<R: ReadOffset>(&self, device: &R, index: u64) -> Result<Vec<u8>, R::Err> {
+        let mut block = vec![0u8; self.block_size as usize];
+        let mut read = 0;
+        while read < block.len() {
+            match device.read_at(index * self.block_size + read as u64, &mut block[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        block.truncate(read);
+        Ok(block)
+    }
+}
+
+/// A lock for state shared between handles: a [`std::sync::Mutex`] with `std`, and a
+/// [`RefCell`](core::cell::RefCell) without it, where handles cannot be shared between threads.
+/// Readers use [`Lock::try_lock`] and are never blocked, writers wait in [`Lock::lock`].
+#[derive(Debug, Default)]
+pub(crate) struct Lock<T> {
+    #[cfg(feature = "std")]
+    value: std::sync::Mutex<T>,
+    #[cfg(not(feature = "std"))]
+    value: core::cell::RefCell<T>,
+}
+
+#[cfg(feature = "std")]
+pub(crate) type LockGuard<'lock, T> = std::sync::MutexGuard<'lock, T>;
+#[cfg(not(feature = "std"))]
+pub(crate) type LockGuard<'lock, T> = core::cell::RefMut<'lock, T>;
+
+impl<T> Lock<T> {
+    pub(crate) fn new(value: T) -> Lock<T> {
+        Lock {
+            value: value.into(),
+        }
+    }
+
+    /// Acquires the lock, unless it is held already.
+    pub(crate) fn try_lock(&self) -> Option<LockGuard<'_, T>> {
+        #[cfg(feature = "std")]
+        return match self.value.try_lock() {
+            Ok(guard) => Some(guard),
+            // the state is kept consistent by its users, also if one of them panicked
+            Err(std::sync::TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(std::sync::TryLockError::WouldBlock) => None,
+        };
+        #[cfg(not(feature = "std"))]
+        return self.value.try_borrow_mut().ok();
+    }
+
+    /// Acquires the lock, waiting while it is held.
+    pub(crate) fn lock(&self) -> LockGuard<'_, T> {
+        #[cfg(feature = "std")]
+        return self
+            .value
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        #[cfg(not(feature = "std"))]
+        return self.value.borrow_mut();
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn block_cache() {
+    use std::io::Cursor;
+
+    let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+    let device = Cursor::new(data.clone());
+    let cache = BlockCache::new(256, 2);
+
+    let mut buf = [0u8; 100];
+    // reads end at the end of their block
+    assert_eq!(cache.read(&device, 200, &mut buf).unwrap().unwrap(), 56);
+    assert_eq!(buf[..56], data[200..256]);
+    assert_eq!(cache.read(&device, 300, &mut buf).unwrap().unwrap(), 100);
+    // ... and of the device
+    assert_eq!(cache.read(&device, 950, &mut buf).unwrap().unwrap(), 50);
+    assert_eq!(buf[..50], data[950..]);
+
+    // the least recently used block was evicted
+    let cached: Vec<u64> = cache.blocks.lock().blocks.keys().copied().collect();
+    assert_eq!(cached, [1, 3]);
+    cache.invalidate(600..800);
+    let cached: Vec<u64> = cache.blocks.lock().blocks.keys().copied().collect();
+    assert_eq!(cached, [1]);
+
+    // the cache is bypassed while in use
+    let guard = cache.blocks.try_lock().unwrap();
+    assert!(cache.read(&device, 0, &mut buf).is_none());
+    drop(guard);
+    assert!(cache.read(&device, 0, &mut buf).is_some());
+}
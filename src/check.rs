@@ -0,0 +1,515 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+    vec::Vec,
+};
+use core::fmt::{Display, Formatter};
+
+use crate::{
+    FIRST_USABLE_CLUSTER_INDEX,
+    bitmap::AllocationBitmap,
+    checksum,
+    disk::ReadOffset,
+    error::{CheckError, ScrubError, WriteError},
+    fs::{EntryLocation, FsElement, walk::WalkOrder},
+    root::{OpenOptionsBuilder, Root},
+    volume::{BOOT_REGIONS, CHECKSUMMED_SECTORS},
+};
+
+/// A problem with the on-disk structures, either tolerated while opening a volume or detected
+/// while verifying it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Finding {
+    /// A root directory entry duplicating volume metadata (label, allocation bitmap or up-case
+    /// table) that was already found. The first valid entry is used, the duplicate is ignored.
+    DuplicateMetadataEntry {
+        entry_type: u8,
+        location: EntryLocation,
+    },
+    /// An invalid root directory entry describing volume metadata, which was skipped in favour of
+    /// a later valid one. Removed along with duplicates by
+    /// [`Volume::remove_duplicate_entries`](crate::volume::Volume::remove_duplicate_entries).
+    InvalidMetadataEntry {
+        entry_type: u8,
+        location: EntryLocation,
+    },
+    /// The checksum sector of the main (or backup) boot region does not match its contents.
+    BootChecksumMismatch { backup: bool },
+    /// The given sector of the backup boot region differs from the main boot region (apart from
+    /// the volume flags and the percentage in use).
+    BackupBootRegionMismatch { sector: u32 },
+    /// The up-case table does not match the checksum stored in its directory entry.
+    UpcaseTableChecksumMismatch { stored: u32, computed: u32 },
+    /// The set checksum of the entry set at the given location does not match its entries.
+    EntrySetChecksumMismatch { location: EntryLocation },
+    /// The file entry set at the given location is damaged (e.g. an invalid stream extension or
+    /// file name entry) and was skipped while listing its directory.
+    DamagedEntrySet { location: EntryLocation },
+    /// The name hash stored in the stream extension entry of the set at the given location does
+    /// not match its name.
+    NameHashMismatch {
+        location: EntryLocation,
+        stored: u16,
+        computed: u16,
+    },
+    /// The cluster chain of the file at the given location does not end with an end-of-chain
+    /// marker: `cluster` is followed by a free, bad or out-of-range cluster, or by a cluster of
+    /// the chain itself.
+    UnterminatedChain {
+        location: EntryLocation,
+        cluster: u32,
+    },
+    /// The cluster chain of a file consists of `clusters` clusters, while its `DataLength`
+    /// requires `expected` clusters.
+    ChainLengthMismatch {
+        location: EntryLocation,
+        clusters: u32,
+        expected: u32,
+    },
+    /// A cluster of a file is not marked as allocated in the allocation bitmap.
+    UnallocatedCluster {
+        location: EntryLocation,
+        cluster: u32,
+    },
+    /// A cluster of a file is also claimed by the file at `owner`.
+    CrossLinkedCluster {
+        location: EntryLocation,
+        cluster: u32,
+        owner: EntryLocation,
+    },
+    /// `count` clusters starting at `first` are marked as allocated, but are not used by any
+    /// file, directory or volume metadata.
+    OrphanedClusters { first: u32, count: u32 },
+    /// The entry set at the given location could not be read.
+    UnreadableEntrySet { location: EntryLocation },
+    /// The contents of the directory at the given location could not be read, so its entries were
+    /// not checked.
+    UnreadableDirectory { location: EntryLocation },
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Finding::DuplicateMetadataEntry {
+                entry_type,
+                location,
+            } => write!(
+                f,
+                "duplicate metadata entry of type {entry_type:#x} at {location}"
+            ),
+            Finding::InvalidMetadataEntry {
+                entry_type,
+                location,
+            } => write!(
+                f,
+                "invalid metadata entry of type {entry_type:#x} at {location}"
+            ),
+            Finding::BootChecksumMismatch { backup } => write!(
+                f,
+                "checksum mismatch in the {} boot region",
+                if *backup { "backup" } else { "main" }
+            ),
+            Finding::BackupBootRegionMismatch { sector } => write!(
+                f,
+                "sector {sector} of the backup boot region differs from the main boot region"
+            ),
+            Finding::UpcaseTableChecksumMismatch { stored, computed } => write!(
+                f,
+                "up-case table checksum is {computed:#010x}, expected {stored:#010x}"
+            ),
+            Finding::EntrySetChecksumMismatch { location } => {
+                write!(f, "entry set checksum mismatch at {location}")
+            }
+            Finding::DamagedEntrySet { location } => {
+                write!(f, "damaged entry set at {location} was skipped")
+            }
+            Finding::NameHashMismatch {
+                location,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "name hash of the entry set at {location} is {computed:#06x}, expected {stored:#06x}"
+            ),
+            Finding::UnterminatedChain { location, cluster } => write!(
+                f,
+                "cluster chain of the file at {location} is not terminated after cluster {cluster}"
+            ),
+            Finding::ChainLengthMismatch {
+                location,
+                clusters,
+                expected,
+            } => write!(
+                f,
+                "cluster chain of the file at {location} has {clusters} clusters, expected {expected}"
+            ),
+            Finding::UnallocatedCluster { location, cluster } => write!(
+                f,
+                "cluster {cluster} of the file at {location} is not marked as allocated"
+            ),
+            Finding::CrossLinkedCluster {
+                location,
+                cluster,
+                owner,
+            } => write!(
+                f,
+                "cluster {cluster} of the file at {location} is also used by the file at {owner}"
+            ),
+            Finding::OrphanedClusters { first, count } => write!(
+                f,
+                "clusters {first}..{} are allocated but not in use",
+                first + count
+            ),
+            Finding::UnreadableEntrySet { location } => {
+                write!(f, "entry set at {location} could not be read")
+            }
+            Finding::UnreadableDirectory { location } => {
+                write!(
+                    f,
+                    "contents of the directory at {location} could not be read"
+                )
+            }
+        }
+    }
+}
+
+/// Allocation state and owners of the clusters of a volume, against which the cluster chains of
+/// files are verified (see [`File::verify_chain`](crate::fs::file::File::verify_chain)). Created
+/// by [`Root::cluster_map`].
+#[derive(Clone, Debug)]
+pub struct ClusterMap {
+    pub(crate) bitmap: AllocationBitmap,
+    owners: BTreeMap<u32, EntryLocation>,
+}
+
+impl ClusterMap {
+    /// File owning the given cluster, among the files verified so far.
+    pub fn owner(&self, cluster: u32) -> Option<EntryLocation> {
+        self.owners.get(&cluster).copied()
+    }
+
+    /// Records `owner` as the owner of `cluster`, unless the cluster is owned already. Returns
+    /// the existing owner, if any.
+    pub fn claim(&mut self, cluster: u32, owner: EntryLocation) -> Option<EntryLocation> {
+        match self.owners.get(&cluster) {
+            Some(existing) => Some(*existing),
+            None => {
+                self.owners.insert(cluster, owner);
+                None
+            }
+        }
+    }
+}
+
+impl<O: ReadOffset> Root<O> {
+    /// Loads the allocation bitmap of the active FAT into a [`ClusterMap`] without any owners.
+    pub fn cluster_map(&self) -> Result<ClusterMap, WriteError<O>> {
+        let active = AllocationBitmap::active_index(&self.boot, self.allocation_bitmaps.len());
+        Ok(ClusterMap {
+            bitmap: AllocationBitmap::load(
+                &self.disk,
+                &self.boot,
+                &self.fat,
+                &self.allocation_bitmaps[active],
+            )?,
+            owners: BTreeMap::new(),
+        })
+    }
+}
+
+/// Result of [`ExfatChecker::check`].
+#[derive(Clone, Debug, Default)]
+pub struct CheckReport {
+    /// Amount of files that were verified.
+    pub files: u64,
+    /// Amount of directories that were verified.
+    pub directories: u64,
+    /// All problems found, in the order they were detected.
+    pub findings: Vec<Finding>,
+}
+
+impl CheckReport {
+    /// Whether no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// Read-only consistency checker for exFAT volumes, similar to `fsck.exfat -n`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ExfatChecker;
+
+impl ExfatChecker {
+    /// Verifies the volume on `device`: the checksums of both boot regions and whether they
+    /// match, the up-case table checksum, the checksums and name hashes of all reachable entry
+    /// sets, and the cluster chains of all files and directories against the FAT and the
+    /// allocation bitmap, including allocated clusters no longer in use.
+    ///
+    /// Duplicate metadata entries and damaged entry sets are reported instead of preventing the
+    /// volume from being opened. Nothing is written to the device.
+    pub fn check<O: ReadOffset>(device: O) -> Result<CheckReport, CheckError<O>>
+    where
+        O::Err: core::fmt::Debug,
+    {
+        let options = OpenOptionsBuilder::default()
+            .recover_duplicates(true)
+            .skip_damaged_entry_sets(true)
+            .build()
+            .expect("all options have defaults");
+        let root = Root::open_with(device, options)?;
+
+        let mut report = CheckReport {
+            findings: root.findings().to_vec(),
+            ..CheckReport::default()
+        };
+        check_boot_regions(&root, &mut report.findings)?;
+
+        let stored = { root.upcase_table.table_checksum };
+        let computed = root.upcase_table_checksum()?;
+        if stored != computed {
+            report
+                .findings
+                .push(Finding::UpcaseTableChecksumMismatch { stored, computed });
+        }
+
+        let mut map = root.cluster_map().map_err(ScrubError::Bitmap)?;
+        let metadata = metadata_clusters(&root).map_err(|err| {
+            let fat_offset = root.boot.fat_offset as u64 * root.boot.bytes_per_sector() as u64;
+            ScrubError::ReadFailed(fat_offset, err)
+        })?;
+        for item in root.walk(WalkOrder::DepthFirst) {
+            let (_, item) = item.map_err(ScrubError::Directory)?;
+            let location = match &item {
+                FsElement::F(file) => file.location(),
+                FsElement::D(dir) => dir.location(),
+            };
+            check_entry_set(&root, location, &mut report.findings)?;
+
+            match &item {
+                FsElement::F(file) => {
+                    report.files += 1;
+                    report.findings.extend(file.verify_chain(&mut map));
+                }
+                FsElement::D(dir) => {
+                    report.directories += 1;
+                    for cluster in dir
+                        .cluster_reader()
+                        .map_err(ScrubError::ClusterChain)?
+                        .chain()
+                    {
+                        if !map.bitmap.is_allocated(*cluster) {
+                            report.findings.push(Finding::UnallocatedCluster {
+                                location,
+                                cluster: *cluster,
+                            });
+                        }
+                        if let Some(owner) = map.claim(*cluster, location)
+                            && owner != location
+                        {
+                            report.findings.push(Finding::CrossLinkedCluster {
+                                location,
+                                cluster: *cluster,
+                                owner,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let heap = FIRST_USABLE_CLUSTER_INDEX..FIRST_USABLE_CLUSTER_INDEX + root.boot.cluster_count;
+        let mut orphaned: Option<(u32, u32)> = None;
+        for cluster in heap {
+            let orphan = map.bitmap.is_allocated(cluster)
+                && map.owner(cluster).is_none()
+                && !metadata.contains(&cluster);
+            match &mut orphaned {
+                Some((_, count)) if orphan => *count += 1,
+                None if orphan => orphaned = Some((cluster, 1)),
+                _ => report
+                    .findings
+                    .extend(orphaned.take().map(orphaned_clusters)),
+            }
+        }
+        report.findings.extend(orphaned.map(orphaned_clusters));
+
+        Ok(report)
+    }
+}
+
+fn orphaned_clusters((first, count): (u32, u32)) -> Finding {
+    Finding::OrphanedClusters { first, count }
+}
+
+/// Verifies the checksums of both boot regions and compares the backup to the main region.
+fn check_boot_regions<O: ReadOffset>(
+    root: &Root<O>,
+    findings: &mut Vec<Finding>,
+) -> Result<(), ScrubError<O>>
+where
+    O::Err: core::fmt::Debug,
+{
+    for (i, offset) in BOOT_REGIONS.into_iter().enumerate() {
+        if !root.boot_checksum_valid(offset)? {
+            findings.push(Finding::BootChecksumMismatch { backup: i != 0 });
+        }
+    }
+
+    let bytes_per_sector = root.boot.bytes_per_sector() as usize;
+    let mut regions = [0, 1].map(|_| vec![0u8; CHECKSUMMED_SECTORS as usize * bytes_per_sector]);
+    for (region, offset_sectors) in regions.iter_mut().zip(BOOT_REGIONS) {
+        let offset = offset_sectors * bytes_per_sector as u64;
+        root.disk
+            .read_exact(offset, region)
+            .map_err(|err| ScrubError::ReadFailed(offset, err))?;
+        // volume flags and percentage in use are excluded, just like from the checksum
+        for excluded in [106, 107, 112] {
+            region[excluded] = 0;
+        }
+    }
+
+    let [main, backup] = &regions;
+    let sectors = main
+        .chunks_exact(bytes_per_sector)
+        .zip(backup.chunks_exact(bytes_per_sector));
+    if let Some(sector) = sectors
+        .into_iter()
+        .position(|(main, backup)| main != backup)
+    {
+        findings.push(Finding::BackupBootRegionMismatch {
+            sector: sector as u32,
+        });
+    }
+    Ok(())
+}
+
+/// Verifies the set checksum and the name hash of the entry set at `location`.
+fn check_entry_set<O: ReadOffset>(
+    root: &Root<O>,
+    location: EntryLocation,
+    findings: &mut Vec<Finding>,
+) -> Result<(), ScrubError<O>>
+where
+    O::Err: core::fmt::Debug,
+{
+    let entries = root
+        .read_entry_set(location)
+        .map_err(|err| ScrubError::ReadFailed(location.offset, err))?;
+
+    let stored = u16::from_le_bytes([entries[0][2], entries[0][3]]);
+    if checksum::entry_set(&entries) != stored {
+        findings.push(Finding::EntrySetChecksumMismatch { location });
+    }
+
+    let Some(stream) = entries.get(1) else {
+        return Ok(());
+    };
+    let name: Vec<u16> = entries[2..]
+        .iter()
+        .flat_map(|entry| entry[2..].chunks_exact(2))
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take(stream[3] as usize)
+        .collect();
+    let stored = u16::from_le_bytes([stream[4], stream[5]]);
+    let computed = root.upcase.name_hash(&name);
+    if stored != computed {
+        findings.push(Finding::NameHashMismatch {
+            location,
+            stored,
+            computed,
+        });
+    }
+    Ok(())
+}
+
+/// Clusters of the allocation bitmaps, the up-case table and the root directory.
+fn metadata_clusters<O: ReadOffset>(root: &Root<O>) -> Result<BTreeSet<u32>, O::Err> {
+    let bytes_per_cluster = root.boot.bytes_per_cluster() as u64;
+    let mut clusters = BTreeSet::new();
+
+    let metadata = root
+        .allocation_bitmaps
+        .iter()
+        .map(|bitmap| (bitmap.first_cluster, bitmap.data_len))
+        .chain([(root.upcase_table.first_cluster, root.upcase_table.data_len)]);
+    for (first_cluster, data_len) in metadata {
+        let count = data_len.div_ceil(bytes_per_cluster) as usize;
+        clusters.extend(
+            root.fat
+                .read_chain_prefix(&root.disk, first_cluster, count)?,
+        );
+    }
+    // the chain of the root directory is bounded by the size of the heap
+    clusters.extend(
+        root.fat
+            .read_chain(&root.disk, root.boot.first_cluster_of_root_directory)?,
+    );
+    Ok(clusters)
+}
+
+#[cfg(test)]
+#[test]
+fn consistency_check() {
+    use crate::{
+        format::ImageBuilder,
+        test_utils::{TestImage, format_options},
+    };
+    use std::io::Cursor;
+
+    let mut builder = ImageBuilder::new(format_options().build().unwrap());
+    builder
+        .add_file("a.txt", Cursor::new(vec![1u8; 3 * 4096]), 3 * 4096)
+        .unwrap()
+        .add_file("dir/b.txt", Cursor::new(b"b".to_vec()), 1)
+        .unwrap();
+    let mut image = TestImage::build(builder).image;
+
+    let report = ExfatChecker::check(Cursor::new(image.clone())).unwrap();
+    assert!(report.is_clean(), "{:?}", report.findings);
+    assert_eq!((report.files, report.directories), (2, 1));
+
+    let mut root = Root::open(Cursor::new(image.clone())).unwrap();
+    let location = match &root.items()[0] {
+        FsElement::F(file) => file.location(),
+        FsElement::D(_) => panic!("expected a file"),
+    };
+    let active = AllocationBitmap::active_index(&root.boot, root.allocation_bitmaps.len());
+    let bitmap = root
+        .boot
+        .cluster_offset(root.allocation_bitmaps[active].first_cluster)
+        .unwrap() as usize;
+    let last = root.boot.cluster_count + FIRST_USABLE_CLUSTER_INDEX - 1;
+
+    // wrong name hash of `a.txt`
+    image[location.offset as usize + 32 + 4] ^= 0xFF;
+    // the last two clusters are allocated without being used
+    for cluster in [last - 1, last] {
+        let bit = (cluster - FIRST_USABLE_CLUSTER_INDEX) as usize;
+        image[bitmap + bit / 8] |= 1 << (bit % 8);
+    }
+    // the backup boot region no longer matches the main one
+    image[14 * 512] ^= 0xFF;
+
+    let report = ExfatChecker::check(Cursor::new(image)).unwrap();
+    let computed = root
+        .upcase
+        .name_hash(&"a.txt".encode_utf16().collect::<Vec<_>>());
+    assert_eq!(
+        report.findings,
+        vec![
+            Finding::BootChecksumMismatch { backup: true },
+            Finding::BackupBootRegionMismatch { sector: 2 },
+            Finding::EntrySetChecksumMismatch { location },
+            Finding::NameHashMismatch {
+                location,
+                stored: computed ^ 0x00FF,
+                computed,
+            },
+            Finding::OrphanedClusters {
+                first: last - 1,
+                count: 2,
+            },
+        ]
+    );
+}
@@ -0,0 +1,152 @@
+use alloc::vec::Vec;
+
+use crate::boot_sector::Checksum;
+
+/// Number of sectors of a boot region covered by its checksum.
+pub const BOOT_REGION_CHECKSUMMED_SECTORS: usize = 11;
+
+/// Computes the checksum of a boot region from its first 11 sectors (boot sector, extended boot
+/// sectors, OEM parameters and reserved sector). The `VolumeFlags` and `PercentInUse` fields of
+/// the boot sector are skipped. Returns `None` if `sectors` does not hold exactly 11 sectors of
+/// `bytes_per_sector` bytes.
+pub fn boot_region(sectors: &[u8], bytes_per_sector: u16) -> Option<u32> {
+    let sector_size = bytes_per_sector as usize;
+    if sector_size == 0 || sectors.len() != BOOT_REGION_CHECKSUMMED_SECTORS * sector_size {
+        return None;
+    }
+
+    let mut checksum = Checksum::new(bytes_per_sector);
+    let (boot_sector, rest) = sectors.split_at(sector_size);
+    checksum.boot_sector(boot_sector);
+    for sector in rest.chunks_exact(sector_size) {
+        checksum.sector(sector);
+    }
+    Some(u32::from_le(checksum.get()))
+}
+
+/// Computes the checksum of a raw entry set. The `SetChecksum` field of the primary entry is
+/// skipped.
+pub fn entry_set(entries: &[[u8; 32]]) -> u16 {
+    let mut sum = 0u16;
+    for (i, entry) in entries.iter().enumerate() {
+        for (j, byte) in entry.iter().enumerate() {
+            if i == 0 && (j == 2 || j == 3) {
+                continue;
+            }
+            sum = sum.rotate_right(1).wrapping_add(*byte as u16);
+        }
+    }
+    sum
+}
+
+/// Computes the checksum of an up-case table as stored on disk (compressed or not).
+pub fn upcase_table(table: &[u8]) -> u32 {
+    table.iter().fold(0u32, |sum, byte| {
+        sum.rotate_right(1).wrapping_add(*byte as u32)
+    })
+}
+
+/// A hash of file contents which is computed incrementally, e.g. to verify extracted files
+/// (see [`File::extract_verified`](crate::fs::file::File::extract_verified)).
+pub trait ContentHasher: Default {
+    type Output: PartialEq + core::fmt::Debug;
+
+    fn update(&mut self, bytes: &[u8]);
+    fn finish(self) -> Self::Output;
+}
+
+/// 64-bit FNV-1a hash. Not suitable to detect deliberate tampering.
+#[derive(Copy, Clone, Debug)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl ContentHasher for Fnv1a {
+    type Output = u64;
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    }
+
+    fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+/// Expected hashes of consecutive blocks of file contents, e.g. from the manifest of a firmware
+/// update, to verify with [`File::extract_verified_blocks`](crate::fs::file::File::extract_verified_blocks).
+/// The last block may be shorter than the others.
+#[derive(Clone, Debug)]
+pub struct BlockHashes<'a, H: ContentHasher> {
+    block_size: usize,
+    hashes: &'a [H::Output],
+}
+
+impl<'a, H: ContentHasher> BlockHashes<'a, H> {
+    pub fn new(block_size: usize, hashes: &'a [H::Output]) -> Self {
+        BlockHashes { block_size, hashes }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn hashes(&self) -> &[H::Output] {
+        self.hashes
+    }
+}
+
+/// Computes the hashes of consecutive blocks of `data`, as expected by [`BlockHashes`]. Returns
+/// no hashes if `block_size` is zero.
+pub fn block_hashes<H: ContentHasher>(data: &[u8], block_size: usize) -> Vec<H::Output> {
+    if block_size == 0 {
+        return Vec::new();
+    }
+    data.chunks(block_size)
+        .map(|block| {
+            let mut hasher = H::default();
+            hasher.update(block);
+            hasher.finish()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn checksums_of_formatted_volume() {
+    use crate::{
+        format::upcase_table::{DEFAULT_UPCASE_TABLE, DEFAULT_UPCASE_TABLE_CHECKSUM},
+        test_utils::TestImage,
+    };
+
+    assert_eq!(
+        upcase_table(&DEFAULT_UPCASE_TABLE),
+        DEFAULT_UPCASE_TABLE_CHECKSUM
+    );
+
+    let image = TestImage::new().image;
+    let region = &image[..BOOT_REGION_CHECKSUMMED_SECTORS * 512];
+    let stored = u32::from_le_bytes(image[11 * 512..11 * 512 + 4].try_into().unwrap());
+    assert_eq!(boot_region(region, 512), Some(stored));
+    assert_eq!(boot_region(&region[1..], 512), None);
+
+    // the volume flags are not covered by the checksum
+    let mut region = region.to_vec();
+    region[106] ^= 0x02;
+    assert_eq!(boot_region(&region, 512), Some(stored));
+    region[0] ^= 0xFF;
+    assert_ne!(boot_region(&region, 512), Some(stored));
+
+    let mut entries = [[0u8; 32]; 2];
+    entries[0][0] = 0x85;
+    entries[1][0] = 0xC0;
+    let sum = entry_set(&entries);
+    entries[0][2] = 0xAB;
+    assert_eq!(entry_set(&entries), sum);
+}
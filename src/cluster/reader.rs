@@ -4,7 +4,8 @@ use crate::{
     boot_sector::BootSector,
     disk::{PartitionError, ReadOffset},
     error::ClusterChainError,
-    fat::{ClusterChain, Fat},
+    fat::Fat,
+    root::ReadProfile,
 };
 
 use super::ClusterChainOptions;
@@ -16,12 +17,49 @@ pub(crate) struct ClusterChainReader<O, B> {
     data_length: u64,
     offset: u64,
     disk: O,
+    profile: ReadProfile,
+    /// Data read ahead, starting at `cache_start` within the chain.
+    cache: Vec<u8>,
+    cache_start: u64,
 }
 
 impl<O, B: AsRef<BootSector>> ClusterChainReader<O, B> {
+    pub(crate) fn boot(&self) -> &BootSector {
+        self.boot.as_ref()
+    }
+
+    /// Clusters of the chain, in order.
+    pub(crate) fn chain(&self) -> &[u32] {
+        &self.chain
+    }
+
+    /// Sizes and caches reads according to `profile` instead of [`ReadProfile::Balanced`].
+    pub(crate) fn with_profile(mut self, profile: ReadProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub(crate) fn profile(&self) -> ReadProfile {
+        self.profile
+    }
+
     pub(crate) fn data_length(&self) -> u64 {
         self.data_length
     }
+
+    /// Follows `chain`, the chain the reader was created from after it was written to, up to the
+    /// new `data_length`. Data read ahead is dropped, as it may have been overwritten.
+    pub(crate) fn follow(&mut self, chain: &[u32], data_length: u64) {
+        let cluster_size = self.boot().bytes_per_cluster() as u64;
+        let clusters = (data_length.div_ceil(cluster_size) as usize).min(chain.len());
+        // clusters past the end may have been released
+        self.chain.truncate(clusters);
+        self.chain
+            .extend_from_slice(&chain[self.chain.len()..clusters]);
+        self.data_length = data_length;
+        self.cache.clear();
+    }
+
     pub(crate) fn seek(&mut self, off: u64) -> bool {
         if off > self.data_length {
             return false;
@@ -40,7 +78,7 @@ impl<O, B: AsRef<BootSector>> ClusterChainReader<O, B> {
     }
 }
 
-impl<O, B: AsRef<BootSector>> ClusterChainReader<O, B> {
+impl<O: ReadOffset, B: AsRef<BootSector>> ClusterChainReader<O, B> {
     pub(crate) fn try_new(
         boot: B,
         fat: &Fat,
@@ -71,7 +109,9 @@ impl<O, B: AsRef<BootSector>> ClusterChainReader<O, B> {
                 }
             }
             ClusterChainOptions::Fat { data_length } => {
-                let chain: Vec<u32> = ClusterChain::new(fat, first_cluster).collect();
+                let chain = fat
+                    .read_chain(&disk, first_cluster)
+                    .map_err(|_| ClusterChainError::FatReadFailed(first_cluster))?;
                 if chain.is_empty() {
                     return Err(ClusterChainError::InvalidFirstCluster);
                 }
@@ -96,10 +136,19 @@ impl<O, B: AsRef<BootSector>> ClusterChainReader<O, B> {
             data_length,
             offset: 0,
             disk,
+            profile: ReadProfile::default(),
+            cache: Vec::new(),
+            cache_start: 0,
         })
     }
-    pub fn current(&self) -> u32 {
-        self.chain[(self.offset / self.boot.as_ref().bytes_per_cluster() as u64) as usize]
+    /// Cluster at the current position, or `None` at the end of the chain.
+    pub fn current(&self) -> Option<u32> {
+        if self.offset >= self.data_length {
+            return None;
+        }
+        self.chain
+            .get((self.offset / self.boot.as_ref().bytes_per_cluster() as u64) as usize)
+            .copied()
     }
 }
 
@@ -110,20 +159,72 @@ impl<O: ReadOffset, B: AsRef<BootSector>> ClusterChainReader<O, B> {
             return Ok(0);
         }
 
-        // Get remaining data in the current cluster.
-        let boot = &self.boot;
-        let cluster_size = boot.as_ref().bytes_per_cluster() as u64;
-        let cluster_remaining = cluster_size - self.offset % cluster_size;
-        let remaining = cluster_remaining.min(self.data_length - self.offset);
+        // Serve the read from data read ahead.
+        if let Some(cached) = self
+            .offset
+            .checked_sub(self.cache_start)
+            .and_then(|start| self.cache.get(start as usize..))
+            .filter(|cached| !cached.is_empty())
+        {
+            let amount = buf.len().min(cached.len());
+            buf[..amount].copy_from_slice(&cached[..amount]);
+            self.offset += amount as u64;
+            return Ok(amount);
+        }
+
+        // Get remaining data in the current run of contiguous clusters, or in the current cluster
+        // for low memory reads.
+        let boot = self.boot.as_ref();
+        let cluster_size = boot.bytes_per_cluster() as u64;
+        let index = (self.offset / cluster_size) as usize;
+        let mut end = (index as u64 + 1) * cluster_size;
+        if self.profile != ReadProfile::LowMemory {
+            // the run is only followed as far as this read (and its readahead) reaches
+            let wanted = match self.profile {
+                ReadProfile::HighThroughput => {
+                    buf.len().max(ReadProfile::HIGH_THROUGHPUT_READAHEAD)
+                }
+                _ => buf.len(),
+            } as u64;
+            let clusters = (self.offset % cluster_size + wanted).div_ceil(cluster_size) as usize;
+            let run = self.chain[index..]
+                .windows(2)
+                .take(clusters - 1)
+                .take_while(|pair| pair[1] == pair[0] + 1)
+                .count();
+            end += run as u64 * cluster_size;
+        }
+        let mut remaining = end.min(self.data_length) - self.offset;
 
         // Get the offset in the partition.
-        let cluster = self.chain[(self.offset / cluster_size) as usize];
+        let cluster = self.chain[index];
         let offset = boot
-            .as_ref()
             .cluster_offset(cluster)
             .ok_or(PartitionError::cluster_not_found(cluster))?
             + self.offset % cluster_size;
 
+        let readahead = match self.profile {
+            ReadProfile::LowMemory => {
+                let sector_size = boot.bytes_per_sector() as u64;
+                remaining = remaining.min(sector_size - self.offset % sector_size);
+                remaining as usize
+            }
+            ReadProfile::Balanced => 0,
+            ReadProfile::HighThroughput => ReadProfile::HIGH_THROUGHPUT_READAHEAD,
+        };
+
+        // Fill the cache for small reads.
+        if buf.len() < readahead.min(remaining as usize) {
+            self.cache.resize(readahead.min(remaining as usize), 0);
+            self.disk.read_exact(offset, &mut self.cache)?;
+            self.cache_start = self.offset;
+
+            let amount = buf.len();
+            buf.copy_from_slice(&self.cache[..amount]);
+            self.offset += amount as u64;
+            return Ok(amount);
+        }
+
         // Read the image
         let amount = buf.len().min(remaining as usize);
 
@@ -0,0 +1,463 @@
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc, vec, vec::Vec};
+
+use crate::{
+    cluster::{ClusterChainOptions, reader::ClusterChainReader},
+    diagnostics::layout,
+    disk::ReadOffset,
+    error::{ClusterChainError, CompareError, ExfatError},
+    format::{Exfat, FormatVolumeOptions},
+    root::Root,
+    timestamp::Clock,
+    volume::{BOOT_REGIONS, Volume},
+};
+
+/// Fields of the boot sector: offset, length and name.
+const BOOT_SECTOR: &[(usize, usize, &str)] = &[
+    (0, 3, "JumpBoot"),
+    (3, 8, "FileSystemName"),
+    (11, 53, "MustBeZero"),
+    (64, 8, "PartitionOffset"),
+    (72, 8, "VolumeLength"),
+    (80, 4, "FatOffset"),
+    (84, 4, "FatLength"),
+    (88, 4, "ClusterHeapOffset"),
+    (92, 4, "ClusterCount"),
+    (96, 4, "FirstClusterOfRootDirectory"),
+    (100, 4, "VolumeSerialNumber"),
+    (104, 2, "FileSystemRevision"),
+    (106, 2, "VolumeFlags"),
+    (108, 1, "BytesPerSectorShift"),
+    (109, 1, "SectorsPerClusterShift"),
+    (110, 1, "NumberOfFats"),
+    (111, 1, "DriveSelect"),
+    (112, 1, "PercentInUse"),
+    (113, 7, "Reserved"),
+    (120, 390, "BootCode"),
+    (510, 2, "BootSignature"),
+];
+
+/// Names of the sectors following the boot sector within a boot region.
+const BOOT_REGION_SECTORS: &[(usize, usize, &str)] = &[
+    (1, 8, "ExtendedBootSectors"),
+    (9, 1, "OemParameters"),
+    (10, 1, "Reserved"),
+    (11, 1, "BootChecksum"),
+];
+
+/// Entry types (with `InUse` set) of the root directory entries describing the volume.
+const METADATA_ENTRY_TYPES: [u8; 4] = [0x83, 0xA0, 0x81, 0x82];
+
+/// A metadata region of a volume.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    MainBootRegion,
+    BackupBootRegion,
+    Fat,
+    AllocationBitmap,
+    UpcaseTable,
+    /// The entries of the root directory describing the volume (label, GUID, allocation bitmap
+    /// and up-case table).
+    RootDirectory,
+}
+
+/// A field whose contents differ between a volume and its reference.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub region: Region,
+    /// Spec name of the field, if the region has a field layout.
+    pub field: String,
+    /// Offset (in bytes) of the compared bytes, relative to the start of the region.
+    pub offset: u64,
+    /// Bytes of the reference. Empty if the field is missing in the reference.
+    pub expected: Vec<u8>,
+    /// Bytes of the volume. Empty if the field is missing on the volume.
+    pub actual: Vec<u8>,
+}
+
+/// Metadata regions of a volume, each read according to the volume's own layout.
+struct Metadata {
+    boot_regions: [Vec<u8>; 2],
+    bytes_per_sector: usize,
+    fat: Vec<u8>,
+    bitmap: Vec<u8>,
+    upcase_table: Vec<u8>,
+    root_entries: Vec<[u8; 32]>,
+}
+
+enum ReadError<O: ReadOffset> {
+    Io(O::Err),
+    ClusterChain(ClusterChainError),
+}
+
+impl<O: ReadOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Formats a reference image in memory using `options` and compares its metadata regions
+    /// against those of this volume, e.g. to check that media produced by another formatter
+    /// matches the output of this crate. The reference adopts the serial number of the volume, so
+    /// boot region checksums are comparable. Each region is located according to the respective
+    /// boot sector; the result is empty if all regions are identical.
+    pub fn compare_with_reference<T: Clock>(
+        &self,
+        options: FormatVolumeOptions,
+    ) -> Result<Vec<Divergence>, CompareError<T, O>>
+    where
+        T::Err: core::fmt::Debug,
+    {
+        let mut formatter = Exfat::try_from::<T>(options)?;
+        formatter.set_volume_serial_number(self.root.volume_serial_number());
+
+        let mut image = SparseImage::new(options.dev_size());
+        formatter
+            .write::<T, _>(&mut image)
+            .map_err(|err| match err {
+                ExfatError::Format(err) => CompareError::Format(err),
+                ExfatError::Io(phase, err) => {
+                    CompareError::Reference(format!("I/O error while {phase}: {err}"))
+                }
+            })?;
+        let reference =
+            Root::open(image).map_err(|err| CompareError::Reference(format!("{err}")))?;
+
+        let expected = read_metadata(&reference).map_err(|err| match err {
+            ReadError::Io(err) => CompareError::Reference(format!("{err}")),
+            ReadError::ClusterChain(err) => CompareError::Reference(format!("{err}")),
+        })?;
+        let actual = read_metadata(&self.root).map_err(|err| match err {
+            ReadError::Io(err) => CompareError::Io(err),
+            ReadError::ClusterChain(err) => CompareError::ClusterChain(err),
+        })?;
+
+        let mut divergences = Vec::new();
+        let regions = [Region::MainBootRegion, Region::BackupBootRegion];
+        for (i, region) in regions.into_iter().enumerate() {
+            let (expected_region, actual_region) =
+                (&expected.boot_regions[i], &actual.boot_regions[i]);
+            for (offset, len, field) in BOOT_SECTOR {
+                let range = *offset..offset + len;
+                divergences.extend(compare_field(
+                    region,
+                    field,
+                    *offset,
+                    &expected_region[range.clone()],
+                    &actual_region[range],
+                ));
+            }
+
+            // the sectors following the boot sector, which may differ in size
+            let sectors = |region: &[u8], bytes_per_sector: usize, first: usize, count: usize| {
+                region[first * bytes_per_sector..(first + count) * bytes_per_sector].to_vec()
+            };
+            for (first, count, field) in BOOT_REGION_SECTORS {
+                divergences.extend(compare_field(
+                    region,
+                    field,
+                    first * expected.bytes_per_sector,
+                    &sectors(expected_region, expected.bytes_per_sector, *first, *count),
+                    &sectors(actual_region, actual.bytes_per_sector, *first, *count),
+                ));
+            }
+        }
+
+        compare_bytes(Region::Fat, &expected.fat, &actual.fat, &mut divergences);
+        compare_bytes(
+            Region::AllocationBitmap,
+            &expected.bitmap,
+            &actual.bitmap,
+            &mut divergences,
+        );
+        compare_bytes(
+            Region::UpcaseTable,
+            &expected.upcase_table,
+            &actual.upcase_table,
+            &mut divergences,
+        );
+
+        for entry_type in METADATA_ENTRY_TYPES {
+            let (name, fields) = layout(entry_type);
+            match (
+                expected.root_entry(entry_type),
+                actual.root_entry(entry_type),
+            ) {
+                (Some((index, expected)), Some((_, actual))) => {
+                    let fields = [(0, 1, "EntryType")].into_iter().chain(
+                        fields
+                            .iter()
+                            .map(|(offset, len, field, _)| (*offset, *len, *field)),
+                    );
+                    for (offset, len, field) in fields {
+                        let range = offset..offset + len;
+                        divergences.extend(compare_field(
+                            Region::RootDirectory,
+                            &format!("{name}: {field}"),
+                            index * 32 + offset,
+                            &expected[range.clone()],
+                            &actual[range],
+                        ));
+                    }
+                }
+                (None, None) => {}
+                (expected, actual) => divergences.push(Divergence {
+                    region: Region::RootDirectory,
+                    field: String::from(name),
+                    offset: expected.or(actual).map_or(0, |(i, _)| i as u64 * 32),
+                    expected: expected.map_or(Vec::new(), |(_, entry)| entry.to_vec()),
+                    actual: actual.map_or(Vec::new(), |(_, entry)| entry.to_vec()),
+                }),
+            }
+        }
+
+        Ok(divergences)
+    }
+}
+
+impl Metadata {
+    /// Index and contents of the first root directory entry of the given type, in use or not.
+    fn root_entry(&self, entry_type: u8) -> Option<(usize, [u8; 32])> {
+        self.root_entries
+            .iter()
+            .position(|entry| entry[0] | 0x80 == entry_type)
+            .map(|i| (i, self.root_entries[i]))
+    }
+}
+
+/// Compares a single field, returning a divergence if its contents differ.
+fn compare_field(
+    region: Region,
+    field: &str,
+    offset: usize,
+    expected: &[u8],
+    actual: &[u8],
+) -> Option<Divergence> {
+    (expected != actual).then(|| Divergence {
+        region,
+        field: String::from(field),
+        offset: offset as u64,
+        expected: expected.to_vec(),
+        actual: actual.to_vec(),
+    })
+}
+
+/// Compares a region without field layout, reporting every run of differing bytes. Bytes
+/// beyond the end of the shorter region always differ.
+fn compare_bytes(region: Region, expected: &[u8], actual: &[u8], out: &mut Vec<Divergence>) {
+    let len = expected.len().max(actual.len());
+    let differs = |i: usize| expected.get(i) != actual.get(i);
+
+    let mut i = 0;
+    while i < len {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < len && differs(i) {
+            i += 1;
+        }
+        let clamp = |bytes: &[u8]| bytes[start.min(bytes.len())..i.min(bytes.len())].to_vec();
+        out.push(Divergence {
+            region,
+            field: format!("Bytes {start:#x}..{i:#x}"),
+            offset: start as u64,
+            expected: clamp(expected),
+            actual: clamp(actual),
+        });
+    }
+}
+
+/// Reads all metadata regions of a volume.
+fn read_metadata<O: ReadOffset>(root: &Root<O>) -> Result<Metadata, ReadError<O>> {
+    let boot = &root.boot;
+    let bytes_per_sector = boot.bytes_per_sector() as usize;
+
+    let read = |offset: u64, len: usize| {
+        let mut bytes = vec![0u8; len];
+        root.disk
+            .read_exact(offset, &mut bytes)
+            .map_err(ReadError::Io)?;
+        Ok(bytes)
+    };
+    let read_chain = |first_cluster: u32, len: u64| {
+        let mut reader = ClusterChainReader::try_new(
+            Arc::clone(boot),
+            &root.fat,
+            first_cluster,
+            ClusterChainOptions::Fat {
+                data_length: Some(len),
+            },
+            Arc::clone(&root.disk),
+        )
+        .map_err(ReadError::ClusterChain)?;
+        let mut bytes = vec![0u8; len as usize];
+        reader.read_exact(&mut bytes).map_err(ReadError::Io)?;
+        Ok(bytes)
+    };
+
+    let [main, backup] = BOOT_REGIONS.map(|sector| sector * bytes_per_sector as u64);
+    let boot_regions = [
+        read(main, 12 * bytes_per_sector)?,
+        read(backup, 12 * bytes_per_sector)?,
+    ];
+    let fat = read(
+        boot.fat_offset as u64 * bytes_per_sector as u64,
+        boot.fat_length as usize * bytes_per_sector,
+    )?;
+
+    let bitmap = &root.allocation_bitmaps[0];
+    let bitmap = read_chain(bitmap.first_cluster, bitmap.data_len)?;
+    let upcase_table = read_chain(root.upcase_table.first_cluster, root.upcase_table.data_len)?;
+
+    let root_offset = boot
+        .cluster_offset(boot.first_cluster_of_root_directory)
+        .unwrap_or_default();
+    let root_entries = read(root_offset, boot.bytes_per_cluster() as usize)?
+        .chunks_exact(32)
+        .map(|entry| entry.try_into().unwrap_or([0; 32]))
+        .take_while(|entry: &[u8; 32]| entry[0] != 0)
+        .collect();
+
+    Ok(Metadata {
+        boot_regions,
+        bytes_per_sector,
+        fat,
+        bitmap,
+        upcase_table,
+        root_entries,
+    })
+}
+
+/// Page size of a [`SparseImage`].
+const PAGE_SIZE: u64 = 4096;
+
+/// In-memory image storing only the pages that were written with data other than zeroes, so
+/// volumes of any size can be formatted.
+struct SparseImage {
+    len: u64,
+    position: u64,
+    pages: BTreeMap<u64, Vec<u8>>,
+}
+
+impl SparseImage {
+    fn new(len: u64) -> SparseImage {
+        SparseImage {
+            len,
+            position: 0,
+            pages: BTreeMap::new(),
+        }
+    }
+}
+
+impl std::io::Write for SparseImage {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let page = self.position / PAGE_SIZE;
+        let at = (self.position % PAGE_SIZE) as usize;
+        let amount = buf
+            .len()
+            .min(PAGE_SIZE as usize - at)
+            .min(self.len.saturating_sub(self.position) as usize);
+        let buf = &buf[..amount];
+
+        if let Some(contents) = self.pages.get_mut(&page) {
+            contents[at..at + amount].copy_from_slice(buf);
+        } else if buf.iter().any(|byte| *byte != 0) {
+            let mut contents = vec![0u8; PAGE_SIZE as usize];
+            contents[at..at + amount].copy_from_slice(buf);
+            self.pages.insert(page, contents);
+        }
+        self.position += amount as u64;
+        Ok(amount)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::io::Seek for SparseImage {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            std::io::SeekFrom::Start(offset) => Some(offset),
+            std::io::SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            std::io::SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position =
+            position.ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        Ok(self.position)
+    }
+}
+
+impl ReadOffset for SparseImage {
+    type Err = std::io::Error;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        let page = offset / PAGE_SIZE;
+        let at = (offset % PAGE_SIZE) as usize;
+        let amount = buf
+            .len()
+            .min(PAGE_SIZE as usize - at)
+            .min(self.len.saturating_sub(offset) as usize);
+
+        match self.pages.get(&page) {
+            Some(contents) => buf[..amount].copy_from_slice(&contents[at..at + amount]),
+            None => buf[..amount].fill(0),
+        }
+        Ok(amount)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn compare_against_reference() {
+    use crate::{
+        Label,
+        test_utils::{TestImage, format_options},
+    };
+    use std::{string::ToString, sync::Mutex, time::SystemTime};
+
+    let options = |label: &str, boundary_align: u32| {
+        format_options()
+            .boundary_align(boundary_align)
+            .label(Label::new(label.to_string()).unwrap())
+            .build()
+            .unwrap()
+    };
+
+    let image = TestImage::with_options(options("Photos", 4096));
+    let volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+
+    let divergences = volume
+        .compare_with_reference::<SystemTime>(options("Photos", 4096))
+        .unwrap();
+    assert_eq!(divergences, []);
+
+    let divergences = volume
+        .compare_with_reference::<SystemTime>(options("Backup", 4096))
+        .unwrap();
+    assert!(!divergences.is_empty());
+    assert!(
+        divergences
+            .iter()
+            .all(|d| d.region == Region::RootDirectory)
+    );
+    assert!(
+        divergences
+            .iter()
+            .any(|d| d.field == "Volume Label: VolumeLabel")
+    );
+
+    let divergences = volume
+        .compare_with_reference::<SystemTime>(options("Photos", 1 << 20))
+        .unwrap();
+    let fat_offset = divergences
+        .iter()
+        .find(|d| d.region == Region::MainBootRegion && d.field == "FatOffset")
+        .unwrap();
+    assert_eq!(fat_offset.offset, 80);
+    assert_eq!(fat_offset.expected, 2048u32.to_le_bytes());
+    assert!(
+        divergences
+            .iter()
+            .any(|d| d.region == Region::BackupBootRegion && d.field == "BootChecksum")
+    );
+}
@@ -0,0 +1,436 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+use crate::{
+    FIRST_USABLE_CLUSTER_INDEX, checksum,
+    disk::ReadOffset,
+    fat::FatEntry,
+    fs::EntryLocation,
+    name::UpcaseTable,
+    root::Root,
+    timestamp::{Timestamp, UtcOffset},
+};
+
+/// How the bytes of a field are interpreted.
+#[derive(Copy, Clone)]
+pub(crate) enum Decode {
+    Unsigned,
+    Hex,
+    Timestamp,
+    Utf16,
+    Attributes,
+    SecondaryFlags,
+    Checksum,
+    NameHash,
+}
+
+/// A field of a directory entry: offset, length, name and interpretation.
+pub(crate) type Field = (usize, usize, &'static str, Decode);
+
+const FILE: &[Field] = &[
+    (1, 1, "SecondaryCount", Decode::Unsigned),
+    (2, 2, "SetChecksum", Decode::Checksum),
+    (4, 2, "FileAttributes", Decode::Attributes),
+    (6, 2, "Reserved1", Decode::Hex),
+    (8, 4, "CreateTimestamp", Decode::Timestamp),
+    (12, 4, "LastModifiedTimestamp", Decode::Timestamp),
+    (16, 4, "LastAccessedTimestamp", Decode::Timestamp),
+    (20, 1, "Create10msIncrement", Decode::Unsigned),
+    (21, 1, "LastModified10msIncrement", Decode::Unsigned),
+    (22, 1, "CreateUtcOffset", Decode::Hex),
+    (23, 1, "LastModifiedUtcOffset", Decode::Hex),
+    (24, 1, "LastAccessedUtcOffset", Decode::Hex),
+    (25, 7, "Reserved2", Decode::Hex),
+];
+
+const STREAM_EXTENSION: &[Field] = &[
+    (1, 1, "GeneralSecondaryFlags", Decode::SecondaryFlags),
+    (2, 1, "Reserved1", Decode::Hex),
+    (3, 1, "NameLength", Decode::Unsigned),
+    (4, 2, "NameHash", Decode::NameHash),
+    (6, 2, "Reserved2", Decode::Hex),
+    (8, 8, "ValidDataLength", Decode::Unsigned),
+    (16, 4, "Reserved3", Decode::Hex),
+    (20, 4, "FirstCluster", Decode::Unsigned),
+    (24, 8, "DataLength", Decode::Unsigned),
+];
+
+const FILE_NAME: &[Field] = &[
+    (1, 1, "GeneralSecondaryFlags", Decode::SecondaryFlags),
+    (2, 30, "FileName", Decode::Utf16),
+];
+
+const ALLOCATION_BITMAP: &[Field] = &[
+    (1, 1, "BitmapFlags", Decode::Hex),
+    (2, 18, "Reserved", Decode::Hex),
+    (20, 4, "FirstCluster", Decode::Unsigned),
+    (24, 8, "DataLength", Decode::Unsigned),
+];
+
+const UPCASE_TABLE: &[Field] = &[
+    (1, 3, "Reserved1", Decode::Hex),
+    (4, 4, "TableChecksum", Decode::Hex),
+    (8, 12, "Reserved2", Decode::Hex),
+    (20, 4, "FirstCluster", Decode::Unsigned),
+    (24, 8, "DataLength", Decode::Unsigned),
+];
+
+const VOLUME_LABEL: &[Field] = &[
+    (1, 1, "CharacterCount", Decode::Unsigned),
+    (2, 22, "VolumeLabel", Decode::Utf16),
+    (24, 8, "Reserved", Decode::Hex),
+];
+
+const VOLUME_GUID: &[Field] = &[
+    (1, 1, "SecondaryCount", Decode::Unsigned),
+    (2, 2, "SetChecksum", Decode::Checksum),
+    (4, 2, "GeneralPrimaryFlags", Decode::Hex),
+    (6, 16, "VolumeGuid", Decode::Hex),
+    (22, 10, "Reserved", Decode::Hex),
+];
+
+const UNKNOWN: &[Field] = &[(1, 31, "Data", Decode::Hex)];
+
+/// Name and field layout of an entry type.
+pub(crate) fn layout(entry_type: u8) -> (&'static str, &'static [Field]) {
+    // deleted entries keep their layout, only the `InUse` bit is cleared
+    match entry_type | 0x80 {
+        0x85 => ("File", FILE),
+        0xC0 => ("Stream Extension", STREAM_EXTENSION),
+        0xC1 => ("File Name", FILE_NAME),
+        0x81 => ("Allocation Bitmap", ALLOCATION_BITMAP),
+        0x82 => ("Up-case Table", UPCASE_TABLE),
+        0x83 => ("Volume Label", VOLUME_LABEL),
+        0xA0 => ("Volume GUID", VOLUME_GUID),
+        0xA1 => ("TexFAT Padding", UNKNOWN),
+        0xE0 => ("Vendor Extension", UNKNOWN),
+        0xE1 => ("Vendor Allocation", UNKNOWN),
+        _ => ("Unknown", UNKNOWN),
+    }
+}
+
+/// A raw directory entry, displayed as its type name followed by its decoded fields.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RawEntry(pub [u8; 32]);
+
+impl RawEntry {
+    /// Type code of the entry.
+    pub fn entry_type(&self) -> u8 {
+        self.0[0]
+    }
+}
+
+impl core::fmt::Display for RawEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (name, fields) = layout(self.entry_type());
+        write!(f, "{name} ({:#04x})", self.entry_type())?;
+
+        let mut separator = ": ";
+        for (offset, len, field, decode) in fields {
+            // reserved and opaque fields are left out of summaries
+            if field.starts_with("Reserved") || matches!(decode, Decode::Hex) && *len > 8 {
+                continue;
+            }
+            let value = decode_field(&self.0[*offset..offset + len], *decode);
+            write!(f, "{separator}{field}={value}")?;
+            separator = ", ";
+        }
+        Ok(())
+    }
+}
+
+/// Renders raw directory entries, typically a complete entry set, as an annotated hexdump. Each
+/// entry is shown as raw bytes followed by its fields with their spec names and decoded values.
+/// The set checksum and name hash are verified if the entries form a complete file entry set.
+pub fn annotate_entries(entries: &[[u8; 32]]) -> String {
+    let mut out = String::new();
+
+    let set_checksum = entries.first().and_then(|primary| {
+        let secondary_count = primary[1] as usize;
+        let has_set = matches!(primary[0] | 0x80, 0x85 | 0xA0);
+        (has_set && entries.len() > secondary_count)
+            .then(|| checksum::entry_set(&entries[..=secondary_count]))
+    });
+    let name_hash = expected_name_hash(entries);
+
+    for (index, entry) in entries.iter().enumerate() {
+        let (name, fields) = layout(entry[0]);
+        let state = match entry[0] {
+            0x00 => "end of directory",
+            t if t & 0x80 != 0 => "in use",
+            _ => "not in use",
+        };
+        let _ = writeln!(out, "entry {index}: {name} ({:#04x}, {state})", entry[0]);
+
+        for row in 0..2 {
+            let bytes = &entry[row * 16..row * 16 + 16];
+            let _ = writeln!(out, "  {:02x}: {}", row * 16, hex(bytes));
+        }
+
+        for (offset, len, field, decode) in fields {
+            let bytes = &entry[*offset..offset + len];
+            let value = match decode {
+                Decode::Checksum if index == 0 => checked(le(bytes) as u16, set_checksum),
+                Decode::NameHash => checked(le(bytes) as u16, name_hash),
+                _ => decode_field(bytes, *decode),
+            };
+
+            let shown = if bytes.len() > 8 {
+                alloc::format!("{} ..", hex(&bytes[..8]))
+            } else {
+                hex(bytes)
+            };
+            let _ = writeln!(out, "    +{offset:02}  {shown:<26} {field:<26} {value}");
+        }
+    }
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 3);
+    for (i, byte) in bytes.iter().enumerate() {
+        if i != 0 {
+            out.push(' ');
+        }
+        let _ = write!(out, "{byte:02x}");
+    }
+    out
+}
+
+/// Little-endian value of a field of at most 8 bytes.
+fn le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u64, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn checked(stored: u16, expected: Option<u16>) -> String {
+    match expected {
+        Some(expected) if expected == stored => alloc::format!("{stored:#06x} (valid)"),
+        Some(expected) => alloc::format!("{stored:#06x} (INVALID, expected {expected:#06x})"),
+        None => alloc::format!("{stored:#06x} (not verified)"),
+    }
+}
+
+fn decode_field(bytes: &[u8], decode: Decode) -> String {
+    match decode {
+        Decode::Unsigned => alloc::format!("{}", le(bytes)),
+        Decode::Hex if bytes.len() <= 8 => alloc::format!("{:#x}", le(bytes)),
+        Decode::Hex if bytes.iter().all(|b| *b == 0) => String::from("all zero"),
+        Decode::Hex => String::new(),
+        Decode::Timestamp => {
+            let timestamp = Timestamp::new(le(bytes) as u32, 0, UtcOffset::UNKNOWN);
+            let (date, time) = (timestamp.date(), timestamp.time());
+            alloc::format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                date.year,
+                date.month,
+                date.day,
+                time.hour,
+                time.minute,
+                time.second
+            )
+        }
+        Decode::Utf16 => {
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            alloc::format!("{:?}", String::from_utf16_lossy(&units))
+        }
+        Decode::Attributes => flags(
+            le(bytes),
+            &[
+                (0x01, "ReadOnly"),
+                (0x02, "Hidden"),
+                (0x04, "System"),
+                (0x10, "Directory"),
+                (0x20, "Archive"),
+            ],
+        ),
+        Decode::SecondaryFlags => flags(
+            le(bytes),
+            &[(0x1, "AllocationPossible"), (0x2, "NoFatChain")],
+        ),
+        Decode::Checksum | Decode::NameHash => alloc::format!("{:#06x}", le(bytes)),
+    }
+}
+
+fn flags(value: u64, names: &[(u64, &str)]) -> String {
+    let set: Vec<&str> = names
+        .iter()
+        .filter(|(bit, _)| value & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if set.is_empty() {
+        String::from("none")
+    } else {
+        set.join(" | ")
+    }
+}
+
+/// Name hash of the file name contained in a file entry set, computed with the default up-case
+/// table.
+fn expected_name_hash(entries: &[[u8; 32]]) -> Option<u16> {
+    let (primary, stream) = (entries.first()?, entries.get(1)?);
+    if primary[0] | 0x80 != 0x85 || stream[0] | 0x80 != 0xC0 {
+        return None;
+    }
+
+    let name_length = stream[3] as usize;
+    let units: Vec<u16> = entries[2..]
+        .iter()
+        .take_while(|entry| entry[0] | 0x80 == 0xC1)
+        .flat_map(|entry| entry[2..].chunks_exact(2))
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take(name_length)
+        .collect();
+    (units.len() == name_length).then(|| UpcaseTable::default().name_hash(&units))
+}
+
+impl<O: ReadOffset> Root<O> {
+    /// Reads the entry set whose primary entry is at the given location and renders it with
+    /// [`annotate_entries`].
+    pub fn dump_entry_set(&self, location: EntryLocation) -> Result<String, O::Err> {
+        Ok(annotate_entries(&self.read_entry_set(location)?))
+    }
+
+    /// Reads the raw entries of the entry set whose primary entry is at the given location. The
+    /// set is cut short if it would extend beyond the cluster heap.
+    pub(crate) fn read_entry_set(&self, location: EntryLocation) -> Result<Vec<[u8; 32]>, O::Err> {
+        let entry_size = 32u64;
+        let bytes_per_cluster = self.boot.bytes_per_cluster() as u64;
+        let heap = FIRST_USABLE_CLUSTER_INDEX..self.boot.cluster_count + FIRST_USABLE_CLUSTER_INDEX;
+
+        let mut primary = [0u8; 32];
+        self.disk.read_exact(location.offset, &mut primary)?;
+        // of the critical primary entries, only file entries have a SecondaryCount field, benign
+        // primary entries all do
+        let entry_type = primary[0] | 0x80;
+        let count = if entry_type & 0x40 == 0 && (entry_type & 0x20 != 0 || entry_type == 0x85) {
+            1 + primary[1] as usize
+        } else {
+            1
+        };
+
+        let mut entries = Vec::with_capacity(count);
+        entries.push(primary);
+
+        let (mut cluster, mut offset) = (location.cluster, location.offset);
+        let mut index = location.index as u64;
+        while entries.len() < count {
+            index += 1;
+            offset += entry_size;
+            if index * entry_size == bytes_per_cluster {
+                // directories without a FAT chain continue in the following cluster
+                cluster = match self.fat.read_entry(&self.disk, cluster)? {
+                    Some(FatEntry(next)) if heap.contains(&next) => next,
+                    _ => cluster + 1,
+                };
+                let Some(cluster_offset) = self.boot.cluster_offset(cluster) else {
+                    break;
+                };
+                index = 0;
+                offset = cluster_offset;
+            }
+
+            let mut entry = [0u8; 32];
+            self.disk.read_exact(offset, &mut entry)?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn entry_summary() {
+    let entries = crate::test_utils::entry_set("hello.txt", false, 100, 13);
+
+    assert_eq!(
+        RawEntry(entries[1]).to_string(),
+        "Stream Extension (0xc0): GeneralSecondaryFlags=AllocationPossible | NoFatChain, \
+         NameLength=9, NameHash=0x0000, ValidDataLength=13, FirstCluster=100, DataLength=13"
+    );
+    assert!(
+        RawEntry(entries[0]).to_string().starts_with(
+            "File (0x85): SecondaryCount=2, SetChecksum=0x0000, FileAttributes=Archive"
+        )
+    );
+    assert_eq!(
+        RawEntry(entries[2]).to_string(),
+        "File Name (0xc1): GeneralSecondaryFlags=none, FileName=\"hello.txt\\0\\0\\0\\0\\0\\0\""
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn annotated_entry_set() {
+    use crate::{entry::set, name::Normalization, test_utils::TestImage};
+
+    let mut root = Root::open(TestImage::sample().cursor()).unwrap();
+    let location = match root.find("hello.txt", Normalization::None).unwrap() {
+        crate::fs::FsElement::F(file) => file.location(),
+        _ => panic!("expected a file"),
+    };
+
+    let dump = root.dump_entry_set(location).unwrap();
+    assert!(dump.contains("entry 0: File (0x85, in use)"));
+    assert!(dump.contains("entry 2: File Name (0xc1, in use)"));
+    assert!(dump.contains("CreateTimestamp"));
+    assert!(dump.contains("2024-05-17 12:30:20"));
+    assert!(dump.contains("\"hello.txt"));
+    assert!(dump.contains("Archive"));
+    assert!(dump.contains("FirstCluster               100"));
+    // the test fixture does not fill in the name hash and the checksum
+    assert!(dump.contains("INVALID"));
+
+    // entry sets written by the crate itself verify
+    let set = set::EntrySet::new(
+        &"hello.txt".encode_utf16().collect::<Vec<_>>(),
+        crate::entry::FileAttributes::ARCHIVE,
+        &crate::timestamp::Timestamps::new(
+            Timestamp::from_unix_secs(0),
+            Timestamp::from_unix_secs(0),
+            Timestamp::from_unix_secs(0),
+        ),
+        set::Allocation::default(),
+        &UpcaseTable::default(),
+    );
+    let dump = annotate_entries(&set.raw_entries());
+    assert!(!dump.contains("INVALID"));
+    assert_eq!(dump.matches("(valid)").count(), 2);
+}
+
+#[cfg(test)]
+#[test]
+fn entries_without_secondaries() {
+    use crate::{
+        Label,
+        test_utils::{TestImage, format_options},
+    };
+    use alloc::string::ToString;
+
+    let options = format_options()
+        .label(Label::new("Volume".to_string()).unwrap())
+        .build()
+        .unwrap();
+    let image = TestImage::with_options(options);
+    let cluster = image.root_cluster();
+    let offset = image.cluster_offset(cluster) as u64;
+    let root = Root::open(image.cursor()).unwrap();
+
+    // the label, bitmap and up-case table entries stand alone, whatever their second byte holds
+    for index in 0..3 {
+        let location = EntryLocation {
+            cluster,
+            index,
+            offset: offset + 32 * index as u64,
+        };
+        let dump = root.dump_entry_set(location).unwrap();
+        assert!(dump.contains("entry 0: "), "{dump}");
+        assert!(!dump.contains("entry 1: "), "{dump}");
+    }
+}
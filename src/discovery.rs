@@ -0,0 +1,89 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    vec::Vec,
+};
+
+use crate::{
+    probe::probe,
+    root::{OpenOptionsBuilder, Root},
+};
+
+/// Identifier of a volume.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VolumeId {
+    /// Serial number from the boot sector.
+    Serial(u32),
+    /// GUID from the volume GUID directory entry.
+    Guid(u128),
+}
+
+impl VolumeId {
+    /// Whether `file` holds an exFAT volume with this identifier. Serial numbers are compared
+    /// using the boot sector alone, while GUIDs require the root directory; the FAT is only
+    /// loaded as far as needed to read it.
+    fn matches(&self, file: File) -> bool {
+        let Some(info) = probe(&file) else {
+            return false;
+        };
+        match self {
+            VolumeId::Serial(serial) => info.volume_serial_number == *serial,
+            VolumeId::Guid(guid) => {
+                let options = OpenOptionsBuilder::default()
+                    .defer_fat(true)
+                    .read_only(true)
+                    .build()
+                    .unwrap();
+                Root::open_with(file, options).is_ok_and(|root| root.volume_guid() == Some(*guid))
+            }
+        }
+    }
+}
+
+/// Checks each of the candidate devices or images and returns the paths of those containing an
+/// exFAT volume with the given identifier. Candidates that cannot be opened or do not contain a
+/// valid exFAT volume are skipped.
+pub fn find_volumes<P: AsRef<Path>>(
+    candidates: impl IntoIterator<Item = P>,
+    id: VolumeId,
+) -> Vec<PathBuf> {
+    candidates
+        .into_iter()
+        .filter(|path| File::open(path).is_ok_and(|file| id.matches(file)))
+        .map(|path| path.as_ref().to_path_buf())
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn find_by_guid() {
+    use crate::test_utils::{TestImage, format_options};
+
+    let dir = std::env::temp_dir().join(std::format!("exfat-discovery-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut paths = Vec::new();
+    for guid in [1u128, 0xC0FFEE] {
+        let image = TestImage::with_options(format_options().guid(Some(guid)).build().unwrap());
+        let path = dir.join(std::format!("{guid:x}.img"));
+        std::fs::write(&path, image.image).unwrap();
+        paths.push(path);
+    }
+    let garbage = dir.join("garbage.img");
+    std::fs::write(&garbage, [0xFFu8; 4096]).unwrap();
+    paths.push(garbage);
+    paths.push(dir.join("missing.img"));
+
+    assert_eq!(
+        find_volumes(&paths, VolumeId::Guid(0xC0FFEE)),
+        vec![paths[1].clone()]
+    );
+    assert!(find_volumes(&paths, VolumeId::Guid(2)).is_empty());
+
+    let serial = Root::open(File::open(&paths[0]).unwrap())
+        .unwrap()
+        .volume_serial_number();
+    assert!(find_volumes(&paths, VolumeId::Serial(serial)).contains(&paths[0]));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,6 +1,15 @@
-use core::ops::Deref;
+use core::{
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec};
+
+use crate::{
+    cache::BlockCache,
+    checksum::{ContentHasher, Fnv1a},
+    throttle::{MonotonicClock, TokenBucket},
+};
 /// Writes zeroes to a file from the given absolute offset (in bytes), up to the given size.
 pub fn write_zeroes<T>(f: &mut T, size: u64, offset: u64) -> Result<(), T::Err>
 where
@@ -31,6 +40,16 @@ pub trait WriteSeek {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Err>;
     fn stream_position(&mut self) -> Result<u64, Self::Err>;
 }
+
+/// A device that can discard (TRIM) ranges of its blocks, which is much faster than zeroing them
+/// on flash media. Used by [`Exfat::write_discarding`](crate::format::Exfat::write_discarding).
+pub trait BlockDiscard: WriteSeek {
+    /// Discards `len` bytes starting at the absolute offset `offset` (in bytes). Returns `false`
+    /// if the device cannot discard the range, in which case it is zeroed instead. Devices must
+    /// only return `true` if discarded ranges read back as zeroes afterwards.
+    fn discard(&mut self, offset: u64, len: u64) -> Result<bool, Self::Err>;
+}
+
 #[cfg(feature = "std")]
 impl<T> WriteSeek for T
 where
@@ -55,6 +74,101 @@ where
     }
 }
 
+/// A purely sequential output, such as a pipe or a network stream.
+pub trait SequentialWrite {
+    type Err;
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Err>;
+    fn failed_to_write(&self) -> Self::Err;
+    /// Error returned when an operation would require seeking on the output.
+    fn failed_to_seek(&self) -> Self::Err;
+}
+
+#[cfg(feature = "std")]
+impl<T> SequentialWrite for T
+where
+    T: std::io::Write,
+{
+    type Err = std::io::Error;
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Err> {
+        std::io::Write::write_all(self, buf)
+    }
+    fn failed_to_write(&self) -> Self::Err {
+        Self::Err::new(std::io::ErrorKind::WriteZero, "Failed to write 0s")
+    }
+    fn failed_to_seek(&self) -> Self::Err {
+        Self::Err::new(
+            std::io::ErrorKind::Unsupported,
+            "Sequential output cannot seek backwards",
+        )
+    }
+}
+
+/// Adapts a [`SequentialWrite`] output to [`WriteSeek`]. Seeking forward emits an explicit run of
+/// zeroes, seeking backwards (or relative to the end) fails.
+#[derive(Debug)]
+pub struct StreamWriter<W> {
+    inner: W,
+    position: u64,
+}
+
+impl<W: SequentialWrite> StreamWriter<W> {
+    pub fn new(inner: W) -> StreamWriter<W> {
+        StreamWriter { inner, position: 0 }
+    }
+
+    /// Returns the underlying output.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Emits zeroes until the given absolute offset (in bytes) is reached.
+    pub(crate) fn pad_to(&mut self, offset: u64) -> Result<(), W::Err> {
+        if offset < self.position {
+            return Err(self.inner.failed_to_seek());
+        }
+
+        let buffer = [0u8; 4 * crate::KB as usize];
+
+        while self.position < offset {
+            let iter_size = (offset - self.position).min(buffer.len() as u64);
+            // `iter_size` is max 4KB so this cast is fine
+            self.inner.write_all(&buffer[..iter_size as usize])?;
+            self.position += iter_size;
+        }
+        Ok(())
+    }
+}
+
+impl<W: SequentialWrite> WriteSeek for StreamWriter<W> {
+    type Err = W::Err;
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Err> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+    fn failed_to_write(&self) -> Self::Err {
+        self.inner.failed_to_write()
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Err> {
+        self.inner.write_all(buf)?;
+        self.position += buf.len() as u64;
+        Ok(())
+    }
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Err> {
+        let offset = match pos {
+            SeekFrom::Start(x) => x,
+            SeekFrom::Current(x) if x >= 0 => self.position + x as u64,
+            _ => return Err(self.inner.failed_to_seek()),
+        };
+        self.pad_to(offset)?;
+        Ok(self.position)
+    }
+    fn stream_position(&mut self) -> Result<u64, Self::Err> {
+        Ok(self.position)
+    }
+}
+
 pub enum SeekFrom {
     Start(u64),
     End(i64),
@@ -72,10 +186,22 @@ impl From<SeekFrom> for std::io::SeekFrom {
     }
 }
 
-pub trait PartitionError: core::fmt::Debug {
+pub trait PartitionError: core::fmt::Debug + Sized {
     fn unexpected_eop() -> Self;
 
     fn cluster_not_found(cluster: u32) -> Self;
+
+    /// Error returned when writing to a volume that must not be modified. Defaults to
+    /// [`PartitionError::unexpected_eop`] for error types without a dedicated error.
+    fn read_only() -> Self {
+        Self::unexpected_eop()
+    }
+
+    /// Error returned when reading through a handle after the device has been modified. Defaults
+    /// to [`PartitionError::unexpected_eop`] for error types without a dedicated error.
+    fn stale_handle() -> Self {
+        Self::unexpected_eop()
+    }
 }
 
 pub trait ReadOffset {
@@ -98,6 +224,614 @@ pub trait ReadOffset {
         }
         Ok(())
     }
+
+    /// Counter that changes whenever the contents of the device are modified, if the device
+    /// keeps track of it (see [`VersionedDevice`]). Handles to files and directories become stale
+    /// once it changes.
+    fn generation(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A device that can additionally be written at arbitrary offsets through a shared reference.
+pub trait WriteOffset: ReadOffset {
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<usize, Self::Err>;
+
+    /// Makes sure all previous writes reached the underlying storage.
+    fn flush(&self) -> Result<(), Self::Err> {
+        Ok(())
+    }
+
+    fn write_all_at(&self, mut offset: u64, mut buffer: &[u8]) -> Result<(), Self::Err> {
+        while !buffer.is_empty() {
+            match self.write_at(offset, buffer) {
+                Ok(0) => return Err(PartitionError::unexpected_eop()),
+                Ok(n) => {
+                    buffer = &buffer[n..];
+                    offset = offset
+                        .checked_add(n as u64)
+                        .ok_or(PartitionError::unexpected_eop())?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a device so that every write fails with [`PartitionError::read_only`], while reads are
+/// passed through. This guarantees at the type level that inspection tooling cannot modify the
+/// underlying image.
+#[derive(Debug)]
+pub struct ReadOnlyDevice<T> {
+    inner: T,
+}
+
+impl<T> ReadOnlyDevice<T> {
+    pub fn new(inner: T) -> ReadOnlyDevice<T> {
+        ReadOnlyDevice { inner }
+    }
+
+    /// Returns a shared reference to the underlying device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the underlying device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: ReadOffset> ReadOffset for ReadOnlyDevice<T> {
+    type Err = T::Err;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        self.inner.read_at(offset, buf)
+    }
+    fn generation(&self) -> Option<u64> {
+        self.inner.generation()
+    }
+}
+
+impl<T: ReadOffset> WriteOffset for ReadOnlyDevice<T> {
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> Result<usize, Self::Err> {
+        Err(PartitionError::read_only())
+    }
+}
+
+/// A single write observed by an [`AuditDevice`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WriteRecord {
+    /// Absolute offset (in bytes) of the write.
+    pub offset: u64,
+    /// Amount of bytes written.
+    pub len: usize,
+    /// 64-bit FNV-1a hash of the written bytes, if enabled.
+    pub hash: Option<u64>,
+}
+
+/// Wraps a device or output and reports every write to a callback, so it can be audited which
+/// regions were touched. Reads are passed through unmodified.
+pub struct AuditDevice<T, F> {
+    inner: T,
+    log: F,
+    hash: bool,
+}
+
+impl<T, F: Fn(WriteRecord)> AuditDevice<T, F> {
+    pub fn new(inner: T, log: F) -> AuditDevice<T, F> {
+        AuditDevice {
+            inner,
+            log,
+            hash: false,
+        }
+    }
+
+    /// Additionally hashes the contents of every write.
+    pub fn with_hashes(mut self) -> AuditDevice<T, F> {
+        self.hash = true;
+        self
+    }
+
+    /// Returns the underlying device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(&self, offset: u64, buf: &[u8]) {
+        (self.log)(WriteRecord {
+            offset,
+            len: buf.len(),
+            hash: self.hash.then(|| fnv1a(buf)),
+        });
+    }
+}
+
+impl<T: core::fmt::Debug, F> core::fmt::Debug for AuditDevice<T, F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AuditDevice")
+            .field("inner", &self.inner)
+            .field("hash", &self.hash)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 64-bit FNV-1a hash.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+impl<T: ReadOffset, F: Fn(WriteRecord)> ReadOffset for AuditDevice<T, F> {
+    type Err = T::Err;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        self.inner.read_at(offset, buf)
+    }
+    fn generation(&self) -> Option<u64> {
+        self.inner.generation()
+    }
+}
+
+impl<T: WriteOffset, F: Fn(WriteRecord)> WriteOffset for AuditDevice<T, F> {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        let written = self.inner.write_at(offset, buf)?;
+        self.record(offset, &buf[..written]);
+        Ok(written)
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.inner.flush()
+    }
+}
+
+impl<T: WriteSeek, F: Fn(WriteRecord)> WriteSeek for AuditDevice<T, F> {
+    type Err = T::Err;
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Err> {
+        let offset = self.inner.stream_position()?;
+        let written = self.inner.write(buf)?;
+        self.record(offset, &buf[..written]);
+        Ok(written)
+    }
+    fn failed_to_write(&self) -> Self::Err {
+        self.inner.failed_to_write()
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Err> {
+        let offset = self.inner.stream_position()?;
+        self.inner.write_all(buf)?;
+        self.record(offset, buf);
+        Ok(())
+    }
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Err> {
+        self.inner.seek(pos)
+    }
+    fn stream_position(&mut self) -> Result<u64, Self::Err> {
+        self.inner.stream_position()
+    }
+}
+
+/// Wraps a device and limits its throughput to a fixed amount of bytes per second, so background
+/// work such as a scan or a scrub can run on production media without starving foreground I/O.
+/// The limit is enforced by a token bucket, which allows short bursts of at most its capacity.
+/// Reads and writes share the budget; the caller is blocked using the [`MonotonicClock`] `C`.
+pub struct ThrottledDevice<T, C> {
+    inner: T,
+    bucket: TokenBucket<C>,
+}
+
+impl<T, C: MonotonicClock> ThrottledDevice<T, C> {
+    /// Limits the device to `max_bytes_per_sec`, with a bucket holding one second of I/O.
+    pub fn new(inner: T, max_bytes_per_sec: u64) -> ThrottledDevice<T, C> {
+        ThrottledDevice::with_burst(inner, max_bytes_per_sec, max_bytes_per_sec)
+    }
+
+    /// Limits the device to `max_bytes_per_sec`, with a bucket holding `burst` bytes. A burst of
+    /// zero paces every single request.
+    pub fn with_burst(inner: T, max_bytes_per_sec: u64, burst: u64) -> ThrottledDevice<T, C> {
+        ThrottledDevice {
+            inner,
+            bucket: TokenBucket::new(max_bytes_per_sec, burst),
+        }
+    }
+
+    /// Returns a shared reference to the underlying device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the underlying device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: core::fmt::Debug, C> core::fmt::Debug for ThrottledDevice<T, C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ThrottledDevice")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: ReadOffset, C: MonotonicClock> ReadOffset for ThrottledDevice<T, C> {
+    type Err = T::Err;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        let read = self.inner.read_at(offset, buf)?;
+        self.bucket.take(read as u64);
+        Ok(read)
+    }
+    fn generation(&self) -> Option<u64> {
+        self.inner.generation()
+    }
+}
+
+impl<T: WriteOffset, C: MonotonicClock> WriteOffset for ThrottledDevice<T, C> {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        let written = self.inner.write_at(offset, buf)?;
+        self.bucket.take(written as u64);
+        Ok(written)
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.inner.flush()
+    }
+}
+
+/// Largest amount of bytes an [`AlignedDevice`] transfers with a single request.
+const MAX_ALIGNED_TRANSFER: u64 = 1024 * 1024;
+
+/// Wraps a device which only supports I/O in whole blocks at block-aligned offsets (e.g. raw
+/// character devices). Unaligned reads are widened to the surrounding blocks, unaligned writes
+/// read, modify and write back the partially covered blocks.
+#[derive(Debug)]
+pub struct AlignedDevice<T> {
+    inner: T,
+    block_size: u64,
+}
+
+impl<T> AlignedDevice<T> {
+    /// Wraps `inner`, aligning all accesses to `block_size`, which must be a power of two.
+    pub fn new(inner: T, block_size: u64) -> AlignedDevice<T> {
+        assert!(
+            block_size.is_power_of_two(),
+            "block size must be a power of two"
+        );
+        AlignedDevice { inner, block_size }
+    }
+
+    /// Returns a shared reference to the underlying device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the underlying device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Whether the given range can be passed to the underlying device unmodified.
+    fn is_aligned(&self, offset: u64, len: usize) -> bool {
+        offset.is_multiple_of(self.block_size) && (len as u64).is_multiple_of(self.block_size)
+    }
+
+    /// Aligned range covering `len` bytes at `offset`, limited to one transfer.
+    fn covering(&self, offset: u64, len: usize) -> (u64, usize) {
+        let start = offset - offset % self.block_size;
+        let len = (len as u64).min(MAX_ALIGNED_TRANSFER);
+        let end = (offset + len).next_multiple_of(self.block_size);
+        (start, (end - start) as usize)
+    }
+}
+
+impl<T: ReadOffset> AlignedDevice<T> {
+    /// Reads whole blocks, returning the amount of bytes read before the end of the device.
+    fn read_blocks(&self, mut offset: u64, mut buf: &mut [u8]) -> Result<usize, T::Err> {
+        let mut read = 0;
+        while !buf.is_empty() {
+            match self.inner.read_at(offset, buf)? {
+                0 => break,
+                n => {
+                    read += n;
+                    offset += n as u64;
+                    buf = &mut buf[n..];
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl<T: ReadOffset> ReadOffset for AlignedDevice<T> {
+    type Err = T::Err;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        if self.is_aligned(offset, buf.len()) {
+            return self.inner.read_at(offset, buf);
+        }
+
+        let (start, len) = self.covering(offset, buf.len());
+        let mut blocks = vec![0u8; len];
+        let read = self.read_blocks(start, &mut blocks)?;
+
+        let skip = (offset - start) as usize;
+        let amount = read.saturating_sub(skip).min(buf.len());
+        buf[..amount].copy_from_slice(&blocks[skip..skip + amount]);
+        Ok(amount)
+    }
+    fn generation(&self) -> Option<u64> {
+        self.inner.generation()
+    }
+}
+
+impl<T: WriteOffset> WriteOffset for AlignedDevice<T> {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        if self.is_aligned(offset, buf.len()) {
+            return self.inner.write_at(offset, buf);
+        }
+
+        let (start, len) = self.covering(offset, buf.len());
+        let mut blocks = vec![0u8; len];
+        let skip = (offset - start) as usize;
+        let amount = buf.len().min(len - skip);
+
+        // only the first and the last block may be partially covered
+        let block_size = self.block_size as usize;
+        if skip != 0 {
+            self.read_blocks(start, &mut blocks[..block_size])?;
+        }
+        if !(skip + amount).is_multiple_of(block_size) {
+            let last = len - block_size;
+            self.read_blocks(start + last as u64, &mut blocks[last..])?;
+        }
+
+        blocks[skip..skip + amount].copy_from_slice(&buf[..amount]);
+        self.inner.write_all_at(start, &blocks)?;
+        Ok(amount)
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.inner.flush()
+    }
+}
+
+/// Wraps a device and counts its modifications, so [`File`](crate::fs::file::File) and
+/// [`Directory`](crate::fs::directory::Directory) handles read from an older state of the device
+/// fail with [`PartitionError::stale_handle`] instead of returning inconsistent data. Writes
+/// through the wrapper are counted automatically; modifications made by other means (e.g. another
+/// process) must be announced with [`VersionedDevice::invalidate`].
+#[derive(Debug)]
+pub struct VersionedDevice<T> {
+    inner: T,
+    generation: AtomicU64,
+}
+
+impl<T> VersionedDevice<T> {
+    pub fn new(inner: T) -> VersionedDevice<T> {
+        VersionedDevice {
+            inner,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Marks all existing handles as stale, e.g. after the device was modified by another writer.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Returns a shared reference to the underlying device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the underlying device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: ReadOffset> ReadOffset for VersionedDevice<T> {
+    type Err = T::Err;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        self.inner.read_at(offset, buf)
+    }
+    fn generation(&self) -> Option<u64> {
+        Some(self.generation.load(Ordering::Acquire))
+    }
+}
+
+impl<T: WriteOffset> WriteOffset for VersionedDevice<T> {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        // counted before the write, so readers never miss a partial modification
+        self.invalidate();
+        self.inner.write_at(offset, buf)
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.inner.flush()
+    }
+}
+
+/// Wraps a device and keeps recently read blocks in memory, evicting the least recently used
+/// block once the cache is full. Repeated small reads of the same regions, e.g. of directories
+/// while walking the tree, are served without accessing the device; reads at least as large as
+/// the cache bypass it. Writes through the wrapper update the cache automatically; modifications
+/// made by other means must be announced with [`CachedDevice::invalidate`].
+#[derive(Debug)]
+pub struct CachedDevice<T> {
+    inner: T,
+    cache: BlockCache,
+}
+
+impl<T> CachedDevice<T> {
+    /// Wraps `inner`, caching up to `capacity` blocks of `block_size` bytes each.
+    pub fn new(inner: T, block_size: u64, capacity: usize) -> CachedDevice<T> {
+        assert!(block_size > 0, "block size must not be zero");
+        CachedDevice {
+            inner,
+            cache: BlockCache::new(block_size, capacity),
+        }
+    }
+
+    /// Drops all cached blocks, e.g. after the device was modified by another writer.
+    pub fn invalidate(&self) {
+        self.cache.clear();
+    }
+
+    /// Returns a shared reference to the underlying device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the underlying device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: ReadOffset> ReadOffset for CachedDevice<T> {
+    type Err = T::Err;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        let cache_size = self.cache.block_size() * self.cache.capacity() as u64;
+        if (buf.len() as u64) < cache_size
+            && let Some(read) = self.cache.read(&self.inner, offset, buf)
+        {
+            return read;
+        }
+        self.inner.read_at(offset, buf)
+    }
+    fn generation(&self) -> Option<u64> {
+        self.inner.generation()
+    }
+}
+
+impl<T: WriteOffset> WriteOffset for CachedDevice<T> {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        let written = self.inner.write_at(offset, buf);
+        // dropped after writing, so concurrent reads cannot cache the previous contents
+        self.cache
+            .invalidate(offset..offset.saturating_add(buf.len() as u64));
+        written
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.inner.flush()
+    }
+}
+
+/// Exposes the `len` bytes of a larger device or image starting at `start`, e.g. a partition of
+/// a whole-disk image. Offsets are translated and all accesses are limited to the slice: reads
+/// end at its end and writes beyond it fail with [`PartitionError::unexpected_eop`]. As a
+/// [`WriteSeek`], the slice keeps its own position, so volumes can be formatted into it.
+#[derive(Debug)]
+pub struct Slice<T> {
+    inner: T,
+    start: u64,
+    len: u64,
+    /// Position of the [`WriteSeek`] implementation, relative to the start of the slice.
+    position: u64,
+}
+
+impl<T> Slice<T> {
+    pub fn new(inner: T, start: u64, len: u64) -> Slice<T> {
+        assert!(
+            start.checked_add(len).is_some(),
+            "slice must end within the addressable range"
+        );
+        Slice {
+            inner,
+            start,
+            len,
+            position: 0,
+        }
+    }
+
+    /// Offset of the slice on the underlying device in bytes.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Length of the slice in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a shared reference to the underlying device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the underlying device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Length of an access of `len` bytes at `offset`, limited to the slice.
+    fn limit(&self, offset: u64, len: usize) -> usize {
+        self.len.saturating_sub(offset).min(len as u64) as usize
+    }
+}
+
+impl<T: ReadOffset> ReadOffset for Slice<T> {
+    type Err = T::Err;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        let amount = self.limit(offset, buf.len());
+        if amount == 0 {
+            return Ok(0);
+        }
+        self.inner.read_at(self.start + offset, &mut buf[..amount])
+    }
+    fn generation(&self) -> Option<u64> {
+        self.inner.generation()
+    }
+}
+
+impl<T: WriteOffset> WriteOffset for Slice<T> {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        let amount = self.limit(offset, buf.len());
+        if amount == 0 && !buf.is_empty() {
+            return Err(PartitionError::unexpected_eop());
+        }
+        self.inner.write_at(self.start + offset, &buf[..amount])
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.inner.flush()
+    }
+}
+
+impl<T: WriteOffset> WriteSeek for Slice<T> {
+    type Err = T::Err;
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Err> {
+        let written = self.write_at(self.position, buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+    fn failed_to_write(&self) -> Self::Err {
+        PartitionError::unexpected_eop()
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Err> {
+        self.write_all_at(self.position, buf)?;
+        self.position += buf.len() as u64;
+        Ok(())
+    }
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Err> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or(PartitionError::unexpected_eop())?;
+        Ok(self.position)
+    }
+    fn stream_position(&mut self) -> Result<u64, Self::Err> {
+        Ok(self.position)
+    }
 }
 
 #[cfg(feature = "std")]
@@ -107,10 +841,15 @@ impl PartitionError for std::io::Error {
     }
 
     fn cluster_not_found(cluster: u32) -> Self {
-        std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("cluster #{cluster} is not available"),
-        )
+        std::io::Error::other(format!("cluster #{cluster} is not available"))
+    }
+
+    fn read_only() -> Self {
+        std::io::Error::from(std::io::ErrorKind::ReadOnlyFilesystem)
+    }
+
+    fn stale_handle() -> Self {
+        std::io::Error::from(std::io::ErrorKind::StaleNetworkFileHandle)
     }
 }
 
@@ -120,6 +859,9 @@ impl<T: ReadOffset> ReadOffset for &T {
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
         (*self).read_at(offset, buf)
     }
+    fn generation(&self) -> Option<u64> {
+        (*self).generation()
+    }
 }
 impl<T: ReadOffset> ReadOffset for Arc<T> {
     type Err = T::Err;
@@ -127,6 +869,25 @@ impl<T: ReadOffset> ReadOffset for Arc<T> {
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
         self.deref().read_at(offset, buf)
     }
+    fn generation(&self) -> Option<u64> {
+        self.deref().generation()
+    }
+}
+impl<T: WriteOffset> WriteOffset for &T {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        (*self).write_at(offset, buf)
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        (*self).flush()
+    }
+}
+impl<T: WriteOffset> WriteOffset for Arc<T> {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        self.deref().write_at(offset, buf)
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.deref().flush()
+    }
 }
 #[cfg(feature = "std")]
 impl ReadOffset for std::fs::File {
@@ -142,3 +903,374 @@ impl ReadOffset for std::fs::File {
         std::os::windows::fs::FileExt::seek_read(self, buf, offset)
     }
 }
+
+#[cfg(feature = "std")]
+impl<T: AsRef<[u8]>> ReadOffset for std::io::Cursor<T> {
+    type Err = std::io::Error;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        let data = self.get_ref().as_ref();
+        let start = offset.min(data.len() as u64) as usize;
+        let amount = buf.len().min(data.len() - start);
+
+        buf[..amount].copy_from_slice(&data[start..start + amount]);
+        Ok(amount)
+    }
+}
+
+#[cfg(feature = "std")]
+impl WriteOffset for std::fs::File {
+    #[cfg(unix)]
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        std::os::unix::fs::FileExt::write_at(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        std::os::windows::fs::FileExt::seek_write(self, buf, offset)
+    }
+
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.sync_data()
+    }
+}
+
+/// Any seekable stream (e.g. an in-memory [`std::io::Cursor`]) can be used as a device by
+/// guarding it with a mutex.
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Seek> ReadOffset for std::sync::Mutex<T> {
+    type Err = std::io::Error;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        let mut inner = self
+            .lock()
+            .map_err(|_| std::io::Error::other("device lock poisoned"))?;
+        inner.seek(std::io::SeekFrom::Start(offset))?;
+        inner.read(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Write + std::io::Seek> WriteOffset for std::sync::Mutex<T> {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        let mut inner = self
+            .lock()
+            .map_err(|_| std::io::Error::other("device lock poisoned"))?;
+        inner.seek(std::io::SeekFrom::Start(offset))?;
+        inner.write(buf)
+    }
+
+    fn flush(&self) -> Result<(), Self::Err> {
+        self.lock()
+            .map_err(|_| std::io::Error::other("device lock poisoned"))?
+            .flush()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn read_only_device_rejects_writes() {
+    use crate::{test_utils::TestImage, volume::Volume};
+    use std::{io::Cursor, sync::Mutex};
+
+    let image = TestImage::sample().image;
+    let volume = Volume::open(ReadOnlyDevice::new(Mutex::new(Cursor::new(image.clone())))).unwrap();
+
+    let mut buffer = vec![0u8; 13];
+    volume.read_cluster(100, &mut buffer).unwrap();
+    assert_eq!(&buffer, b"Hello, World!");
+
+    let err = volume.write_cluster(100, b"Howdy").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::ReadOnlyFilesystem);
+
+    let device = volume.root.disk.get_ref().lock().unwrap();
+    assert!(device.get_ref() == &image, "image must not be modified");
+}
+
+#[cfg(test)]
+#[test]
+fn audit_device_records_writes() {
+    use crate::{
+        format::Exfat,
+        test_utils::{IMAGE_SIZE, format_options},
+    };
+    use std::{cell::RefCell, io::Cursor};
+
+    let size = IMAGE_SIZE;
+    let format_options = format_options().build().unwrap();
+
+    let records = RefCell::new(Vec::new());
+    let mut f = AuditDevice::new(Cursor::new(vec![0u8; size as usize]), |record| {
+        records.borrow_mut().push(record)
+    })
+    .with_hashes();
+
+    let mut formatter = Exfat::try_from::<std::time::SystemTime>(format_options).unwrap();
+    formatter.write::<std::time::SystemTime, _>(&mut f).unwrap();
+    let image = f.into_inner().into_inner();
+
+    let records = records.into_inner();
+    assert!(records.iter().all(|r| r.offset + r.len as u64 <= size));
+
+    // the region is zeroed before the boot sector is written
+    assert_eq!(records[0].offset, 0);
+    let boot = records.iter().rev().find(|r| r.offset == 0).unwrap();
+    assert_eq!(boot.len, 512);
+    assert_eq!(boot.hash, Some(fnv1a(&image[..512])));
+}
+
+#[cfg(test)]
+#[test]
+fn throttled_device_paces_io() {
+    use core::{
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    };
+    use std::{io::Cursor, sync::Mutex};
+
+    /// Clock that only advances while sleeping.
+    static NOW: AtomicU64 = AtomicU64::new(0);
+    struct Simulated;
+    impl MonotonicClock for Simulated {
+        fn now() -> Duration {
+            Duration::from_nanos(NOW.load(Ordering::SeqCst))
+        }
+        fn sleep(duration: Duration) {
+            NOW.fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+        }
+    }
+
+    let device =
+        ThrottledDevice::<_, Simulated>::new(Mutex::new(Cursor::new(vec![0u8; 8192])), 1024);
+    let mut buf = [0u8; 4096];
+    // the first second is covered by the full bucket
+    device.read_exact(0, &mut buf[..2048]).unwrap();
+    assert_eq!(Simulated::now(), Duration::from_secs(1));
+
+    // writes share the budget
+    device.write_all_at(0, &buf[..512]).unwrap();
+    assert_eq!(Simulated::now(), Duration::from_millis(1500));
+
+    // idle time refills the bucket only up to its capacity
+    Simulated::sleep(Duration::from_secs(10));
+    device.read_exact(0, &mut buf).unwrap();
+    assert_eq!(Simulated::now(), Duration::from_millis(14_500));
+
+    // without a burst, every request is paced
+    let device = ThrottledDevice::<_, Simulated>::with_burst(
+        Mutex::new(Cursor::new(vec![0u8; 8192])),
+        1024,
+        0,
+    );
+    device.read_exact(0, &mut buf[..1024]).unwrap();
+    assert_eq!(Simulated::now(), Duration::from_millis(15_500));
+}
+
+#[cfg(test)]
+#[test]
+fn versioned_device_detects_stale_handles() {
+    use crate::{
+        error::DirectoryError, fs::FsElement, name::Normalization, root::Root,
+        test_utils::TestImage, volume::Volume,
+    };
+    use std::{
+        io::{ErrorKind, Read},
+        sync::Mutex,
+        time::SystemTime,
+    };
+
+    let device = Arc::new(VersionedDevice::new(Mutex::new(
+        TestImage::sample().cursor(),
+    )));
+    let mut root = Root::open(Arc::clone(&device)).unwrap();
+    assert!(!root.is_stale());
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("directory not found");
+    };
+    let Some(FsElement::F(mut file)) = dir.find("nested.txt", Normalization::None).unwrap() else {
+        panic!("nested.txt not found");
+    };
+
+    // another writer modifies the device
+    device.write_all_at(0x1_0000, b"modified").unwrap();
+    assert!(dir.is_stale() && file.is_stale());
+    assert!(matches!(dir.open(), Err(DirectoryError::StaleHandle)));
+    let err = file.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::StaleNetworkFileHandle);
+    assert!(root.is_stale());
+
+    // writes through a volume start from the current state of the device
+    let mut volume = Volume::open(Arc::clone(&device)).unwrap();
+    device.invalidate();
+    volume.write_atomic::<SystemTime>("a.txt", b"a").unwrap();
+    assert!(!volume.root().is_stale());
+
+    // devices without a generation never become stale
+    let root = Root::open(TestImage::sample().cursor()).unwrap();
+    assert!(!root.is_stale());
+}
+
+#[cfg(test)]
+#[test]
+fn partition_error_defaults() {
+    /// An error type implementing only the required methods.
+    #[derive(Debug, PartialEq)]
+    enum Error {
+        Eop,
+        Cluster(u32),
+    }
+    impl PartitionError for Error {
+        fn unexpected_eop() -> Self {
+            Error::Eop
+        }
+
+        fn cluster_not_found(cluster: u32) -> Self {
+            Error::Cluster(cluster)
+        }
+    }
+
+    assert_eq!(Error::stale_handle(), Error::Eop);
+    assert_eq!(Error::read_only(), Error::Eop);
+    assert_eq!(Error::cluster_not_found(7), Error::Cluster(7));
+}
+
+#[cfg(test)]
+#[test]
+fn aligned_device_widens_accesses() {
+    use std::{io::Cursor, sync::Mutex};
+
+    /// Device rejecting accesses that are not 512-byte aligned.
+    #[derive(Debug)]
+    struct Strict(Mutex<Cursor<Vec<u8>>>);
+    impl Strict {
+        fn check(offset: u64, len: usize) -> Result<(), std::io::Error> {
+            if !offset.is_multiple_of(512) || !len.is_multiple_of(512) {
+                return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+            }
+            Ok(())
+        }
+    }
+    impl ReadOffset for Strict {
+        type Err = std::io::Error;
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            Strict::check(offset, buf.len())?;
+            self.0.read_at(offset, buf)
+        }
+    }
+    impl WriteOffset for Strict {
+        fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+            Strict::check(offset, buf.len())?;
+            self.0.write_at(offset, buf)
+        }
+    }
+
+    let data: Vec<u8> = (0..4096u32).map(|i| i as u8).collect();
+    let device = AlignedDevice::new(Strict(Mutex::new(Cursor::new(data.clone()))), 512);
+
+    let mut buf = [0u8; 700];
+    device.read_exact(300, &mut buf).unwrap();
+    assert_eq!(buf, data[300..1000]);
+
+    device.write_all_at(1000, &[0xAA; 100]).unwrap();
+    let mut expected = data;
+    expected[1000..1100].fill(0xAA);
+    let mut all = vec![0u8; 4096];
+    device.read_exact(0, &mut all).unwrap();
+    assert_eq!(all, expected);
+
+    // reads end with the device
+    assert_eq!(device.read_at(4000, &mut [0u8; 200]).unwrap(), 96);
+}
+
+#[cfg(test)]
+#[test]
+fn cached_device() {
+    use crate::{name::Normalization, root::Root, test_utils::TestImage};
+    use core::cell::Cell;
+    use std::{io::Cursor, sync::Mutex};
+
+    /// Device counting its reads.
+    #[derive(Debug)]
+    struct Counting(Mutex<Cursor<Vec<u8>>>, Cell<usize>);
+    impl ReadOffset for Counting {
+        type Err = std::io::Error;
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            self.1.set(self.1.get() + 1);
+            self.0.read_at(offset, buf)
+        }
+    }
+    impl WriteOffset for Counting {
+        fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+            self.0.write_at(offset, buf)
+        }
+    }
+
+    let image = TestImage::sample();
+    let nested = image.cluster_offset(101) as u64;
+    let device = CachedDevice::new(Counting(Mutex::new(image.cursor()), Cell::new(0)), 512, 64);
+    let mut root = Root::open(&device).unwrap();
+
+    // listing a directory again is served from the cache
+    let Some(crate::fs::FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    assert_eq!(dir.open().unwrap()[0].name(), "nested.txt");
+    let reads = device.get_ref().1.get();
+    assert_eq!(dir.open().unwrap()[0].name(), "nested.txt");
+    assert_eq!(device.get_ref().1.get(), reads);
+
+    // writes replace cached blocks
+    let mut entry = [0u8; 32];
+    device.read_exact(nested, &mut entry).unwrap();
+    device.write_all_at(nested, &[0u8; 32]).unwrap();
+    let mut read = [0xFFu8; 32];
+    device.read_exact(nested, &mut read).unwrap();
+    assert_eq!(read, [0u8; 32]);
+
+    // other writers require invalidating the cache
+    device.get_ref().0.write_all_at(nested, &entry).unwrap();
+    device.read_exact(nested, &mut read).unwrap();
+    assert_eq!(read, [0u8; 32]);
+    device.invalidate();
+    device.read_exact(nested, &mut read).unwrap();
+    assert_eq!(read, entry);
+}
+
+#[cfg(test)]
+#[test]
+fn slice_limits_accesses() {
+    use crate::{
+        format::Exfat,
+        root::Root,
+        test_utils::{IMAGE_SIZE, format_options},
+    };
+    use std::{io::Cursor, sync::Mutex, time::SystemTime};
+
+    let size = IMAGE_SIZE;
+    let image = Mutex::new(Cursor::new(vec![
+        0xAAu8;
+        2 * crate::MB as usize + size as usize
+    ]));
+    let mut slice = Slice::new(&image, 2 * crate::MB as u64, size);
+
+    // volumes can be formatted into and opened from a slice
+    Exfat::try_from::<SystemTime>(format_options().build().unwrap())
+        .unwrap()
+        .write::<SystemTime, _>(&mut slice)
+        .unwrap();
+    assert!(Root::open(&slice).is_ok());
+    // ... without touching the rest of the device
+    let mut before = [0u8; 16];
+    image
+        .read_exact(2 * crate::MB as u64 - 16, &mut before)
+        .unwrap();
+    assert_eq!(before, [0xAA; 16]);
+
+    let mut buf = [0u8; 32];
+    assert_eq!(slice.read_at(size - 8, &mut buf).unwrap(), 8);
+    assert_eq!(slice.read_at(size, &mut buf).unwrap(), 0);
+    assert_eq!(slice.write_at(size - 8, &buf).unwrap(), 8);
+    assert!(slice.write_at(size, &buf).is_err());
+    assert!(slice.seek(SeekFrom::Current(-1)).is_ok());
+    assert!(slice.seek(SeekFrom::End(-(size as i64) - 1)).is_err());
+}
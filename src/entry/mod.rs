@@ -3,12 +3,13 @@
 
 use core::mem::transmute;
 
+use bitflags::bitflags;
 use enumeric::range_enum;
 
-use crate::FIRST_USABLE_CLUSTER_INDEX;
 use crate::Label;
 use crate::error::DirEntryError;
 use crate::format::upcase_table::{DEFAULT_UPCASE_TABLE, DEFAULT_UPCASE_TABLE_CHECKSUM};
+use crate::timestamp::Timestamps;
 
 use reader::DirEntryReader;
 
@@ -16,6 +17,7 @@ pub(crate) const VOLUME_GUID_ENTRY_TYPE: u8 = 0xA0;
 
 pub(crate) mod parsed;
 pub(crate) mod reader;
+pub(crate) mod set;
 
 /// A generic exFAT directory entry.
 #[derive(Copy, Clone)]
@@ -98,7 +100,8 @@ impl DirEntry {
         sum = sum.rotate_right(1);
         sum = sum.wrapping_add(bytes[1] as u16);
 
-        let start = if (self.entry_type() & 0b00000100) == 0 {
+        // the set checksum field of primary entries is skipped
+        let start = if self.primary() {
             4 // primary
         } else {
             2 // secondary
@@ -113,6 +116,12 @@ impl DirEntry {
     }
 }
 
+impl core::fmt::Display for DirEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        crate::diagnostics::RawEntry(self.bytes()).fmt(f)
+    }
+}
+
 pub(crate) trait ClusterAllocation {
     fn valid(&self) -> bool;
 }
@@ -128,11 +137,12 @@ pub(crate) struct BitmapEntry {
 }
 
 impl BitmapEntry {
-    pub(crate) fn new(data_len: u64) -> Self {
+    /// Entry of the allocation bitmap of the FAT with the given index.
+    pub(crate) fn new(index: u8, first_cluster: u32, data_len: u64) -> Self {
         Self {
-            flags: 0, // currently, only one FAT and allocation bitmap are supported
+            flags: index & 1,
             _reserved: [0; 18],
-            first_cluster: FIRST_USABLE_CLUSTER_INDEX.to_le(),
+            first_cluster: first_cluster.to_le(),
             data_len: data_len.to_le(),
         }
     }
@@ -186,8 +196,8 @@ pub(crate) struct VolumeLabelEntry {
 impl VolumeLabelEntry {
     pub(crate) fn new(label: Label) -> Self {
         VolumeLabelEntry {
-            character_count: label.1,
-            volume_label: label.0,
+            character_count: label.len,
+            volume_label: label.units,
             _reserved: 0,
         }
     }
@@ -212,34 +222,55 @@ pub(crate) struct FileEntry {
 }
 
 impl FileEntry {
-    pub(crate) fn new() -> Self {
-        unimplemented!("file entry creation");
+    /// Creates a file entry. The set checksum is computed once the entry set is complete.
+    pub(crate) fn new(
+        secondary_count: u8,
+        file_attributes: FileAttributes,
+        timestamps: &Timestamps,
+    ) -> Self {
+        let (created, modified, accessed) = (
+            timestamps.created(),
+            timestamps.modified(),
+            timestamps.accessed(),
+        );
+        FileEntry {
+            secondary_count,
+            set_checksum: 0,
+            file_attributes,
+            _reserved1: 0,
+            create_timestamp: created.raw().to_le(),
+            last_modified_timestamp: modified.raw().to_le(),
+            last_accessed_timestamp: accessed.raw().to_le(),
+            create_10ms_increment: created.raw_increment(),
+            last_modified_10ms_increment: modified.raw_increment(),
+            create_utc_offset: created.raw_utc_offset(),
+            last_modified_utc_offset: modified.raw_utc_offset(),
+            last_accessed_utc_offset: accessed.raw_utc_offset(),
+            _reserved2: [0; 7],
+        }
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
-#[repr(transparent)]
-pub(crate) struct FileAttributes(u16);
-
-impl FileAttributes {
-    pub(crate) fn is_read_only(self) -> bool {
-        (self.0 & 0x0001) != 0
-    }
-
-    pub(crate) fn is_hidden(self) -> bool {
-        (self.0 & 0x0002) != 0
-    }
-
-    pub(crate) fn is_system(self) -> bool {
-        (self.0 & 0x0004) != 0
+bitflags! {
+    /// Attributes of a file or directory, combined with `|`, e.g.
+    /// `FileAttributes::ARCHIVE | FileAttributes::HIDDEN`.
+    #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+    #[repr(transparent)]
+    pub struct FileAttributes: u16 {
+        const READ_ONLY = 1 << 0;
+        const HIDDEN = 1 << 1;
+        const SYSTEM = 1 << 2;
+        /// Set for directories only; it cannot be changed.
+        const DIRECTORY = 1 << 4;
+        /// Set when the file has been modified, e.g. to be picked up by backup tools.
+        const ARCHIVE = 1 << 5;
     }
+}
 
+impl FileAttributes {
+    /// Takes `self` by value, so that it can be called on fields of packed entries.
     pub(crate) fn is_directory(self) -> bool {
-        (self.0 & 0x0010) != 0
-    }
-
-    pub(crate) fn is_archive(self) -> bool {
-        (self.0 & 0x0020) != 0
+        self.contains(FileAttributes::DIRECTORY)
     }
 }
 
@@ -290,14 +321,38 @@ pub(crate) struct StreamExtensionEntry {
 }
 
 impl StreamExtensionEntry {
-    pub(crate) fn new() -> Self {
-        unimplemented!("stream extension entry creation");
+    /// Creates a stream extension entry whose data is fully valid (`ValidDataLength` equals
+    /// `DataLength`).
+    pub(crate) fn new(
+        name_length: u8,
+        name_hash: u16,
+        first_cluster: u32,
+        data_len: u64,
+        no_fat_chain: bool,
+    ) -> Self {
+        let mut flags = GeneralSecondaryFlags::ALLOCATION_POSSIBLE;
+        if no_fat_chain {
+            flags.0 |= GeneralSecondaryFlags::NO_FAT_CHAIN.0;
+        }
+
+        StreamExtensionEntry {
+            general_secondary_flags: flags,
+            _reserved1: 0,
+            name_length,
+            name_hash: name_hash.to_le(),
+            _reserved2: 0,
+            valid_data_length: data_len.to_le(),
+            _reserved3: 0,
+            first_cluster: first_cluster.to_le(),
+            data_len: data_len.to_le(),
+        }
     }
 }
 
 impl ClusterAllocation for StreamExtensionEntry {
     fn valid(&self) -> bool {
-        !(self.first_cluster == 0 && self.data_len != 0 || self.first_cluster < 2)
+        // empty elements have no cluster allocated
+        !(self.first_cluster == 0 && self.data_len != 0 || self.first_cluster == 1)
             && self.general_secondary_flags.allocation_possible()
             && self.name_length > 0
             && self.valid_data_length <= self.data_len
@@ -309,6 +364,9 @@ impl ClusterAllocation for StreamExtensionEntry {
 pub(crate) struct GeneralSecondaryFlags(u8);
 
 impl GeneralSecondaryFlags {
+    pub(crate) const ALLOCATION_POSSIBLE: GeneralSecondaryFlags = GeneralSecondaryFlags(1);
+    pub(crate) const NO_FAT_CHAIN: GeneralSecondaryFlags = GeneralSecondaryFlags(2);
+
     pub(crate) fn allocation_possible(self) -> bool {
         (self.0 & 1) != 0
     }
@@ -326,8 +384,18 @@ pub(crate) struct FileNameEntry {
 }
 
 impl FileNameEntry {
-    pub(crate) fn new() -> Self {
-        unimplemented!("file name entry creation");
+    /// Creates a file name entry holding up to 15 UTF-16 code units of a name.
+    pub(crate) fn new(units: &[u16]) -> Self {
+        assert!(units.len() <= 15);
+        let mut file_name = [0u8; 30];
+        for (i, unit) in units.iter().enumerate() {
+            file_name[2 * i..2 * i + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+
+        FileNameEntry {
+            general_secondary_flags: GeneralSecondaryFlags::default(),
+            file_name,
+        }
     }
 }
 
@@ -341,8 +409,12 @@ pub(crate) struct VendorExtensionEntry {
 }
 
 impl VendorExtensionEntry {
-    pub(crate) fn new() -> Self {
-        unimplemented!("vendor extesnion entry creation");
+    pub(crate) fn new(vendor_guid: u128, vendor_defined: [u8; 14]) -> Self {
+        Self {
+            general_secondary_flag: 0,
+            vendor_guid: vendor_guid.to_le(),
+            vendor_defined,
+        }
     }
 }
 
@@ -361,3 +433,43 @@ impl VendorAllocationEntry {
         unimplemented!("vendor allocaton entry creation");
     }
 }
+
+#[cfg(test)]
+#[test]
+fn entry_checksum() {
+    let entry = |bytes: [u8; 32]| DirEntry::try_from(bytes).unwrap();
+
+    // the set checksum field of a primary entry is skipped
+    let mut file = [0u8; 32];
+    file[0] = 0x85;
+    file[1] = 1;
+    let mut other = file;
+    other[2..4].copy_from_slice(&[0xAB, 0xCD]);
+    assert_eq!(entry(file).checksum(0), entry(other).checksum(0));
+
+    // secondary entries are covered completely
+    let mut stream = [0u8; 32];
+    stream[0] = 0xC0;
+    let mut other = stream;
+    other[2] = 1;
+    assert_ne!(entry(stream).checksum(0), entry(other).checksum(0));
+}
+
+#[cfg(test)]
+#[test]
+fn unallocated_stream_extension() {
+    // empty files and directories have no cluster allocated
+    let stream = StreamExtensionEntry {
+        general_secondary_flags: GeneralSecondaryFlags(1),
+        name_length: 1,
+        ..Default::default()
+    };
+    assert!(stream.valid());
+
+    // but data requires one
+    let stream = StreamExtensionEntry {
+        data_len: 1u64.to_le(),
+        ..stream
+    };
+    assert!(!stream.valid());
+}
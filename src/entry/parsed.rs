@@ -2,11 +2,14 @@ use crate::{
     boot_sector::BootSector,
     disk::ReadOffset,
     error::FileParserError,
+    fs::EntryLocation,
+    name::MAX_NAME_LENGTH,
     timestamp::{Timestamp, Timestamps},
 };
 
 use super::{
     ClusterAllocation, DirEntry, DirEntryReader, FileAttributes, FileEntry, StreamExtensionEntry,
+    VendorExtensionEntry,
 };
 
 #[derive(Clone, Debug)]
@@ -15,6 +18,11 @@ pub(crate) struct ParsedFileEntry {
     pub(crate) attributes: FileAttributes,
     pub(crate) stream_extension_entry: StreamExtensionEntry,
     pub(crate) timestamps: Timestamps,
+    pub(crate) location: EntryLocation,
+    /// Offsets of all entries of the set, starting with the primary entry.
+    pub(crate) entry_offsets: Vec<u64>,
+    /// Vendor extension entries following the file name entries, with their index in the set.
+    pub(crate) vendor_extensions: Vec<(usize, VendorExtensionEntry)>,
 }
 
 impl ParsedFileEntry {
@@ -25,15 +33,19 @@ impl ParsedFileEntry {
     where
         R::Err: core::fmt::Debug,
     {
+        // the primary entry has just been read
+        let location = reader.last_location();
+
         let secondary_count = file_entry.secondary_count;
         if secondary_count < 1 {
-            return Err(FileParserError::NoStreamExtension);
+            return Err(FileParserError::NoStreamExtension(location));
         } else if secondary_count < 2 {
-            return Err(FileParserError::NoFileName);
+            return Err(FileParserError::NoFileName(location));
         }
 
         // parse stream extension entry afterward
         let stream_extension = reader.read()?;
+        let mut entry_offsets = vec![location.offset, reader.last_location().offset];
 
         let stream_extension_entry = if let DirEntry::StreamExtension(stream_extension_entry) =
             stream_extension
@@ -42,97 +54,104 @@ impl ParsedFileEntry {
                 || file_entry.file_attributes.is_directory()
                     && stream_extension_entry.valid_data_length != stream_extension_entry.data_len
             {
-                return Err(FileParserError::InvalidStreamExtension);
+                return Err(FileParserError::InvalidStreamExtension(
+                    reader.last_location(),
+                ));
             }
             stream_extension_entry
         } else {
-            return Err(FileParserError::NoStreamExtension);
+            return Err(FileParserError::NoStreamExtension(reader.last_location()));
         };
 
-        // read file names
-        let name_count = secondary_count - 1;
+        // read file names, which may be followed by benign secondary entries
+        let name_count = stream_extension_entry.name_length.div_ceil(15);
+        if secondary_count - 1 < name_count {
+            return Err(FileParserError::WrongFileNameEntries(location));
+        }
         let mut names = Vec::with_capacity(name_count as usize);
 
         for _ in 0..name_count {
             // parse file name entry
             let file_name = reader.read()?;
+            entry_offsets.push(reader.last_location().offset);
             if let DirEntry::FileName(file_name_entry) = file_name {
-                names.push(file_name_entry);
+                names.push((file_name_entry, reader.last_location()));
             } else {
-                return Err(FileParserError::NoFileName);
+                return Err(FileParserError::NoFileName(reader.last_location()));
             }
         }
-        if names.len() != stream_extension_entry.name_length.div_ceil(15) as usize {
-            return Err(FileParserError::WrongFileNameEntries);
+
+        let mut vendor_extensions = Vec::new();
+        for index in 2 + name_count as usize..=secondary_count as usize {
+            let entry = reader.read()?;
+            entry_offsets.push(reader.last_location().offset);
+            match entry {
+                DirEntry::VendorExtension(entry) => vendor_extensions.push((index, entry)),
+                DirEntry::VendorAllocation(_) => {}
+                DirEntry::FileName(_) => {
+                    return Err(FileParserError::WrongFileNameEntries(location));
+                }
+                // more entries than any valid name needs
+                _ if secondary_count as usize - 1 > MAX_NAME_LENGTH.div_ceil(15) => {
+                    return Err(FileParserError::NameTooLong(secondary_count - 1, location));
+                }
+                _ => return Err(FileParserError::NoFileName(reader.last_location())),
+            }
         }
-        // construct a filename
+        // collect the UTF-16 code units of all file name entries. The name is decoded as a whole,
+        // since a surrogate pair may be split across two entries.
         let mut byte_len = 2 * stream_extension_entry.name_length as usize;
-        let mut name = String::with_capacity(15 * names.len());
+        let mut units = Vec::with_capacity(stream_extension_entry.name_length as usize);
 
-        for entry in names {
+        for (entry, entry_location) in &names {
             if entry.general_secondary_flags.allocation_possible() {
-                return Err(FileParserError::InvalidFileName);
+                return Err(FileParserError::InvalidFileName(*entry_location));
             }
 
             // load name
             let raw_name = &entry.file_name[..30.min(byte_len)];
             if raw_name.len() % 2 != 0 {
-                return Err(FileParserError::InvalidFileName);
+                return Err(FileParserError::InvalidFileName(*entry_location));
             }
 
             byte_len -= raw_name.len();
 
             // convert to native endian
-            let mut file_name = [0u16; 15];
-            let file_name = &mut file_name[..(raw_name.len() / 2)];
-
-            for (i, chunk) in raw_name.chunks_exact(2).enumerate() {
-                file_name[i] = u16::from_le_bytes([chunk[0], chunk[1]]);
-            }
-            match String::from_utf16(file_name) {
-                Ok(part) => name.push_str(&part),
-                Err(_) => return Err(FileParserError::InvalidFileName),
-            }
+            units.extend(
+                raw_name
+                    .chunks_exact(2)
+                    .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]])),
+            );
         }
 
-        // read timestamps
-        let create_utc_offset = if ((file_entry.create_utc_offset >> 7) & 1) == 1 {
-            (file_entry.create_utc_offset & 0x7F) as i8
-        } else {
-            0
-        };
-        let last_modified_utc_offset = if ((file_entry.last_modified_utc_offset >> 7) & 1) == 1 {
-            (file_entry.last_modified_utc_offset & 0x7F) as i8
-        } else {
-            0
-        };
-        let last_accessed_utc_offset = if ((file_entry.last_accessed_utc_offset >> 7) & 1) == 1 {
-            (file_entry.last_accessed_utc_offset & 0x7F) as i8
-        } else {
-            0
+        let Ok(name) = String::from_utf16(&units) else {
+            return Err(FileParserError::InvalidFileName(names[0].1));
         };
 
         Ok(ParsedFileEntry {
             name,
+            location,
+            entry_offsets,
             stream_extension_entry,
             attributes: file_entry.file_attributes,
             timestamps: Timestamps::new(
-                Timestamp::new(
+                Timestamp::from_raw(
                     file_entry.create_timestamp,
                     file_entry.create_10ms_increment,
-                    create_utc_offset,
+                    file_entry.create_utc_offset,
                 ),
-                Timestamp::new(
+                Timestamp::from_raw(
                     file_entry.last_modified_timestamp,
                     file_entry.last_modified_10ms_increment,
-                    last_modified_utc_offset,
+                    file_entry.last_modified_utc_offset,
                 ),
-                Timestamp::new(
+                Timestamp::from_raw(
                     file_entry.last_accessed_timestamp,
                     0,
-                    last_accessed_utc_offset,
+                    file_entry.last_accessed_utc_offset,
                 ),
             ),
+            vendor_extensions,
         })
     }
 }
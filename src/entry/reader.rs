@@ -1,13 +1,18 @@
 use super::DirEntry;
 use crate::{
     boot_sector::BootSector, cluster::reader::ClusterChainReader, disk::ReadOffset,
-    error::EntryReaderError,
+    error::EntryReaderError, fs::EntryLocation,
 };
 
 /// Directory Entry Reader
 pub(crate) struct DirEntryReader<O, B> {
     cluster_reader: ClusterChainReader<O, B>,
     index: usize,
+    last: EntryLocation,
+    /// Raw bytes of the most recently read entry.
+    last_raw: [u8; 32],
+    /// Whether the next read returns the most recently read entry again.
+    repeat: bool,
 }
 
 impl<O, B> From<ClusterChainReader<O, B>> for DirEntryReader<O, B> {
@@ -15,14 +20,34 @@ impl<O, B> From<ClusterChainReader<O, B>> for DirEntryReader<O, B> {
         DirEntryReader {
             cluster_reader: value,
             index: 0,
+            last: EntryLocation::default(),
+            last_raw: [0u8; 32],
+            repeat: false,
         }
     }
 }
 
+impl<O, B> DirEntryReader<O, B> {
+    /// Location of the most recently read entry.
+    pub(crate) fn last_location(&self) -> EntryLocation {
+        self.last
+    }
+}
+
 impl<O: ReadOffset, B: AsRef<BootSector>> DirEntryReader<O, B> {
     pub(crate) fn read(&mut self) -> Result<DirEntry, EntryReaderError<O>> {
-        // Get current cluster and entry index.
-        let cluster = self.cluster_reader.current();
+        if self.repeat {
+            self.repeat = false;
+            return DirEntry::try_from(self.last_raw)
+                .map_err(|err| EntryReaderError::Entry(err, self.last));
+        }
+
+        // Get current cluster and entry index. A directory whose clusters are filled entirely has
+        // no end of directory entry, it ends with its cluster chain instead.
+        let Some(cluster) = self.cluster_reader.current() else {
+            self.last_raw = [0u8; 32];
+            return Ok(DirEntry::EndOfDirectory([0u8; 31]));
+        };
         let index = self.index;
 
         // Read directory entry.
@@ -32,13 +57,50 @@ impl<O: ReadOffset, B: AsRef<BootSector>> DirEntryReader<O, B> {
             return Err(EntryReaderError::ReadFailed(index, cluster, e));
         }
 
+        self.last = EntryLocation {
+            cluster,
+            index: index as u32,
+            offset: self
+                .cluster_reader
+                .boot()
+                .cluster_offset(cluster)
+                .unwrap_or_default()
+                + (index * size_of::<DirEntry>()) as u64,
+        };
+
+        self.last_raw = entry;
+
         // Update entry index
-        if self.cluster_reader.current() != cluster {
+        if self.cluster_reader.current() != Some(cluster) {
             self.index = 0;
         } else {
             self.index += 1;
         }
 
-        DirEntry::try_from(entry).map_err(|err| err.into())
+        DirEntry::try_from(entry).map_err(|err| EntryReaderError::Entry(err, self.last))
+    }
+
+    /// Skips the rest of the damaged entry set whose primary entry is at `primary`: all secondary
+    /// entries following the most recently read one. The entry ending the set, which may be the
+    /// most recently read one itself, is returned again by the next [`DirEntryReader::read`].
+    pub(crate) fn skip_entry_set(
+        &mut self,
+        primary: EntryLocation,
+    ) -> Result<(), EntryReaderError<O>> {
+        // the entry revealing the damage may already be the primary entry of the next set
+        if self.last != primary {
+            self.repeat = true;
+        }
+
+        loop {
+            if let Err(err @ EntryReaderError::ReadFailed(..)) = self.read() {
+                return Err(err);
+            }
+            let entry_type = self.last_raw[0];
+            if entry_type < 0x81 || entry_type & 0x40 == 0 {
+                self.repeat = true;
+                return Ok(());
+            }
+        }
     }
 }
@@ -0,0 +1,80 @@
+use alloc::vec::Vec;
+
+use crate::{name::UpcaseTable, timestamp::Timestamps};
+
+use super::{DirEntry, FileAttributes, FileEntry, FileNameEntry, StreamExtensionEntry};
+
+/// A complete file entry set: file entry, stream extension entry and file name entries.
+#[derive(Clone, Debug)]
+pub(crate) struct EntrySet {
+    pub(crate) file: FileEntry,
+    pub(crate) stream: StreamExtensionEntry,
+    pub(crate) names: Vec<FileNameEntry>,
+}
+
+/// Allocation of the data described by an entry set.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Allocation {
+    pub(crate) first_cluster: u32,
+    pub(crate) data_len: u64,
+    pub(crate) no_fat_chain: bool,
+}
+
+impl EntrySet {
+    /// Creates the entry set for a name, which must already be validated (see
+    /// [`crate::name::validate_name`]).
+    pub(crate) fn new(
+        name: &[u16],
+        attributes: FileAttributes,
+        timestamps: &Timestamps,
+        allocation: Allocation,
+        upcase: &UpcaseTable,
+    ) -> EntrySet {
+        let names: Vec<FileNameEntry> = name.chunks(15).map(FileNameEntry::new).collect();
+        let stream = StreamExtensionEntry::new(
+            name.len() as u8,
+            upcase.name_hash(name),
+            allocation.first_cluster,
+            allocation.data_len,
+            allocation.no_fat_chain,
+        );
+        let file = FileEntry::new(1 + names.len() as u8, attributes, timestamps);
+
+        let mut set = EntrySet {
+            file,
+            stream,
+            names,
+        };
+        set.update_checksum();
+        set
+    }
+
+    /// Recomputes the set checksum stored in the file entry.
+    pub(crate) fn update_checksum(&mut self) {
+        self.file.set_checksum = crate::checksum::entry_set(&self.raw_entries()).to_le();
+    }
+
+    pub(crate) fn entries(&self) -> Vec<DirEntry> {
+        let mut entries = Vec::with_capacity(2 + self.names.len());
+        entries.push(DirEntry::File(self.file));
+        entries.push(DirEntry::StreamExtension(self.stream));
+        entries.extend(self.names.iter().map(|name| DirEntry::FileName(*name)));
+        entries
+    }
+
+    /// Number of entries in the set.
+    pub(crate) fn len(&self) -> usize {
+        2 + self.names.len()
+    }
+
+    pub(crate) fn raw_entries(&self) -> Vec<[u8; 32]> {
+        self.entries().iter().map(DirEntry::bytes).collect()
+    }
+
+    pub(crate) fn bytes(&self) -> Vec<u8> {
+        self.entries()
+            .iter()
+            .flat_map(|entry| entry.bytes())
+            .collect()
+    }
+}
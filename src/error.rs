@@ -1,11 +1,86 @@
+#[cfg(feature = "async")]
+use crate::{async_io::Prefetched, disk::PartitionError};
 use crate::{
-    boot_sector::UnixEpochDuration,
+    diagnostics::RawEntry,
     disk::{ReadOffset, WriteSeek},
+    format::BitmapPackingConstraint,
+    fs::EntryLocation,
+    timestamp::Clock,
 };
-use alloc::sync::Arc;
+use alloc::{string::String, sync::Arc};
+use core::fmt::Display;
+
+/// Step of formatting a volume during which an I/O error occurred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatPhase {
+    /// Determining the length of the device.
+    CheckingDevice,
+    /// Extending a device shorter than the volume.
+    ExtendingDevice,
+    /// Zeroing or discarding the device before writing the file system structures.
+    ClearingDevice,
+    WritingBootRegion,
+    WritingBackupBootRegion,
+    WritingFat,
+    WritingBitmap,
+    WritingUpcaseTable,
+    WritingRootDirectory,
+    /// Writing the entries of a directory other than the root directory.
+    WritingDirectory,
+    /// Writing the contents of the given file.
+    WritingFile(String),
+    /// Reading back the written file system structures.
+    Verifying,
+}
+
+impl Display for FormatPhase {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FormatPhase::CheckingDevice => write!(f, "checking the device length"),
+            FormatPhase::ExtendingDevice => write!(f, "extending the device"),
+            FormatPhase::ClearingDevice => write!(f, "clearing the device"),
+            FormatPhase::WritingBootRegion => write!(f, "writing the main boot region"),
+            FormatPhase::WritingBackupBootRegion => write!(f, "writing the backup boot region"),
+            FormatPhase::WritingFat => write!(f, "writing the FAT"),
+            FormatPhase::WritingBitmap => write!(f, "writing the allocation bitmap"),
+            FormatPhase::WritingUpcaseTable => write!(f, "writing the up-case table"),
+            FormatPhase::WritingRootDirectory => write!(f, "writing the root directory"),
+            FormatPhase::WritingDirectory => write!(f, "writing a directory"),
+            FormatPhase::WritingFile(path) => write!(f, "writing `{path}`"),
+            FormatPhase::Verifying => write!(f, "verifying the written structures"),
+        }
+    }
+}
+
+/// Step of opening a volume during which an I/O error occurred.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MountPhase {
+    OpeningDevice,
+    ReadingBootSector,
+    /// Reading the entry with the given index within the given cluster of the root directory.
+    ParsingRootEntry {
+        cluster: u32,
+        index: u32,
+    },
+    ReadingUpcaseTable,
+}
+
+impl Display for MountPhase {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MountPhase::OpeningDevice => write!(f, "opening the device"),
+            MountPhase::ReadingBootSector => write!(f, "reading the boot sector"),
+            MountPhase::ParsingRootEntry { cluster, index } => write!(
+                f,
+                "parsing root directory entry #{index} on cluster #{cluster}"
+            ),
+            MountPhase::ReadingUpcaseTable => write!(f, "reading the up-case table"),
+        }
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
-pub enum ExfatFormatError<T: UnixEpochDuration> {
+pub enum ExfatFormatError<T: Clock> {
     #[error("Invalid bytes per sector. Must be a power of `2` and between `512` and `4096`: {0}.")]
     InvalidBytesPerSector(u16),
     #[error("Invalid volume size: {0}.")]
@@ -20,35 +95,64 @@ pub enum ExfatFormatError<T: UnixEpochDuration> {
     BoundaryAlignemntTooBig(u32),
     #[error("Unable to generate unique serial number. Error: {0}")]
     NoSerial(#[source] T::Err),
-    #[error("Unable to pack bitmap.")]
-    CannotPackBitmap,
-    #[error("File size does not match exFAT size.")]
-    InvalidFileSize,
+    #[error("Unable to pack bitmap: {0}.")]
+    CannotPackBitmap(BitmapPackingConstraint),
+    #[error("Device length of {len} bytes does not match the exFAT size of {dev_size} bytes.")]
+    InvalidFileSize { len: u64, dev_size: u64 },
+    #[error("Root directory with {0} entries does not fit onto the volume.")]
+    RootDirectoryTooBig(u32),
 }
 
 #[derive(Debug, thiserror::Error)]
-pub enum ExfatError<T: UnixEpochDuration, O: WriteSeek>
+pub enum ExfatError<T: Clock, O: WriteSeek>
 where
     T::Err: core::fmt::Debug,
 {
     #[error("{0}")]
     Format(#[from] ExfatFormatError<T>),
-    #[error("I/O error: {0}.")]
-    Io(#[source] O::Err),
+    #[error("I/O error while {0}: {1}.")]
+    Io(FormatPhase, #[source] O::Err),
 }
 
 #[derive(Debug, thiserror::Error)]
-pub enum RootError<O: ReadOffset> {
+pub enum CompareError<T: Clock, O: ReadOffset>
+where
+    T::Err: core::fmt::Debug,
+{
+    #[error("{0}")]
+    Format(#[from] ExfatFormatError<T>),
+    #[error("Unable to create the reference image: {0}.")]
+    Reference(String),
     #[error("I/O error: {0}.")]
     Io(O::Err),
+    #[error("Cluster chain could not be parsed: {0}.")]
+    ClusterChain(#[from] ClusterChainError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RootError<O: ReadOffset> {
+    #[error("I/O error while {0}: {1}.")]
+    Io(MountPhase, O::Err),
     #[error("The provided volume is not an exFAT filesystem.")]
     WrongFs,
+    #[error("Invalid jump instruction in boot sector: {0:x?}.")]
+    InvalidJumpBoot([u8; 3]),
+    #[error("The `MustBeZero` field of the boot sector is not zero.")]
+    ReservedNotZero,
+    #[error("Invalid boot signature: {0:#x}. Must be `0xAA55`.")]
+    InvalidBootSignature(u16),
+    #[error("Unsupported file system revision: {0}.{1}.")]
+    UnsupportedRevision(u8, u8),
     #[error("Invalid bytes per sector shift detected: {0}. Must be between `9` and `12`")]
     InvalidBytesPerSectorShift(u8),
     #[error("Invalid sectors per cluster shift detected: {0}.")]
     InvalidSectorsPerClusterShift(u8),
     #[error("Invalid number of FATs detected: {0}. Must be either `1` or `2`.")]
     InvalidNumberOfFats(u8),
+    #[error("The volume is marked dirty.")]
+    VolumeDirty,
+    #[error("The checksum of the main boot region does not match its contents.")]
+    BootChecksumMismatch,
     #[error("Fat could not be parsed: {0}.")]
     Fat(#[from] FatLoadError<Arc<O>>),
     #[error(
@@ -60,31 +164,39 @@ pub enum RootError<O: ReadOffset> {
     #[error("Entry Reader Error: {0}.")]
     DirEntry(#[from] EntryReaderError<Arc<O>>),
     #[error(
-        "All directory entries of the root directory must be of type `PRIMARY`. Detected entry type: {0}"
+        "All directory entries of the root directory must be of type `PRIMARY`. Detected entry: {0} at {1}"
     )]
-    RootEntryNotPrimary(u8),
+    RootEntryNotPrimary(RawEntry, EntryLocation),
     #[error("More than 2 allocation bitmap root entry fields detected.")]
     InvalidNumberOfAllocationBitmaps,
-    #[error("Corrupt allocation bitmap entry.")]
-    InvalidAllocationBitmap,
+    #[error("Corrupt allocation bitmap entry at {0}.")]
+    InvalidAllocationBitmap(EntryLocation),
     #[error("More than 1 upcase table root entry field detected.")]
     InvalidNumberOfUpcaseTables,
-    #[error("Corrupt upcase table entry.")]
-    InvalidUpcaseTable,
+    #[error("Corrupt upcase table entry at {0}.")]
+    InvalidUpcaseTable(EntryLocation),
+    #[error("Upcase table checksum mismatch: stored {0:#010x}, computed {1:#010x}.")]
+    UpcaseTableChecksumMismatch(u32, u32),
     #[error("More than 1 volume label root entry field detected.")]
     InvalidNumberOfVolumeLabels,
-    #[error("Corrupt volume label entry.")]
-    InvalidVolumeLabel,
+    #[error("Corrupt volume label entry at {0}.")]
+    InvalidVolumeLabel(EntryLocation),
+    #[error("More than 1 volume GUID root entry field detected.")]
+    InvalidNumberOfVolumeGuids,
+    #[error("Corrupt volume GUID entry at {0}.")]
+    InvalidVolumeGuid(EntryLocation),
     #[error("Unable to parse file entry: {0}")]
     InvalidFileEntry(#[from] FileParserError<Arc<O>>),
-    #[error("Unexpected directory entry in root directory. Detected entry type: {0}")]
-    UnexpectedRootEntry(u8),
+    #[error("Unexpected directory entry in root directory. Detected entry: {0} at {1}")]
+    UnexpectedRootEntry(RawEntry, EntryLocation),
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum FatLoadError<O: ReadOffset> {
     #[error("FAT starts at invalid offset.")]
     InvalidOffset,
+    #[error("The volume has no FAT with index {0}.")]
+    MissingFat(u8),
     #[error("Read failed at: {0:#x}.")]
     ReadFailed(u64, #[source] O::Err),
 }
@@ -95,14 +207,18 @@ pub enum ClusterChainError {
     InvalidFirstCluster,
     #[error("Invalid data length for cluster chain.")]
     InvalidDataLength,
+    #[error("FAT entries of the chain starting at cluster {0} could not be read.")]
+    FatReadFailed(u32),
+    #[error("Zero-length file with first cluster {0}.")]
+    ZeroLengthWithCluster(u32),
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum EntryReaderError<O: ReadOffset> {
     #[error("Cannot read entry #{0} on cluster #{1}.")]
     ReadFailed(usize, u32, #[source] O::Err),
-    #[error("{0}")]
-    Entry(#[from] DirEntryError),
+    #[error("{0} Location: {1}.")]
+    Entry(#[source] DirEntryError, EntryLocation),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -116,18 +232,33 @@ pub enum FileParserError<O: ReadOffset>
 where
     O::Err: core::fmt::Debug,
 {
-    #[error("File entry is missing stream extension.")]
-    NoStreamExtension,
-    #[error("File entry is missing file name.")]
-    NoFileName,
+    #[error("File entry is missing stream extension at {0}.")]
+    NoStreamExtension(EntryLocation),
+    #[error("File entry is missing file name at {0}.")]
+    NoFileName(EntryLocation),
     #[error("{0}")]
     ReadFailed(#[from] EntryReaderError<O>),
-    #[error("Invalid stream extension entry detected.")]
-    InvalidStreamExtension,
-    #[error("Wrong number of file name entries detected.")]
-    WrongFileNameEntries,
-    #[error("Invalid file name entry detected.")]
-    InvalidFileName,
+    #[error("Invalid stream extension entry detected at {0}.")]
+    InvalidStreamExtension(EntryLocation),
+    #[error("Wrong number of file name entries detected at {0}.")]
+    WrongFileNameEntries(EntryLocation),
+    #[error("Invalid file name entry detected at {0}.")]
+    InvalidFileName(EntryLocation),
+    #[error("File name of {0} entries exceeds the maximum of 255 UTF-16 code units at {1}.")]
+    NameTooLong(u8, EntryLocation),
+}
+
+impl<O: ReadOffset> FileParserError<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Whether the entry set itself is damaged, as opposed to the device failing to read it.
+    pub(crate) fn is_damage(&self) -> bool {
+        !matches!(
+            self,
+            FileParserError::ReadFailed(EntryReaderError::ReadFailed(..))
+        )
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -139,10 +270,172 @@ where
     CreateClustersReaderFailed(#[from] ClusterChainError),
     #[error("Cannot read an entry: {0}")]
     ReadEntryFailed(#[from] EntryReaderError<Arc<O>>),
-    #[error("Detected directory entry that is not `PRIMARY`. Detected entry type: {0}")]
-    NotPrimaryEntry(u8),
-    #[error("Detected directory entry that is not a file entry. Detected entry type: {0}")]
-    NotFileEntry(u8),
+    #[error("Detected directory entry that is not `PRIMARY`. Detected entry: {0} at {1}")]
+    NotPrimaryEntry(RawEntry, EntryLocation),
+    #[error("Detected directory entry that is not a file entry. Detected entry: {0} at {1}")]
+    NotFileEntry(RawEntry, EntryLocation),
     #[error("Unable to parse file entry: {0}")]
     InvalidFileEntry(#[from] FileParserError<Arc<O>>),
+    #[error("The device has been modified since the directory was read.")]
+    StaleHandle,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError<I: ReadOffset, O: WriteSeek>
+where
+    I::Err: core::fmt::Debug,
+{
+    #[error("Read failed at: {0:#x}.")]
+    ReadFailed(u64, #[source] I::Err),
+    #[error("I/O error: {0}.")]
+    Io(#[source] O::Err),
+    #[error("Cluster chain could not be parsed: {0}.")]
+    ClusterChain(#[from] ClusterChainError),
+    #[error("Unable to read directory: {0}")]
+    Directory(#[from] DirectoryError<I>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum NameError {
+    #[error("File names must not be empty.")]
+    Empty,
+    #[error("File name is {0} UTF-16 code units long, at most 255 are allowed.")]
+    TooLong(usize),
+    #[error("File names must not contain {0:?}.")]
+    InvalidCharacter(char),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageTreeError {
+    #[error("Invalid name: {0}")]
+    InvalidName(#[from] NameError),
+    #[error("`{0}` already exists.")]
+    AlreadyExists(String),
+    #[error("`{0}` is not a directory.")]
+    NotADirectory(String),
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, thiserror::Error)]
+pub enum CopyInError {
+    #[error("I/O error while reading {0:?}: {1}.")]
+    Io(std::path::PathBuf, #[source] std::io::Error),
+    #[error("{0}")]
+    Tree(#[from] ImageTreeError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageBuilderError<T: Clock, O: WriteSeek, S: ReadOffset>
+where
+    T::Err: core::fmt::Debug,
+{
+    #[error("{0}")]
+    Format(#[from] ExfatError<T, O>),
+    #[error("I/O error while {0}: {1}.")]
+    Io(FormatPhase, #[source] O::Err),
+    #[error("Failed to read the contents of `{0}`: {1:?}.")]
+    ReadFailed(String, S::Err),
+    #[error("The content needs {0} clusters, which do not fit onto the volume.")]
+    NoSpace(u64),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WriteError<O: ReadOffset> {
+    #[error("I/O error: {0}.")]
+    Io(#[source] O::Err),
+    #[error("Cluster chain could not be parsed: {0}.")]
+    ClusterChain(#[from] ClusterChainError),
+    #[error("Unable to read directory: {0}")]
+    Directory(#[from] DirectoryError<O>),
+    #[error("Invalid name: {0}")]
+    InvalidName(#[from] NameError),
+    #[error("Not enough free clusters: {0} requested.")]
+    NoSpace(u32),
+    #[error("Corrupt entry set at {0}.")]
+    CorruptEntrySet(EntryLocation),
+    #[error("`{0}` does not exist.")]
+    NotFound(String),
+    #[error("`{0}` is not a directory.")]
+    NotADirectory(String),
+    #[error("`{0}` is a directory.")]
+    IsADirectory(String),
+    #[error("`{0}` already exists.")]
+    AlreadyExists(String),
+    #[error("`{0}` is not empty.")]
+    DirectoryNotEmpty(String),
+    #[error("`{0}` cannot be moved into itself or its subdirectories.")]
+    MoveIntoItself(String),
+    #[error("Cluster {0} is outside of the cluster heap.")]
+    InvalidCluster(u32),
+    #[error("Cluster {0} is already allocated.")]
+    ClusterInUse(u32),
+    #[error("Unable to reopen the volume: {0}")]
+    Reopen(#[from] RootError<O>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScrubError<O: ReadOffset> {
+    #[error("Metadata read failed at: {0:#x}.")]
+    ReadFailed(u64, #[source] O::Err),
+    #[error("Unable to load the allocation bitmap: {0}")]
+    Bitmap(#[source] WriteError<O>),
+    #[error("Cluster chain could not be parsed: {0}.")]
+    ClusterChain(#[from] ClusterChainError),
+    #[error("Unable to read directory: {0}")]
+    Directory(#[from] DirectoryError<O>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CheckError<O: ReadOffset> {
+    #[error("Unable to open the volume: {0}")]
+    Open(#[from] RootError<O>),
+    #[error("Unable to verify the volume: {0}")]
+    Verify(#[from] ScrubError<O>),
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, thiserror::Error)]
+pub enum ExtractError {
+    #[error("I/O error while extracting: {0}.")]
+    Io(#[from] std::io::Error),
+    /// The bytes of the given range are the first ones not matching their expected hash. Bytes
+    /// missing from the file, or not covered by any hash, count as not matching.
+    #[error("Bytes {}..{} do not match their expected hash.", .0.start, .0.end)]
+    HashMismatch(core::ops::Range<u64>),
+    #[error("The block size of the expected hashes is zero.")]
+    EmptyBlocks,
+    #[error("Unable to read a directory: {0}")]
+    Directory(String),
+    /// The element at the given path has a name that would escape the target directory.
+    #[error("`{0}` cannot be extracted safely.")]
+    UnsafeName(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PartitionTableError<O: ReadOffset> {
+    #[error("I/O error while reading the partition table: {0}.")]
+    Io(O::Err),
+    #[error("The device does not contain an MBR or GPT partition table.")]
+    NoPartitionTable,
+    #[error("The protective MBR is not followed by a GPT header.")]
+    MissingGptHeader,
+    #[error("The GPT header is invalid.")]
+    InvalidGptHeader,
+    #[error("The GPT header or partition entries do not match their checksum.")]
+    GptChecksumMismatch,
+    #[error("Invalid extended boot record at: {0:#x}.")]
+    InvalidExtendedBootRecord(u64),
+    #[error("Partition entry {0} ends before it starts.")]
+    InvalidPartition(usize),
+}
+
+#[cfg(feature = "async")]
+#[derive(Debug, thiserror::Error)]
+pub enum AsyncError<E: PartitionError + 'static> {
+    #[error("I/O error while fetching metadata: {0}.")]
+    Io(E),
+    #[error("Unable to open the volume: {0}")]
+    Open(#[from] RootError<Prefetched<E>>),
+    #[error("Unable to read directory: {0}")]
+    Directory(#[from] DirectoryError<Prefetched<E>>),
 }
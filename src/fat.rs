@@ -1,10 +1,11 @@
 use crate::{
     boot_sector::{BootSector, VolumeFlags},
-    disk::ReadOffset,
+    cache::BlockCache,
+    disk::{ReadOffset, WriteOffset},
     error::FatLoadError,
 };
-use alloc::vec;
-use alloc::vec::Vec;
+use alloc::{collections::BTreeMap, vec::Vec};
+use alloc::{sync::Arc, vec};
 use bytemuck::{AnyBitPattern, NoUninit, checked::cast_slice};
 use checked_num::CheckedU64;
 use endify::Endify;
@@ -31,32 +32,45 @@ impl FatEntry {
 }
 
 #[repr(C)]
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub(crate) struct Fat {
     entries: Vec<FatEntry>,
+    /// Byte offset of the active FAT on the device if its entries are read on demand instead (see
+    /// [`OpenOptions`](crate::root::OpenOptions)).
+    deferred: Option<u64>,
+    /// Sectors of a deferred FAT read so far, if they are cached.
+    cache: Option<BlockCache>,
+    /// Number of entries in the table.
+    len: usize,
+    /// Entries modified since the last [`Fat::flush`], by cluster.
+    dirty: BTreeMap<u32, FatEntry>,
+}
+
+impl Clone for Fat {
+    fn clone(&self) -> Fat {
+        Fat {
+            entries: self.entries.clone(),
+            deferred: self.deferred,
+            // the copy starts out with an empty cache of the same size
+            cache: self.cache.as_ref().map(BlockCache::empty_copy),
+            len: self.len,
+            dirty: self.dirty.clone(),
+        }
+    }
 }
 
 impl Fat {
+    /// Loads the FAT with the given index (`1` for the second FAT of TexFAT volumes).
     pub(crate) fn load<R: ReadOffset>(
         device: &R,
         boot: &BootSector,
+        index: u8,
     ) -> Result<Fat, FatLoadError<R>> {
-        assert!([1, 2].contains(&boot.number_of_fats));
-        let volume_flags = VolumeFlags::from_bits_truncate(boot.volume_flags);
-        let index = if volume_flags.contains(VolumeFlags::ACTIVE_FAT) {
-            1
-        } else {
-            0
-        };
-        assert_eq!(index + 1, boot.number_of_fats);
-
-        let sector_offset =
-            CheckedU64::new(boot.fat_length as u64) * index as u64 + boot.fat_offset as u64;
-        let byte_offset =
-            (sector_offset * boot.bytes_per_sector() as u64).ok_or(FatLoadError::InvalidOffset)?;
+        let byte_offset = Self::offset(boot, index)?;
 
         // load FAT entries from disk
-        let mut entries = vec![0u8; boot.cluster_count as usize * 4];
+        // the first two entries are reserved, so the heap is described by `cluster_count + 2`
+        let mut entries = vec![0u8; (boot.cluster_count as usize + 2) * 4];
 
         device
             .read_exact(byte_offset, &mut entries)
@@ -67,17 +81,241 @@ impl Fat {
             .map(|c| FatEntry(u32::from_le_bytes(c.try_into().unwrap())))
             .collect::<Vec<FatEntry>>();
 
-        Ok(Self { entries })
+        let len = entries.len();
+        Ok(Self {
+            entries,
+            deferred: None,
+            cache: None,
+            len,
+            dirty: BTreeMap::new(),
+        })
+    }
+
+    /// A FAT whose entries are read from the device as chains are followed, see
+    /// [`Fat::read_entry`]. Up to `cache_sectors` sectors of the FAT are kept in memory; without
+    /// a cache, every entry is read on its own.
+    pub(crate) fn deferred<R: ReadOffset>(
+        boot: &BootSector,
+        index: u8,
+        cache_sectors: usize,
+    ) -> Result<Fat, FatLoadError<R>> {
+        let cache = (cache_sectors > 0)
+            .then(|| BlockCache::new(boot.bytes_per_sector() as u64, cache_sectors));
+        Ok(Self {
+            entries: Vec::new(),
+            deferred: Some(Self::offset(boot, index)?),
+            cache,
+            len: boot.cluster_count as usize + 2,
+            dirty: BTreeMap::new(),
+        })
+    }
+
+    /// Index of the active FAT: the second FAT if `ActiveFat` is set on a volume with two FATs,
+    /// the first one otherwise.
+    pub(crate) fn active_index(boot: &BootSector) -> u8 {
+        let volume_flags = VolumeFlags::from_bits_truncate(boot.volume_flags);
+        (volume_flags.contains(VolumeFlags::ACTIVE_FAT) && boot.number_of_fats == 2) as u8
+    }
+
+    /// Byte offset of the FAT with the given index.
+    fn offset<R: ReadOffset>(boot: &BootSector, index: u8) -> Result<u64, FatLoadError<R>> {
+        if index >= boot.number_of_fats {
+            return Err(FatLoadError::MissingFat(index));
+        }
+        let sector_offset =
+            CheckedU64::new(boot.fat_length as u64) * index as u64 + boot.fat_offset as u64;
+        (sector_offset * boot.bytes_per_sector() as u64).ok_or(FatLoadError::InvalidOffset)
+    }
+}
+
+impl Fat {
+    /// Entry of the given cluster, if it is part of the loaded table. Always `None` for deferred
+    /// FATs, whose entries are only available through [`Fat::read_entry`].
+    fn entry(&self, cluster: u32) -> Option<FatEntry> {
+        self.entries.get(cluster as usize).copied()
+    }
+
+    /// Entries of both loaded FATs which differ, by cluster. Deferred FATs have no entries in
+    /// memory and must be loaded with [`Fat::load`] first.
+    pub(crate) fn differences<'a>(
+        &'a self,
+        other: &'a Fat,
+    ) -> impl Iterator<Item = (u32, FatEntry, FatEntry)> + 'a {
+        debug_assert!(!self.is_deferred() && !other.is_deferred());
+        (0..self.entries.len().min(other.entries.len()) as u32).filter_map(|cluster| {
+            let (a, b) = (self.entry(cluster)?, other.entry(cluster)?);
+            (a != b).then_some((cluster, a, b))
+        })
+    }
+
+    /// Whether the entries are read on demand, see [`Fat::deferred`].
+    pub(crate) fn is_deferred(&self) -> bool {
+        self.deferred.is_some()
+    }
+
+    /// Entry of the given cluster like [`Fat::entry`], reading it from the device if the FAT is
+    /// deferred.
+    pub(crate) fn read_entry<R: ReadOffset>(
+        &self,
+        device: &R,
+        cluster: u32,
+    ) -> Result<Option<FatEntry>, R::Err> {
+        let Some(offset) = self.deferred else {
+            return Ok(self.entry(cluster));
+        };
+        if cluster as usize >= self.len {
+            return Ok(None);
+        }
+        if let Some(entry) = self.dirty.get(&cluster) {
+            return Ok(Some(*entry));
+        }
+
+        let offset = offset + cluster as u64 * 4;
+        // handles sharing the FAT read the device directly while another one uses the cache
+        let mut entry = [0u8; 4];
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.read(device, offset, &mut entry))
+            .transpose()?;
+        if cached != Some(entry.len()) {
+            device.read_exact(offset, &mut entry)?;
+        }
+        Ok(Some(FatEntry(u32::from_le_bytes(entry))))
+    }
+
+    /// Clusters of the chain starting at `first`, following the same rules as [`ClusterChain`].
+    /// Deferred FATs are read from the device, stopping after as many clusters as there are in
+    /// the heap.
+    pub(crate) fn read_chain<R: ReadOffset>(
+        &self,
+        device: &R,
+        first: u32,
+    ) -> Result<Vec<u32>, R::Err> {
+        self.read_chain_prefix(device, first, usize::MAX)
+    }
+
+    /// Up to `max` clusters of the chain starting at `first`, see [`Fat::read_chain`].
+    pub(crate) fn read_chain_prefix<R: ReadOffset>(
+        &self,
+        device: &R,
+        first: u32,
+        max: usize,
+    ) -> Result<Vec<u32>, R::Err> {
+        if !self.is_deferred() {
+            return Ok(ClusterChain::new(self, first).take(max).collect());
+        }
+
+        let mut chain = Vec::new();
+        let mut next = first;
+        while next >= 2 && chain.len() < max.min(self.len - 2) {
+            let Some(entry) = self.read_entry(device, next)? else {
+                break;
+            };
+            if entry == FatEntry::bad() {
+                break;
+            }
+            chain.push(next);
+            next = entry.0;
+        }
+        Ok(chain)
+    }
+
+    /// Sets an entry in every FAT on `disk` and in `fat`.
+    pub(crate) fn write_entry<O: WriteOffset>(
+        fat: &mut Arc<Fat>,
+        disk: &O,
+        boot: &BootSector,
+        cluster: u32,
+        entry: FatEntry,
+    ) -> Result<(), O::Err> {
+        let fat = Arc::make_mut(fat);
+        fat.set_entry(cluster, entry);
+        fat.flush(disk, boot)
+    }
+
+    /// Updates a single entry in memory, to be written by the next [`Fat::flush`]. Entries
+    /// outside of the table are ignored.
+    pub(crate) fn set_entry(&mut self, cluster: u32, entry: FatEntry) {
+        if cluster as usize >= self.len {
+            return;
+        }
+        self.set(cluster, entry);
+        self.dirty.insert(cluster, entry);
+    }
+
+    /// Links `clusters` into a chain terminated by an end of chain marker, continuing the chain
+    /// ending at `last` if given.
+    pub(crate) fn append_chain(&mut self, last: Option<u32>, clusters: &[u32]) {
+        let Some(end) = clusters.last() else {
+            return;
+        };
+        for pair in last.iter().chain(clusters).collect::<Vec<_>>().windows(2) {
+            self.set_entry(*pair[0], FatEntry(*pair[1]));
+        }
+        self.set_entry(*end, FatEntry::eof());
+    }
+
+    /// Marks up to `max` clusters of the chain starting at `first` as free (see
+    /// [`Fat::read_chain`]) and returns them.
+    pub(crate) fn free_chain<R: ReadOffset>(
+        &mut self,
+        device: &R,
+        first: u32,
+        max: usize,
+    ) -> Result<Vec<u32>, R::Err> {
+        let chain = self.read_chain_prefix(device, first, max)?;
+        for cluster in &chain {
+            self.set_entry(*cluster, FatEntry(0));
+        }
+        Ok(chain)
+    }
+
+    /// Writes all modified entries into every FAT on `disk`, so that both FATs of TexFAT
+    /// volumes stay identical. Runs of consecutive entries are written at once.
+    pub(crate) fn flush<O: WriteOffset>(
+        &mut self,
+        disk: &O,
+        boot: &BootSector,
+    ) -> Result<(), O::Err> {
+        let fat_bytes = boot.fat_length as u64 * boot.bytes_per_sector() as u64;
+        let fat_offset = boot.fat_offset as u64 * boot.bytes_per_sector() as u64;
+
+        let mut dirty = self.dirty.iter().peekable();
+        while let Some((first, entry)) = dirty.next() {
+            let mut bytes = Vec::from(entry.0.to_le_bytes());
+            let mut next = first + 1;
+            while let Some((_, entry)) = dirty.next_if(|(cluster, _)| **cluster == next) {
+                bytes.extend_from_slice(&entry.0.to_le_bytes());
+                next += 1;
+            }
+
+            let offset = fat_offset + *first as u64 * size_of::<FatEntry>() as u64;
+            for index in 0..boot.number_of_fats as u64 {
+                disk.write_all_at(offset + index * fat_bytes, &bytes)?;
+            }
+        }
+        self.dirty.clear();
+        Ok(())
+    }
+
+    /// Updates a single entry. Entries outside of the table are ignored.
+    pub(crate) fn set(&mut self, cluster: u32, entry: FatEntry) {
+        if let Some(e) = self.entries.get_mut(cluster as usize) {
+            *e = entry;
+        }
     }
 }
 
-pub(crate) struct ClusterChain<'fat> {
+/// Iterator over a chain of a loaded FAT. Chains of deferred FATs are read with
+/// [`Fat::read_chain`] instead.
+struct ClusterChain<'fat> {
     entries: &'fat [u32],
     next: u32,
 }
 
 impl<'fat> ClusterChain<'fat> {
-    pub(crate) fn new(table: &'fat Fat, first: u32) -> ClusterChain<'fat> {
+    fn new(table: &'fat Fat, first: u32) -> ClusterChain<'fat> {
         Self {
             entries: cast_slice(&table.entries),
             next: first,
@@ -103,3 +341,102 @@ impl Iterator for ClusterChain<'_> {
         Some(next as u32)
     }
 }
+
+#[cfg(test)]
+#[test]
+fn write_back() {
+    use crate::test_utils::TestImage;
+    use std::{io::Cursor, sync::Mutex};
+
+    let image = TestImage::new();
+    let mut boot = image.boot;
+    let fat_offset = boot.fat_offset as usize * boot.bytes_per_sector() as usize;
+    let fat_bytes = boot.fat_length as usize * boot.bytes_per_sector() as usize;
+    let disk = Mutex::new(image.cursor());
+    let entry_at = |disk: &Mutex<Cursor<Vec<u8>>>, offset: usize, cluster: usize| {
+        let image = disk.lock().unwrap();
+        let at = offset + cluster * 4;
+        u32::from_le_bytes(image.get_ref()[at..at + 4].try_into().unwrap())
+    };
+
+    let mut fat = Fat::load(&disk, &boot, 0).unwrap();
+    fat.append_chain(None, &[20, 21, 30]);
+    fat.append_chain(Some(30), &[31]);
+    // nothing is written before flushing
+    assert_eq!(entry_at(&disk, fat_offset, 20), 0);
+    assert_eq!(
+        ClusterChain::new(&fat, 20).collect::<Vec<_>>(),
+        [20, 21, 30, 31]
+    );
+
+    // both FATs of TexFAT volumes are written
+    boot.number_of_fats = 2;
+    fat.flush(&disk, &boot).unwrap();
+    for offset in [fat_offset, fat_offset + fat_bytes] {
+        assert_eq!(entry_at(&disk, offset, 21), 30);
+        assert_eq!(entry_at(&disk, offset, 31), FatEntry::eof().0);
+    }
+
+    // deferred FATs see their modified entries before they are flushed
+    boot.number_of_fats = 1;
+    let mut deferred = Fat::deferred::<Mutex<Cursor<Vec<u8>>>>(&boot, 0, 0).unwrap();
+    assert_eq!(deferred.free_chain(&disk, 20, 2).unwrap(), [20, 21]);
+    assert_eq!(deferred.read_chain(&disk, 30).unwrap(), [30, 31]);
+    assert_eq!(deferred.read_chain_prefix(&disk, 30, 1).unwrap(), [30]);
+    assert_eq!(deferred.read_entry(&disk, 21).unwrap(), Some(FatEntry(0)));
+    deferred.flush(&disk, &boot).unwrap();
+    assert_eq!(entry_at(&disk, fat_offset, 20), 0);
+    assert_eq!(entry_at(&disk, fat_offset, 30), 31);
+}
+
+#[cfg(test)]
+#[test]
+fn deferred_sector_cache() {
+    use crate::test_utils::TestImage;
+    use core::cell::Cell;
+    use std::io::Cursor;
+
+    /// Device counting its reads.
+    #[derive(Debug)]
+    struct Counting(Cursor<Vec<u8>>, Cell<usize>);
+    impl ReadOffset for Counting {
+        type Err = std::io::Error;
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            self.1.set(self.1.get() + 1);
+            self.0.read_at(offset, buf)
+        }
+    }
+
+    let mut image = TestImage::new();
+    let boot = image.boot;
+    let fat_offset = boot.fat_offset as usize * 512;
+    for cluster in [10u32, 11, 200, 300] {
+        let at = fat_offset + cluster as usize * 4;
+        image.image[at..at + 4].copy_from_slice(&(cluster + 1).to_le_bytes());
+    }
+    let device = Counting(image.cursor(), Cell::new(0));
+
+    // two sectors of 128 entries each are cached
+    let fat = Fat::deferred::<Counting>(&boot, 0, 2).unwrap();
+    let reads = |cluster: u32, expected: usize| {
+        assert_eq!(
+            fat.read_entry(&device, cluster).unwrap(),
+            Some(FatEntry(cluster + 1))
+        );
+        assert_eq!(device.1.get(), expected, "reads after cluster {cluster}");
+    };
+    reads(10, 1);
+    reads(11, 1);
+    reads(200, 2);
+    reads(10, 2);
+    // replaces the sector of cluster 200, which was used least recently
+    reads(300, 3);
+    reads(10, 3);
+    reads(200, 4);
+
+    // copies start out with an empty cache
+    let copy = fat.clone();
+    assert_eq!(copy.read_entry(&device, 10).unwrap(), Some(FatEntry(11)));
+    assert_eq!(device.1.get(), 5);
+}
@@ -1,8 +1,7 @@
 use bytemuck::{bytes_of, cast_slice};
 
 use crate::{
-    MB,
-    boot_sector::BootSector,
+    boot_sector::{BootSector, Checksum},
     disk::{self, SeekFrom, WriteSeek},
 };
 
@@ -14,10 +13,7 @@ use alloc::vec::Vec;
 pub(super) const MAIN_BOOT_OFFSET: u64 = 0;
 /// Offset to backup boot region (in sectors)
 pub(super) const BACKUP_BOOT_OFFSET: u64 = 12;
-/// Maximum amount of clusters
-pub(super) const MAX_CLUSTER_COUNT: u32 = 0xFFFFFFF5;
-/// Maximux size of clusters
-pub(super) const MAX_CLUSTER_SIZE: u32 = 32 * MB;
+pub(super) use crate::limits::{MAX_CLUSTER_COUNT, MAX_CLUSTER_SIZE};
 pub(super) const DRIVE_SELECT: u8 = 0x80;
 /// Signature of regular boot sector
 pub(super) const BOOT_SIGNATURE: u16 = 0xAA55;
@@ -48,7 +44,7 @@ impl BootSector {
             volume_flags: meta.volume_flags.bits().to_le(),
             file_system_revision: meta.file_system_revision,
             drive_select: DRIVE_SELECT,
-            percent_in_use: 0xFF, // not currently supported
+            percent_in_use: meta.percent_in_use(),
             _reserved2: [0; 7],
             boot_code: [0xF4; 390],
             boot_signature: BOOT_SIGNATURE,
@@ -56,59 +52,6 @@ impl BootSector {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub(super) struct Checksum {
-    inner: u32,
-    sector_size_in_bytes: u16,
-}
-
-impl Checksum {
-    pub(super) fn new(sector_size_in_bytes: u16) -> Checksum {
-        Self {
-            inner: 0,
-            sector_size_in_bytes,
-        }
-    }
-}
-
-impl Checksum {
-    /// Updates the checksum according to one entirely empty sector.
-    pub(super) fn zero_sector(&mut self) {
-        for _ in 0..self.sector_size_in_bytes {
-            self.inner = (self.inner & 1) * 0x80000000 + (self.inner >> 1);
-        }
-    }
-
-    /// Updates the checksum according to a boot sector.
-    pub(super) fn boot_sector(&mut self, sector: &[u8]) {
-        assert_eq!(sector.len(), self.sector_size_in_bytes as usize);
-        for i in 0..self.sector_size_in_bytes {
-            if i == 106 || i == 107 || i == 112 {
-                continue;
-            }
-
-            self.inner =
-                (self.inner & 1) * 0x80000000 + (self.inner >> 1) + sector[i as usize] as u32;
-        }
-    }
-
-    /// Updates the checksum according to a set of extended boot sectors.
-    pub(super) fn extended_boot_sector(&mut self, sector: &[u8], amount: u64) {
-        assert_eq!(sector.len(), self.sector_size_in_bytes as usize);
-        for _ in 0..amount {
-            for i in 0..self.sector_size_in_bytes {
-                self.inner =
-                    (self.inner & 1) * 0x80000000 + (self.inner >> 1) + sector[i as usize] as u32;
-            }
-        }
-    }
-
-    /// Returns a copy of the current state of the checksum in little-endian format.
-    pub(super) fn get(&self) -> u32 {
-        self.inner.to_le()
-    }
-}
-
 impl Exfat {
     /// Attempts to write a boot region to a disk at the specified sector offet.
     pub(super) fn write_boot_region<T: WriteSeek>(
@@ -131,14 +74,22 @@ impl Exfat {
         checksum.extended_boot_sector(cast_slice(&bytes), EXTENDED_BOOT);
         offset_sectors += EXTENDED_BOOT;
 
-        // write oem sector (unused so entirely empty)
-        // todo: add flash/custom parameter support
-        disk::write_zeroes(
-            f,
-            self.format_options.bytes_per_sector as u64,
-            self.offset_sector_bytes(offset_sectors),
-        )?;
-        checksum.zero_sector();
+        // write oem sector, which is empty without flash parameters
+        match self.format_options.flash_parameters {
+            Some(parameters) => {
+                let bytes = parameters.oem_sector(self.format_options.bytes_per_sector);
+                self.write_sector(f, &bytes, offset_sectors)?;
+                checksum.sector(&bytes);
+            }
+            None => {
+                disk::write_zeroes(
+                    f,
+                    self.format_options.bytes_per_sector as u64,
+                    self.offset_sector_bytes(offset_sectors),
+                )?;
+                checksum.zero_sector();
+            }
+        }
         offset_sectors += 1;
 
         // write reserved sector
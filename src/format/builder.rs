@@ -0,0 +1,887 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    FIRST_USABLE_CLUSTER_INDEX,
+    disk::{PartitionError, ReadOffset, SeekFrom, WriteSeek},
+    entry::{
+        DirEntry, FileAttributes,
+        set::{Allocation, EntrySet},
+    },
+    error::{
+        ExfatError, ExfatFormatError, FormatPhase, ImageBuilderError, ImageTreeError, NameError,
+    },
+    name::{MAX_NAME_LENGTH, UpcaseTable, is_invalid_name_character, validate_name},
+    root::RawRoot,
+    timestamp::{Clock, Timestamp, Timestamps},
+};
+
+#[cfg(feature = "std")]
+use crate::error::CopyInError;
+
+use super::{Exfat, FormatVolumeOptions};
+
+/// Formats a volume and populates it with an initial set of files and directories.
+///
+/// All content is laid out contiguously right after the root directory, in the order it was
+/// added. Every file and directory occupies a single run of clusters (`NoFatChain`), so the
+/// resulting image is free of fragmentation.
+///
+/// The contents of a file are read from any [`ReadOffset`] source once the image is written.
+pub struct ImageBuilder<S: ReadOffset> {
+    format_options: FormatVolumeOptions,
+    upcase_table: UpcaseTable,
+    items: Vec<Node<S>>,
+    name_policy: NamePolicy,
+    mappings: Vec<NameMapping>,
+}
+
+/// How an [`ImageBuilder`] handles names that are invalid under exFAT, or that collide with an
+/// existing name since names are compared case-insensitively. Adding a directory that already
+/// exists is never a collision.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NamePolicy {
+    /// Fail with an [`ImageTreeError`].
+    #[default]
+    Error,
+    /// Leave the item (and everything added below it) out of the image.
+    Skip,
+    /// Replace invalid characters with `_`, shorten overlong names and append ` (1)`, ` (2)`, ...
+    /// in front of the extension until the name is unique.
+    Rename,
+}
+
+/// A path stored differently than it was added, because of the [`NamePolicy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NameMapping {
+    /// Path as added, without leading or trailing `/`.
+    pub original: String,
+    /// Path within the image, or `None` if the item was skipped.
+    pub stored: Option<String>,
+}
+
+/// Source of a file added to an [`ImageBuilder`], held in memory or read from the host file
+/// system.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum FileSource {
+    Bytes(Vec<u8>),
+    File(std::fs::File),
+}
+
+#[cfg(feature = "std")]
+impl ReadOffset for FileSource {
+    type Err = std::io::Error;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        match self {
+            FileSource::Bytes(bytes) => {
+                let start = offset.min(bytes.len() as u64) as usize;
+                let amount = buf.len().min(bytes.len() - start);
+                buf[..amount].copy_from_slice(&bytes[start..start + amount]);
+                Ok(amount)
+            }
+            FileSource::File(file) => ReadOffset::read_at(file, offset, buf),
+        }
+    }
+}
+
+/// Where an item is placed within its parent directory.
+enum Placement {
+    /// The existing directory at the given index.
+    Existing(usize),
+    New {
+        name: String,
+        units: Vec<u16>,
+    },
+    Skipped,
+}
+
+struct Node<S> {
+    name: String,
+    units: Vec<u16>,
+    kind: NodeKind<S>,
+}
+
+enum NodeKind<S> {
+    File { source: S, len: u64 },
+    Directory(Vec<Node<S>>),
+}
+
+impl<S: ReadOffset> ImageBuilder<S> {
+    pub fn new(format_options: FormatVolumeOptions) -> ImageBuilder<S> {
+        ImageBuilder {
+            format_options,
+            upcase_table: UpcaseTable::default(),
+            items: Vec::default(),
+            name_policy: NamePolicy::default(),
+            mappings: Vec::default(),
+        }
+    }
+
+    /// Sets how invalid and colliding names of subsequently added items are handled. Defaults to
+    /// [`NamePolicy::Error`].
+    pub fn name_policy(&mut self, policy: NamePolicy) -> &mut Self {
+        self.name_policy = policy;
+        self
+    }
+
+    /// All added paths that were renamed or skipped, in the order they were added.
+    pub fn name_mappings(&self) -> &[NameMapping] {
+        &self.mappings
+    }
+
+    /// Adds a directory. Paths are separated by `/` and missing parent directories are created.
+    /// Adding an already existing directory has no effect.
+    pub fn add_dir(&mut self, path: &str) -> Result<&mut Self, ImageTreeError> {
+        self.insert(path, NodeKind::Directory(Vec::default()))?;
+        Ok(self)
+    }
+
+    /// Adds a file whose `len` bytes of content are read from `source`, starting at offset `0`.
+    /// Missing parent directories are created.
+    pub fn add_file(
+        &mut self,
+        path: &str,
+        source: S,
+        len: u64,
+    ) -> Result<&mut Self, ImageTreeError> {
+        self.insert(path, NodeKind::File { source, len })?;
+        Ok(self)
+    }
+
+    fn insert(&mut self, path: &str, kind: NodeKind<S>) -> Result<(), ImageTreeError> {
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let Some((name, parents)) = components.split_last() else {
+            return Err(ImageTreeError::InvalidName(NameError::Empty));
+        };
+        let original = components.join("/");
+        let policy = self.name_policy;
+        let upcase_table = &self.upcase_table;
+        let mappings = &mut self.mappings;
+
+        let mut stored: Vec<String> = Vec::default();
+        let mut items = &mut self.items;
+        for depth in 0..parents.len() {
+            let prefix = components[..=depth].join("/");
+
+            // directories renamed or skipped before are resolved the same way again
+            let placement = match mappings.iter().find(|mapping| mapping.original == prefix) {
+                Some(NameMapping {
+                    stored: Some(path), ..
+                }) => {
+                    let name = path.rsplit('/').next().unwrap_or_default();
+                    let units: Vec<u16> = name.encode_utf16().collect();
+                    match position(items, &units, upcase_table) {
+                        Some(index) => Placement::Existing(index),
+                        None => Placement::New {
+                            name: name.to_string(),
+                            units,
+                        },
+                    }
+                }
+                Some(NameMapping { stored: None, .. }) => Placement::Skipped,
+                None => place(items, parents[depth], true, policy, upcase_table, || {
+                    ImageTreeError::NotADirectory(prefix.clone())
+                })?,
+            };
+
+            let index = match placement {
+                Placement::Existing(index) => index,
+                Placement::New { name, units } => {
+                    if name != parents[depth] {
+                        let path = stored.iter().chain([&name]).map(String::as_str);
+                        mappings.push(NameMapping {
+                            original: prefix,
+                            stored: Some(path.collect::<Vec<_>>().join("/")),
+                        });
+                    }
+                    items.push(Node {
+                        name,
+                        units,
+                        kind: NodeKind::Directory(Vec::default()),
+                    });
+                    items.len() - 1
+                }
+                Placement::Skipped => {
+                    if !mappings.iter().any(|mapping| mapping.original == prefix) {
+                        mappings.push(NameMapping {
+                            original: prefix,
+                            stored: None,
+                        });
+                    }
+                    mappings.push(NameMapping {
+                        original,
+                        stored: None,
+                    });
+                    return Ok(());
+                }
+            };
+
+            stored.push(items[index].name.clone());
+            items = match &mut items[index].kind {
+                NodeKind::Directory(children) => children,
+                NodeKind::File { .. } => {
+                    return Err(ImageTreeError::NotADirectory(
+                        components[..=depth].join("/"),
+                    ));
+                }
+            };
+        }
+
+        let directory = matches!(kind, NodeKind::Directory(_));
+        let placement = place(items, name, directory, policy, upcase_table, || {
+            ImageTreeError::AlreadyExists(original.clone())
+        })?;
+        match placement {
+            Placement::Existing(_) => {}
+            Placement::New { name, units } => {
+                stored.push(name.clone());
+                let stored = stored.join("/");
+                if stored != original {
+                    mappings.push(NameMapping {
+                        original,
+                        stored: Some(stored),
+                    });
+                }
+                items.push(Node { name, units, kind });
+            }
+            Placement::Skipped => mappings.push(NameMapping {
+                original,
+                stored: None,
+            }),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ImageBuilder<FileSource> {
+    /// Adds a file holding `bytes`. Missing parent directories are created.
+    pub fn add_bytes(
+        &mut self,
+        path: &str,
+        bytes: impl Into<Vec<u8>>,
+    ) -> Result<&mut Self, ImageTreeError> {
+        let bytes = bytes.into();
+        let len = bytes.len() as u64;
+        self.add_file(path, FileSource::Bytes(bytes), len)
+    }
+
+    /// Copies a file or a whole directory tree of the host file system to `path`, which is `""`
+    /// to copy the contents of a directory into the root directory. Entries are added in the
+    /// order of their names and symbolic links are followed. Files are only read once the image
+    /// is written.
+    pub fn copy_in(
+        &mut self,
+        path: &str,
+        host: impl AsRef<std::path::Path>,
+    ) -> Result<&mut Self, CopyInError> {
+        let host = host.as_ref();
+        let io = |err| CopyInError::Io(host.to_path_buf(), err);
+        let metadata = std::fs::metadata(host).map_err(io)?;
+        if !metadata.is_dir() {
+            let file = std::fs::File::open(host).map_err(io)?;
+            self.add_file(path, FileSource::File(file), metadata.len())?;
+            return Ok(self);
+        }
+
+        if !path.split('/').all(str::is_empty) {
+            self.add_dir(path)?;
+        }
+        let mut entries = std::fs::read_dir(host)
+            .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+            .map_err(io)?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name();
+            let child = format!("{path}/{}", name.to_string_lossy());
+            self.copy_in(&child, entry.path())?;
+        }
+        Ok(self)
+    }
+}
+
+impl<S: ReadOffset> ImageBuilder<S> {
+    /// Formats the device and writes all added content onto it. The file length must be the same
+    /// as the provided `dev_size` in the [`FormatVolumeOptions`], unless shorter devices are
+    /// extended.
+    pub fn write<T: Clock, O: WriteSeek>(
+        mut self,
+        f: &mut O,
+    ) -> Result<(), ImageBuilderError<T, O, S>>
+    where
+        T::Err: core::fmt::Debug,
+    {
+        // the root directory is sized to hold all of its entry sets
+        let root_entries = RawRoot::METADATA_ENTRIES + entry_count(&self.items);
+        self.format_options.root_entries = self.format_options.root_entries.max(root_entries);
+
+        let mut exfat = Exfat::try_from::<T>(self.format_options).map_err(ExfatError::Format)?;
+        let timestamp = Timestamp::from_unix_secs(
+            self.format_options
+                .now::<T>()
+                .map_err(|err| ExfatError::Format(ExfatFormatError::NoSerial(err)))?,
+        );
+
+        let first_data_cluster = exfat.first_cluster_of_root_directory
+            + exfat.root_length_bytes.div_ceil(exfat.bytes_per_cluster);
+        let mut layout = Layout {
+            bytes_per_cluster: exfat.bytes_per_cluster as u64,
+            next_cluster: first_data_cluster as u64,
+            timestamps: Timestamps::new(timestamp, timestamp, timestamp),
+            upcase_table: &self.upcase_table,
+            extents: Vec::default(),
+        };
+        let root = layout.allocate(&self.items, "");
+
+        let data_clusters = layout.next_cluster - first_data_cluster as u64;
+        if layout.next_cluster > FIRST_USABLE_CLUSTER_INDEX as u64 + exfat.cluster_count as u64 {
+            return Err(ImageBuilderError::NoSpace(data_clusters));
+        }
+        exfat.data_clusters = data_clusters as u32;
+
+        exfat.write(f)?;
+
+        // entry sets of the root directory follow the volume metadata entries
+        let root_items_offset = exfat.root_offset_bytes as u64
+            + exfat.raw_root().metadata_entries() as u64 * size_of::<DirEntry>() as u64;
+        let writing_root = |err| ImageBuilderError::Io(FormatPhase::WritingRootDirectory, err);
+        f.seek(SeekFrom::Start(root_items_offset))
+            .map_err(writing_root)?;
+        f.write_all(&root).map_err(writing_root)?;
+
+        for extent in layout.extents {
+            exfat.write_extent(f, extent)?;
+        }
+        Ok(())
+    }
+}
+
+/// Position of the item with the given name, ignoring case.
+fn position<S>(items: &[Node<S>], units: &[u16], upcase_table: &UpcaseTable) -> Option<usize> {
+    let name = upcase_table.upcase_name(units);
+    items
+        .iter()
+        .position(|item| upcase_table.upcase_name(&item.units) == name)
+}
+
+/// Places an item named `name` into `items` according to `policy`. `collision` is the error
+/// reported if the name is taken and the policy is [`NamePolicy::Error`].
+fn place<S>(
+    items: &[Node<S>],
+    name: &str,
+    directory: bool,
+    policy: NamePolicy,
+    upcase_table: &UpcaseTable,
+    collision: impl FnOnce() -> ImageTreeError,
+) -> Result<Placement, ImageTreeError> {
+    let (name, units) = match (validate_name(name), policy) {
+        (Ok(units), _) => (name.to_string(), units),
+        (Err(err), NamePolicy::Error) => return Err(ImageTreeError::InvalidName(err)),
+        (Err(_), NamePolicy::Skip) => return Ok(Placement::Skipped),
+        (Err(_), NamePolicy::Rename) => {
+            let name = sanitize_name(name);
+            let units = name.encode_utf16().collect();
+            (name, units)
+        }
+    };
+
+    let Some(index) = position(items, &units, upcase_table) else {
+        return Ok(Placement::New { name, units });
+    };
+    if directory && matches!(items[index].kind, NodeKind::Directory(_)) {
+        return Ok(Placement::Existing(index));
+    }
+
+    match policy {
+        NamePolicy::Error => Err(collision()),
+        NamePolicy::Skip => Ok(Placement::Skipped),
+        NamePolicy::Rename => {
+            let (stem, extension) = match name.rfind('.') {
+                Some(dot) if dot > 0 => name.split_at(dot),
+                _ => (name.as_str(), ""),
+            };
+            (1..)
+                .map(|n| {
+                    let suffix = format!(" ({n}){extension}");
+                    let stem = truncate(stem, MAX_NAME_LENGTH - suffix.encode_utf16().count());
+                    format!("{stem}{suffix}")
+                })
+                .map(|name| {
+                    let units: Vec<u16> = name.encode_utf16().collect();
+                    (name, units)
+                })
+                .find(|(_, units)| position(items, units, upcase_table).is_none())
+                .map(|(name, units)| Placement::New { name, units })
+                .ok_or_else(collision)
+        }
+    }
+}
+
+/// Replaces invalid characters with `_` and shortens the name to the maximum length.
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if is_invalid_name_character(c) { '_' } else { c })
+        .collect();
+    match name.is_empty() {
+        true => String::from("_"),
+        false => String::from(truncate(&name, MAX_NAME_LENGTH)),
+    }
+}
+
+/// Longest prefix of `name` with at most `max` UTF-16 code units.
+fn truncate(name: &str, max: usize) -> &str {
+    let mut units = 0;
+    let end = name
+        .char_indices()
+        .find(|(_, c)| {
+            units += c.len_utf16();
+            units > max
+        })
+        .map_or(name.len(), |(i, _)| i);
+    &name[..end]
+}
+
+/// Number of directory entries needed to store the entry sets of the given items.
+fn entry_count<S>(items: &[Node<S>]) -> u32 {
+    items
+        .iter()
+        .map(|item| 2 + item.units.len().div_ceil(15) as u32)
+        .sum()
+}
+
+/// Contiguous run of clusters that is to be filled with content.
+struct Extent<'a, S> {
+    first_cluster: u32,
+    content: Content<'a, S>,
+}
+
+enum Content<'a, S> {
+    /// Raw entry sets of a directory.
+    Directory(Vec<u8>),
+    File {
+        path: String,
+        source: &'a S,
+        len: u64,
+    },
+}
+
+/// Assigns clusters to all content.
+struct Layout<'a, S> {
+    bytes_per_cluster: u64,
+    next_cluster: u64,
+    timestamps: Timestamps,
+    upcase_table: &'a UpcaseTable,
+    extents: Vec<Extent<'a, S>>,
+}
+
+impl<'a, S> Layout<'a, S> {
+    /// Allocates clusters for the given items (depth first, in the order they have been added)
+    /// and returns the raw entry sets of the directory holding them.
+    fn allocate(&mut self, items: &'a [Node<S>], parent: &str) -> Vec<u8> {
+        let mut entries = Vec::default();
+
+        for item in items {
+            let path = if parent.is_empty() {
+                item.name.clone()
+            } else {
+                [parent, &item.name].join("/")
+            };
+
+            let (attributes, clusters, data_len) = match &item.kind {
+                NodeKind::File { len, .. } => (
+                    FileAttributes::ARCHIVE,
+                    len.div_ceil(self.bytes_per_cluster),
+                    *len,
+                ),
+                NodeKind::Directory(children) => {
+                    // directories always occupy at least one cluster
+                    let clusters = (entry_count(children) as u64 * size_of::<DirEntry>() as u64)
+                        .div_ceil(self.bytes_per_cluster)
+                        .max(1);
+                    (
+                        FileAttributes::DIRECTORY,
+                        clusters,
+                        clusters * self.bytes_per_cluster,
+                    )
+                }
+            };
+
+            // cluster numbers beyond the heap are rejected before anything is written
+            let first_cluster = if clusters == 0 {
+                0
+            } else {
+                self.next_cluster.min(u32::MAX as u64) as u32
+            };
+            self.next_cluster = self.next_cluster.saturating_add(clusters);
+
+            let content = match &item.kind {
+                NodeKind::File { source, len } => Content::File {
+                    path: path.clone(),
+                    source,
+                    len: *len,
+                },
+                // the contents of a directory directly follow its own clusters
+                NodeKind::Directory(children) => Content::Directory(self.allocate(children, &path)),
+            };
+            if clusters != 0 {
+                self.extents.push(Extent {
+                    first_cluster,
+                    content,
+                });
+            }
+
+            let set = EntrySet::new(
+                &item.units,
+                attributes,
+                &self.timestamps,
+                Allocation {
+                    first_cluster,
+                    data_len,
+                    no_fat_chain: clusters != 0,
+                },
+                self.upcase_table,
+            );
+            entries.extend(set.bytes());
+        }
+
+        entries
+    }
+}
+
+impl Exfat {
+    /// Writes the content of an extent, padding its last cluster with zeroes.
+    fn write_extent<T: Clock, O: WriteSeek, S: ReadOffset>(
+        &self,
+        f: &mut O,
+        extent: Extent<'_, S>,
+    ) -> Result<(), ImageBuilderError<T, O, S>>
+    where
+        T::Err: core::fmt::Debug,
+    {
+        let offset = self.cluster_heap_offset as u64 * self.format_options.bytes_per_sector as u64
+            + (extent.first_cluster - FIRST_USABLE_CLUSTER_INDEX) as u64
+                * self.bytes_per_cluster as u64;
+        let phase = match &extent.content {
+            Content::Directory(_) => FormatPhase::WritingDirectory,
+            Content::File { path, .. } => FormatPhase::WritingFile(path.clone()),
+        };
+        let io = |err| ImageBuilderError::Io(phase.clone(), err);
+        f.seek(SeekFrom::Start(offset)).map_err(io)?;
+
+        let mut buffer = vec![0u8; self.bytes_per_cluster as usize];
+        match extent.content {
+            Content::Directory(entries) => {
+                for chunk in entries.chunks(buffer.len()) {
+                    buffer.fill(0);
+                    buffer[..chunk.len()].copy_from_slice(chunk);
+                    f.write_all(&buffer).map_err(io)?;
+                }
+                // empty directories still occupy a zeroed cluster
+                if entries.is_empty() {
+                    f.write_all(&buffer).map_err(io)?;
+                }
+            }
+            Content::File { path, source, len } => {
+                let mut position = 0;
+                while position < len {
+                    let amount = (len - position).min(buffer.len() as u64) as usize;
+                    buffer.fill(0);
+                    read_all(source, position, &mut buffer[..amount])
+                        .map_err(|err| ImageBuilderError::ReadFailed(path.clone(), err))?;
+                    f.write_all(&buffer).map_err(io)?;
+                    position += amount as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fills the buffer from the source, failing if the source ends prematurely.
+fn read_all<S: ReadOffset>(
+    source: &S,
+    mut offset: u64,
+    mut buffer: &mut [u8],
+) -> Result<(), S::Err> {
+    while !buffer.is_empty() {
+        match source.read_at(offset, buffer)? {
+            0 => return Err(S::Err::unexpected_eop()),
+            n => {
+                buffer = &mut buffer[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn populated_image() {
+    use crate::{
+        fs::FsElement,
+        name::Normalization,
+        root::Root,
+        test_utils::{TestImage, format_options},
+    };
+    use std::io::{Cursor, Read};
+
+    let firmware: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+
+    let mut builder = ImageBuilder::new(format_options().build().unwrap());
+    builder
+        .add_file("readme.txt", Cursor::new(b"Hello, World!".to_vec()), 13)
+        .unwrap()
+        .add_file(
+            "/firmware/v1/image.bin",
+            Cursor::new(firmware.clone()),
+            10_000,
+        )
+        .unwrap()
+        .add_file("firmware/empty", Cursor::new(Vec::new()), 0)
+        .unwrap()
+        .add_dir("logs")
+        .unwrap();
+
+    // names are compared case-insensitively
+    assert!(matches!(
+        builder.add_dir("README.TXT"),
+        Err(ImageTreeError::AlreadyExists(_))
+    ));
+    assert!(matches!(
+        builder.add_dir("readme.txt/sub"),
+        Err(ImageTreeError::NotADirectory(_))
+    ));
+    assert!(builder.add_dir("Firmware").is_ok());
+
+    let mut root = Root::open(TestImage::build(builder).cursor()).unwrap();
+    assert_eq!(root.items().len(), 3);
+
+    let Some(FsElement::F(readme)) = root.find("readme.txt", Normalization::None) else {
+        panic!("file not found");
+    };
+    let mut content = String::default();
+    readme.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "Hello, World!");
+
+    let Some(FsElement::D(dir)) = root.find("firmware", Normalization::None) else {
+        panic!("directory not found");
+    };
+    let Some(FsElement::F(empty)) = dir.find("empty", Normalization::None).unwrap() else {
+        panic!("file not found");
+    };
+    assert!(empty.is_empty());
+
+    let Some(FsElement::D(dir)) = dir.find("v1", Normalization::None).unwrap() else {
+        panic!("directory not found");
+    };
+    let Some(FsElement::F(mut image)) = dir.find("image.bin", Normalization::None).unwrap() else {
+        panic!("file not found");
+    };
+    let mut content = Vec::default();
+    image.read_to_end(&mut content).unwrap();
+    assert_eq!(content, firmware);
+}
+
+#[cfg(test)]
+#[test]
+fn name_collision_policies() {
+    use crate::{
+        root::Root,
+        test_utils::{TestImage, format_options},
+    };
+    use std::io::Cursor;
+
+    let file = || Cursor::new(b"data".to_vec());
+    let mapping = |original: &str, stored: Option<&str>| NameMapping {
+        original: String::from(original),
+        stored: stored.map(String::from),
+    };
+
+    let mut builder = ImageBuilder::new(format_options().build().unwrap());
+    assert!(matches!(
+        builder.add_file("a:b.txt", file(), 4),
+        Err(ImageTreeError::InvalidName(NameError::InvalidCharacter(
+            ':'
+        )))
+    ));
+
+    builder
+        .name_policy(NamePolicy::Skip)
+        .add_file("Notes.txt", file(), 4)
+        .unwrap()
+        .add_file("NOTES.TXT", file(), 4)
+        .unwrap()
+        .add_file("what?/inner.txt", file(), 4)
+        .unwrap();
+
+    builder
+        .name_policy(NamePolicy::Rename)
+        .add_file("notes.txt", file(), 4)
+        .unwrap()
+        .add_file("a:b.txt", file(), 4)
+        .unwrap()
+        .add_file("Notes.txt/child", file(), 4)
+        .unwrap()
+        // renamed directories are reused
+        .add_file("Notes.txt/other", file(), 4)
+        .unwrap()
+        .add_file("what?/inner.txt", file(), 4)
+        .unwrap();
+
+    assert_eq!(
+        builder.name_mappings(),
+        [
+            mapping("NOTES.TXT", None),
+            mapping("what?", None),
+            mapping("what?/inner.txt", None),
+            mapping("notes.txt", Some("notes (1).txt")),
+            mapping("a:b.txt", Some("a_b.txt")),
+            mapping("Notes.txt", Some("Notes (2).txt")),
+            mapping("Notes.txt/child", Some("Notes (2).txt/child")),
+            mapping("Notes.txt/other", Some("Notes (2).txt/other")),
+            mapping("what?/inner.txt", None),
+        ]
+    );
+
+    let mut root = Root::open(TestImage::build(builder).cursor()).unwrap();
+    let names: Vec<&str> = root.items().iter().map(|item| item.name()).collect();
+    assert_eq!(
+        names,
+        ["Notes.txt", "notes (1).txt", "a_b.txt", "Notes (2).txt"]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn reproducible_image() {
+    use crate::{fs::FsElement, name::Normalization, root::Root};
+    use std::io::Cursor;
+
+    /// Clocks stopped at two different points in time.
+    #[derive(Debug)]
+    struct Early;
+    impl Clock for Early {
+        type Err = ();
+
+        fn as_secs() -> Result<u64, Self::Err> {
+            Ok(1_600_000_000)
+        }
+    }
+    #[derive(Debug)]
+    struct Late;
+    impl Clock for Late {
+        type Err = ();
+
+        fn as_secs() -> Result<u64, Self::Err> {
+            Ok(1_800_000_000)
+        }
+    }
+
+    const SIZE: u64 = 8 * crate::MB as u64;
+    fn build<T: Clock<Err = ()> + core::fmt::Debug>() -> Vec<u8> {
+        let format_options = super::FormatVolumeOptionsBuilder::default()
+            .dev_size(SIZE)
+            .bytes_per_sector(512)
+            .timestamp(Some(1_700_000_000))
+            .build()
+            .unwrap();
+        let mut builder = ImageBuilder::new(format_options);
+        builder
+            .add_file("data/config.txt", Cursor::new(b"key=value".to_vec()), 9)
+            .unwrap();
+
+        let mut f = Cursor::new(vec![0u8; SIZE as usize]);
+        builder.write::<T, Cursor<Vec<u8>>>(&mut f).unwrap();
+        f.into_inner()
+    }
+
+    let image = build::<Early>();
+    assert!(image == build::<Late>());
+
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    assert_eq!(root.volume_serial_number(), 1_700_000_000);
+    let Some(FsElement::D(data)) = root.find("data", Normalization::None) else {
+        panic!("directory not found");
+    };
+    // 2023-11-14
+    let date = data.timestamps().created().date();
+    assert_eq!((date.year, date.month, date.day), (2023, 11, 14));
+}
+
+#[cfg(test)]
+#[test]
+fn copy_in_host_tree() {
+    use crate::{fs::FsElement, name::Normalization, root::Root};
+    use std::io::{Cursor, Read};
+
+    let host = std::env::temp_dir().join(format!("exfat-copy-in-{}", std::process::id()));
+    std::fs::create_dir_all(host.join("docs/empty")).unwrap();
+    std::fs::write(host.join("readme.txt"), b"Hello, World!").unwrap();
+    std::fs::write(host.join("docs/manual.txt"), vec![b'x'; 10_000]).unwrap();
+
+    let size: u64 = 8 * crate::MB as u64;
+    let format_options = super::FormatVolumeOptionsBuilder::default()
+        .dev_size(size)
+        .bytes_per_sector(512)
+        .build()
+        .unwrap();
+    let mut builder = ImageBuilder::new(format_options);
+    builder
+        .copy_in("", &host)
+        .unwrap()
+        .copy_in("backup/readme.txt", host.join("readme.txt"))
+        .unwrap()
+        .add_bytes("docs/notes.txt", "in memory")
+        .unwrap();
+    assert!(matches!(
+        builder.copy_in("missing", host.join("missing")),
+        Err(CopyInError::Io(..))
+    ));
+
+    let mut f = Cursor::new(vec![0u8; size as usize]);
+    builder
+        .write::<std::time::SystemTime, Cursor<Vec<u8>>>(&mut f)
+        .unwrap();
+    std::fs::remove_dir_all(&host).unwrap();
+
+    let mut root = Root::open(f).unwrap();
+    assert_eq!(root.items().len(), 3);
+    let read = |element: Option<FsElement<_>>| {
+        let Some(FsElement::F(mut file)) = element else {
+            panic!("file not found");
+        };
+        let mut content = Vec::default();
+        file.read_to_end(&mut content).unwrap();
+        content
+    };
+    let Some(FsElement::F(readme)) = root.find("readme.txt", Normalization::None) else {
+        panic!("file not found");
+    };
+    let mut content = Vec::default();
+    readme.read_to_end(&mut content).unwrap();
+    assert_eq!(content, b"Hello, World!");
+
+    let Some(FsElement::D(docs)) = root.find("docs", Normalization::None) else {
+        panic!("directory not found");
+    };
+    assert_eq!(
+        read(docs.find("manual.txt", Normalization::None).unwrap()),
+        vec![b'x'; 10_000]
+    );
+    assert_eq!(
+        read(docs.find("notes.txt", Normalization::None).unwrap()),
+        b"in memory"
+    );
+    assert!(matches!(
+        docs.find("empty", Normalization::None).unwrap(),
+        Some(FsElement::D(_))
+    ));
+}
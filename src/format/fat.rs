@@ -7,24 +7,27 @@ use crate::{
 use super::Exfat;
 
 impl Exfat {
-    pub(super) fn write_fat<T: WriteSeek>(&mut self, device: &mut T) -> Result<(), T::Err> {
-        // write entry 0 (media type)
-        self.write_fat_entry(device, FatEntry::media_type(), 0)?;
-
-        // write entry 1 (reserved)
-        self.write_fat_entry(device, FatEntry::eof(), 1)?;
-
-        // write bitmap entries
-        let mut index =
-            self.write_fat_entries(device, FIRST_USABLE_CLUSTER_INDEX, self.bitmap_length_bytes)?;
-
-        // write upcase table entries
-        index = self.write_fat_entries(device, index, self.uptable_length_bytes)?;
-
-        // write root directory entries
-        index = self.write_fat_entries(device, index, self.root_length_bytes)?;
-
-        self.cluster_count_used = index - FIRST_USABLE_CLUSTER_INDEX;
+    /// Writes the same entries into each FAT.
+    pub(super) fn write_fat<T: WriteSeek>(&self, device: &mut T) -> Result<(), T::Err> {
+        for fat in 0..self.number_of_fats {
+            // write entry 0 (media type)
+            self.write_fat_entry(device, fat, FatEntry::media_type(), 0)?;
+
+            // write entry 1 (reserved)
+            self.write_fat_entry(device, fat, FatEntry::eof(), 1)?;
+
+            // write bitmap entries
+            let mut index = FIRST_USABLE_CLUSTER_INDEX;
+            for _ in 0..self.number_of_fats {
+                index = self.write_fat_entries(device, fat, index, self.bitmap_length_bytes)?;
+            }
+
+            // write upcase table entries
+            index = self.write_fat_entries(device, fat, index, self.uptable_length_bytes)?;
+
+            // write root directory entries
+            self.write_fat_entries(device, fat, index, self.root_length_bytes)?;
+        }
 
         Ok(())
     }
@@ -32,10 +35,12 @@ impl Exfat {
     fn write_fat_entry<T: WriteSeek>(
         &self,
         device: &mut T,
+        fat: u8,
         entry: FatEntry,
         index: u64,
     ) -> Result<(), T::Err> {
-        let offset_bytes = self.fat_offset as u64 * self.format_options.bytes_per_sector as u64
+        let offset_bytes = (self.fat_offset as u64 + fat as u64 * self.fat_length as u64)
+            * self.format_options.bytes_per_sector as u64
             + index * size_of::<FatEntry>() as u64;
         device.seek(SeekFrom::Start(offset_bytes))?;
         device.write_all(&entry.0.to_le_bytes())
@@ -45,23 +50,24 @@ impl Exfat {
     fn write_fat_entries<T: WriteSeek>(
         &self,
         device: &mut T,
+        fat: u8,
         cluster: u32,
         length: u32,
     ) -> Result<u32, T::Err> {
-        let count =
-            cluster + length.next_multiple_of(self.bytes_per_cluster) / self.bytes_per_cluster;
+        let count = cluster + length.div_ceil(self.bytes_per_cluster);
 
         // write fat entry for each cluster in chain
         for current_cluster in cluster..count - 1 {
             self.write_fat_entry(
                 device,
+                fat,
                 FatEntry(current_cluster + 1),
                 current_cluster as u64,
             )?;
         }
 
         // write cluster chain EOF
-        self.write_fat_entry(device, FatEntry::eof(), count as u64 - 1)?;
+        self.write_fat_entry(device, fat, FatEntry::eof(), count as u64 - 1)?;
 
         Ok(count)
     }
@@ -2,11 +2,13 @@ use core::ops::{Div, Sub};
 
 use crate::{
     DEFAULT_BOUNDARY_ALIGNEMENT, FIRST_USABLE_CLUSTER_INDEX, GB, KB, Label, MB,
-    boot_sector::{FileSystemRevision, UnixEpochDuration, VolumeFlags, VolumeSerialNumber},
-    disk::{SeekFrom, WriteSeek},
-    entry::DirEntry,
-    error::ExfatError,
-    root::RawRoot,
+    boot_sector::{FileSystemRevision, VolumeFlags, VolumeSerialNumber},
+    disk::{BlockDiscard, SeekFrom, SequentialWrite, StreamWriter, WriteSeek},
+    entry::{BitmapEntry, DirEntry},
+    error::{ExfatError, FormatPhase},
+    limits::{is_valid_cluster_size, is_valid_sector_size},
+    root::{RawRoot, Root},
+    timestamp::Clock,
 };
 use upcase_table::{DEFAULT_UPCASE_TABLE, UPCASE_TABLE_SIZE_BYTES};
 
@@ -21,20 +23,59 @@ use alloc::string::ToString;
 use alloc::vec;
 /// ExFat boot sector creation.
 mod boot;
+mod builder;
 mod fat;
+mod oem;
+mod plan;
 pub(crate) mod upcase_table;
+mod verify;
+
+#[cfg(feature = "std")]
+pub use builder::FileSource;
+pub use builder::{ImageBuilder, NameMapping, NamePolicy};
+pub use oem::{FlashParameters, FlashParametersBuilder};
+pub use plan::{BitmapPackingConstraint, FormatLayout, FormatPlan, FormatWarning};
+pub use verify::{FormatStructure, Mismatch, VerifyReport};
 
 /// A struct of exfat formatting options. It implements the [`derive_builder::Builder`] pattern.
 #[derive(Builder, Copy, Clone, Debug)]
 #[builder(no_std, build_fn(validate = "Self::validate"))]
 pub struct FormatVolumeOptions {
+    /// Kind of media the volume is formatted for, which determines the cluster size and the
+    /// defaults of [`FormatVolumeOptionsBuilder::boundary_align`] and
+    /// [`FormatVolumeOptionsBuilder::pack_bitmap`]. Defaults to [`MediaProfile::Generic`].
+    #[builder(default)]
+    profile: MediaProfile,
     /// Whether or not to pack the bitmap right after the FAT for better performance and space
-    /// usage. Defaults to `true`.
-    #[builder(default = true)]
+    /// usage. Defaults to `true`, unless the [`MediaProfile`] recommends otherwise.
+    #[builder(default = "self.profile.unwrap_or_default().pack_bitmap()")]
     pack_bitmap: bool,
+    /// Whether to store the allocation bitmap in the cluster heap instead of failing with
+    /// [`ExfatFormatError::CannotPackBitmap`] if it cannot be packed. The reason is available
+    /// through [`Exfat::bitmap_packing_constraint`]. Defaults to `false`.
+    #[builder(default)]
+    pack_bitmap_fallback: bool,
+    /// Amount of FATs and allocation bitmaps: `1`, or `2` for the TexFAT layout, in which
+    /// transaction-safe hosts switch between two copies. The first FAT is active after
+    /// formatting. Defaults to `1`.
+    #[builder(default = 1)]
+    number_of_fats: u8,
+    /// Volume flags set right after formatting, e.g. [`VolumeFlags::VOLUME_DIRTY`] to make hosts
+    /// check the volume when mounting it. `ActiveFat` cannot be set this way. Defaults to none.
+    #[builder(default)]
+    volume_flags: VolumeFlags,
     /// Whether to fully format the volume, which takes longer. Defaults to `false`.
     #[builder(default)]
     full_format: bool,
+    /// Whether the device already reads as zeroes, e.g. a newly created sparse image file, so that
+    /// clearing it is skipped and it stays sparse. Defaults to `false`.
+    #[builder(default)]
+    sparse: bool,
+    /// Whether to extend a device shorter than `dev_size`, e.g. a regular file or a sparse image,
+    /// by writing its last byte instead of failing with [`ExfatFormatError::InvalidFileSize`].
+    /// Defaults to `false`.
+    #[builder(default)]
+    extend_device: bool,
     /// Size of the target device (in bytes)
     dev_size: u64,
     /// Label of the format
@@ -43,33 +84,176 @@ pub struct FormatVolumeOptions {
     /// Optional GUID. Defaults to `None`.
     #[builder(default)]
     guid: Option<u128>,
+    /// Serial number of the volume. Defaults to `None`, which derives it from the time of
+    /// formatting.
+    #[builder(default)]
+    volume_serial_number: Option<u32>,
+    /// Fixed time of formatting in seconds since the unix epoch, used instead of the current time
+    /// so repeated runs with the same inputs produce identical images. Determines the serial
+    /// number (unless given) and the timestamps of the entries written by an [`ImageBuilder`].
+    /// Defaults to `None`.
+    #[builder(default)]
+    timestamp: Option<u64>,
     /// Media-relative sector offset of the partition which hosts the given exFAT volume. Defaults
     /// to `0`.
     #[builder(default)]
     partition_offset: u64,
     /// Amount of bytes per sector. Must be a power of `2` and between `512` and `4096`.
     bytes_per_sector: u16,
-    /// Byte alignment for filesystem structures like the FAT and Up-case table. Defaults to
-    /// [`DEFAULT_BOUNDARY_ALIGNEMENT`].
-    #[builder(default = DEFAULT_BOUNDARY_ALIGNEMENT)]
+    /// Amount of bytes per cluster. Must be a power of `2` between the sector size and
+    /// [`MAX_CLUSTER_SIZE`]. Defaults to `None`, in which case
+    /// the cluster size recommended by the [`MediaProfile`] for the device size is used.
+    #[builder(default)]
+    bytes_per_cluster: Option<u32>,
+    /// Byte alignment for filesystem structures like the FAT and Up-case table. Defaults to the
+    /// alignment recommended by the [`MediaProfile`], which is [`DEFAULT_BOUNDARY_ALIGNEMENT`]
+    /// for [`MediaProfile::Generic`].
+    #[builder(
+        default = "self.profile.unwrap_or_default().boundary_align(self.dev_size.unwrap_or(0))"
+    )]
     boundary_align: u32,
+    /// Minimum amount of directory entries the root directory can hold right after formatting.
+    /// Pre-sizing the root directory avoids fragmenting it on first use. The root directory
+    /// always occupies at least one cluster. Defaults to `0`.
+    #[builder(default)]
+    root_entries: u32,
+    /// File system revision reported in the boot sector as `(major, minor)`. Only revisions `1.x`
+    /// with a minor revision of at most `99` are valid; old devices may reject minor revisions they
+    /// do not know. Defaults to `(1, 0)`.
+    #[builder(default = (1, 0))]
+    file_system_revision: (u8, u8),
+    /// Whether to only write the structures every implementation understands, for old devices
+    /// (e.g. car stereos and TVs) which are picky about exFAT details: the root directory starts
+    /// with the volume label, allocation bitmap and up-case table entries in this order, and no
+    /// placeholder for a missing volume GUID is written. Defaults to `false`.
+    #[builder(default)]
+    conservative: bool,
+    /// Characteristics of the flash medium, stored in the OEM parameters sector of the boot
+    /// regions. Defaults to `None`, in which case the sector is left empty.
+    #[builder(default)]
+    flash_parameters: Option<FlashParameters>,
+}
+
+/// Kind of media a volume is formatted for. Flash media erase and write in large units, so
+/// aligning the file system structures and the cluster heap to these units avoids slow
+/// read-modify-write cycles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MediaProfile {
+    /// The size-based defaults also used by other formatters.
+    #[default]
+    Generic,
+    /// SD cards, formatted as recommended by the SD Association: 32 KiB clusters up to 32 GiB
+    /// and 128 KiB clusters beyond, the cluster heap aligned to the boundary unit of the card
+    /// (4 MiB up to 16 MiB, 32 MiB, 64 MiB or 128 MiB depending on the capacity) and the
+    /// allocation bitmap at the start of the cluster heap.
+    SdCard,
+    /// USB flash drives, whose erase blocks are usually at most 4 MiB: clusters of at least
+    /// 32 KiB, structures aligned to 4 MiB and the allocation bitmap in the cluster heap.
+    UsbFlash,
+    /// Hard disks, which have no erase blocks: the size-based cluster size, structures aligned
+    /// to 1 MiB and a packed allocation bitmap.
+    Hdd,
+}
+
+impl MediaProfile {
+    /// Cluster size for a device of `dev_size` bytes.
+    pub(crate) fn cluster_size(self, dev_size: u64) -> u32 {
+        match self {
+            MediaProfile::Generic | MediaProfile::Hdd => default_cluster_size(dev_size),
+            MediaProfile::SdCard if dev_size <= 32 * GB as u64 => 32 * KB as u32,
+            MediaProfile::SdCard => 128 * KB as u32,
+            MediaProfile::UsbFlash => default_cluster_size(dev_size).max(32 * KB as u32),
+        }
+    }
+
+    /// Alignment of the file system structures for a device of `dev_size` bytes.
+    pub(crate) fn boundary_align(self, dev_size: u64) -> u32 {
+        const SD_BOUNDARY_UNITS: [(u64, u32); 4] = [
+            (32 * GB as u64, 4 * MB),
+            (64 * GB as u64, 16 * MB),
+            (256 * GB as u64, 32 * MB),
+            (1024 * GB as u64, 64 * MB),
+        ];
+        match self {
+            MediaProfile::Generic | MediaProfile::Hdd => DEFAULT_BOUNDARY_ALIGNEMENT,
+            MediaProfile::SdCard => SD_BOUNDARY_UNITS
+                .iter()
+                .find(|(capacity, _)| dev_size <= *capacity)
+                .map_or(128 * MB, |(_, boundary_unit)| *boundary_unit),
+            MediaProfile::UsbFlash => 4 * MB,
+        }
+    }
+
+    /// Whether the allocation bitmap is packed before the cluster heap.
+    pub(crate) fn pack_bitmap(self) -> bool {
+        matches!(self, MediaProfile::Generic | MediaProfile::Hdd)
+    }
+}
+
+impl FormatVolumeOptions {
+    /// Time of formatting in seconds since the unix epoch.
+    pub(crate) fn now<T: Clock>(&self) -> Result<u64, T::Err> {
+        self.timestamp.map_or_else(T::as_secs, Ok)
+    }
+
+    /// Size of the target device (in bytes).
+    pub(crate) fn dev_size(&self) -> u64 {
+        self.dev_size
+    }
+
+    /// Requested cluster size, or the one recommended by the profile.
+    pub(crate) fn bytes_per_cluster(&self) -> u32 {
+        self.bytes_per_cluster
+            .unwrap_or_else(|| self.profile.cluster_size(self.dev_size))
+    }
 }
 
 impl FormatVolumeOptionsBuilder {
+    /// Reuses the serial number and GUID of an existing volume, e.g. the one about to be
+    /// reformatted, so tools identifying volumes by them keep recognizing it.
+    pub fn preserve_identity<O: disk::ReadOffset>(&mut self, root: &Root<O>) -> &mut Self {
+        self.volume_serial_number(Some(root.volume_serial_number()))
+            .guid(root.volume_guid())
+    }
+
     fn validate(&self) -> Result<(), String> {
-        if let Some(ref bytes_per_sector) = self.bytes_per_sector {
-            if !bytes_per_sector.is_power_of_two() || !(512..=4096).contains(bytes_per_sector) {
-                return Err(
-                    "Bytes per sector field must be a power of two and between `512` and `4096`."
-                        .to_string(),
-                );
-            }
+        if let Some(ref bytes_per_sector) = self.bytes_per_sector
+            && !is_valid_sector_size(*bytes_per_sector)
+        {
+            return Err(
+                "Bytes per sector field must be a power of two and between `512` and `4096`."
+                    .to_string(),
+            );
         }
 
-        if let Some(ref boundary_align) = self.boundary_align {
-            if !boundary_align.is_power_of_two() {
-                return Err("Boundary alignment field must be a power of two.".to_string());
-            }
+        if let Some(ref boundary_align) = self.boundary_align
+            && !boundary_align.is_power_of_two()
+        {
+            return Err("Boundary alignment field must be a power of two.".to_string());
+        }
+
+        if let Some(number_of_fats) = self.number_of_fats
+            && !(1..=2).contains(&number_of_fats)
+        {
+            return Err("Number of FATs field must be `1` or `2`.".to_string());
+        }
+
+        if let Some(Some(bytes_per_cluster)) = self.bytes_per_cluster
+            && !is_valid_cluster_size(self.bytes_per_sector.unwrap_or(512), bytes_per_cluster)
+        {
+            return Err(
+                "Bytes per cluster field must be a power of two between the sector size and `32MB`."
+                    .to_string(),
+            );
+        }
+
+        if let Some((major, minor)) = self.file_system_revision
+            && (major != 1 || minor > 99)
+        {
+            return Err(
+                "File system revision must be `1.x` with a minor revision of at most `99`."
+                    .to_string(),
+            );
         }
 
         Ok(())
@@ -100,21 +284,40 @@ pub struct Exfat {
     root_length_bytes: u32,
     uptable_offset_bytes: u32,
     uptable_start_cluster: u32,
+    /// Clusters right after the root directory which are occupied by content laid out at format
+    /// time (see [`ImageBuilder`]).
+    data_clusters: u32,
+    /// Constraint which prevented packing the allocation bitmap, if it is not packed despite
+    /// [`FormatVolumeOptions::pack_bitmap`].
+    packing_constraint: Option<BitmapPackingConstraint>,
 }
 
 impl Exfat {
     /// Attempts to initialize an exFAT formatter instance based on the [`FormatVolumeOptions`]
     /// provided.
-    pub fn try_from<T: UnixEpochDuration>(
+    pub fn try_from<T: Clock>(
         format_options: FormatVolumeOptions,
     ) -> Result<Self, ExfatFormatError<T>> {
-        let size = format_options.dev_size;
+        Self::with_cluster_size(format_options, format_options.bytes_per_cluster())
+    }
 
-        let bytes_per_cluster = default_cluster_size(size);
+    /// The constraint which prevented packing the allocation bitmap, if packing was requested but
+    /// the formatter fell back to storing it in the cluster heap (see
+    /// [`FormatVolumeOptionsBuilder::pack_bitmap_fallback`]).
+    pub fn bitmap_packing_constraint(&self) -> Option<BitmapPackingConstraint> {
+        self.packing_constraint
+    }
 
-        // format volume with a single FAT
-        let number_of_fats = 1u8;
-        let volume_flags = VolumeFlags::empty();
+    /// Computes the layout of the volume for the given cluster size.
+    fn with_cluster_size<T: Clock>(
+        format_options: FormatVolumeOptions,
+        bytes_per_cluster: u32,
+    ) -> Result<Self, ExfatFormatError<T>> {
+        let size = format_options.dev_size;
+
+        // the first FAT is active
+        let number_of_fats = format_options.number_of_fats;
+        let volume_flags = format_options.volume_flags - VolumeFlags::ACTIVE_FAT;
 
         // transform partition_offset to be measured by sectors
         let partition_offset =
@@ -192,9 +395,10 @@ impl Exfat {
             return Err(ExfatFormatError::InvlaidClusterSize(bytes_per_cluster));
         }
 
-        // bitmap is first cluster of cluster heap
+        // bitmaps are the first clusters of the cluster heap, one for each FAT
         let mut bitmap_offset_bytes = cluster_heap_offset_bytes;
-        let mut bitmap_length_bytes = cluster_count.next_multiple_of(8) / 8;
+        let mut bitmap_length_bytes = cluster_count.div_ceil(8);
+        let mut packing_constraint = None;
 
         if format_options.pack_bitmap {
             let fat_end_bytes = fat_offset_bytes as u64 + fat_length_bytes;
@@ -203,13 +407,27 @@ impl Exfat {
                 bitmap_length_bytes.next_multiple_of(bytes_per_cluster);
 
             loop {
-                let bitmap_cluster_count_packed = bitmap_length_clusters_packed / bytes_per_cluster;
-                // check if there is enough space to put bitmap before alignment boundary
-                if ((cluster_heap_offset_bytes - bitmap_length_clusters_packed) as u64)
-                    < fat_end_bytes
-                    || cluster_count > MAX_CLUSTER_COUNT - bitmap_cluster_count_packed
+                let bitmaps_length_packed = bitmap_length_clusters_packed * number_of_fats as u32;
+                let bitmap_cluster_count_packed = bitmaps_length_packed / bytes_per_cluster;
+                // check if there is enough space to put the bitmaps before alignment boundary
+                let constraint = if cluster_heap_offset_bytes
+                    .checked_sub(bitmaps_length_packed)
+                    .is_none_or(|start| (start as u64) < fat_end_bytes)
                 {
-                    return Err(ExfatFormatError::CannotPackBitmap);
+                    Some(BitmapPackingConstraint::NoRoomBeforeClusterHeap)
+                } else if cluster_count > MAX_CLUSTER_COUNT - bitmap_cluster_count_packed {
+                    Some(BitmapPackingConstraint::TooManyClusters)
+                } else {
+                    None
+                };
+                match constraint {
+                    // nothing has been changed yet, keep the bitmap in the cluster heap
+                    Some(constraint) if format_options.pack_bitmap_fallback => {
+                        packing_constraint = Some(constraint);
+                        break;
+                    }
+                    Some(constraint) => return Err(ExfatFormatError::CannotPackBitmap(constraint)),
+                    None => {}
                 }
 
                 let total_cluster_count = cluster_count + bitmap_cluster_count_packed;
@@ -218,9 +436,9 @@ impl Exfat {
                     bitmap_length_bytes_packed.next_multiple_of(bytes_per_cluster);
 
                 if new_bitmap_length_clusters == bitmap_length_clusters_packed {
-                    cluster_heap_offset_bytes -= bitmap_length_clusters_packed;
+                    cluster_heap_offset_bytes -= bitmaps_length_packed;
                     cluster_count = total_cluster_count;
-                    bitmap_offset_bytes -= bitmap_length_clusters_packed;
+                    bitmap_offset_bytes -= bitmaps_length_packed;
                     bitmap_length_bytes = bitmap_length_bytes_packed;
                     break;
                 }
@@ -231,7 +449,8 @@ impl Exfat {
             cluster_heap_offset =
                 cluster_heap_offset_bytes / format_options.bytes_per_sector as u32;
         }
-        let cluster_length = bitmap_length_bytes.next_multiple_of(bytes_per_cluster);
+        let cluster_length =
+            bitmap_length_bytes.next_multiple_of(bytes_per_cluster) * number_of_fats as u32;
 
         let uptable_offset_bytes = bitmap_offset_bytes + cluster_length;
         let uptable_start_cluster = FIRST_USABLE_CLUSTER_INDEX + cluster_length / bytes_per_cluster;
@@ -243,11 +462,35 @@ impl Exfat {
         let first_cluster_of_root_directory =
             uptable_start_cluster + cluster_length / bytes_per_cluster;
 
-        let file_system_revision = FileSystemRevision::default();
-        let volume_serial_number =
-            VolumeSerialNumber::try_new::<T>().map_err(|err| ExfatFormatError::NoSerial(err))?;
+        let (major, minor) = format_options.file_system_revision;
+        let file_system_revision = FileSystemRevision::new(major, minor);
+        let volume_serial_number = match format_options.volume_serial_number {
+            Some(serial) => VolumeSerialNumber::new(serial),
+            None => VolumeSerialNumber::new(
+                format_options
+                    .now::<T>()
+                    .map_err(|err| ExfatFormatError::NoSerial(err))? as u32,
+            ),
+        };
+
+        let root_length_bytes = (size_of::<DirEntry>() as u32)
+            .checked_mul(format_options.root_entries)
+            .ok_or(ExfatFormatError::RootDirectoryTooBig(
+                format_options.root_entries,
+            ))?
+            .max(size_of::<DirEntry>() as u32 * 3);
+
+        // the root directory must fit into the cluster heap
+        let root_clusters = root_length_bytes.div_ceil(bytes_per_cluster);
+        if (first_cluster_of_root_directory - FIRST_USABLE_CLUSTER_INDEX) as u64
+            + root_clusters as u64
+            > cluster_count as u64
+        {
+            return Err(ExfatFormatError::RootDirectoryTooBig(
+                format_options.root_entries,
+            ));
+        }
 
-        let root_length_bytes = size_of::<DirEntry>() as u32 * 3;
         let cluster_count_used = 0; // in the beginning no cluster is used
 
         Ok(Self {
@@ -273,66 +516,176 @@ impl Exfat {
             bitmap_offset_bytes,
             uptable_offset_bytes,
             uptable_start_cluster,
+            data_clusters: 0,
+            packing_constraint,
         })
     }
 }
 
 impl Exfat {
     /// Attempts to write the boot region & FAT onto the device. The file length must be the same as the
-    /// provided `dev_size` in the [`Exfat`].
-    pub fn write<T: UnixEpochDuration, O: WriteSeek>(
+    /// provided `dev_size` in the [`Exfat`], unless shorter devices are extended (see
+    /// [`FormatVolumeOptionsBuilder::extend_device`]).
+    pub fn write<T: Clock, O: WriteSeek>(&mut self, f: &mut O) -> Result<(), ExfatError<T, O>>
+    where
+        T::Err: core::fmt::Debug,
+    {
+        self.write_cleared(f, |f, size| disk::write_zeroes(f, size, 0))
+    }
+
+    /// Like [`Exfat::write`], but discards the cleared part of the device (all of it for a
+    /// `full_format`) instead of writing zeroes to it. Falls back to zeroing if the device
+    /// cannot discard it.
+    pub fn write_discarding<T: Clock, O: BlockDiscard>(
         &mut self,
         f: &mut O,
     ) -> Result<(), ExfatError<T, O>>
     where
         T::Err: core::fmt::Debug,
     {
-        let old_pos = f.stream_position().map_err(|err| ExfatError::Io(err))?;
-        let len = f
-            .seek(SeekFrom::End(0))
-            .map_err(|err| ExfatError::Io(err))?;
+        self.write_cleared(f, |f, size| {
+            if !f.discard(0, size)? {
+                disk::write_zeroes(f, size, 0)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Clears the first `size` bytes of the device with `clear` and writes all filesystem
+    /// structures.
+    fn write_cleared<T: Clock, O: WriteSeek>(
+        &mut self,
+        f: &mut O,
+        clear: impl FnOnce(&mut O, u64) -> Result<(), O::Err>,
+    ) -> Result<(), ExfatError<T, O>>
+    where
+        T::Err: core::fmt::Debug,
+    {
+        self.prepare(f, clear)?;
+        self.write_regions(f)
+            .map_err(|(phase, err)| ExfatError::Io(phase, err))
+    }
+
+    /// Checks (or extends) the length of the device and clears it with `clear` as needed.
+    fn prepare<T: Clock, O: WriteSeek>(
+        &mut self,
+        f: &mut O,
+        clear: impl FnOnce(&mut O, u64) -> Result<(), O::Err>,
+    ) -> Result<(), ExfatError<T, O>>
+    where
+        T::Err: core::fmt::Debug,
+    {
+        let checking = |err| ExfatError::Io(FormatPhase::CheckingDevice, err);
+        let old_pos = f.stream_position().map_err(checking)?;
+        let len = f.seek(SeekFrom::End(0)).map_err(checking)?;
 
         if old_pos != len {
-            f.seek(SeekFrom::Start(old_pos))
-                .map_err(|err| ExfatError::Io(err))?;
+            f.seek(SeekFrom::Start(old_pos)).map_err(checking)?;
         }
 
-        assert_eq!(len, self.format_options.dev_size);
+        let mut size = self.cleared_size();
+        let dev_size = self.format_options.dev_size;
+        if len < dev_size && self.format_options.extend_device {
+            let extending = |err| ExfatError::Io(FormatPhase::ExtendingDevice, err);
+            f.seek(SeekFrom::Start(dev_size - 1)).map_err(extending)?;
+            f.write_all(&[0]).map_err(extending)?;
+            // the extension reads as zeroes already
+            size = size.min(len);
+        } else if len != dev_size {
+            return Err(ExfatError::Format(ExfatFormatError::InvalidFileSize {
+                len,
+                dev_size,
+            }));
+        }
 
-        if len != self.format_options.dev_size {
-            return Err(ExfatError::Format(ExfatFormatError::InvalidFileSize));
+        // clear disk size as needed
+        if size > 0 {
+            clear(f, size).map_err(|err| ExfatError::Io(FormatPhase::ClearingDevice, err))?;
         }
+        Ok(())
+    }
 
-        let size = if self.format_options.full_format {
-            self.format_options.dev_size
-        } else {
-            self.root_offset_bytes as u64 + self.bytes_per_cluster as u64
-        };
+    /// Attempts to write the entire volume onto a purely sequential output (e.g. a pipe). All
+    /// regions are generated in ascending order and the gaps between them are emitted as explicit
+    /// runs of zeroes, so exactly `dev_size` bytes are written.
+    pub fn write_sequential<T: Clock, W: SequentialWrite>(
+        &mut self,
+        f: W,
+    ) -> Result<(), ExfatError<T, StreamWriter<W>>>
+    where
+        T::Err: core::fmt::Debug,
+    {
+        let mut f = StreamWriter::new(f);
 
-        // clear disk size as needed
-        disk::write_zeroes(f, size, 0).map_err(|err| ExfatError::Io(err))?;
+        self.write_regions(&mut f)
+            .map_err(|(phase, err)| ExfatError::Io(phase, err))?;
+
+        // trailing zeroes up to the end of the volume
+        f.pad_to(self.format_options.dev_size)
+            .map_err(|err| ExfatError::Io(FormatPhase::ClearingDevice, err))
+    }
+
+    /// Writes all filesystem structures in ascending order of their offsets, failing with the
+    /// phase that could not be completed.
+    fn write_regions<O: WriteSeek>(&mut self, f: &mut O) -> Result<(), (FormatPhase, O::Err)> {
+        // the boot regions already record how many clusters are in use
+        self.cluster_count_used = self.used_clusters();
 
         // write main boot region
         self.write_boot_region(f, MAIN_BOOT_OFFSET)
-            .map_err(|err| ExfatError::Io(err))?;
+            .map_err(|err| (FormatPhase::WritingBootRegion, err))?;
 
         // write backup boot region
         self.write_boot_region(f, BACKUP_BOOT_OFFSET)
-            .map_err(|err| ExfatError::Io(err))?;
+            .map_err(|err| (FormatPhase::WritingBackupBootRegion, err))?;
 
         // write fat
-        self.write_fat(f).map_err(|err| ExfatError::Io(err))?;
+        self.write_fat(f)
+            .map_err(|err| (FormatPhase::WritingFat, err))?;
 
         // write bitmap
-        self.write_bitmap(f).map_err(|err| ExfatError::Io(err))?;
+        self.write_bitmap(f)
+            .map_err(|err| (FormatPhase::WritingBitmap, err))?;
 
         // write uptable
         self.write_upcase_table(f)
-            .map_err(|err| ExfatError::Io(err))?;
+            .map_err(|err| (FormatPhase::WritingUpcaseTable, err))?;
 
         // write root directory
-        self.write_root_dir(f).map_err(|err| ExfatError::Io(err))?;
-        Ok(())
+        self.write_root_dir(f)
+            .map_err(|err| (FormatPhase::WritingRootDirectory, err))
+    }
+}
+
+impl Exfat {
+    /// Amount of bytes at the start of the device that are zeroed before writing the filesystem
+    /// structures.
+    fn cleared_size(&self) -> u64 {
+        if self.format_options.sparse {
+            0
+        } else if self.format_options.full_format {
+            self.format_options.dev_size
+        } else {
+            self.root_offset_bytes as u64
+                + self
+                    .root_length_bytes
+                    .next_multiple_of(self.bytes_per_cluster) as u64
+        }
+    }
+
+    /// Amount of clusters used by the bitmaps, the up-case table, the root directory and content
+    /// placed after it, which is stored contiguously (`NoFatChain`).
+    fn used_clusters(&self) -> u32 {
+        let clusters = |length: u32| length.div_ceil(self.bytes_per_cluster);
+        self.number_of_fats as u32 * clusters(self.bitmap_length_bytes)
+            + clusters(self.uptable_length_bytes)
+            + clusters(self.root_length_bytes)
+            + self.data_clusters
+    }
+
+    /// Percentage of used clusters in the cluster heap, rounded down.
+    pub(super) fn percent_in_use(&self) -> u8 {
+        (self.cluster_count_used as u64 * 100 / self.cluster_count.max(1) as u64) as u8
     }
 }
 
@@ -352,11 +705,17 @@ fn default_cluster_size(size: u64) -> u32 {
 }
 
 impl Exfat {
+    /// Overrides the generated volume serial number, e.g. to reproduce an existing volume.
+    pub(crate) fn set_volume_serial_number(&mut self, serial: u32) {
+        self.volume_serial_number = VolumeSerialNumber::new(serial);
+    }
+
     fn write_upcase_table<T: WriteSeek>(&self, device: &mut T) -> Result<(), T::Err> {
         device.seek(SeekFrom::Start(self.uptable_offset_bytes as u64))?;
         device.write_all(&DEFAULT_UPCASE_TABLE)
     }
 
+    /// Writes the same allocation bitmap for each FAT.
     fn write_bitmap<T: WriteSeek>(&self, device: &mut T) -> Result<(), T::Err> {
         let mut bitmap = vec![0u8; self.bitmap_length_bytes as usize];
 
@@ -380,17 +739,41 @@ impl Exfat {
             bitmap[(zero_offset as usize)..].fill(0);
         }
 
-        device.seek(SeekFrom::Start(self.bitmap_offset_bytes as u64))?;
-        device.write_all(cast_slice(&bitmap))
+        for index in 0..self.number_of_fats as u32 {
+            let offset = self.bitmap_offset_bytes
+                + index
+                    * self
+                        .bitmap_length_bytes
+                        .next_multiple_of(self.bytes_per_cluster);
+            device.seek(SeekFrom::Start(offset as u64))?;
+            device.write_all(cast_slice(&bitmap))?;
+        }
+        Ok(())
     }
 
-    fn write_root_dir<T: WriteSeek>(&self, device: &mut T) -> Result<(), T::Err> {
-        let root = RawRoot::new(
+    /// Entries of the root directory describing the volume.
+    pub(super) fn raw_root(&self) -> RawRoot {
+        let bitmap_clusters = self.bitmap_length_bytes.div_ceil(self.bytes_per_cluster);
+        let bitmaps = (0..self.number_of_fats)
+            .map(|index| {
+                BitmapEntry::new(
+                    index,
+                    FIRST_USABLE_CLUSTER_INDEX + index as u32 * bitmap_clusters,
+                    self.bitmap_length_bytes as u64,
+                )
+            })
+            .collect();
+        RawRoot::new(
             self.format_options.label,
             self.format_options.guid,
-            self.bitmap_length_bytes as u64,
+            bitmaps,
             self.uptable_start_cluster,
-        );
+            self.format_options.conservative,
+        )
+    }
+
+    fn write_root_dir<T: WriteSeek>(&self, device: &mut T) -> Result<(), T::Err> {
+        let root = self.raw_root();
 
         device.seek(SeekFrom::Start(self.root_offset_bytes as u64))?;
         device.write_all(&root.bytes())?;
@@ -452,7 +835,7 @@ fn small_format() {
     // assert volume label data is correct
     assert_eq!(
         &read_buffer[2..2 + vol_label_length as usize],
-        &label.0[..vol_label_length as usize],
+        &label.units[..vol_label_length as usize],
         "Volume Label Root Directory Entry has invalid data"
     );
     let offset_upcase_table_entry_bytes = 0x203060;
@@ -495,3 +878,528 @@ fn small_format() {
         "Allocation Bitmap Root Directory Entry has invalid size"
     );
 }
+
+#[cfg(test)]
+#[test]
+fn sequential_format() {
+    use crate::test_utils::{IMAGE_SIZE, format_options};
+    use std::vec::Vec;
+
+    let size = IMAGE_SIZE;
+    let format_options = format_options().pack_bitmap(true).build().unwrap();
+
+    let mut formatter = Exfat::try_from::<std::time::SystemTime>(format_options).unwrap();
+
+    let mut seekable = std::io::Cursor::new(vec![0u8; size as usize]);
+    formatter
+        .write::<std::time::SystemTime, std::io::Cursor<Vec<u8>>>(&mut seekable)
+        .unwrap();
+
+    let mut sequential = Vec::new();
+    formatter
+        .write_sequential::<std::time::SystemTime, _>(&mut sequential)
+        .unwrap();
+
+    assert_eq!(sequential.len() as u64, size);
+    assert!(
+        sequential == seekable.into_inner(),
+        "sequential output must match the seekable output"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn presized_root_directory() {
+    use crate::{
+        fat::Fat,
+        format::FormatVolumeOptionsBuilder,
+        root::Root,
+        test_utils::{IMAGE_SIZE, format_options},
+    };
+    use std::vec::Vec;
+
+    let size = IMAGE_SIZE;
+    let mut f = std::io::Cursor::new(vec![0xAAu8; size as usize]);
+
+    let format_options = format_options().root_entries(1000).build().unwrap();
+
+    let mut formatter = Exfat::try_from::<std::time::SystemTime>(format_options).unwrap();
+    formatter
+        .write::<std::time::SystemTime, std::io::Cursor<Vec<u8>>>(&mut f)
+        .unwrap();
+
+    // bitmap (1), up-case table (2) and 1000 entries * 32 bytes (8 clusters)
+    assert_eq!(formatter.cluster_count_used, 11);
+
+    let root = Root::open(f).unwrap();
+    let fat = Fat::load(&root.disk, &root.boot, 0).unwrap();
+    let chain = fat
+        .read_chain(&root.disk, root.boot.first_cluster_of_root_directory)
+        .unwrap();
+    assert_eq!(chain.len(), 8);
+
+    let format_options = FormatVolumeOptionsBuilder::default()
+        .dev_size(size)
+        .bytes_per_sector(512)
+        .root_entries(u32::MAX / 32)
+        .build()
+        .unwrap();
+    assert!(matches!(
+        Exfat::try_from::<std::time::SystemTime>(format_options),
+        Err(ExfatFormatError::RootDirectoryTooBig(_))
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn conservative_format() {
+    use crate::format::FormatVolumeOptionsBuilder;
+    use crate::root::Root;
+    use crate::test_utils::{IMAGE_SIZE, format_options};
+    use std::vec::Vec;
+
+    let size = IMAGE_SIZE;
+    let format_options = format_options()
+        .file_system_revision((1, 1))
+        .conservative(true)
+        .build()
+        .unwrap();
+
+    let mut formatter = Exfat::try_from::<std::time::SystemTime>(format_options).unwrap();
+    let mut image = std::io::Cursor::new(vec![0u8; size as usize]);
+    formatter
+        .write::<std::time::SystemTime, std::io::Cursor<Vec<u8>>>(&mut image)
+        .unwrap();
+    let image = image.into_inner();
+
+    // minor and major revision
+    assert_eq!(image[104..106], [1, 1]);
+    // label, bitmap and up-case table, without a GUID placeholder
+    let root = formatter.root_offset_bytes as usize;
+    let types: Vec<u8> = (0..4).map(|i| image[root + i * 32]).collect();
+    assert_eq!(types, [0x83, 0x81, 0x82, 0]);
+    assert!(Root::open(std::io::Cursor::new(image)).is_ok());
+
+    assert!(
+        FormatVolumeOptionsBuilder::default()
+            .dev_size(size)
+            .bytes_per_sector(512)
+            .file_system_revision((2, 0))
+            .build()
+            .is_err()
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn failing_phase() {
+    use crate::test_utils::{IMAGE_SIZE, format_options};
+    use std::io::{Cursor, Seek, Write};
+
+    /// Device failing writes of anything but zeroes to a byte range.
+    struct Failing(Cursor<Vec<u8>>, core::ops::Range<u64>);
+    impl Write for Failing {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let start = self.0.position();
+            let end = start + buf.len() as u64;
+            if start < self.1.end && self.1.start < end && buf.iter().any(|b| *b != 0) {
+                return Err(std::io::Error::other("bad sector"));
+            }
+            Write::write(&mut self.0, buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush()
+        }
+    }
+    impl Seek for Failing {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            Seek::seek(&mut self.0, pos)
+        }
+    }
+
+    let size = IMAGE_SIZE;
+    let mut formatter =
+        Exfat::try_from::<std::time::SystemTime>(format_options().build().unwrap()).unwrap();
+    let fat_offset = formatter.fat_offset as u64 * 512;
+
+    let mut f = Failing(
+        Cursor::new(vec![0u8; size as usize]),
+        fat_offset..fat_offset + 1,
+    );
+    let err = formatter
+        .write::<std::time::SystemTime, _>(&mut f)
+        .unwrap_err();
+    assert!(matches!(err, ExfatError::Io(FormatPhase::WritingFat, _)));
+    assert_eq!(
+        err.to_string(),
+        "I/O error while writing the FAT: bad sector."
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn media_profiles() {
+    use std::time::SystemTime;
+
+    let size: u64 = 64 * crate::MB as u64;
+    let layout = |builder: &mut FormatVolumeOptionsBuilder| {
+        let format_options = builder
+            .dev_size(size)
+            .bytes_per_sector(512)
+            .build()
+            .unwrap();
+        Exfat::plan::<SystemTime>(format_options).unwrap().layout
+    };
+
+    let generic = layout(&mut FormatVolumeOptionsBuilder::default());
+    assert_eq!(generic.bytes_per_cluster, 4 * KB as u32);
+    // the packed bitmap precedes the aligned boundary
+    assert_ne!(generic.cluster_heap_offset % MB as u64, 0);
+
+    // SD cards use bigger clusters and keep the bitmap at the start of the aligned heap
+    let sd_card = layout(FormatVolumeOptionsBuilder::default().profile(MediaProfile::SdCard));
+    assert_eq!(sd_card.bytes_per_cluster, 32 * KB as u32);
+    assert_eq!(sd_card.fat_offset % (4 * MB) as u64, 0);
+    assert_eq!(sd_card.cluster_heap_offset % (4 * MB) as u64, 0);
+    assert_eq!(sd_card.bitmap_offset, sd_card.cluster_heap_offset);
+    assert_eq!(
+        MediaProfile::SdCard.boundary_align(128 * GB as u64),
+        32 * MB
+    );
+    assert_eq!(
+        MediaProfile::SdCard.cluster_size(128 * GB as u64),
+        128 * KB as u32
+    );
+
+    // options set explicitly take precedence over the profile
+    let packed = layout(
+        FormatVolumeOptionsBuilder::default()
+            .profile(MediaProfile::UsbFlash)
+            .pack_bitmap(true)
+            .boundary_align(MB),
+    );
+    assert_eq!(packed.bytes_per_cluster, 32 * KB as u32);
+    assert_eq!(packed.fat_offset, MB as u64);
+    assert_ne!(packed.cluster_heap_offset % MB as u64, 0);
+}
+
+#[cfg(test)]
+#[test]
+fn custom_cluster_size() {
+    use std::time::SystemTime;
+
+    let size: u64 = 64 * crate::MB as u64;
+    let options = |bytes_per_cluster| {
+        FormatVolumeOptionsBuilder::default()
+            .dev_size(size)
+            .bytes_per_sector(4096)
+            .bytes_per_cluster(bytes_per_cluster)
+            .build()
+    };
+
+    let plan = Exfat::plan::<SystemTime>(options(Some(256 * KB as u32)).unwrap()).unwrap();
+    assert_eq!(plan.layout.bytes_per_cluster, 256 * KB as u32);
+    assert!(plan.warnings.is_empty());
+
+    // smaller than a sector, not a power of two or too big
+    for bytes_per_cluster in [2048, 48 * KB as u32, 64 * MB] {
+        assert!(options(Some(bytes_per_cluster)).is_err());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn texfat_format() {
+    use crate::{check::ExfatChecker, disk::AuditDevice, root::Root, volume::Volume};
+    use core::cell::Cell;
+    use std::{io::Cursor, sync::Mutex, time::SystemTime};
+
+    let size: u64 = 32 * crate::MB as u64;
+    for pack_bitmap in [false, true] {
+        let format_options = FormatVolumeOptionsBuilder::default()
+            .pack_bitmap(pack_bitmap)
+            .dev_size(size)
+            .bytes_per_sector(512)
+            .number_of_fats(2)
+            .build()
+            .unwrap();
+        let plan = Exfat::plan::<SystemTime>(format_options).unwrap();
+        assert!(plan.warnings.is_empty());
+
+        let written = Cell::new(0u64);
+        let mut device = AuditDevice::new(Cursor::new(vec![0u8; size as usize]), |record| {
+            written.set(written.get() + record.len as u64)
+        });
+        let mut formatter = plan.formatter;
+        formatter.write::<SystemTime, _>(&mut device).unwrap();
+        assert_eq!(written.get(), plan.bytes_written);
+        let image = device.into_inner().into_inner();
+
+        let root = Root::open(Cursor::new(image.clone())).unwrap();
+        assert_eq!(root.boot.number_of_fats, 2);
+        assert_eq!(root.allocation_bitmaps.len(), 2);
+        assert!(root.compare_fats().unwrap().is_empty());
+        assert!(
+            ExfatChecker::check(Cursor::new(image.clone()))
+                .unwrap()
+                .is_clean()
+        );
+
+        // both FATs and bitmaps are kept in sync
+        let mut volume = Volume::open(Mutex::new(Cursor::new(image))).unwrap();
+        volume
+            .create_files::<SystemTime, _, _>("", [("file.txt", vec![1u8; 10_000])])
+            .unwrap();
+        let image = volume.root.disk.lock().unwrap().get_ref().clone();
+        assert!(ExfatChecker::check(Cursor::new(image)).unwrap().is_clean());
+    }
+
+    assert!(
+        FormatVolumeOptionsBuilder::default()
+            .dev_size(size)
+            .bytes_per_sector(512)
+            .number_of_fats(3)
+            .build()
+            .is_err()
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn initial_volume_flags() {
+    use crate::error::RootError;
+    use crate::root::{OpenOptionsBuilder, Root};
+    use crate::test_utils::{TestImage, format_options};
+    use std::io::Cursor;
+
+    let format_options = format_options()
+        .volume_flags(
+            VolumeFlags::VOLUME_DIRTY | VolumeFlags::MEDIA_FAILURE | VolumeFlags::ACTIVE_FAT,
+        )
+        .build()
+        .unwrap();
+    let image = TestImage::with_options(format_options).image;
+
+    let root = Root::open(Cursor::new(image.clone())).unwrap();
+    assert!(root.is_dirty());
+    assert_eq!(
+        VolumeFlags::from_bits_retain(root.boot.volume_flags),
+        VolumeFlags::VOLUME_DIRTY | VolumeFlags::MEDIA_FAILURE
+    );
+    let options = OpenOptionsBuilder::default()
+        .allow_dirty(false)
+        .build()
+        .unwrap();
+    assert!(matches!(
+        Root::open_with(Cursor::new(image), options),
+        Err(RootError::VolumeDirty)
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn discard_before_formatting() {
+    use crate::check::ExfatChecker;
+    use std::{io::Cursor, time::SystemTime};
+
+    #[derive(Debug)]
+    struct Trimmable {
+        device: Cursor<Vec<u8>>,
+        supported: bool,
+        discarded: Vec<(u64, u64)>,
+    }
+    impl WriteSeek for Trimmable {
+        type Err = std::io::Error;
+
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Err> {
+            WriteSeek::write(&mut self.device, buf)
+        }
+        fn failed_to_write(&self) -> Self::Err {
+            WriteSeek::failed_to_write(&self.device)
+        }
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Err> {
+            WriteSeek::write_all(&mut self.device, buf)
+        }
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Err> {
+            WriteSeek::seek(&mut self.device, pos)
+        }
+        fn stream_position(&mut self) -> Result<u64, Self::Err> {
+            WriteSeek::stream_position(&mut self.device)
+        }
+    }
+    impl BlockDiscard for Trimmable {
+        fn discard(&mut self, offset: u64, len: u64) -> Result<bool, Self::Err> {
+            if self.supported {
+                self.device.get_mut()[offset as usize..(offset + len) as usize].fill(0);
+                self.discarded.push((offset, len));
+            }
+            Ok(self.supported)
+        }
+    }
+
+    let size: u64 = 8 * crate::MB as u64;
+    for (full_format, supported) in [(true, true), (false, true), (true, false)] {
+        let format_options = FormatVolumeOptionsBuilder::default()
+            .dev_size(size)
+            .bytes_per_sector(512)
+            .full_format(full_format)
+            .build()
+            .unwrap();
+        let mut exfat = Exfat::try_from::<SystemTime>(format_options).unwrap();
+        let mut device = Trimmable {
+            // leftovers of a previous file system
+            device: Cursor::new(vec![0xAA; size as usize]),
+            supported,
+            discarded: Vec::new(),
+        };
+        exfat
+            .write_discarding::<SystemTime, _>(&mut device)
+            .unwrap();
+
+        let cleared = if full_format {
+            size
+        } else {
+            exfat.root_offset_bytes as u64
+                + exfat
+                    .root_length_bytes
+                    .next_multiple_of(exfat.bytes_per_cluster) as u64
+        };
+        if supported {
+            assert_eq!(device.discarded, [(0, cleared)]);
+        }
+        let image = device.device.into_inner();
+        if full_format {
+            assert!(image[size as usize - 512..].iter().all(|byte| *byte == 0));
+        }
+        assert!(ExfatChecker::check(Cursor::new(image)).unwrap().is_clean());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn extend_device() {
+    use crate::check::ExfatChecker;
+    use std::{io::Cursor, time::SystemTime};
+
+    let size: u64 = 8 * crate::MB as u64;
+    let format_options = |extend_device| {
+        FormatVolumeOptionsBuilder::default()
+            .dev_size(size)
+            .bytes_per_sector(512)
+            .extend_device(extend_device)
+            .build()
+            .unwrap()
+    };
+
+    // devices of the wrong length are rejected
+    for len in [size / 2, size + 512] {
+        let mut f = Cursor::new(vec![0u8; len as usize]);
+        let err = Exfat::try_from::<SystemTime>(format_options(false))
+            .unwrap()
+            .write::<SystemTime, _>(&mut f)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ExfatError::Format(ExfatFormatError::InvalidFileSize { dev_size, .. }) if dev_size == size
+        ));
+    }
+
+    // ... unless they are shorter and may be extended
+    let mut f = Cursor::new(Vec::new());
+    Exfat::try_from::<SystemTime>(format_options(true))
+        .unwrap()
+        .write::<SystemTime, _>(&mut f)
+        .unwrap();
+    let image = f.into_inner();
+    assert_eq!(image.len() as u64, size);
+    assert!(ExfatChecker::check(Cursor::new(image)).unwrap().is_clean());
+
+    let mut f = Cursor::new(vec![0u8; size as usize + 512]);
+    assert!(
+        Exfat::try_from::<SystemTime>(format_options(true))
+            .unwrap()
+            .write::<SystemTime, _>(&mut f)
+            .is_err()
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn sparse_format() {
+    use crate::{check::ExfatChecker, disk::AuditDevice};
+    use std::{cell::Cell, io::Cursor, time::SystemTime};
+
+    let size: u64 = 64 * crate::MB as u64;
+    for (len, sparse) in [(size, true), (0, false)] {
+        let format_options = FormatVolumeOptionsBuilder::default()
+            .dev_size(size)
+            .bytes_per_sector(512)
+            .full_format(true)
+            .sparse(sparse)
+            .extend_device(true)
+            .build()
+            .unwrap();
+        let plan = Exfat::plan::<SystemTime>(format_options).unwrap();
+
+        let written = Cell::new(0u64);
+        let mut device = AuditDevice::new(Cursor::new(vec![0u8; len as usize]), |record| {
+            written.set(written.get() + record.len as u64)
+        });
+        let mut formatter = plan.formatter;
+        formatter.write::<SystemTime, _>(&mut device).unwrap();
+
+        // only the filesystem structures are written, not the zeroes in between
+        assert!(written.get() < crate::MB as u64);
+        if sparse {
+            assert_eq!(written.get(), plan.bytes_written);
+        }
+        let image = device.into_inner().into_inner();
+        assert_eq!(image.len() as u64, size);
+        assert!(ExfatChecker::check(Cursor::new(image)).unwrap().is_clean());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn preserve_identity() {
+    use std::{io::Cursor, time::SystemTime};
+
+    let size: u64 = 8 * crate::MB as u64;
+    let format_options = FormatVolumeOptionsBuilder::default()
+        .dev_size(size)
+        .bytes_per_sector(512)
+        .guid(Some(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10))
+        .volume_serial_number(Some(0xCAFE_F00D))
+        .build()
+        .unwrap();
+    let mut f = Cursor::new(vec![0u8; size as usize]);
+    Exfat::try_from::<SystemTime>(format_options)
+        .unwrap()
+        .write::<SystemTime, _>(&mut f)
+        .unwrap();
+
+    // reformat in place with another cluster size
+    let root = Root::open(Cursor::new(f.get_ref().clone())).unwrap();
+    let format_options = FormatVolumeOptionsBuilder::default()
+        .dev_size(size)
+        .bytes_per_sector(512)
+        .bytes_per_cluster(Some(16 * KB as u32))
+        .preserve_identity(&root)
+        .build()
+        .unwrap();
+    drop(root);
+    Exfat::try_from::<SystemTime>(format_options)
+        .unwrap()
+        .write::<SystemTime, _>(&mut f)
+        .unwrap();
+
+    // the serial number is stored in little-endian byte order
+    assert_eq!(f.get_ref()[100..104], 0xCAFE_F00Du32.to_le_bytes());
+    let root = Root::open(f).unwrap();
+    assert_eq!(root.boot.bytes_per_cluster(), 16 * KB as u32);
+    assert_eq!(root.volume_serial_number(), 0xCAFE_F00D);
+    assert_eq!(
+        root.volume_guid(),
+        Some(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10)
+    );
+}
@@ -0,0 +1,98 @@
+use alloc::{vec, vec::Vec};
+
+use derive_builder::Builder;
+
+/// GUID identifying flash parameters, `{0A0C7E46-3399-4021-90C8-FA6D389C4BA2}`, in its on-disk
+/// byte order.
+const FLASH_PARAMETERS_GUID: [u8; 16] = [
+    0x46, 0x7E, 0x0C, 0x0A, 0x99, 0x33, 0x21, 0x40, 0x90, 0xC8, 0xFA, 0x6D, 0x38, 0x9C, 0x4B, 0xA2,
+];
+/// Size of one of the ten parameter records of the OEM parameters sector.
+const PARAMETERS_SIZE: usize = 48;
+
+/// Characteristics of the flash medium a volume is formatted for, stored in the OEM parameters
+/// sector of both boot regions. Hosts may use them to align their writes. It implements the
+/// [`derive_builder::Builder`] pattern; all parameters default to `0`, which means unknown.
+#[derive(Builder, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[builder(no_std)]
+pub struct FlashParameters {
+    /// Size of the erase blocks of the medium in bytes.
+    #[builder(default)]
+    erase_block_size: u32,
+    /// Size of the pages of the medium in bytes.
+    #[builder(default)]
+    page_size: u32,
+    /// Amount of sectors the medium reserves for bad sector replacement.
+    #[builder(default)]
+    spare_sectors: u32,
+    /// Random access time of the medium in nanoseconds.
+    #[builder(default)]
+    random_access_time: u32,
+    /// Time to program a page in nanoseconds.
+    #[builder(default)]
+    programming_time: u32,
+    /// Duration of a read cycle in nanoseconds.
+    #[builder(default)]
+    read_cycle: u32,
+    /// Duration of a write cycle in nanoseconds.
+    #[builder(default)]
+    write_cycle: u32,
+}
+
+impl FlashParameters {
+    /// The OEM parameters sector holding the flash parameters as its first record.
+    pub(super) fn oem_sector(&self, bytes_per_sector: u16) -> Vec<u8> {
+        let mut sector = vec![0u8; bytes_per_sector as usize];
+        let record = &mut sector[..PARAMETERS_SIZE];
+        record[..16].copy_from_slice(&FLASH_PARAMETERS_GUID);
+        let fields = [
+            self.erase_block_size,
+            self.page_size,
+            self.spare_sectors,
+            self.random_access_time,
+            self.programming_time,
+            self.read_cycle,
+            self.write_cycle,
+        ];
+        for (field, value) in record[16..44].chunks_exact_mut(4).zip(fields) {
+            field.copy_from_slice(&value.to_le_bytes());
+        }
+        sector
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn flash_parameters_sector() {
+    use crate::{
+        root::{OpenOptionsBuilder, Root},
+        test_utils::{TestImage, format_options},
+    };
+    use std::io::Cursor;
+
+    let flash = FlashParametersBuilder::default()
+        .erase_block_size(4 * crate::MB)
+        .page_size(16 * crate::KB as u32)
+        .build()
+        .unwrap();
+    let format_options = format_options()
+        .flash_parameters(Some(flash))
+        .build()
+        .unwrap();
+    let image = TestImage::with_options(format_options).image;
+
+    // both boot regions hold the parameters
+    for region in [0, 12] {
+        let sector = &image[(region + 9) * 512..(region + 10) * 512];
+        assert_eq!(sector[..16], FLASH_PARAMETERS_GUID);
+        assert_eq!(sector[16..20], (4 * crate::MB).to_le_bytes());
+        assert_eq!(sector[20..24], (16 * crate::KB as u32).to_le_bytes());
+        assert!(sector[24..].iter().all(|byte| *byte == 0));
+    }
+    // ... which are covered by the checksum
+    let options = OpenOptionsBuilder::default()
+        .verify_checksums(true)
+        .build()
+        .unwrap();
+    assert!(Root::open_with(Cursor::new(image), options).is_ok());
+}
@@ -0,0 +1,257 @@
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::{
+    boot_sector::BootSector, entry::DirEntry, error::ExfatFormatError, fat::FatEntry,
+    timestamp::Clock,
+};
+
+use super::{Exfat, FormatVolumeOptions, boot::MAX_CLUSTER_SIZE};
+
+/// The outcome of planning a format: what would be written, without touching the device.
+/// Created by [`Exfat::plan`].
+#[derive(Clone, Debug)]
+pub struct FormatPlan {
+    /// Location of the filesystem structures on the volume.
+    pub layout: FormatLayout,
+    /// Deviations from the requested options.
+    pub warnings: Vec<FormatWarning>,
+    /// Amount of bytes [`Exfat::write`] writes onto a device of `dev_size` bytes.
+    pub bytes_written: u64,
+    /// Formatter producing exactly this layout.
+    pub formatter: Exfat,
+}
+
+/// Location of the filesystem structures of a volume. All offsets and lengths are measured in
+/// bytes, relative to the start of the volume.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FormatLayout {
+    pub bytes_per_sector: u16,
+    pub bytes_per_cluster: u32,
+    pub fat_offset: u64,
+    /// Length of each FAT; volumes with two FATs store the second one right after the first.
+    pub fat_length: u64,
+    pub cluster_heap_offset: u64,
+    pub cluster_count: u32,
+    /// Offset of the first allocation bitmap. Volumes with two FATs store the second bitmap in
+    /// the clusters following the first one.
+    pub bitmap_offset: u64,
+    pub bitmap_length: u64,
+    pub upcase_table_offset: u64,
+    pub upcase_table_length: u64,
+    pub root_directory_offset: u64,
+    /// Space reserved for the root directory, a multiple of the cluster size.
+    pub root_directory_length: u64,
+}
+
+/// A deviation from the requested [`FormatVolumeOptions`] the formatter had to make.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FormatWarning {
+    /// The default cluster size results in too many clusters, so a bigger one is used.
+    ClusterSizeIncreased { default: u32, used: u32 },
+    /// The allocation bitmap cannot be packed before the cluster heap, so it is stored in the
+    /// first clusters of the heap instead.
+    BitmapPackingDisabled(BitmapPackingConstraint),
+}
+
+/// The reason the allocation bitmap cannot be packed in front of the cluster heap.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitmapPackingConstraint {
+    /// The bitmap does not fit between the end of the FAT and the aligned start of the cluster
+    /// heap.
+    NoRoomBeforeClusterHeap,
+    /// The clusters gained by moving the bitmap would exceed the maximum cluster count.
+    TooManyClusters,
+}
+
+impl Display for BitmapPackingConstraint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BitmapPackingConstraint::NoRoomBeforeClusterHeap => write!(
+                f,
+                "no room between the end of the FAT and the start of the cluster heap"
+            ),
+            BitmapPackingConstraint::TooManyClusters => {
+                write!(f, "the maximum cluster count would be exceeded")
+            }
+        }
+    }
+}
+
+impl Display for FormatWarning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FormatWarning::ClusterSizeIncreased { default, used } => write!(
+                f,
+                "Cluster size increased from {default} to {used} bytes to address the whole volume"
+            ),
+            FormatWarning::BitmapPackingDisabled(constraint) => write!(
+                f,
+                "Allocation bitmap cannot be packed ({constraint}) and is stored in the cluster heap"
+            ),
+        }
+    }
+}
+
+impl Exfat {
+    /// Computes the layout a format with the given options would produce, without touching the
+    /// device. Unlike [`Exfat::try_from`], options that cannot be honored are adjusted where
+    /// possible (see [`FormatWarning`]) instead of failing.
+    pub fn plan<T: Clock>(
+        format_options: FormatVolumeOptions,
+    ) -> Result<FormatPlan, ExfatFormatError<T>> {
+        let default = format_options.bytes_per_cluster();
+        let mut options = format_options;
+        options.pack_bitmap_fallback = true;
+        let mut bytes_per_cluster = default;
+        let mut warnings = Vec::new();
+        let mut first_error = None;
+
+        let formatter = loop {
+            match Exfat::with_cluster_size::<T>(options, bytes_per_cluster) {
+                Ok(formatter) => break formatter,
+                Err(err @ ExfatFormatError::InvlaidClusterSize(_))
+                    if bytes_per_cluster < MAX_CLUSTER_SIZE =>
+                {
+                    first_error.get_or_insert(err);
+                    bytes_per_cluster *= 2;
+                }
+                // report the problem of the default cluster size, not of the biggest one
+                Err(err) => return Err(first_error.unwrap_or(err)),
+            }
+        };
+
+        warnings.extend(
+            formatter
+                .packing_constraint
+                .map(FormatWarning::BitmapPackingDisabled),
+        );
+        if bytes_per_cluster != default {
+            warnings.push(FormatWarning::ClusterSizeIncreased {
+                default,
+                used: bytes_per_cluster,
+            });
+        }
+
+        Ok(FormatPlan {
+            layout: formatter.layout(),
+            warnings,
+            bytes_written: formatter.bytes_written(),
+            formatter,
+        })
+    }
+
+    pub(super) fn layout(&self) -> FormatLayout {
+        let bytes_per_sector = self.format_options.bytes_per_sector;
+        let bytes_per_cluster = self.bytes_per_cluster;
+        FormatLayout {
+            bytes_per_sector,
+            bytes_per_cluster,
+            fat_offset: self.fat_offset as u64 * bytes_per_sector as u64,
+            fat_length: self.fat_length as u64 * bytes_per_sector as u64,
+            cluster_heap_offset: self.cluster_heap_offset as u64 * bytes_per_sector as u64,
+            cluster_count: self.cluster_count,
+            bitmap_offset: self.bitmap_offset_bytes as u64,
+            bitmap_length: self.bitmap_length_bytes as u64,
+            upcase_table_offset: self.uptable_offset_bytes as u64,
+            upcase_table_length: self.uptable_length_bytes as u64,
+            root_directory_offset: self.root_offset_bytes as u64,
+            root_directory_length: self.root_length_bytes.next_multiple_of(bytes_per_cluster)
+                as u64,
+        }
+    }
+
+    /// Amount of bytes written by [`Exfat::write`].
+    fn bytes_written(&self) -> u64 {
+        let bytes_per_sector = self.format_options.bytes_per_sector as u64;
+        let clusters = |bytes: u32| bytes.div_ceil(self.bytes_per_cluster) as u64;
+
+        let zeroed = self.cleared_size();
+        // boot sector followed by eleven full sectors
+        let boot_region = size_of::<BootSector>() as u64 + 11 * bytes_per_sector;
+        let fats = self.number_of_fats as u64;
+        // media type, reserved entry and the chains of bitmaps, up-case table and root directory
+        let fat_entries = fats
+            * (2 + fats * clusters(self.bitmap_length_bytes)
+                + clusters(self.uptable_length_bytes)
+                + clusters(self.root_length_bytes));
+        let root_entries = self.raw_root().metadata_entries() as u64 * size_of::<DirEntry>() as u64;
+
+        zeroed
+            + 2 * boot_region
+            + fat_entries * size_of::<FatEntry>() as u64
+            + fats * self.bitmap_length_bytes as u64
+            + self.uptable_length_bytes as u64
+            + root_entries
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn plan_matches_format() {
+    use crate::{
+        disk::AuditDevice,
+        format::FormatVolumeOptionsBuilder,
+        test_utils::{IMAGE_SIZE, format_options},
+    };
+    use core::cell::Cell;
+    use std::{io::Cursor, time::SystemTime};
+
+    let size = IMAGE_SIZE;
+    let options = format_options().root_entries(300).build().unwrap();
+
+    let plan = Exfat::plan::<SystemTime>(options).unwrap();
+    assert!(plan.warnings.is_empty());
+    assert_eq!(plan.layout.bytes_per_cluster, 4096);
+    assert_eq!(plan.layout.root_directory_length, 3 * 4096);
+    assert_eq!(
+        plan.layout.root_directory_offset,
+        plan.layout.upcase_table_offset + plan.layout.upcase_table_length.next_multiple_of(4096)
+    );
+
+    let written = Cell::new(0u64);
+    let mut device = AuditDevice::new(Cursor::new(vec![0u8; size as usize]), |record| {
+        written.set(written.get() + record.len as u64)
+    });
+    let mut formatter = plan.formatter;
+    formatter.write::<SystemTime, _>(&mut device).unwrap();
+    assert_eq!(written.get(), plan.bytes_written);
+
+    // no room in front of the cluster heap for the bitmap
+    let options = FormatVolumeOptionsBuilder::default()
+        .dev_size(size)
+        .bytes_per_sector(512)
+        .boundary_align(512)
+        .build()
+        .unwrap();
+    assert!(matches!(
+        Exfat::try_from::<SystemTime>(options),
+        Err(ExfatFormatError::CannotPackBitmap(
+            BitmapPackingConstraint::NoRoomBeforeClusterHeap
+        ))
+    ));
+    let plan = Exfat::plan::<SystemTime>(options).unwrap();
+    assert_eq!(
+        plan.warnings,
+        [FormatWarning::BitmapPackingDisabled(
+            BitmapPackingConstraint::NoRoomBeforeClusterHeap
+        )]
+    );
+    assert_eq!(
+        plan.formatter.bitmap_packing_constraint(),
+        Some(BitmapPackingConstraint::NoRoomBeforeClusterHeap)
+    );
+    let options = FormatVolumeOptionsBuilder::default()
+        .dev_size(size)
+        .bytes_per_sector(512)
+        .boundary_align(512)
+        .pack_bitmap_fallback(true)
+        .build()
+        .unwrap();
+    let formatter = Exfat::try_from::<SystemTime>(options).unwrap();
+    assert_eq!(formatter.layout(), plan.layout);
+    assert_eq!(
+        plan.layout.bitmap_offset, plan.layout.cluster_heap_offset,
+        "bitmap must start the cluster heap"
+    );
+}
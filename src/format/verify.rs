@@ -0,0 +1,350 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    checksum,
+    disk::{ReadOffset, SeekFrom, WriteSeek},
+    error::{ExfatError, FormatPhase},
+    root::boot_checksum_valid,
+    timestamp::Clock,
+};
+
+use super::{
+    Exfat,
+    boot::{BACKUP_BOOT_OFFSET, MAIN_BOOT_OFFSET},
+    upcase_table::DEFAULT_UPCASE_TABLE,
+};
+
+/// Sectors of a boot region: the boot sector, the extended boot sectors, the OEM parameters,
+/// the reserved sector and the checksum sector.
+const BOOT_REGION_SECTORS: u64 = 12;
+
+/// A file system structure written by the formatter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FormatStructure {
+    MainBootRegion,
+    BackupBootRegion,
+    /// The FAT with the given index.
+    Fat(u8),
+    /// The allocation bitmap with the given index.
+    AllocationBitmap(u8),
+    UpcaseTable,
+    RootDirectory,
+}
+
+/// A run of bytes that read back differently from how they were written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub structure: FormatStructure,
+    /// Offset (in bytes) of the run, relative to the start of the structure.
+    pub offset: u64,
+    /// Bytes as written.
+    pub expected: Vec<u8>,
+    /// Bytes as read back.
+    pub actual: Vec<u8>,
+}
+
+/// Result of [`Exfat::write_and_verify`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Written bytes that read back differently, in ascending order of their offsets.
+    pub mismatches: Vec<Mismatch>,
+    /// Boot regions and up-case tables whose checksum does not match after reading them back.
+    pub bad_checksums: Vec<FormatStructure>,
+}
+
+impl VerifyReport {
+    /// Whether everything read back as written.
+    pub fn is_clean(&self) -> bool {
+        self.mismatches.is_empty() && self.bad_checksums.is_empty()
+    }
+}
+
+impl Exfat {
+    /// Like [`Exfat::write`], but reads all written structures back afterwards and compares them
+    /// against what was written, e.g. to detect faulty media. The boot region and up-case table
+    /// checksums are verified as well. Devices should not cache reads for this to be meaningful.
+    pub fn write_and_verify<T: Clock, O>(
+        &mut self,
+        f: &mut O,
+    ) -> Result<VerifyReport, ExfatError<T, O>>
+    where
+        O: WriteSeek + ReadOffset<Err = <O as WriteSeek>::Err>,
+        T::Err: core::fmt::Debug,
+    {
+        self.prepare(f, |f, size| crate::disk::write_zeroes(f, size, 0))?;
+
+        let structures = self.structures();
+        let mut recorder = Recorder {
+            device: f,
+            position: 0,
+            structures: &structures,
+            writes: Vec::new(),
+        };
+        recorder
+            .seek(SeekFrom::Start(0))
+            .map_err(|err| ExfatError::Io(FormatPhase::CheckingDevice, err))?;
+        self.write_regions(&mut recorder)
+            .map_err(|(phase, err)| ExfatError::Io(phase, err))?;
+        let writes = recorder.writes;
+
+        let verifying = |err| ExfatError::Io(FormatPhase::Verifying, err);
+        let mut report = VerifyReport::default();
+        for (offset, expected) in writes {
+            let Some(index) = structure_at(&structures, offset) else {
+                continue;
+            };
+            let (structure, start, _) = structures[index];
+            let mut actual = vec![0u8; expected.len()];
+            f.read_exact(offset, &mut actual).map_err(verifying)?;
+            report.mismatches.extend(mismatches(&expected, &actual).map(
+                |(run, expected, actual)| Mismatch {
+                    structure,
+                    offset: offset - start + run as u64,
+                    expected,
+                    actual,
+                },
+            ));
+        }
+
+        let bytes_per_sector = self.format_options.bytes_per_sector;
+        for (structure, offset) in [
+            (FormatStructure::MainBootRegion, MAIN_BOOT_OFFSET),
+            (FormatStructure::BackupBootRegion, BACKUP_BOOT_OFFSET),
+        ] {
+            if !boot_checksum_valid(f, bytes_per_sector, offset).map_err(verifying)? {
+                report.bad_checksums.push(structure);
+            }
+        }
+        let mut upcase_table = vec![0u8; self.uptable_length_bytes as usize];
+        f.read_exact(self.uptable_offset_bytes as u64, &mut upcase_table)
+            .map_err(verifying)?;
+        if checksum::upcase_table(&upcase_table) != checksum::upcase_table(&DEFAULT_UPCASE_TABLE) {
+            report.bad_checksums.push(FormatStructure::UpcaseTable);
+        }
+
+        Ok(report)
+    }
+
+    /// Offset and length (in bytes) of each structure, in ascending order of their offsets.
+    fn structures(&self) -> Vec<(FormatStructure, u64, u64)> {
+        let layout = self.layout();
+        let boot_region_length = BOOT_REGION_SECTORS * layout.bytes_per_sector as u64;
+        let bitmap_stride = layout
+            .bitmap_length
+            .next_multiple_of(layout.bytes_per_cluster as u64);
+
+        let mut structures = vec![
+            (
+                FormatStructure::MainBootRegion,
+                MAIN_BOOT_OFFSET * layout.bytes_per_sector as u64,
+                boot_region_length,
+            ),
+            (
+                FormatStructure::BackupBootRegion,
+                BACKUP_BOOT_OFFSET * layout.bytes_per_sector as u64,
+                boot_region_length,
+            ),
+        ];
+        for index in 0..self.number_of_fats {
+            structures.push((
+                FormatStructure::Fat(index),
+                layout.fat_offset + index as u64 * layout.fat_length,
+                layout.fat_length,
+            ));
+        }
+        for index in 0..self.number_of_fats {
+            structures.push((
+                FormatStructure::AllocationBitmap(index),
+                layout.bitmap_offset + index as u64 * bitmap_stride,
+                layout.bitmap_length,
+            ));
+        }
+        structures.push((
+            FormatStructure::UpcaseTable,
+            layout.upcase_table_offset,
+            layout.upcase_table_length,
+        ));
+        structures.push((
+            FormatStructure::RootDirectory,
+            layout.root_directory_offset,
+            layout.root_directory_length,
+        ));
+        structures.sort_by_key(|(_, offset, _)| *offset);
+        structures
+    }
+}
+
+/// Index of the structure containing `offset`.
+fn structure_at(structures: &[(FormatStructure, u64, u64)], offset: u64) -> Option<usize> {
+    structures
+        .iter()
+        .position(|(_, start, len)| (*start..start + len).contains(&offset))
+}
+
+/// Runs of differing bytes: their offset and contents in `expected` and `actual`.
+fn mismatches<'a>(
+    expected: &'a [u8],
+    actual: &'a [u8],
+) -> impl Iterator<Item = (usize, Vec<u8>, Vec<u8>)> + 'a {
+    let mut i = 0;
+    core::iter::from_fn(move || {
+        while i < expected.len() && expected[i] == actual[i] {
+            i += 1;
+        }
+        if i == expected.len() {
+            return None;
+        }
+        let start = i;
+        while i < expected.len() && expected[i] != actual[i] {
+            i += 1;
+        }
+        Some((
+            start,
+            expected[start..i].to_vec(),
+            actual[start..i].to_vec(),
+        ))
+    })
+}
+
+/// Passes writes through to a device and keeps a copy of them, merging adjacent writes to the
+/// same structure.
+struct Recorder<'a, O> {
+    device: &'a mut O,
+    position: u64,
+    structures: &'a [(FormatStructure, u64, u64)],
+    writes: Vec<(u64, Vec<u8>)>,
+}
+
+impl<O: WriteSeek> Recorder<'_, O> {
+    fn record(&mut self, buf: &[u8]) {
+        let structure = structure_at(self.structures, self.position);
+        if structure.is_none() {
+            return;
+        }
+        match self.writes.last_mut() {
+            Some((offset, bytes))
+                if *offset + bytes.len() as u64 == self.position
+                    && structure_at(self.structures, *offset) == structure =>
+            {
+                bytes.extend_from_slice(buf);
+            }
+            _ => self.writes.push((self.position, buf.to_vec())),
+        }
+    }
+}
+
+impl<O: WriteSeek> WriteSeek for Recorder<'_, O> {
+    type Err = O::Err;
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Err> {
+        let amount = self.device.write(buf)?;
+        self.record(&buf[..amount]);
+        self.position += amount as u64;
+        Ok(amount)
+    }
+    fn failed_to_write(&self) -> Self::Err {
+        self.device.failed_to_write()
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Err> {
+        self.device.write_all(buf)?;
+        self.record(buf);
+        self.position += buf.len() as u64;
+        Ok(())
+    }
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Err> {
+        self.position = self.device.seek(pos)?;
+        Ok(self.position)
+    }
+    fn stream_position(&mut self) -> Result<u64, Self::Err> {
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn write_and_verify() {
+    use crate::test_utils::{IMAGE_SIZE, format_options};
+    use std::{io::Cursor, time::SystemTime};
+
+    /// Flips the bits of the byte at `offset` whenever it is written.
+    #[derive(Debug)]
+    struct Flaky {
+        device: Cursor<Vec<u8>>,
+        offsets: Vec<u64>,
+    }
+    impl WriteSeek for Flaky {
+        type Err = std::io::Error;
+
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Err> {
+            let position = self.device.position();
+            let amount = WriteSeek::write(&mut self.device, buf)?;
+            for offset in &self.offsets {
+                if (position..position + amount as u64).contains(offset) {
+                    self.device.get_mut()[*offset as usize] ^= 0xFF;
+                }
+            }
+            Ok(amount)
+        }
+        fn failed_to_write(&self) -> Self::Err {
+            WriteSeek::failed_to_write(&self.device)
+        }
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Self::Err> {
+            while !buf.is_empty() {
+                let amount = WriteSeek::write(self, buf)?;
+                buf = &buf[amount..];
+            }
+            Ok(())
+        }
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Err> {
+            WriteSeek::seek(&mut self.device, pos)
+        }
+        fn stream_position(&mut self) -> Result<u64, Self::Err> {
+            Ok(self.device.position())
+        }
+    }
+    impl ReadOffset for Flaky {
+        type Err = std::io::Error;
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            self.device.read_at(offset, buf)
+        }
+    }
+
+    let size = IMAGE_SIZE;
+    let format_options = format_options().number_of_fats(2).build().unwrap();
+    let mut exfat = Exfat::try_from::<SystemTime>(format_options).unwrap();
+    let layout = exfat.layout();
+
+    let mut device = Flaky {
+        device: Cursor::new(vec![0u8; size as usize]),
+        offsets: Vec::new(),
+    };
+    let report = exfat
+        .write_and_verify::<SystemTime, _>(&mut device)
+        .unwrap();
+    assert!(report.is_clean(), "{report:?}");
+
+    // the volume serial number of the backup boot region and an entry of the second FAT
+    let serial_number = 12 * 512 + 100;
+    let fat_entry = layout.fat_offset + layout.fat_length + 4;
+    let mut device = Flaky {
+        device: Cursor::new(vec![0u8; size as usize]),
+        offsets: vec![serial_number, fat_entry],
+    };
+    let report = exfat
+        .write_and_verify::<SystemTime, _>(&mut device)
+        .unwrap();
+    assert_eq!(report.mismatches.len(), 2);
+    assert_eq!(
+        report.mismatches[0].structure,
+        FormatStructure::BackupBootRegion
+    );
+    assert_eq!(report.mismatches[0].offset, 100);
+    assert_eq!(
+        report.mismatches[0].actual,
+        [report.mismatches[0].expected[0] ^ 0xFF]
+    );
+    assert_eq!(report.mismatches[1].structure, FormatStructure::Fat(1));
+    assert_eq!(report.mismatches[1].offset, 4);
+    assert_eq!(report.bad_checksums, [FormatStructure::BackupBootRegion]);
+}
@@ -1,45 +1,109 @@
 use crate::{
     boot_sector::BootSector,
+    cache::Lock,
+    check::Finding,
     cluster::{ClusterChainOptions, reader::ClusterChainReader},
+    diagnostics::RawEntry,
     disk::ReadOffset,
-    entry::{DirEntry, StreamExtensionEntry, parsed::ParsedFileEntry, reader::DirEntryReader},
-    error::DirectoryError,
+    entry::{
+        DirEntry, FileAttributes, StreamExtensionEntry, parsed::ParsedFileEntry,
+        reader::DirEntryReader,
+    },
+    error::{ClusterChainError, DirectoryError},
     fat::Fat,
+    name::{NameMatcher, UpcaseTable},
+    root::OpenOptions,
     timestamp::Timestamps,
+    volume::write::WriteState,
 };
-use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::{format, string::String, vec::Vec};
 
-use super::{FsElement, file::File};
+use super::{EntryLocation, FsElement, file::File};
+
+/// Elements of a directory and the damaged entry sets skipped while reading them.
+pub type Listing<O> = (Vec<FsElement<O>>, Vec<Finding>);
 
 /// Represents a directory in an exFAT filesystem.
 pub struct Directory<O> {
-    disk: Arc<O>,
-    boot: Arc<BootSector>,
-    fat: Arc<Fat>,
+    pub(super) disk: Arc<O>,
+    pub(super) boot: Arc<BootSector>,
+    pub(super) fat: Arc<Fat>,
     name: String,
-    stream: StreamExtensionEntry,
+    pub(super) stream: StreamExtensionEntry,
     timestamps: Timestamps,
+    pub(super) attributes: FileAttributes,
+    location: EntryLocation,
+    /// Offsets of the entries of the directory's entry set.
+    pub(crate) entry_offsets: Vec<u64>,
+    /// Generation of the device the handle was read from.
+    pub(super) generation: Option<u64>,
+    /// Options the volume was opened with, which also apply to reading the directory.
+    pub(super) options: OpenOptions,
+    /// Whether the volume must not be modified (see
+    /// [`Root::is_read_only`](crate::root::Root::is_read_only)).
+    pub(super) read_only: bool,
+    /// Allocation state shared by all handles of the volume.
+    pub(super) writes: Arc<Lock<WriteState>>,
+    /// Path of the directory relative to the root directory, separated by `/`.
+    pub(super) path: String,
+    /// Up-case table of the volume, used to compare names.
+    pub(super) upcase_table: Arc<UpcaseTable>,
 }
 
-type Type = BootSector;
+// not derived, as the device is shared instead of cloned
+impl<O> Clone for Directory<O> {
+    fn clone(&self) -> Self {
+        Self {
+            disk: Arc::clone(&self.disk),
+            boot: Arc::clone(&self.boot),
+            fat: Arc::clone(&self.fat),
+            name: self.name.clone(),
+            stream: self.stream,
+            timestamps: self.timestamps,
+            attributes: self.attributes,
+            location: self.location,
+            entry_offsets: self.entry_offsets.clone(),
+            generation: self.generation,
+            options: self.options,
+            read_only: self.read_only,
+            writes: Arc::clone(&self.writes),
+            path: self.path.clone(),
+            upcase_table: Arc::clone(&self.upcase_table),
+        }
+    }
+}
 
 impl<O> Directory<O> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         disk: Arc<O>,
-        boot: Arc<Type>,
+        boot: Arc<BootSector>,
         fat: Arc<Fat>,
-        name: String,
-        stream: StreamExtensionEntry,
-        timestamps: Timestamps,
+        upcase_table: Arc<UpcaseTable>,
+        parsed: ParsedFileEntry,
+        generation: Option<u64>,
+        options: OpenOptions,
+        read_only: bool,
+        writes: Arc<Lock<WriteState>>,
+        path: String,
     ) -> Self {
         Self {
             disk,
             boot,
             fat,
-            name,
-            stream,
-            timestamps,
+            name: parsed.name,
+            stream: parsed.stream_extension_entry,
+            timestamps: parsed.timestamps,
+            attributes: parsed.attributes,
+            location: parsed.location,
+            entry_offsets: parsed.entry_offsets,
+            generation,
+            options,
+            read_only,
+            writes,
+            path,
+            upcase_table,
         }
     }
 
@@ -50,13 +114,60 @@ impl<O> Directory<O> {
     pub fn timestamps(&self) -> &Timestamps {
         &self.timestamps
     }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.attributes
+    }
+
+    /// Whether the directory is marked read-only, which is independent of whether the volume can be
+    /// modified (see [`Root::is_read_only`](crate::root::Root::is_read_only)).
+    pub fn is_read_only(&self) -> bool {
+        self.attributes.contains(FileAttributes::READ_ONLY)
+    }
+
+    /// Whether the directory is hidden from regular listings.
+    pub fn is_hidden(&self) -> bool {
+        self.attributes.contains(FileAttributes::HIDDEN)
+    }
+
+    /// Whether the directory is used by the operating system.
+    pub fn is_system(&self) -> bool {
+        self.attributes.contains(FileAttributes::SYSTEM)
+    }
+
+    /// Whether the directory is marked for archiving, i.e. modified since the last backup.
+    pub fn is_archive(&self) -> bool {
+        self.attributes.contains(FileAttributes::ARCHIVE)
+    }
+
+    /// On-disk location of the directory's entry set.
+    pub fn location(&self) -> EntryLocation {
+        self.location
+    }
+
+    /// Up-case table of the volume, e.g. to look up elements the way Windows does with
+    /// [`Directory::find`].
+    pub fn upcase_table(&self) -> &UpcaseTable {
+        &self.upcase_table
+    }
+
+    /// Whether the directory is stored contiguously, without a FAT chain.
+    pub(crate) fn no_fat_chain(&self) -> bool {
+        self.stream.general_secondary_flags.no_fat_chain()
+    }
 }
 
 impl<O: ReadOffset> Directory<O> {
-    pub fn open(&self) -> Result<Vec<FsElement<O>>, DirectoryError<O>>
-    where
-        O::Err: core::fmt::Debug,
-    {
+    /// Whether the device has been modified since this handle was read (see
+    /// [`ReadOffset::generation`]). Stale directories can no longer be opened.
+    pub fn is_stale(&self) -> bool {
+        self.disk.generation() != self.generation
+    }
+
+    /// Creates a reader over the cluster chain holding the directory entries.
+    pub(crate) fn cluster_reader(
+        &self,
+    ) -> Result<ClusterChainReader<Arc<O>, Arc<BootSector>>, ClusterChainError> {
         let options = if self.stream.general_secondary_flags.no_fat_chain() {
             ClusterChainOptions::Contiguous {
                 data_length: self.stream.data_len,
@@ -67,16 +178,40 @@ impl<O: ReadOffset> Directory<O> {
             }
         };
 
-        let mut reader = DirEntryReader::from(ClusterChainReader::try_new(
+        ClusterChainReader::try_new(
             Arc::clone(&self.boot),
             &self.fat,
             self.stream.first_cluster,
             options,
             Arc::clone(&self.disk),
-        )?);
+        )
+        .map(|reader| reader.with_profile(self.options.read_profile))
+    }
+
+    /// Reads the elements of the directory. Fails with [`DirectoryError::StaleHandle`] if the
+    /// device is modified before or while reading.
+    pub fn open(&self) -> Result<Vec<FsElement<O>>, DirectoryError<O>>
+    where
+        O::Err: core::fmt::Debug,
+    {
+        self.open_with_findings().map(|(items, _)| items)
+    }
+
+    /// Reads the elements of the directory like [`Directory::open`], also returning the damaged
+    /// entry sets which were skipped if the volume was opened with
+    /// [`OpenOptionsBuilder::skip_damaged_entry_sets`](crate::root::OpenOptionsBuilder::skip_damaged_entry_sets).
+    pub fn open_with_findings(&self) -> Result<Listing<O>, DirectoryError<O>>
+    where
+        O::Err: core::fmt::Debug,
+    {
+        if self.is_stale() {
+            return Err(DirectoryError::StaleHandle);
+        }
+        let mut reader = DirEntryReader::from(self.cluster_reader()?);
 
         // Read file entries.
         let mut items: Vec<FsElement<O>> = Vec::new();
+        let mut findings: Vec<Finding> = Vec::new();
 
         loop {
             // read primary entry
@@ -91,37 +226,134 @@ impl<O: ReadOffset> Directory<O> {
             if !entry.regular() {
                 break;
             } else if !entry.primary() {
-                return Err(DirectoryError::NotPrimaryEntry(entry.entry_type()));
+                return Err(DirectoryError::NotPrimaryEntry(
+                    RawEntry(entry.bytes()),
+                    reader.last_location(),
+                ));
             }
 
             let DirEntry::File(entry) = entry else {
-                return Err(DirectoryError::NotFileEntry(entry.entry_type()));
+                return Err(DirectoryError::NotFileEntry(
+                    RawEntry(entry.bytes()),
+                    reader.last_location(),
+                ));
             };
 
             // parse file entry
-            let parsed = ParsedFileEntry::try_new(&entry, &mut reader)?;
+            let location = reader.last_location();
+            let parsed = match ParsedFileEntry::try_new(&entry, &mut reader) {
+                Ok(parsed) => parsed,
+                Err(err) if self.options.skip_damaged_entry_sets && err.is_damage() => {
+                    findings.push(Finding::DamagedEntrySet { location });
+                    reader.skip_entry_set(location)?;
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let path = format!("{}/{}", self.path, parsed.name);
             let item = if entry.file_attributes.is_directory() {
                 FsElement::D(Directory::new(
                     Arc::clone(&self.disk),
                     Arc::clone(&self.boot),
                     Arc::clone(&self.fat),
-                    parsed.name,
-                    parsed.stream_extension_entry,
-                    parsed.timestamps,
+                    Arc::clone(&self.upcase_table),
+                    parsed,
+                    self.generation,
+                    self.options,
+                    self.read_only,
+                    Arc::clone(&self.writes),
+                    path,
                 ))
             } else {
-                FsElement::F(File::try_new(
+                match File::try_new(
                     &self.disk,
                     &self.boot,
                     &self.fat,
-                    parsed.name,
-                    parsed.stream_extension_entry,
-                    parsed.timestamps,
-                )?)
+                    parsed,
+                    self.generation,
+                    &self.options,
+                    self.read_only,
+                    &self.writes,
+                    path,
+                ) {
+                    Ok(file) => FsElement::F(file),
+                    Err(_) if self.options.skip_damaged_entry_sets => {
+                        findings.push(Finding::DamagedEntrySet { location });
+                        continue;
+                    }
+                    Err(err) => return Err(err.into()),
+                }
             };
             items.push(item);
         }
 
-        Ok(items)
+        if self.is_stale() {
+            return Err(DirectoryError::StaleHandle);
+        }
+        Ok((items, findings))
+    }
+
+    /// Looks up an element of this directory by name, comparing names with `matcher` (e.g. a
+    /// [`Normalization`](crate::name::Normalization)).
+    pub fn find(
+        &self,
+        name: &str,
+        matcher: impl NameMatcher,
+    ) -> Result<Option<FsElement<O>>, DirectoryError<O>>
+    where
+        O::Err: core::fmt::Debug,
+    {
+        Ok(self
+            .open()?
+            .into_iter()
+            .find(|item| matcher.matches(item.name(), name)))
     }
 }
+
+#[cfg(test)]
+#[test]
+fn find_with_normalization() {
+    use crate::{name::Normalization, root::Root, test_utils::TestImage};
+
+    let mut image = TestImage::sample();
+    // decomposed spelling, as written by macOS
+    image.add_entry_set(101, "cafe\u{301}.txt", false, 103, 0);
+
+    let mut root = Root::open(image.cursor()).unwrap();
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("directory not found");
+    };
+
+    assert!(dir.find("café.txt", Normalization::None).unwrap().is_none());
+    let found = dir.find("café.txt", Normalization::Canonical).unwrap();
+    assert_eq!(
+        found.map(|f| f.name().to_string()).unwrap(),
+        "cafe\u{301}.txt"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn find_with_matcher() {
+    use crate::{name::UpcaseTable, root::Root, test_utils::TestImage};
+
+    let mut root = Root::open(TestImage::sample().cursor()).unwrap();
+    let upcase_table = UpcaseTable::default();
+    let Some(FsElement::D(dir)) = root.find("DIR", &upcase_table) else {
+        panic!("directory not found");
+    };
+    assert!(dir.find("Nested.TXT", &upcase_table).unwrap().is_some());
+
+    // custom rules, e.g. ignoring the extension
+    let stem = |name: &str| {
+        name.rsplit_once('.')
+            .map_or(name, |(stem, _)| stem)
+            .to_string()
+    };
+    let found = dir
+        .find("nested.md", |stored: &str, searched: &str| {
+            stem(stored) == stem(searched)
+        })
+        .unwrap();
+    assert_eq!(found.map(|f| f.name().to_string()).unwrap(), "nested.txt");
+}
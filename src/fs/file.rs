@@ -1,61 +1,166 @@
-use alloc::sync::Arc;
+use alloc::{collections::BTreeSet, string::String, sync::Arc, vec, vec::Vec};
 
 use crate::{
+    FIRST_USABLE_CLUSTER_INDEX,
     boot_sector::BootSector,
+    cache::Lock,
+    check::{ClusterMap, Finding},
     cluster::{ClusterChainOptions, reader::ClusterChainReader},
     disk::{self, ReadOffset},
-    entry::StreamExtensionEntry,
+    entry::{FileAttributes, parsed::ParsedFileEntry},
     error::ClusterChainError,
-    fat::Fat,
+    fat::{Fat, FatEntry},
+    root::{OpenOptions, ZeroLengthPolicy},
     timestamp::Timestamps,
+    volume::write::WriteState,
+};
+
+use super::EntryLocation;
+#[cfg(feature = "std")]
+use crate::{
+    checksum::{BlockHashes, ContentHasher},
+    error::ExtractError,
 };
 
-#[derive(Clone)]
 pub struct File<O: disk::ReadOffset> {
+    pub(super) disk: Arc<O>,
+    pub(super) boot: Arc<BootSector>,
+    pub(super) fat: Arc<Fat>,
     name: String,
-    len: u64,
-    reader: Option<ClusterChainReader<Arc<O>, Arc<BootSector>>>,
-    timestamps: Timestamps,
+    pub(super) len: u64,
+    pub(super) allocated_len: u64,
+    pub(super) clusters: u32,
+    pub(super) first_cluster: u32,
+    pub(super) no_fat_chain: bool,
+    pub(super) reader: Option<ClusterChainReader<Arc<O>, Arc<BootSector>>>,
+    /// Allocated clusters of the file, read on the first write and kept up to date by later ones.
+    pub(super) chain: Option<Vec<u32>>,
+    pub(super) timestamps: Timestamps,
+    pub(super) attributes: FileAttributes,
+    location: EntryLocation,
+    /// Offsets of the entries of the file's entry set.
+    pub(super) entry_offsets: Vec<u64>,
+    /// Generation of the device the handle was read from.
+    pub(super) generation: Option<u64>,
+    /// Whether the volume must not be modified.
+    pub(super) read_only: bool,
+    /// Allocation state shared by all handles of the volume.
+    pub(super) writes: Arc<Lock<WriteState>>,
+    /// Path of the file relative to the root directory, separated by `/`.
+    pub(super) path: String,
 }
+// not derived, as the device is shared instead of cloned
+impl<O: disk::ReadOffset> Clone for File<O> {
+    fn clone(&self) -> Self {
+        Self {
+            disk: Arc::clone(&self.disk),
+            boot: Arc::clone(&self.boot),
+            fat: Arc::clone(&self.fat),
+            name: self.name.clone(),
+            len: self.len,
+            allocated_len: self.allocated_len,
+            clusters: self.clusters,
+            first_cluster: self.first_cluster,
+            no_fat_chain: self.no_fat_chain,
+            reader: self.reader.clone(),
+            chain: self.chain.clone(),
+            timestamps: self.timestamps,
+            attributes: self.attributes,
+            location: self.location,
+            entry_offsets: self.entry_offsets.clone(),
+            generation: self.generation,
+            read_only: self.read_only,
+            writes: Arc::clone(&self.writes),
+            path: self.path.clone(),
+        }
+    }
+}
+
 impl<O: disk::ReadOffset> File<O> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn try_new(
         disk: &Arc<O>,
         boot: &Arc<BootSector>,
-        fat: &Fat,
-        name: String,
-        stream: StreamExtensionEntry,
-        timestamps: Timestamps,
+        fat: &Arc<Fat>,
+        parsed: ParsedFileEntry,
+        generation: Option<u64>,
+        options: &OpenOptions,
+        read_only: bool,
+        writes: &Arc<Lock<WriteState>>,
+        path: String,
     ) -> Result<Self, ClusterChainError>
     where
         <O as ReadOffset>::Err: core::fmt::Debug,
     {
+        let stream = parsed.stream_extension_entry;
+
         // create a cluster reader
         let first_cluster = stream.first_cluster;
-        let len = stream.valid_data_length;
-        let reader = if first_cluster == 0 {
-            None
-        } else {
-            let options = if stream.general_secondary_flags.no_fat_chain() {
-                ClusterChainOptions::Contiguous { data_length: len }
+        let no_fat_chain = stream.general_secondary_flags.no_fat_chain();
+        let chain_options = match options.zero_length_files {
+            _ if first_cluster == 0 => None,
+            _ if stream.data_len != 0 => Some(if no_fat_chain {
+                ClusterChainOptions::Contiguous {
+                    data_length: stream.valid_data_length,
+                }
             } else {
                 ClusterChainOptions::Fat {
-                    data_length: Some(len),
+                    data_length: Some(stream.valid_data_length),
                 }
-            };
-            Some(ClusterChainReader::try_new(
-                Arc::clone(boot),
-                fat,
-                first_cluster,
-                options,
-                Arc::clone(disk),
-            )?)
+            }),
+            ZeroLengthPolicy::Empty => None,
+            ZeroLengthPolicy::TrustChain if no_fat_chain => Some(ClusterChainOptions::Contiguous {
+                data_length: boot.bytes_per_cluster() as u64,
+            }),
+            ZeroLengthPolicy::TrustChain => Some(ClusterChainOptions::Fat { data_length: None }),
+            ZeroLengthPolicy::Report => {
+                return Err(ClusterChainError::ZeroLengthWithCluster(first_cluster));
+            }
+        };
+        let reader = chain_options
+            .map(|chain_options| {
+                ClusterChainReader::try_new(
+                    Arc::clone(boot),
+                    fat,
+                    first_cluster,
+                    chain_options,
+                    Arc::clone(disk),
+                )
+                .map(|reader| reader.with_profile(options.read_profile))
+            })
+            .transpose()?;
+
+        // trusted chains of zero-length files determine their length
+        let (len, allocated_len) = match &reader {
+            Some(reader) if stream.data_len == 0 => (reader.data_length(), reader.data_length()),
+            _ => (stream.valid_data_length, stream.data_len),
+        };
+        let clusters = if first_cluster == 0 {
+            0
+        } else {
+            allocated_len.div_ceil(boot.bytes_per_cluster() as u64) as u32
         };
 
         Ok(Self {
-            name,
+            disk: Arc::clone(disk),
+            boot: Arc::clone(boot),
+            fat: Arc::clone(fat),
+            name: parsed.name,
             len,
+            allocated_len,
+            clusters,
+            first_cluster,
+            no_fat_chain,
             reader,
-            timestamps,
+            chain: None,
+            timestamps: parsed.timestamps,
+            attributes: parsed.attributes,
+            location: parsed.location,
+            entry_offsets: parsed.entry_offsets,
+            generation,
+            read_only,
+            writes: Arc::clone(writes),
+            path,
         })
     }
 
@@ -67,13 +172,155 @@ impl<O: disk::ReadOffset> File<O> {
         self.len == 0
     }
 
+    /// Length of the file contents in bytes. Same as [`File::valid_len`].
     pub fn len(&self) -> u64 {
         self.len
     }
 
+    /// Amount of bytes that hold valid data (`ValidDataLength`). Reads end here.
+    pub fn valid_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Amount of bytes allocated for the file (`DataLength`). May be bigger than the valid
+    /// length for preallocated files.
+    pub fn allocated_len(&self) -> u64 {
+        self.allocated_len
+    }
+
+    /// Amount of clusters the file occupies on disk.
+    pub fn on_disk_clusters(&self) -> u32 {
+        self.clusters
+    }
+
     pub fn timestamps(&self) -> &Timestamps {
         &self.timestamps
     }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.attributes
+    }
+
+    /// Whether the file is marked read-only, which is independent of whether the volume can be
+    /// modified (see [`Root::is_read_only`](crate::root::Root::is_read_only)).
+    pub fn is_read_only(&self) -> bool {
+        self.attributes.contains(FileAttributes::READ_ONLY)
+    }
+
+    /// Whether the file is hidden from regular listings.
+    pub fn is_hidden(&self) -> bool {
+        self.attributes.contains(FileAttributes::HIDDEN)
+    }
+
+    /// Whether the file is used by the operating system.
+    pub fn is_system(&self) -> bool {
+        self.attributes.contains(FileAttributes::SYSTEM)
+    }
+
+    /// Whether the file is marked for archiving, i.e. modified since the last backup.
+    pub fn is_archive(&self) -> bool {
+        self.attributes.contains(FileAttributes::ARCHIVE)
+    }
+
+    /// On-disk location of the file's entry set.
+    pub fn location(&self) -> EntryLocation {
+        self.location
+    }
+
+    /// The entire contents of the file, regardless of the position.
+    pub(crate) fn contents(&self) -> Result<Vec<u8>, O::Err> {
+        let mut contents = vec![0u8; self.len as usize];
+        if let Some(reader) = &self.reader {
+            let mut reader = reader.clone();
+            reader.rewind();
+            reader.read_exact(&mut contents)?;
+        }
+        Ok(contents)
+    }
+
+    /// Whether the device has been modified since this handle was read (see
+    /// [`ReadOffset::generation`]). Reading a stale file fails with
+    /// [`PartitionError::stale_handle`](disk::PartitionError::stale_handle).
+    pub fn is_stale(&self) -> bool {
+        self.disk.generation() != self.generation
+    }
+
+    /// Verifies the cluster chain of the file against the FAT and `map`: the chain must be
+    /// terminated, match the allocated length, consist of allocated clusters only and not share
+    /// clusters with other files. The clusters of the file are claimed in `map`, so verifying
+    /// every file of a volume with the same map reveals all cross-links.
+    pub fn verify_chain(&self, map: &mut ClusterMap) -> Vec<Finding> {
+        let location = self.location;
+        let mut findings = Vec::new();
+
+        let expected = self.expected_clusters();
+        let (chain, unterminated) = self.cluster_chain();
+        if let Some(cluster) = unterminated {
+            findings.push(Finding::UnterminatedChain { location, cluster });
+        }
+
+        if chain.len() as u64 != expected as u64 {
+            findings.push(Finding::ChainLengthMismatch {
+                location,
+                clusters: chain.len() as u32,
+                expected,
+            });
+        }
+        for cluster in chain {
+            if !map.bitmap.is_allocated(cluster) {
+                findings.push(Finding::UnallocatedCluster { location, cluster });
+            }
+            if let Some(owner) = map.claim(cluster, location)
+                && owner != location
+            {
+                findings.push(Finding::CrossLinkedCluster {
+                    location,
+                    cluster,
+                    owner,
+                });
+            }
+        }
+        findings
+    }
+
+    /// Amount of clusters required by the allocated length.
+    fn expected_clusters(&self) -> u32 {
+        self.allocated_len
+            .div_ceil(self.boot.bytes_per_cluster() as u64)
+            .min(u32::MAX as u64) as u32
+    }
+
+    /// Clusters of the file in order, as described by its FAT chain or its allocated length if
+    /// it is stored contiguously. A FAT chain ends at the end-of-chain marker, at the first
+    /// invalid entry or before a cluster is visited twice; in the latter cases the last cluster of
+    /// the chain is returned as well.
+    pub(crate) fn cluster_chain(&self) -> (Vec<u32>, Option<u32>) {
+        if self.first_cluster == 0 {
+            return (Vec::new(), None);
+        }
+        if self.no_fat_chain {
+            let end = self.first_cluster.saturating_add(self.expected_clusters());
+            return ((self.first_cluster..end).collect(), None);
+        }
+
+        let heap = FIRST_USABLE_CLUSTER_INDEX..self.boot.cluster_count + FIRST_USABLE_CLUSTER_INDEX;
+        let mut visited = BTreeSet::new();
+        let mut chain = Vec::new();
+
+        let mut cluster = self.first_cluster;
+        loop {
+            chain.push(cluster);
+            visited.insert(cluster);
+            // unreadable entries of a deferred FAT break the chain
+            match self.fat.read_entry(&self.disk, cluster).ok().flatten() {
+                Some(next) if next == FatEntry::eof() => return (chain, None),
+                Some(FatEntry(next)) if heap.contains(&next) && !visited.contains(&next) => {
+                    cluster = next
+                }
+                _ => return (chain, Some(cluster)),
+            }
+        }
+    }
 }
 
 #[cfg(feature = "std")]
@@ -139,9 +386,341 @@ where
     D::Err: Into<std::io::Error>,
 {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        match &mut self.reader {
-            Some(v) => v.read(buf).map_err(Into::into),
-            None => Ok(0),
+        use disk::PartitionError;
+
+        let stale = || D::Err::stale_handle().into();
+        if self.is_stale() {
+            return Err(stale());
+        }
+        let read = match &mut self.reader {
+            Some(v) => v.read(buf).map_err(Into::into)?,
+            None => 0,
+        };
+        // the data may have been modified while it was read
+        if self.is_stale() {
+            return Err(stale());
         }
+        Ok(read)
     }
 }
+
+#[cfg(feature = "std")]
+impl<D: ReadOffset> File<D>
+where
+    D::Err: Into<std::io::Error>,
+{
+    /// Writes the contents of the file to `writer` while computing their hash, and compares it
+    /// against `expected` once the whole file was written. As the hash covers the whole file,
+    /// the data reaches the writer before it is verified, and a mismatch is reported as
+    /// [`ExtractError::HashMismatch`] for the whole file. Use
+    /// [`File::extract_verified_blocks`] to keep unverified data from the writer. Returns the
+    /// amount of bytes written.
+    pub fn extract_verified<H: ContentHasher>(
+        &mut self,
+        writer: &mut impl std::io::Write,
+        expected: &H::Output,
+    ) -> Result<u64, ExtractError> {
+        use std::io::{Read, Seek};
+
+        self.rewind()?;
+        let mut buf = vec![0u8; self.boot.bytes_per_cluster() as usize];
+        let mut hasher = H::default();
+        let mut offset = 0;
+        while offset < self.len {
+            let len = (self.len - offset).min(buf.len() as u64) as usize;
+            self.read_exact(&mut buf[..len])?;
+            hasher.update(&buf[..len]);
+            writer.write_all(&buf[..len])?;
+            offset += len as u64;
+        }
+
+        if hasher.finish() != *expected {
+            return Err(ExtractError::HashMismatch(0..self.len));
+        }
+        Ok(offset)
+    }
+
+    /// Writes the contents of the file to `writer`, verifying every block against its expected
+    /// hash before writing it, so that no unverified data reaches the writer. Fails with
+    /// [`ExtractError::HashMismatch`] at the first block that does not match. Returns the amount
+    /// of bytes written.
+    pub fn extract_verified_blocks<H: ContentHasher>(
+        &mut self,
+        writer: &mut impl std::io::Write,
+        expected: &BlockHashes<'_, H>,
+    ) -> Result<u64, ExtractError> {
+        if expected.block_size() == 0 {
+            return Err(ExtractError::EmptyBlocks);
+        }
+        use std::io::{Read, Seek};
+
+        self.rewind()?;
+        let block_size = expected.block_size() as u64;
+        let mut block = vec![0u8; expected.block_size()];
+        let mut offset = 0;
+        for hash in expected.hashes() {
+            let len = (self.len - offset).min(block_size);
+            if len == 0 {
+                let end = expected.hashes().len() as u64 * block_size;
+                return Err(ExtractError::HashMismatch(offset..end));
+            }
+
+            let block = &mut block[..len as usize];
+            self.read_exact(block)?;
+            let mut hasher = H::default();
+            hasher.update(block);
+            if hasher.finish() != *hash {
+                return Err(ExtractError::HashMismatch(offset..offset + len));
+            }
+            writer.write_all(block)?;
+            offset += len;
+        }
+
+        if offset < self.len {
+            return Err(ExtractError::HashMismatch(offset..self.len));
+        }
+        Ok(offset)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn valid_and_allocated_length() {
+    use crate::{fs::FsElement, root::Root, test_utils::TestImage};
+
+    let mut image = TestImage::new();
+    let root = image.root_cluster();
+    let cluster_size = image.boot.bytes_per_cluster() as u64;
+
+    // preallocated file: two clusters allocated, 13 bytes valid
+    image.write_clusters(100, b"Hello, World!");
+    image.add_entry_set(root, "prealloc.bin", false, 100, cluster_size + 1);
+    let stream_offset = image.cluster_offset(root) + 5 * 32;
+    image.image[stream_offset + 8..stream_offset + 16].copy_from_slice(&13u64.to_le_bytes());
+
+    let mut root = Root::open(image.cursor()).unwrap();
+    let FsElement::F(file) = &root.items()[0] else {
+        panic!("expected a file");
+    };
+
+    assert_eq!(file.len(), 13);
+    assert_eq!(file.valid_len(), 13);
+    assert_eq!(file.allocated_len(), cluster_size + 1);
+    assert_eq!(file.on_disk_clusters(), 2);
+}
+
+#[cfg(test)]
+#[test]
+fn verify_cluster_chains() {
+    use crate::{fs::FsElement, root::Root, test_utils::TestImage};
+
+    let mut image = TestImage::sample();
+    let root = image.root_cluster();
+    let cluster_size = image.boot.bytes_per_cluster() as u64;
+
+    // a copy sharing the cluster of `hello.txt`
+    image.add_entry_set(root, "copy.txt", false, 100, 13);
+    // a FAT chain of two clusters, followed by a free cluster instead of the end-of-chain marker,
+    // while three clusters are allocated
+    image.add_entry_set(root, "chained.bin", false, 110, 3 * cluster_size);
+    let stream_offset = (image.cluster_offset(root)..)
+        .step_by(32)
+        .take(cluster_size as usize / 32)
+        .filter(|offset| image.image[*offset] == 0xC0)
+        .last()
+        .unwrap();
+    image.image[stream_offset + 1] &= !0b10; // clear `NoFatChain`
+    image.image[stream_offset + 8..stream_offset + 16].copy_from_slice(&100u64.to_le_bytes());
+    let fat_offset = image.boot.fat_offset as usize * 512;
+    image.image[fat_offset + 110 * 4..fat_offset + 111 * 4].copy_from_slice(&111u32.to_le_bytes());
+
+    // mark clusters 100 to 102 and 110 as allocated, but not 111
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+    image.image[bitmap_offset + 13] |= 0b0001_0000;
+
+    let mut root = Root::open(image.cursor()).unwrap();
+    let mut map = root.cluster_map().unwrap();
+    let [
+        FsElement::F(hello),
+        FsElement::D(_),
+        FsElement::F(copy),
+        FsElement::F(chained),
+    ] = root.items()
+    else {
+        panic!("unexpected root directory");
+    };
+
+    assert_eq!(hello.verify_chain(&mut map), []);
+    assert_eq!(map.owner(100), Some(hello.location()));
+    // verifying a file again does not report it as cross-linked with itself
+    assert_eq!(hello.verify_chain(&mut map), []);
+
+    assert_eq!(
+        copy.verify_chain(&mut map),
+        [Finding::CrossLinkedCluster {
+            location: copy.location(),
+            cluster: 100,
+            owner: hello.location(),
+        }]
+    );
+
+    let location = chained.location();
+    assert_eq!(
+        chained.verify_chain(&mut map),
+        [
+            Finding::UnterminatedChain {
+                location,
+                cluster: 111,
+            },
+            Finding::ChainLengthMismatch {
+                location,
+                clusters: 2,
+                expected: 3,
+            },
+            Finding::UnallocatedCluster {
+                location,
+                cluster: 111,
+            },
+        ]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn zero_length_policies() {
+    use crate::{
+        check::Finding,
+        fs::FsElement,
+        root::{OpenOptionsBuilder, Root},
+        test_utils::TestImage,
+    };
+    use std::io::{Cursor, Read};
+
+    let mut image = TestImage::sample();
+    let root = image.root_cluster();
+    let cluster_size = image.boot.bytes_per_cluster() as u64;
+    // a zero-length file with a FAT chain of two clusters
+    image.add_entry_set(root, "stray.bin", false, 110, 0);
+    let stream_offset = image.cluster_offset(root) + 11 * 32;
+    image.image[stream_offset + 1] &= !0b10; // clear `NoFatChain`
+    let fat_offset = image.boot.fat_offset as usize * 512;
+    let mut link = |cluster: usize, next: u32| {
+        image.image[fat_offset + cluster * 4..fat_offset + cluster * 4 + 4]
+            .copy_from_slice(&next.to_le_bytes())
+    };
+    link(110, 111);
+    link(111, 0xFFFF_FFFF);
+    image.write_clusters(110, b"recovered");
+
+    let open = |policy, skip| {
+        let options = OpenOptionsBuilder::default()
+            .zero_length_files(policy)
+            .skip_damaged_entry_sets(skip)
+            .build()
+            .unwrap();
+        Root::open_with(Cursor::new(image.image.clone()), options)
+    };
+
+    let mut root = open(ZeroLengthPolicy::Empty, false).unwrap();
+    let [.., FsElement::F(stray)] = root.items() else {
+        panic!("stray.bin not found");
+    };
+    assert_eq!((stray.len(), stray.on_disk_clusters()), (0, 0));
+
+    let mut root = open(ZeroLengthPolicy::TrustChain, false).unwrap();
+    let [.., FsElement::F(stray)] = root.items() else {
+        panic!("stray.bin not found");
+    };
+    assert_eq!(stray.len(), 2 * cluster_size);
+    assert_eq!(stray.on_disk_clusters(), 2);
+    let mut contents = Vec::new();
+    stray.read_to_end(&mut contents).unwrap();
+    assert!(contents.starts_with(b"recovered"));
+
+    assert!(open(ZeroLengthPolicy::Report, false).is_err());
+    let root = open(ZeroLengthPolicy::Report, true).unwrap();
+    assert!(matches!(root.findings(), [Finding::DamagedEntrySet { .. }]));
+}
+
+#[cfg(test)]
+#[test]
+fn extract_verified() {
+    use crate::{
+        checksum::{Fnv1a, block_hashes},
+        fs::FsElement,
+        root::Root,
+        test_utils::TestImage,
+    };
+    use std::io::{Seek, SeekFrom};
+
+    let mut image = TestImage::new();
+    let contents: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+    image.write_clusters(100, &contents);
+    image.add_entry_set(image.root_cluster(), "firmware.bin", false, 100, 10_000);
+    let mut root = Root::open(image.cursor()).unwrap();
+    let [FsElement::F(file)] = root.items() else {
+        panic!("firmware.bin not found");
+    };
+
+    // a single hash of the whole file
+    let hash = block_hashes::<Fnv1a>(&contents, contents.len())[0];
+    let mut extracted = Vec::new();
+    let written = file
+        .extract_verified::<Fnv1a>(&mut extracted, &hash)
+        .unwrap();
+    assert_eq!(written, 10_000);
+    assert_eq!(extracted, contents);
+    let err = file
+        .extract_verified::<Fnv1a>(&mut Vec::new(), &(hash ^ 1))
+        .unwrap_err();
+    assert!(matches!(err, ExtractError::HashMismatch(range) if range == (0..10_000)));
+
+    // hashes of blocks
+    let hashes = block_hashes::<Fnv1a>(&contents, 4096);
+    let mut extracted = Vec::new();
+    let written = file
+        .extract_verified_blocks(&mut extracted, &BlockHashes::<Fnv1a>::new(4096, &hashes))
+        .unwrap();
+    assert_eq!(written, 10_000);
+    assert_eq!(extracted, contents);
+
+    // only verified blocks are written
+    let mut damaged = contents.clone();
+    damaged[5000] ^= 0xFF;
+    let hashes = block_hashes::<Fnv1a>(&damaged, 4096);
+    let mut extracted = Vec::new();
+    let err = file
+        .extract_verified_blocks(&mut extracted, &BlockHashes::<Fnv1a>::new(4096, &hashes))
+        .unwrap_err();
+    assert!(matches!(err, ExtractError::HashMismatch(range) if range == (4096..8192)));
+    assert_eq!(extracted, contents[..4096]);
+
+    // hashes of missing or additional bytes
+    let hashes = block_hashes::<Fnv1a>(&contents[..8192], 4096);
+    let err = file
+        .extract_verified_blocks(&mut Vec::new(), &BlockHashes::<Fnv1a>::new(4096, &hashes))
+        .unwrap_err();
+    assert!(matches!(err, ExtractError::HashMismatch(range) if range == (8192..10_000)));
+    let mut longer = contents.clone();
+    longer.push(0);
+    let hashes = block_hashes::<Fnv1a>(&longer, 1000);
+    let err = file
+        .extract_verified_blocks(&mut Vec::new(), &BlockHashes::<Fnv1a>::new(1000, &hashes))
+        .unwrap_err();
+    assert!(matches!(err, ExtractError::HashMismatch(range) if range == (10_000..11_000)));
+
+    // blocks must not be empty
+    let err = file
+        .extract_verified_blocks(&mut Vec::new(), &BlockHashes::<Fnv1a>::new(0, &hashes))
+        .unwrap_err();
+    assert!(matches!(err, ExtractError::EmptyBlocks));
+
+    // the file is read from its start
+    file.seek(SeekFrom::Start(100)).unwrap();
+    let hashes = block_hashes::<Fnv1a>(&contents, 4096);
+    assert!(
+        file.extract_verified_blocks(&mut Vec::new(), &BlockHashes::<Fnv1a>::new(4096, &hashes))
+            .is_ok()
+    );
+}
@@ -5,8 +5,58 @@ use crate::disk::{self};
 
 pub mod directory;
 pub mod file;
+pub mod walk;
+mod write;
+
+pub use crate::entry::FileAttributes;
 
 pub enum FsElement<O: disk::ReadOffset> {
     F(File<O>),
     D(Directory<O>),
 }
+
+impl<O: disk::ReadOffset> Clone for FsElement<O> {
+    fn clone(&self) -> Self {
+        match self {
+            FsElement::F(file) => FsElement::F(file.clone()),
+            FsElement::D(dir) => FsElement::D(dir.clone()),
+        }
+    }
+}
+
+impl<O: disk::ReadOffset> FsElement<O> {
+    pub fn name(&self) -> &str {
+        match self {
+            FsElement::F(file) => file.name(),
+            FsElement::D(dir) => dir.name(),
+        }
+    }
+
+    pub fn location(&self) -> EntryLocation {
+        match self {
+            FsElement::F(file) => file.location(),
+            FsElement::D(dir) => dir.location(),
+        }
+    }
+}
+
+/// On-disk location of the primary directory entry of a file or directory.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntryLocation {
+    /// Cluster of the parent directory containing the entry.
+    pub cluster: u32,
+    /// Index of the entry within its cluster.
+    pub index: u32,
+    /// Absolute byte offset of the entry on the volume.
+    pub offset: u64,
+}
+
+impl core::fmt::Display for EntryLocation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "entry #{} of cluster #{} (offset {:#x})",
+            self.index, self.cluster, self.offset
+        )
+    }
+}
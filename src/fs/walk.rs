@@ -0,0 +1,162 @@
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+
+use crate::{disk::ReadOffset, error::DirectoryError, root::Root};
+
+use super::{FsElement, directory::Directory};
+
+/// Order in which a [`Walk`] visits the elements of a directory tree.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// The contents of a directory are visited right after the directory itself.
+    #[default]
+    DepthFirst,
+    /// All elements of a directory are visited before the contents of its subdirectories.
+    BreadthFirst,
+}
+
+/// Iterator over all files and directories below a directory, yielding each element with its
+/// path (separated by `/`) relative to the walked directory. Directories are visited before
+/// their contents. Created by [`Root::walk`] and [`Directory::walk`].
+///
+/// Directories which cannot be read are reported as errors and skipped.
+pub struct Walk<O: ReadOffset> {
+    order: WalkOrder,
+    /// Elements yet to be visited, in order for breadth-first and in reverse for depth-first
+    /// walks.
+    pending: VecDeque<(String, FsElement<O>)>,
+    /// The directory visited last, whose contents are listed on the next call.
+    unlisted: Option<(String, Directory<O>)>,
+}
+
+impl<O: ReadOffset> Walk<O> {
+    fn new(order: WalkOrder) -> Walk<O> {
+        Walk {
+            order,
+            pending: VecDeque::new(),
+            unlisted: None,
+        }
+    }
+
+    /// Queues the elements of the directory at `parent`.
+    fn push(&mut self, parent: &str, items: Vec<FsElement<O>>) {
+        let entries = items.into_iter().map(|item| {
+            let path = match parent.is_empty() {
+                true => String::from(item.name()),
+                false => format!("{parent}/{}", item.name()),
+            };
+            (path, item)
+        });
+        match self.order {
+            WalkOrder::DepthFirst => entries
+                .rev()
+                .for_each(|entry| self.pending.push_back(entry)),
+            WalkOrder::BreadthFirst => self.pending.extend(entries),
+        }
+    }
+}
+
+impl<O: ReadOffset> Iterator for Walk<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    type Item = Result<(String, FsElement<O>), DirectoryError<O>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some((path, directory)) = self.unlisted.take() {
+            match directory.open() {
+                Ok(items) => self.push(&path, items),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        let (path, item) = match self.order {
+            WalkOrder::DepthFirst => self.pending.pop_back(),
+            WalkOrder::BreadthFirst => self.pending.pop_front(),
+        }?;
+        if let FsElement::D(directory) = &item {
+            self.unlisted = Some((path.clone(), directory.clone()));
+        }
+        Some(Ok((path, item)))
+    }
+}
+
+impl<O: ReadOffset> Root<O> {
+    /// Walks all files and directories of the volume in the given order.
+    pub fn walk(&self, order: WalkOrder) -> Walk<O> {
+        let mut walk = Walk::new(order);
+        walk.push("", self.items.clone());
+        walk
+    }
+}
+
+impl<O: ReadOffset> Directory<O> {
+    /// Walks all files and directories below this directory in the given order. The directory
+    /// itself is not yielded.
+    pub fn walk(&self, order: WalkOrder) -> Walk<O> {
+        let mut walk = Walk::new(order);
+        walk.unlisted = Some((String::new(), self.clone()));
+        walk
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn walk_orders() {
+    use crate::test_utils::TestImage;
+    use std::io::Cursor;
+
+    let mut image = TestImage::sample();
+    let root = image.root_cluster();
+    image.add_entry_set(root, "other", true, 103, 4096);
+    image.add_entry_set(103, "deep", true, 104, 4096);
+    image.add_entry_set(104, "leaf.txt", false, 0, 0);
+    image.add_entry_set(103, "sibling.txt", false, 0, 0);
+    let root = Root::open(Cursor::new(image.image.clone())).unwrap();
+
+    let paths = |walk: Walk<_>| -> Vec<String> { walk.map(|item| item.unwrap().0).collect() };
+    assert_eq!(
+        paths(root.walk(WalkOrder::DepthFirst)),
+        [
+            "hello.txt",
+            "dir",
+            "dir/nested.txt",
+            "other",
+            "other/deep",
+            "other/deep/leaf.txt",
+            "other/sibling.txt"
+        ]
+    );
+    assert_eq!(
+        paths(root.walk(WalkOrder::BreadthFirst)),
+        [
+            "hello.txt",
+            "dir",
+            "other",
+            "dir/nested.txt",
+            "other/deep",
+            "other/sibling.txt",
+            "other/deep/leaf.txt"
+        ]
+    );
+
+    let Some(FsElement::D(other)) = root.open_path("other").unwrap() else {
+        panic!("other not found");
+    };
+    assert_eq!(
+        paths(other.walk(WalkOrder::DepthFirst)),
+        ["deep", "deep/leaf.txt", "sibling.txt"]
+    );
+
+    // unreadable directories are reported, the walk continues with the remaining elements
+    let mut image = TestImage::sample();
+    image.add_entry_set(image.root_cluster(), "broken", true, 105, 4096);
+    // allocation bitmap entries are only allowed in the root directory
+    let mut bitmap = [0u8; 32];
+    bitmap[0] = 0x81;
+    image.add_raw_entry(105, &bitmap);
+    let root = Root::open(Cursor::new(image.image)).unwrap();
+    let items: Vec<_> = root.walk(WalkOrder::BreadthFirst).collect();
+    assert_eq!(items.len(), 5);
+    assert!(items[3].is_err());
+    assert!(matches!(&items[4], Ok((path, _)) if path == "dir/nested.txt"));
+}
@@ -0,0 +1,1599 @@
+use alloc::{collections::BTreeSet, format, string::String, sync::Arc, vec, vec::Vec};
+
+use crate::{
+    checksum,
+    cluster::{ClusterChainOptions, reader::ClusterChainReader},
+    disk::{PartitionError, WriteOffset},
+    entry::{DirEntry, FileAttributes, FileNameEntry, parsed::ParsedFileEntry, set::Allocation},
+    error::WriteError,
+    fat::FatEntry,
+    name::{UpcaseTable, validate_name},
+    root::Root,
+    timestamp::{Clock, Timestamp, Timestamps},
+    volume::write::{
+        DirectoryClusters, FILE_10MS_INCREMENTS, FILE_ATTRIBUTES, FILE_TIMESTAMPS,
+        FILE_UTC_OFFSETS, NO_FAT_CHAIN, STREAM_DATA_LENGTH, STREAM_FIRST_CLUSTER, STREAM_FLAGS,
+        STREAM_NAME_HASH, STREAM_NAME_LENGTH, STREAM_VALID_DATA_LENGTH, Writer,
+    },
+    volume::{DirectoryChange, batch::sorted_batch, usage::element_bytes},
+};
+
+use super::{EntryLocation, FsElement, directory::Directory, file::File};
+
+impl<O: WriteOffset> File<O> {
+    /// Writes all of `buf` at the current position, overwriting the contents there and extending
+    /// the file beyond its end. Clusters are allocated as the file grows; the file stays
+    /// contiguous as long as the clusters following it are free. Timestamps are not updated.
+    ///
+    /// Other handles to the volume, e.g. a [`Volume`](crate::volume::Volume), only see the new
+    /// contents and allocation once they are opened again, or are told through
+    /// [`ReadOffset::generation`](crate::disk::ReadOffset::generation). Fails for files of
+    /// read-only volumes.
+    ///
+    /// The write is reported as [`DirectoryChange::Modified`] to the callback registered with
+    /// [`Volume::on_change`](crate::volume::Volume::on_change).
+    pub fn write_data(&mut self, buf: &[u8]) -> Result<usize, WriteError<O>> {
+        let written = self.write_contents(buf)?;
+        if written != 0 {
+            self.notify_modified();
+        }
+        Ok(written)
+    }
+
+    /// Writes `buf` like [`File::write_data`], without reporting the change.
+    fn write_contents(&mut self, buf: &[u8]) -> Result<usize, WriteError<O>> {
+        if self.read_only {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        if self.is_stale() {
+            return Err(WriteError::Io(O::Err::stale_handle()));
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let position = self.reader.as_ref().map_or(0, |r| r.stream_position());
+        let end = position + buf.len() as u64;
+        let required = end.div_ceil(self.boot.bytes_per_cluster() as u64) as usize;
+
+        let mut chain = self.take_chain()?;
+        let result = match required.saturating_sub(chain.len()) as u32 {
+            0 => Ok(()),
+            missing => self.grow(&mut chain, missing),
+        }
+        .and_then(|()| self.write_at(&chain, position, buf))
+        .and_then(|()| self.extend_len(&chain, end));
+        self.chain = Some(chain);
+        result?;
+
+        self.follow_chain(end)?;
+        Ok(buf.len())
+    }
+
+    /// Takes the allocated clusters of the file out of the handle, reading them on first use, so
+    /// they can be modified along with the handle. They are put back once the write is done.
+    fn take_chain(&mut self) -> Result<Vec<u32>, WriteError<O>> {
+        if let Some(chain) = self.chain.take() {
+            return Ok(chain);
+        }
+
+        // files listed as empty despite a first cluster get new clusters
+        let mut chain = match self.reader {
+            Some(_) => self.cluster_chain().0,
+            None => Vec::new(),
+        };
+        if chain.len() < self.clusters as usize {
+            return Err(WriteError::CorruptEntrySet(self.location()));
+        }
+        chain.truncate(self.clusters as usize);
+        Ok(chain)
+    }
+
+    /// Writes `buf` at `position` into the clusters of `chain`, combining contiguous ones.
+    fn write_at(&self, chain: &[u32], position: u64, buf: &[u8]) -> Result<(), WriteError<O>> {
+        let bytes_per_cluster = self.boot.bytes_per_cluster() as u64;
+        let mut written = 0;
+        while written < buf.len() {
+            let offset = position + written as u64;
+            let remaining = (buf.len() - written) as u64;
+            let index = (offset / bytes_per_cluster) as usize;
+            // the run is only followed as far as the write reaches
+            let clusters = (offset % bytes_per_cluster + remaining).div_ceil(bytes_per_cluster);
+            let run = chain[index..]
+                .windows(2)
+                .take(clusters as usize - 1)
+                .take_while(|pair| pair[1] == pair[0] + 1)
+                .count();
+            let run_end = (index + run + 1) as u64 * bytes_per_cluster;
+            let amount = (run_end - offset).min(remaining) as usize;
+
+            let device_offset = self
+                .boot
+                .cluster_offset(chain[index])
+                .ok_or(WriteError::Io(O::Err::cluster_not_found(chain[index])))?
+                + offset % bytes_per_cluster;
+            self.disk
+                .write_all_at(device_offset, &buf[written..written + amount])
+                .map_err(WriteError::Io)?;
+            written += amount;
+        }
+        Ok(())
+    }
+
+    /// Records that the file reaches up to `end` within `chain`, if it grew.
+    fn extend_len(&mut self, chain: &[u32], end: u64) -> Result<(), WriteError<O>> {
+        if end > self.len {
+            self.len = end;
+            self.allocated_len = self.allocated_len.max(end);
+            self.clusters = self
+                .allocated_len
+                .div_ceil(self.boot.bytes_per_cluster() as u64) as u32;
+            self.first_cluster = chain[0];
+            self.update_stream()?;
+        }
+        Ok(())
+    }
+
+    /// Truncates or extends the file to `len` bytes. Extended files are filled with zeroes;
+    /// clusters are allocated as for [`File::write_data`]. Truncating releases the clusters past
+    /// the new end, including any preallocated ones, so that the allocated length matches `len`
+    /// afterwards. The position is kept, but moves back to the new end if it lies beyond. The
+    /// change is reported like for [`File::write_data`].
+    pub fn set_len(&mut self, len: u64) -> Result<(), WriteError<O>> {
+        if self.read_only {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        if self.is_stale() {
+            return Err(WriteError::Io(O::Err::stale_handle()));
+        }
+
+        let position = self.reader.as_ref().map_or(0, |r| r.stream_position());
+        if len > self.len {
+            let mut chain = self.take_chain()?;
+            let result = self.extend_with_zeroes(&mut chain, len);
+            self.chain = Some(chain);
+            result?;
+        }
+
+        let bytes_per_cluster = self.boot.bytes_per_cluster() as u64;
+        let required = len.div_ceil(bytes_per_cluster) as usize;
+        if self.clusters as usize > required || self.allocated_len != len {
+            let mut chain = self.take_chain()?;
+            let result = self.shrink(&mut chain, required);
+            self.chain = Some(chain);
+            result?;
+            self.len = len;
+            self.allocated_len = len;
+            self.clusters = required as u32;
+            if required == 0 {
+                self.first_cluster = 0;
+            }
+            self.update_stream()?;
+        }
+        self.follow_chain(position.min(len))?;
+        self.notify_modified();
+        Ok(())
+    }
+
+    /// Extends the file to `len` bytes within `chain`, allocating all missing clusters at once and
+    /// filling the new contents with zeroes.
+    fn extend_with_zeroes(&mut self, chain: &mut Vec<u32>, len: u64) -> Result<(), WriteError<O>> {
+        /// Amount of zeroes written at once.
+        const ZEROES: u64 = 1024 * 1024;
+
+        let required = len.div_ceil(self.boot.bytes_per_cluster() as u64) as usize;
+        let missing = required.saturating_sub(chain.len()) as u32;
+        if missing != 0 {
+            self.grow(chain, missing)?;
+        }
+
+        let zeroes = vec![0u8; (len - self.len).min(ZEROES) as usize];
+        let mut offset = self.len;
+        while offset < len {
+            let amount = (len - offset).min(zeroes.len() as u64) as usize;
+            self.write_at(chain, offset, &zeroes[..amount])?;
+            offset += amount as u64;
+        }
+        self.extend_len(chain, len)
+    }
+
+    /// Reports that the contents of the file changed.
+    fn notify_modified(&self) {
+        self.writes
+            .lock()
+            .notify([DirectoryChange::Modified(self.path.clone())]);
+    }
+
+    /// Releases the clusters of `chain` following the first `keep` ones, if there are any.
+    fn shrink(&mut self, chain: &mut Vec<u32>, keep: usize) -> Result<(), WriteError<O>> {
+        if keep >= chain.len() {
+            return Ok(());
+        }
+        let mut writer = Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            file_scope(&self.path),
+        );
+        writer.free_allocation(Allocation {
+            first_cluster: chain[keep],
+            data_len: (chain.len() - keep) as u64 * self.boot.bytes_per_cluster() as u64,
+            no_fat_chain: self.no_fat_chain,
+        })?;
+        if !self.no_fat_chain {
+            if let Some(last) = keep.checked_sub(1) {
+                writer.set_fat_entry(chain[last], FatEntry::eof())?;
+            }
+            // the remaining clusters may no longer need a FAT chain
+            self.no_fat_chain = is_contiguous(&chain[..keep]);
+        }
+        chain.truncate(keep);
+        Ok(())
+    }
+
+    /// Appends `count` clusters picked by the allocator of the volume to `chain`.
+    fn grow(&mut self, chain: &mut Vec<u32>, count: u32) -> Result<(), WriteError<O>> {
+        let mut writer = Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            file_scope(&self.path),
+        );
+        let clusters = writer.allocate(count, chain.last().copied())?;
+
+        // chains that are contiguous as a whole are stored without FAT entries, even if the file
+        // used a FAT chain so far
+        let contiguous = is_contiguous(chain)
+            && is_contiguous(&clusters)
+            && chain.last().is_none_or(|last| clusters[0] == last + 1);
+        if !contiguous {
+            match self.no_fat_chain {
+                // the FAT has not been maintained for contiguous files
+                true => writer.append_chain(None, &[chain.as_slice(), &clusters].concat())?,
+                false => writer.append_chain(chain.last().copied(), &clusters)?,
+            }
+        }
+        self.no_fat_chain = contiguous;
+        chain.extend(clusters);
+        Ok(())
+    }
+
+    /// Replaces the attributes of the file. [`FileAttributes::DIRECTORY`] is ignored, as files
+    /// cannot become directories. Fails for files of read-only volumes.
+    pub fn set_attributes(&mut self, attributes: FileAttributes) -> Result<(), WriteError<O>> {
+        if self.read_only {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        if self.is_stale() {
+            return Err(WriteError::Io(O::Err::stale_handle()));
+        }
+
+        let attributes = attributes.difference(FileAttributes::DIRECTORY);
+        let writer = Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            file_scope(&self.path),
+        );
+        write_attributes(&writer, &self.entry_offsets, attributes)?;
+        self.attributes = attributes;
+        self.generation = self.disk.generation();
+        Ok(())
+    }
+
+    /// Replaces the creation, last modification and last access timestamps of the file. exFAT
+    /// keeps no 10ms increment for the last access, so it is stored with a precision of two
+    /// seconds. Fails for files of read-only volumes.
+    pub fn set_times(
+        &mut self,
+        created: Timestamp,
+        modified: Timestamp,
+        accessed: Timestamp,
+    ) -> Result<(), WriteError<O>> {
+        if self.read_only {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        if self.is_stale() {
+            return Err(WriteError::Io(O::Err::stale_handle()));
+        }
+
+        let writer = Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            file_scope(&self.path),
+        );
+        writer.update_entry_set(&self.entry_offsets, |entries| {
+            let entry = &mut entries[0];
+            for (i, timestamp) in [created, modified, accessed].iter().enumerate() {
+                let field = FILE_TIMESTAMPS + 4 * i;
+                entry[field..field + 4].copy_from_slice(&timestamp.raw().to_le_bytes());
+                entry[FILE_UTC_OFFSETS + i] = timestamp.raw_utc_offset();
+            }
+            entry[FILE_10MS_INCREMENTS] = created.raw_increment();
+            entry[FILE_10MS_INCREMENTS + 1] = modified.raw_increment();
+        })?;
+        // as read back from the entry
+        let accessed = Timestamp::from_raw(accessed.raw(), 0, accessed.raw_utc_offset());
+        self.timestamps = Timestamps::new(created, modified, accessed);
+        self.generation = self.disk.generation();
+        Ok(())
+    }
+
+    /// Sets the last modification and last access timestamps of the file to the current time,
+    /// obtained using `T`, like `touch` does. Fails for files of read-only volumes.
+    pub fn touch<T: Clock>(&mut self) -> Result<(), WriteError<O>> {
+        let now = Timestamp::now::<T>();
+        self.set_times(*self.timestamps.created(), now, now)
+    }
+
+    /// Writes the lengths and allocation of the file into its stream extension entry.
+    fn update_stream(&mut self) -> Result<(), WriteError<O>> {
+        let (len, allocated_len) = (self.len, self.allocated_len);
+        let (first_cluster, no_fat_chain) = (self.first_cluster, self.no_fat_chain);
+        let writer = Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            file_scope(&self.path),
+        );
+        writer.update_entry_set(&self.entry_offsets, |entries| {
+            let stream = &mut entries[1];
+            stream[STREAM_FLAGS] = match no_fat_chain {
+                true => stream[STREAM_FLAGS] | NO_FAT_CHAIN,
+                false => stream[STREAM_FLAGS] & !NO_FAT_CHAIN,
+            };
+            stream[STREAM_VALID_DATA_LENGTH..STREAM_VALID_DATA_LENGTH + 8]
+                .copy_from_slice(&len.to_le_bytes());
+            stream[STREAM_FIRST_CLUSTER..STREAM_FIRST_CLUSTER + 4]
+                .copy_from_slice(&first_cluster.to_le_bytes());
+            stream[STREAM_DATA_LENGTH..STREAM_DATA_LENGTH + 8]
+                .copy_from_slice(&allocated_len.to_le_bytes());
+        })
+    }
+
+    /// Updates the reader to the chain kept in the handle after a write, positioned at `position`.
+    /// The handle stays valid although the device has been modified.
+    fn follow_chain(&mut self, position: u64) -> Result<(), WriteError<O>> {
+        if let (Some(reader), Some(chain)) = (&mut self.reader, &self.chain)
+            && self.first_cluster != 0
+        {
+            reader.follow(chain, self.len);
+            reader.seek(position);
+            self.generation = self.disk.generation();
+            return Ok(());
+        }
+        self.reopen_reader(position)
+    }
+
+    /// Recreates the reader over the updated chain, positioned at `position`. The handle stays
+    /// valid although the device has been modified.
+    fn reopen_reader(&mut self, position: u64) -> Result<(), WriteError<O>> {
+        if self.first_cluster == 0 {
+            self.reader = None;
+            self.generation = self.disk.generation();
+            return Ok(());
+        }
+        let options = match self.no_fat_chain {
+            true => ClusterChainOptions::Contiguous {
+                data_length: self.len,
+            },
+            false => ClusterChainOptions::Fat {
+                data_length: Some(self.len),
+            },
+        };
+        let profile = self
+            .reader
+            .as_ref()
+            .map(|r| r.profile())
+            .unwrap_or_default();
+        let mut reader = ClusterChainReader::try_new(
+            Arc::clone(&self.boot),
+            &self.fat,
+            self.first_cluster,
+            options,
+            Arc::clone(&self.disk),
+        )?
+        .with_profile(profile);
+        reader.seek(position);
+
+        self.reader = Some(reader);
+        self.generation = self.disk.generation();
+        Ok(())
+    }
+}
+
+impl<O: WriteOffset> Directory<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Replaces the attributes of the directory. [`FileAttributes::DIRECTORY`] is always kept.
+    /// Fails for directories of read-only volumes.
+    pub fn set_attributes(&mut self, attributes: FileAttributes) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+
+        let attributes = attributes | FileAttributes::DIRECTORY;
+        let writer = Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            directory_scope(&self.path),
+        );
+        write_attributes(&writer, &self.entry_offsets, attributes)?;
+        self.attributes = attributes;
+        self.generation = self.disk.generation();
+        Ok(())
+    }
+
+    /// Creates an empty file named `name` in this directory, timestamped using `T`, and returns
+    /// it for writing. A first cluster is allocated for the file right away; the directory grows
+    /// if none of its entries are free. As with [`File::write_data`], other handles to the volume
+    /// only see the new file once opened again. Fails for directories of read-only volumes.
+    ///
+    /// Like all changes of the directory tree made through handles, the new file is reported to
+    /// the callback registered with [`Volume::on_change`](crate::volume::Volume::on_change).
+    pub fn create_file<T: Clock>(&mut self, name: &str) -> Result<File<O>, WriteError<O>> {
+        let parsed = self.create_entry_set::<T>(name, FileAttributes::ARCHIVE)?;
+        let path = format!("{}/{}", self.path, parsed.name);
+        Ok(File::try_new(
+            &self.disk,
+            &self.boot,
+            &self.fat,
+            parsed,
+            self.generation,
+            &self.options,
+            self.read_only,
+            &self.writes,
+            path,
+        )?)
+    }
+
+    /// Creates an empty subdirectory named `name`, timestamped using `T`, with a single zeroed
+    /// cluster. Behaves like [`Directory::create_file`] otherwise.
+    pub fn create_dir<T: Clock>(&mut self, name: &str) -> Result<Directory<O>, WriteError<O>> {
+        let parsed = self.create_entry_set::<T>(name, FileAttributes::DIRECTORY)?;
+        let path = format!("{}/{}", self.path, parsed.name);
+        Ok(Directory::new(
+            Arc::clone(&self.disk),
+            Arc::clone(&self.boot),
+            Arc::clone(&self.fat),
+            Arc::clone(&self.upcase_table),
+            parsed,
+            self.generation,
+            self.options,
+            self.read_only,
+            Arc::clone(&self.writes),
+            path,
+        ))
+    }
+
+    /// Creates many files in this directory at once. The files are sorted by name and written in
+    /// chunks: the data of a chunk is stored in a single run of contiguous clusters and its entry
+    /// sets are written as one block, so the allocation bitmap and the directory are updated once
+    /// per chunk instead of once per file. No FAT entries are written, as all files are stored
+    /// contiguously (`NoFatChain`).
+    ///
+    /// Names must be unique within the batch and must not exist in the directory yet; these
+    /// checks happen before anything is written. If writing fails later on, e.g. because the
+    /// volume is full, the files of the chunks written so far remain. Entries are timestamped
+    /// using `T`. Fails for directories of read-only volumes.
+    pub fn create_files<T: Clock, N: AsRef<str>, D: AsRef<[u8]>>(
+        &mut self,
+        files: impl IntoIterator<Item = (N, D)>,
+    ) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+
+        let upcase_table = Arc::clone(&self.upcase_table);
+        let taken: BTreeSet<Vec<u16>> = self
+            .open()?
+            .iter()
+            .map(|item| upcase_table.upcase_name(&item.name().encode_utf16().collect::<Vec<_>>()))
+            .collect();
+        let files = sorted_batch(files, &upcase_table, |upcased| taken.contains(upcased))?;
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let mut clusters = DirectoryClusters::of(self)?;
+        Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            directory_scope(&self.path),
+        )
+        .create_files(
+            &mut clusters,
+            &files,
+            &Timestamps::now::<T>(),
+            &upcase_table,
+        )?;
+        self.reload_stream()?;
+        self.writes.lock().notify(files.iter().map(|(_, name, _)| {
+            DirectoryChange::Created(format!("{}/{}", self.path, name.as_ref()))
+        }));
+        Ok(())
+    }
+
+    /// Writes an entry set for a new element into the directory, see
+    /// [`Writer::create_entry_set`].
+    fn create_entry_set<T: Clock>(
+        &mut self,
+        name: &str,
+        attributes: FileAttributes,
+    ) -> Result<ParsedFileEntry, WriteError<O>> {
+        self.ensure_writable()?;
+
+        let upcase_table = Arc::clone(&self.upcase_table);
+        if self.find(name, upcase_table.as_ref())?.is_some() {
+            return Err(WriteError::AlreadyExists(String::from(name)));
+        }
+
+        let mut clusters = DirectoryClusters::of(self)?;
+        let parsed = Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            directory_scope(&self.path),
+        )
+        .create_entry_set(
+            &mut clusters,
+            name,
+            attributes,
+            Timestamps::now::<T>(),
+            &upcase_table,
+        )?;
+        self.reload_stream()?;
+        self.writes
+            .lock()
+            .notify([DirectoryChange::Created(format!("{}/{name}", self.path))]);
+        Ok(parsed)
+    }
+
+    /// Renames the file or subdirectory `old_name` to `new_name`, keeping its contents,
+    /// attributes and timestamps. The entry set is rewritten in place if the new name fits,
+    /// otherwise it moves to other entries of the directory. Renaming to a name that only differs
+    /// in case is allowed.
+    pub fn rename(&mut self, old_name: &str, new_name: &str) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+
+        let upcase_table = Arc::clone(&self.upcase_table);
+        let upcase_table = upcase_table.as_ref();
+        let units = validate_name(new_name)?;
+        let (location, offsets) = self.element_entries(old_name, upcase_table)?;
+        if let Some(existing) = self.find(new_name, upcase_table)?
+            && existing.location() != location
+        {
+            return Err(WriteError::AlreadyExists(String::from(new_name)));
+        }
+
+        let mut clusters = DirectoryClusters::of(self)?;
+        let mut writer = Writer::new(
+            &self.disk,
+            &self.boot,
+            &mut self.fat,
+            &self.writes,
+            directory_scope(&self.path),
+        );
+        let entries = writer.read_slots(&offsets)?;
+        let renamed = rename_entries(&entries, &units, upcase_table)
+            .ok_or(WriteError::CorruptEntrySet(location))?;
+
+        if renamed.len() <= entries.len() {
+            let mut slots = renamed;
+            slots.extend(entries[slots.len()..].iter().map(|entry| unused(*entry)));
+            writer.write_slots(&offsets, &slots)?;
+        } else {
+            // the new entry set is written before the old one is removed
+            writer.insert_entry_set(&mut clusters, &renamed)?;
+            writer.write_slots(&offsets, &unused_entries(&entries))?;
+        }
+        drop(writer);
+        // the directory may have grown
+        self.reload_stream()?;
+        self.writes.lock().notify([DirectoryChange::Renamed {
+            from: format!("{}/{old_name}", self.path),
+            to: format!("{}/{new_name}", self.path),
+        }]);
+        Ok(())
+    }
+
+    /// Moves the file or subdirectory `name` of this directory into `destination`, keeping its
+    /// name, contents, attributes and timestamps. The entry set is written into the destination
+    /// before it is removed from this directory. Directories cannot be moved into themselves or
+    /// their own subdirectories.
+    pub fn move_to(
+        &mut self,
+        destination: &mut Directory<O>,
+        name: &str,
+    ) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+        destination.ensure_writable()?;
+
+        let upcase_table = Arc::clone(&self.upcase_table);
+        let upcase_table = upcase_table.as_ref();
+        let (location, offsets) = self.element_entries(name, upcase_table)?;
+        if destination.stream.first_cluster == self.stream.first_cluster {
+            return Ok(());
+        }
+        if destination.find(name, upcase_table)?.is_some() {
+            return Err(WriteError::AlreadyExists(String::from(name)));
+        }
+        let moved = self
+            .find(name, upcase_table)?
+            .ok_or_else(|| WriteError::NotFound(String::from(name)))?;
+        if let FsElement::D(moved) = &moved
+            && contains_directory(moved, destination.stream.first_cluster)?
+        {
+            return Err(WriteError::MoveIntoItself(String::from(name)));
+        }
+        // the moved element is accounted to the top-level directory it ends up in
+        let moved_bytes = if directory_scope(&self.path) == directory_scope(&destination.path) {
+            0
+        } else {
+            element_bytes(&moved, self.boot.bytes_per_cluster() as u64)?
+        };
+
+        let mut clusters = DirectoryClusters::of(destination)?;
+        let mut writer = Writer::new(
+            &destination.disk,
+            &destination.boot,
+            &mut destination.fat,
+            &destination.writes,
+            directory_scope(&destination.path),
+        );
+        let entries = writer.read_slots(&offsets)?;
+        if entries.len() < 2 {
+            return Err(WriteError::CorruptEntrySet(location));
+        }
+        writer.insert_entry_set(&mut clusters, &entries)?;
+        writer.write_slots(&offsets, &unused_entries(&entries))?;
+        writer.move_usage(directory_scope(&self.path), moved_bytes);
+        drop(writer);
+        destination.reload_stream()?;
+
+        self.generation = self.disk.generation();
+        destination.generation = self.generation;
+        self.writes.lock().notify([DirectoryChange::Renamed {
+            from: format!("{}/{name}", self.path),
+            to: format!("{}/{name}", destination.path),
+        }]);
+        Ok(())
+    }
+
+    /// Fails for directories of read-only volumes and stale handles.
+    fn ensure_writable(&self) -> Result<(), WriteError<O>> {
+        if self.read_only {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        if self.is_stale() {
+            return Err(WriteError::Io(O::Err::stale_handle()));
+        }
+        Ok(())
+    }
+
+    /// Location and entry offsets of the element `name` of this directory.
+    fn element_entries(
+        &self,
+        name: &str,
+        upcase_table: &UpcaseTable,
+    ) -> Result<(EntryLocation, Vec<u64>), WriteError<O>> {
+        match self.find(name, upcase_table)? {
+            Some(FsElement::F(file)) => Ok((file.location(), file.entry_offsets)),
+            Some(FsElement::D(dir)) => Ok((dir.location(), dir.entry_offsets)),
+            None => Err(WriteError::NotFound(String::from(name))),
+        }
+    }
+
+    /// Reads the stream extension entry of the directory again, as its size changes when it
+    /// grows.
+    fn reload_stream(&mut self) -> Result<(), WriteError<O>> {
+        let offset = *self
+            .entry_offsets
+            .get(1)
+            .ok_or(WriteError::CorruptEntrySet(self.location()))?;
+        let mut entry = [0u8; 32];
+        self.disk
+            .read_exact(offset, &mut entry)
+            .map_err(WriteError::Io)?;
+        if let Ok(DirEntry::StreamExtension(stream)) = DirEntry::try_from(entry) {
+            self.stream = stream;
+        }
+        self.generation = self.disk.generation();
+        Ok(())
+    }
+}
+
+/// Top-level directory the clusters of the file at `path` are accounted to, if it does not lie
+/// in the root directory itself.
+fn file_scope(path: &str) -> Option<&str> {
+    path.split_once('/').map(|(top_level, _)| top_level)
+}
+
+/// Top-level directory the clusters of the directory at `path` and its elements are accounted
+/// to.
+fn directory_scope(path: &str) -> Option<&str> {
+    path.split('/').next()
+}
+
+/// Whether `directory` or any directory below it starts at `first_cluster`.
+fn contains_directory<O: WriteOffset>(
+    directory: &Directory<O>,
+    first_cluster: u32,
+) -> Result<bool, WriteError<O>>
+where
+    O::Err: core::fmt::Debug,
+{
+    if directory.stream.first_cluster == first_cluster {
+        return Ok(true);
+    }
+    for item in directory.open()? {
+        if let FsElement::D(sub) = item
+            && contains_directory(&sub, first_cluster)?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Copy of an entry set with its file name entries replaced by ones for `name`, followed by the
+/// remaining secondary entries. `None` if the set has no stream extension entry.
+fn rename_entries(
+    entries: &[[u8; 32]],
+    name: &[u16],
+    upcase_table: &UpcaseTable,
+) -> Option<Vec<[u8; 32]>> {
+    let (file, rest) = entries.split_first()?;
+    let (stream, rest) = rest.split_first()?;
+    let old_names = (stream[STREAM_NAME_LENGTH] as usize)
+        .div_ceil(15)
+        .min(rest.len());
+
+    let mut renamed = vec![*file, *stream];
+    renamed[1][STREAM_NAME_LENGTH] = name.len() as u8;
+    renamed[1][STREAM_NAME_HASH..STREAM_NAME_HASH + 2]
+        .copy_from_slice(&upcase_table.name_hash(name).to_le_bytes());
+    renamed.extend(
+        name.chunks(15)
+            .map(|chunk| DirEntry::FileName(FileNameEntry::new(chunk)).bytes()),
+    );
+    renamed.extend_from_slice(&rest[old_names..]);
+
+    renamed[0][1] = (renamed.len() - 1) as u8;
+    let checksum = checksum::entry_set(&renamed);
+    renamed[0][2..4].copy_from_slice(&checksum.to_le_bytes());
+    Some(renamed)
+}
+
+/// Marks an entry as unused by clearing its `InUse` bit.
+fn unused(mut entry: [u8; 32]) -> [u8; 32] {
+    entry[0] &= 0x7F;
+    entry
+}
+
+/// Copy of an entry set with all entries marked as unused.
+fn unused_entries(entries: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    entries.iter().map(|entry| unused(*entry)).collect()
+}
+
+/// Whether each cluster of `chain` directly follows the previous one.
+fn is_contiguous(chain: &[u32]) -> bool {
+    chain.windows(2).all(|pair| pair[1] == pair[0] + 1)
+}
+
+/// Writes the attributes into the file entry of an entry set.
+fn write_attributes<O: WriteOffset>(
+    writer: &Writer<'_, O>,
+    offsets: &[u64],
+    attributes: FileAttributes,
+) -> Result<(), WriteError<O>> {
+    writer.update_entry_set(offsets, |entries| {
+        entries[0][FILE_ATTRIBUTES..FILE_ATTRIBUTES + 2]
+            .copy_from_slice(&attributes.bits().to_le_bytes());
+    })
+}
+
+impl<O: WriteOffset> Root<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Creates an empty file named `name` in the root directory, timestamped using `T`, and
+    /// returns a handle to it for writing. The file is listed among the items of the root
+    /// directory as well. Behaves like [`Directory::create_file`] otherwise.
+    pub fn create_file<T: Clock>(&mut self, name: &str) -> Result<File<O>, WriteError<O>> {
+        let parsed = self.create_entry_set::<T>(name, FileAttributes::ARCHIVE)?;
+        let file = File::try_new(
+            &self.disk,
+            &self.boot,
+            &self.fat,
+            parsed,
+            self.generation,
+            &self.options,
+            self.is_read_only(),
+            &self.writes,
+            String::from(name),
+        )?;
+        self.items.push(FsElement::F(file.clone()));
+        Ok(file)
+    }
+
+    /// Creates an empty directory named `name` in the root directory, timestamped using `T`, and
+    /// returns a handle to it. The directory is listed among the items of the root directory as
+    /// well. Behaves like [`Directory::create_dir`] otherwise.
+    pub fn create_dir<T: Clock>(&mut self, name: &str) -> Result<Directory<O>, WriteError<O>> {
+        let parsed = self.create_entry_set::<T>(name, FileAttributes::DIRECTORY)?;
+        let directory = Directory::new(
+            Arc::clone(&self.disk),
+            Arc::clone(&self.boot),
+            Arc::clone(&self.fat),
+            Arc::clone(&self.upcase),
+            parsed,
+            self.generation,
+            self.options,
+            self.is_read_only(),
+            Arc::clone(&self.writes),
+            String::from(name),
+        );
+        self.items.push(FsElement::D(directory.clone()));
+        Ok(directory)
+    }
+
+    /// Writes an entry set for a new element into the root directory, see
+    /// [`Writer::create_entry_set`].
+    fn create_entry_set<T: Clock>(
+        &mut self,
+        name: &str,
+        attributes: FileAttributes,
+    ) -> Result<ParsedFileEntry, WriteError<O>> {
+        if self.is_read_only() {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        if self.is_stale() {
+            return Err(WriteError::Io(O::Err::stale_handle()));
+        }
+
+        let upcase_table = Arc::clone(&self.upcase);
+        if self.find(name, upcase_table.as_ref()).is_some() {
+            return Err(WriteError::AlreadyExists(String::from(name)));
+        }
+
+        // a new top-level directory starts out with the usage of its first cluster
+        let scope = attributes.is_directory().then_some(name);
+        let mut clusters = DirectoryClusters::root(self)?;
+        let parsed = self.writer(scope).create_entry_set(
+            &mut clusters,
+            name,
+            attributes,
+            Timestamps::now::<T>(),
+            &upcase_table,
+        )?;
+        self.generation = self.disk.generation();
+        self.writes
+            .lock()
+            .notify([DirectoryChange::Created(String::from(name))]);
+        Ok(parsed)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<O: WriteOffset> std::io::Write for File<O>
+where
+    O::Err: Into<std::io::Error>,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        use std::io::{Error, ErrorKind};
+
+        self.write_data(buf).map_err(|err| match err {
+            WriteError::Io(err) => err.into(),
+            WriteError::NoSpace(_) => Error::from(ErrorKind::StorageFull),
+            _ => Error::new(ErrorKind::InvalidData, "corrupt file system structures"),
+        })
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        WriteOffset::flush(&*self.disk).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn write_files() {
+    use crate::{fs::FsElement, name::Normalization, root::Root, test_utils::TestImage};
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+        string::String,
+        sync::Mutex,
+    };
+
+    let mut image = TestImage::sample();
+    image.add_entry_set(image.root_cluster(), "empty.txt", false, 0, 0);
+    // clusters 100 to 102 are in use by the sample tree
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+    let cluster_size = image.boot.bytes_per_cluster() as usize;
+
+    let mut root = Root::open(Mutex::new(image.cursor())).unwrap();
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    hello.write_all(b"Howdy").unwrap();
+    // the following cluster is in use, so the file continues elsewhere
+    hello.seek(SeekFrom::End(0)).unwrap();
+    let appended = vec![b'x'; cluster_size];
+    hello.write_all(&appended).unwrap();
+    assert_eq!(hello.len(), 13 + cluster_size as u64);
+    assert_eq!(hello.on_disk_clusters(), 2);
+
+    let Some(FsElement::F(empty)) = root.find("empty.txt", Normalization::None) else {
+        panic!("empty.txt not found");
+    };
+    let contents: Vec<u8> = (0..3 * cluster_size).map(|i| (i % 251) as u8).collect();
+    empty.write_all(&contents).unwrap();
+    empty.flush().unwrap();
+    // the handle can be read after writing
+    empty.rewind().unwrap();
+    let mut read = Vec::new();
+    empty.read_to_end(&mut read).unwrap();
+    assert_eq!(read, contents);
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let mut map = root.cluster_map().unwrap();
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    let mut read = String::new();
+    hello.read_to_string(&mut read).unwrap();
+    assert_eq!(
+        read,
+        String::from("Howdy, World!") + &"x".repeat(cluster_size)
+    );
+    assert_eq!(hello.verify_chain(&mut map), []);
+
+    let Some(FsElement::F(empty)) = root.find("empty.txt", Normalization::None) else {
+        panic!("empty.txt not found");
+    };
+    let mut read = Vec::new();
+    empty.read_to_end(&mut read).unwrap();
+    assert_eq!(read, contents);
+    assert_eq!(empty.verify_chain(&mut map), []);
+}
+
+#[cfg(test)]
+#[test]
+fn contiguous_chains() {
+    use crate::{fs::FsElement, name::Normalization, root::Root, test_utils::TestImage};
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+        sync::Mutex,
+    };
+
+    let mut image = TestImage::sample();
+    let root_cluster = image.root_cluster();
+    image.write_clusters(200, b"Hello, chain!");
+    image.add_entry_set(root_cluster, "chained.txt", false, 200, 13);
+    // `chained.txt` uses a FAT chain
+    let stream_offset = image.cluster_offset(root_cluster) + 11 * 32;
+    image.image[stream_offset + STREAM_FLAGS] &= !NO_FAT_CHAIN;
+    let fat_offset = image.boot.fat_offset as usize * 512;
+    image.image[fat_offset + 200 * 4..fat_offset + 201 * 4].fill(0xFF);
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+    image.image[bitmap_offset + 24] |= 0b0100_0000;
+    let cluster_size = image.boot.bytes_per_cluster() as usize;
+
+    let mut root = Root::open(Mutex::new(image.cursor())).unwrap();
+    let Some(FsElement::F(chained)) = root.find("chained.txt", Normalization::None) else {
+        panic!("chained.txt not found");
+    };
+    assert!(!chained.no_fat_chain);
+    // the chain continues with the following cluster, so the FAT is no longer needed
+    chained.seek(SeekFrom::End(0)).unwrap();
+    chained.write_all(&vec![b'x'; cluster_size]).unwrap();
+    assert!(chained.no_fat_chain);
+
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    hello.seek(SeekFrom::End(0)).unwrap();
+    hello.write_all(&vec![b'x'; cluster_size]).unwrap();
+    assert!(!hello.no_fat_chain);
+    // the remaining cluster is contiguous again
+    hello.set_len(13).unwrap();
+    assert!(hello.no_fat_chain);
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let mut map = root.cluster_map().unwrap();
+    for (name, len) in [("chained.txt", 13 + cluster_size), ("hello.txt", 13)] {
+        let Some(FsElement::F(file)) = root.find(name, Normalization::None) else {
+            panic!("{name} not found");
+        };
+        assert!(file.no_fat_chain, "{name}");
+        let mut read = Vec::new();
+        file.read_to_end(&mut read).unwrap();
+        assert_eq!(read.len(), len);
+        assert_eq!(file.verify_chain(&mut map), []);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn small_writes() {
+    use crate::{
+        fs::FsElement,
+        name::Normalization,
+        root::{OpenOptionsBuilder, ReadProfile, Root},
+        test_utils::TestImage,
+    };
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+        sync::Mutex,
+    };
+
+    let mut image = TestImage::sample();
+    let cluster_size = image.boot.bytes_per_cluster() as usize;
+    let contents: Vec<u8> = (0..3 * cluster_size).map(|i| (i % 251) as u8).collect();
+    image.write_clusters(200, &contents[..100]);
+    image.add_entry_set(image.root_cluster(), "log.txt", false, 200, 100);
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 24] |= 0b0100_0000;
+    let options = OpenOptionsBuilder::default()
+        .read_profile(ReadProfile::HighThroughput)
+        .build()
+        .unwrap();
+
+    let mut root = Root::open_with(Mutex::new(image.cursor()), options).unwrap();
+    let Some(FsElement::F(log)) = root.find("log.txt", Normalization::None) else {
+        panic!("log.txt not found");
+    };
+    log.seek(SeekFrom::End(0)).unwrap();
+    for line in contents[100..].chunks(100) {
+        log.write_all(line).unwrap();
+    }
+    assert_eq!(log.len(), contents.len() as u64);
+    assert_eq!(log.on_disk_clusters(), 3);
+
+    // data read ahead does not outlive a write
+    log.rewind().unwrap();
+    let mut byte = [0u8; 1];
+    log.read_exact(&mut byte).unwrap();
+    log.write_all(b"xyz").unwrap();
+    log.seek(SeekFrom::Start(1)).unwrap();
+    let mut read = [0u8; 3];
+    log.read_exact(&mut read).unwrap();
+    assert_eq!(&read, b"xyz");
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let Some(FsElement::F(log)) = root.find("log.txt", Normalization::None) else {
+        panic!("log.txt not found");
+    };
+    let mut read = Vec::new();
+    log.read_to_end(&mut read).unwrap();
+    assert_eq!(read[..1], contents[..1]);
+    assert_eq!(&read[1..4], b"xyz");
+    assert_eq!(read[4..], contents[4..]);
+}
+
+#[cfg(test)]
+#[test]
+fn create_files() {
+    use crate::{fs::FsElement, name::Normalization, root::Root, test_utils::TestImage};
+    use std::{
+        format,
+        io::{Cursor, Read, Write},
+        sync::Mutex,
+        time::SystemTime,
+    };
+
+    let mut image = TestImage::sample();
+    // clusters 100 to 102 are in use by the sample tree
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+
+    let mut root = Root::open(Mutex::new(image.cursor())).unwrap();
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    let mut created = dir.create_file::<SystemTime>("created.txt").unwrap();
+    assert_eq!(created.len(), 0);
+    created.write_all(b"fresh contents").unwrap();
+    assert!(matches!(
+        dir.create_file::<SystemTime>("CREATED.TXT"),
+        Err(WriteError::AlreadyExists(_))
+    ));
+    assert!(dir.create_file::<SystemTime>("in/valid").is_err());
+
+    // more entry sets than fit into the single cluster of the directory
+    for i in 0..50 {
+        dir.create_file::<SystemTime>(&format!("file{i}")).unwrap();
+    }
+    assert_eq!(dir.open().unwrap().len(), 52);
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let mut map = root.cluster_map().unwrap();
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    // the cluster following the directory is in use, so it continues elsewhere
+    assert!(!dir.no_fat_chain());
+    let items = dir.open().unwrap();
+    assert_eq!(items.len(), 52);
+    assert!(items.iter().any(|item| item.name() == "file49"));
+
+    let Some(FsElement::F(mut created)) = dir.find("created.txt", Normalization::None).unwrap()
+    else {
+        panic!("created.txt not found");
+    };
+    let mut read = Vec::new();
+    created.read_to_end(&mut read).unwrap();
+    assert_eq!(read, b"fresh contents");
+    assert_eq!(created.verify_chain(&mut map), []);
+}
+
+#[cfg(test)]
+#[test]
+fn create_file_batch() {
+    use crate::{
+        fs::FsElement, name::Normalization, root::Root, test_utils::TestImage,
+        volume::write::BATCH_FILES,
+    };
+    use std::{
+        format,
+        io::{Cursor, Read},
+        sync::Mutex,
+        time::SystemTime,
+    };
+
+    let mut image = TestImage::sample();
+    // clusters 100 to 102 are in use by the sample tree
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+
+    let mut root = Root::open(Mutex::new(image.cursor())).unwrap();
+    let disk = Arc::clone(&root.disk);
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    // more files than are written in a single chunk, in reverse order
+    let count = BATCH_FILES + 10;
+    let files: Vec<(String, Vec<u8>)> = (0..count)
+        .rev()
+        .map(|i| (format!("file{i:03}"), format!("contents {i}").into_bytes()))
+        .chain([(String::from("empty"), Vec::new())])
+        .collect();
+    dir.create_files::<SystemTime, _, _>(files).unwrap();
+
+    // nothing is written if a name is taken
+    let image = disk.lock().unwrap().get_ref().clone();
+    assert!(matches!(
+        dir.create_files::<SystemTime, _, _>([("new", b"a"), ("NESTED.txt", b"b")]),
+        Err(WriteError::AlreadyExists(name)) if name == "NESTED.txt"
+    ));
+    assert!(matches!(
+        dir.create_files::<SystemTime, _, _>([("a", b"a"), ("A", b"b")]),
+        Err(WriteError::AlreadyExists(_))
+    ));
+    assert_eq!(*disk.lock().unwrap().get_ref(), image);
+
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let mut map = root.cluster_map().unwrap();
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    let items = dir.open().unwrap();
+    assert_eq!(items.len(), count + 2);
+    // sorted by name, after the existing file
+    assert_eq!(items[1].name(), "empty");
+    assert_eq!(items[2].name(), "file000");
+
+    for name in ["file000", "file123", &format!("file{}", count - 1)] {
+        let Some(FsElement::F(mut file)) = dir.find(name, Normalization::None).unwrap() else {
+            panic!("{name} not found");
+        };
+        let mut read = String::new();
+        file.read_to_string(&mut read).unwrap();
+        assert_eq!(
+            read,
+            format!("contents {}", &name[4..].parse::<usize>().unwrap())
+        );
+        assert_eq!(file.verify_chain(&mut map), []);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn create_directories() {
+    use crate::{fs::FsElement, name::Normalization, root::Root, test_utils::TestImage};
+    use std::{
+        io::{Cursor, Read, Write},
+        sync::Mutex,
+        time::SystemTime,
+    };
+
+    let mut image = TestImage::sample();
+    // clusters 100 to 102 are in use by the sample tree
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+
+    let mut root = Root::open(Mutex::new(image.cursor())).unwrap();
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    let mut sub = dir.create_dir::<SystemTime>("sub").unwrap();
+    assert!(sub.open().unwrap().is_empty());
+    sub.create_file::<SystemTime>("inner.txt")
+        .unwrap()
+        .write_all(b"inside")
+        .unwrap();
+    sub.create_dir::<SystemTime>("deeper").unwrap();
+    assert!(matches!(
+        dir.create_dir::<SystemTime>("nested.txt"),
+        Err(WriteError::AlreadyExists(_))
+    ));
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    let Some(FsElement::D(sub)) = dir.find("sub", Normalization::None).unwrap() else {
+        panic!("dir/sub not found");
+    };
+    assert!(sub.attributes().contains(FileAttributes::DIRECTORY));
+    let items = sub.open().unwrap();
+    assert_eq!(items.len(), 2);
+    assert!(matches!(&items[1], FsElement::D(deeper) if deeper.name() == "deeper"));
+    let FsElement::F(mut inner) = items.into_iter().next().unwrap() else {
+        panic!("inner.txt not found");
+    };
+    let mut read = Vec::new();
+    inner.read_to_end(&mut read).unwrap();
+    assert_eq!(read, b"inside");
+}
+
+#[cfg(test)]
+#[test]
+fn create_in_root() {
+    use crate::{fs::FsElement, name::Normalization, root::Root, test_utils::TestImage};
+    use std::{
+        io::{Cursor, Read, Write},
+        sync::Mutex,
+        time::SystemTime,
+    };
+
+    let mut root = Root::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let items = root.items().len();
+    root.create_file::<SystemTime>("top.txt")
+        .unwrap()
+        .write_all(b"top level")
+        .unwrap();
+    root.create_dir::<SystemTime>("top")
+        .unwrap()
+        .create_file::<SystemTime>("inner.txt")
+        .unwrap()
+        .write_all(b"inside")
+        .unwrap();
+    assert_eq!(root.items().len(), items + 2);
+    assert!(matches!(
+        root.create_file::<SystemTime>("TOP.TXT"),
+        Err(WriteError::AlreadyExists(_))
+    ));
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let Some(FsElement::F(file)) = root.find("top.txt", Normalization::None) else {
+        panic!("top.txt not found");
+    };
+    let mut read = Vec::new();
+    file.read_to_end(&mut read).unwrap();
+    assert_eq!(read, b"top level");
+    let Some(FsElement::D(dir)) = root.find("top", Normalization::None) else {
+        panic!("top not found");
+    };
+    let Some(FsElement::F(mut inner)) = dir.find("inner.txt", Normalization::None).unwrap() else {
+        panic!("top/inner.txt not found");
+    };
+    let mut read = Vec::new();
+    inner.read_to_end(&mut read).unwrap();
+    assert_eq!(read, b"inside");
+}
+
+#[cfg(test)]
+#[test]
+fn rename_and_move() {
+    use crate::{fs::FsElement, name::Normalization, root::Root, test_utils::TestImage};
+    use std::{
+        io::{Cursor, Read},
+        sync::Mutex,
+        time::SystemTime,
+    };
+
+    const LONG_NAME: &str = "a considerably longer name.txt";
+
+    let mut image = TestImage::sample();
+    // clusters 100 to 102 are in use by the sample tree
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+
+    let mut root = Root::open(Mutex::new(image.cursor())).unwrap();
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    let mut sub = dir.create_dir::<SystemTime>("sub").unwrap();
+    let mut deeper = sub.create_dir::<SystemTime>("deeper").unwrap();
+
+    dir.rename("nested.txt", "renamed.txt").unwrap();
+    // the longer name needs more entries
+    dir.rename("renamed.txt", LONG_NAME).unwrap();
+    dir.rename("sub", "SUB").unwrap();
+    assert!(matches!(
+        dir.rename("SUB", LONG_NAME),
+        Err(WriteError::AlreadyExists(_))
+    ));
+    assert!(matches!(
+        dir.rename("missing", "other"),
+        Err(WriteError::NotFound(_))
+    ));
+
+    assert!(matches!(
+        dir.move_to(&mut deeper, "SUB"),
+        Err(WriteError::MoveIntoItself(_))
+    ));
+    dir.move_to(&mut sub, LONG_NAME).unwrap();
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    let items = dir.open().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name(), "SUB");
+    let FsElement::D(sub) = &items[0] else {
+        panic!("SUB is not a directory");
+    };
+    let Some(FsElement::F(mut moved)) = sub.find(LONG_NAME, Normalization::None).unwrap() else {
+        panic!("moved file not found");
+    };
+    let mut read = Vec::new();
+    moved.read_to_end(&mut read).unwrap();
+    assert_eq!(read, b"nested");
+    assert_eq!(sub.open().unwrap().len(), 2);
+}
+
+#[cfg(test)]
+#[test]
+fn set_file_len() {
+    use crate::{fs::FsElement, name::Normalization, root::Root, test_utils::TestImage};
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+        sync::Mutex,
+    };
+
+    let mut image = TestImage::sample();
+    // clusters 100 to 102 are in use by the sample tree
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+    let cluster_size = image.boot.bytes_per_cluster() as u64;
+
+    let mut root = Root::open(Mutex::new(image.cursor())).unwrap();
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    hello.seek(SeekFrom::End(0)).unwrap();
+    hello.set_len(3 * cluster_size + 5).unwrap();
+    assert_eq!(hello.len(), 3 * cluster_size + 5);
+    assert_eq!(hello.on_disk_clusters(), 4);
+    // the missing clusters are allocated at once
+    assert!(is_contiguous(&hello.chain.as_ref().unwrap()[1..]));
+    let mut read = Vec::new();
+    hello.seek(SeekFrom::Start(0)).unwrap();
+    hello.read_to_end(&mut read).unwrap();
+    assert_eq!(read[..13], *b"Hello, World!");
+    assert!(read[13..].iter().all(|&byte| byte == 0));
+    hello.seek(SeekFrom::Start(13)).unwrap();
+    // the position is kept
+    hello.write_all(b"!").unwrap();
+    hello.set_len(5).unwrap();
+    assert_eq!(hello.allocated_len(), 5);
+    assert_eq!(hello.on_disk_clusters(), 1);
+
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    let Some(FsElement::F(mut nested)) = dir.find("nested.txt", Normalization::None).unwrap()
+    else {
+        panic!("nested.txt not found");
+    };
+    nested.set_len(0).unwrap();
+    assert!(nested.is_empty());
+    nested.set_len(2).unwrap();
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let mut map = root.cluster_map().unwrap();
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    let mut read = Vec::new();
+    hello.read_to_end(&mut read).unwrap();
+    assert_eq!(read, b"Hello");
+    assert_eq!(hello.verify_chain(&mut map), []);
+
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    let Some(FsElement::F(mut nested)) = dir.find("nested.txt", Normalization::None).unwrap()
+    else {
+        panic!("nested.txt not found");
+    };
+    let mut read = Vec::new();
+    nested.read_to_end(&mut read).unwrap();
+    assert_eq!(read, [0, 0]);
+    assert_eq!(nested.verify_chain(&mut map), []);
+}
+
+#[cfg(test)]
+#[test]
+fn set_attributes() {
+    use crate::{fs::FsElement, name::Normalization, root::Root, test_utils::TestImage};
+    use std::{io::Cursor, sync::Mutex};
+
+    let mut root = Root::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    hello
+        .set_attributes(FileAttributes::HIDDEN | FileAttributes::DIRECTORY)
+        .unwrap();
+    assert!(hello.is_hidden());
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    dir.set_attributes(FileAttributes::READ_ONLY | FileAttributes::ARCHIVE)
+        .unwrap();
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let mut root = Root::open(Cursor::new(image)).unwrap();
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    // files cannot become directories
+    assert_eq!(hello.attributes(), FileAttributes::HIDDEN);
+    assert!(!hello.is_read_only() && !hello.is_system() && !hello.is_archive());
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    assert!(dir.is_read_only() && dir.is_archive() && !dir.is_hidden());
+    assert!(dir.attributes().contains(FileAttributes::DIRECTORY));
+}
+
+#[cfg(test)]
+#[test]
+fn set_times() {
+    use crate::{
+        fs::FsElement,
+        name::Normalization,
+        root::{OpenOptionsBuilder, Root},
+        test_utils::TestImage,
+        timestamp::{DateTime, UtcOffset},
+    };
+    use std::{
+        io::Cursor,
+        sync::Mutex,
+        time::{Duration, SystemTime},
+    };
+
+    let time = |year, centiseconds, utc_offset_minutes| {
+        let utc_offset = UtcOffset::from_minutes(utc_offset_minutes).unwrap();
+        Timestamp::from_date_time(DateTime {
+            year,
+            month: 6,
+            day: 15,
+            hour: 12,
+            minute: 30,
+            second: 41,
+            centiseconds,
+            utc_offset,
+        })
+        .unwrap()
+    };
+    let mut root = Root::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    hello
+        .set_times(time(2001, 25, 120), time(2002, 50, 0), time(2003, 75, 60))
+        .unwrap();
+    assert_eq!(
+        hello.timestamps().modified().date_time(),
+        time(2002, 50, 0).date_time()
+    );
+
+    let image = root.disk.lock().unwrap().get_ref().clone();
+    let options = OpenOptionsBuilder::default()
+        .verify_checksums(true)
+        .build()
+        .unwrap();
+    let mut root = Root::open_with(Mutex::new(Cursor::new(image)), options).unwrap();
+    let Some(FsElement::F(hello)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    let timestamps = *hello.timestamps();
+    assert_eq!(
+        timestamps.created().date_time(),
+        time(2001, 25, 120).date_time()
+    );
+    assert_eq!(
+        timestamps.modified().date_time(),
+        time(2002, 50, 0).date_time()
+    );
+    // the last access has no 10ms increment, so odd seconds are lost as well
+    let accessed = timestamps.accessed().date_time();
+    assert_eq!(
+        (accessed.year, accessed.second, accessed.centiseconds),
+        (2003, 40, 0)
+    );
+    assert_eq!(accessed.utc_offset.minutes(), Some(60));
+
+    let before = SystemTime::now() - Duration::from_secs(2);
+    hello.touch::<SystemTime>().unwrap();
+    let timestamps = *hello.timestamps();
+    assert_eq!(
+        timestamps.created().date_time(),
+        time(2001, 25, 120).date_time()
+    );
+    assert!(SystemTime::from(*timestamps.modified()) >= before);
+    assert!(SystemTime::from(*timestamps.accessed()) >= before);
+}
+
+#[cfg(test)]
+#[test]
+fn handle_and_volume_writes() {
+    use crate::{test_utils::TestImage, volume::Volume};
+    use std::{
+        io::{Cursor, Seek, SeekFrom, Write},
+        sync::Mutex,
+        time::SystemTime,
+    };
+
+    let mut volume = Volume::open_rw(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let cluster = volume.bytes_per_cluster() as usize;
+    volume
+        .write_atomic::<SystemTime>("a", &vec![b'a'; cluster])
+        .unwrap();
+    volume
+        .write_atomic::<SystemTime>("b", &vec![b'b'; cluster])
+        .unwrap();
+
+    // the handle allocates clusters without the volume being opened again ...
+    let Some(FsElement::F(mut a)) = volume.root().open_path("a").unwrap() else {
+        panic!("a not found");
+    };
+    a.seek(SeekFrom::End(0)).unwrap();
+    a.write_all(&vec![b'A'; 2 * cluster]).unwrap();
+    // ... which the volume must not hand out again
+    volume
+        .write_atomic::<SystemTime>("c", &vec![b'c'; 2 * cluster])
+        .unwrap();
+
+    let image = volume.root.disk.lock().unwrap().get_ref().clone();
+    let volume = Volume::open(Cursor::new(image)).unwrap();
+    assert_eq!(volume.find_cross_links().unwrap(), []);
+    assert_eq!(
+        volume.read("a").unwrap(),
+        [vec![b'a'; cluster], vec![b'A'; 2 * cluster]].concat()
+    );
+    assert_eq!(volume.read("c").unwrap(), vec![b'c'; 2 * cluster]);
+}
@@ -3,9 +3,13 @@
 //! exFAT filesystem implementation in Rust.
 //!
 //! ## Features
-//! - exFAT formatting
+//! - exFAT formatting, including building pre-populated images
 //! - `no-std` support
 //! - reading
+//! - creating files and directories
+//! - writing, truncating and extending files
+//! - renaming, moving and removing
+//! - consistency checks, scrubbing and recovery of lost data
 //!
 //! ## Usage
 //!
@@ -58,8 +62,26 @@
 //! }
 //! ```
 //!
+//!
+//! ### Writing
+//! ```no_run
+//! use exfat_fs::volume::Volume;
+//! use std::{fs::OpenOptions, time::SystemTime};
+//!
+//! # let file = OpenOptions::new().read(true).write(true).open("exfat_vol").unwrap();
+//! let mut volume = Volume::open_rw(file).unwrap();
+//!
+//! volume.create_dir::<SystemTime>("notes").unwrap();
+//! volume.write_atomic::<SystemTime>("notes/hello.txt", b"Hello, World!").unwrap();
+//! assert_eq!(volume.read("notes/hello.txt").unwrap(), b"Hello, World!");
+//!
+//! volume.close().unwrap();
+//! ```
+//!
 //! ## Limitations
-//! Writing is currently not supported (WIP).
+//! Modifications are not journaled: if a write is interrupted, the volume may be left
+//! inconsistent. Volumes opened with [`Volume::open_rw`](volume::Volume::open_rw) are marked dirty
+//! until they are flushed, so such volumes can be detected and checked.
 #![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 #[cfg(any(feature = "std", test))]
@@ -68,9 +90,33 @@ extern crate std;
 extern crate alloc;
 
 use alloc::{string::String, vec::Vec};
+/// Cluster allocation strategies
+pub mod allocator;
+/// Asynchronous device access
+#[cfg(feature = "async")]
+pub mod async_io;
+/// Synthetic volumes for benchmarks and stress tests
+#[cfg(feature = "bench")]
+pub mod bench;
+/// Allocation bitmap handling
+pub(crate) mod bitmap;
 pub(crate) mod boot_sector;
+/// Block caches shared between handles
+pub(crate) mod cache;
+/// Consistency findings
+pub mod check;
+/// Checksum algorithms of the exFAT specification
+pub mod checksum;
 /// Cluster I/O
 pub(crate) mod cluster;
+/// Comparison of volumes against reference images
+#[cfg(feature = "std")]
+pub mod compare;
+/// Annotated dumps of on-disk structures
+pub mod diagnostics;
+/// Locating volumes by identifier
+#[cfg(feature = "std")]
+pub mod discovery;
 /// Disk utility functions
 pub mod disk;
 /// Internal directory abstractions
@@ -81,8 +127,32 @@ pub(crate) mod fat;
 pub mod format;
 /// Filesystem abstractions
 pub mod fs;
+/// Limits of the exFAT specification
+pub mod limits;
+/// File name handling
+pub mod name;
+/// MBR and GPT partition tables of whole-disk images
+pub mod partition;
+/// Quick identification of exFAT volumes
+mod probe;
+/// Recovery of lost data
+pub mod repair;
 pub mod root;
+/// Background verification of volume contents
+pub mod scrub;
+/// Metadata-only image snapshots
+pub mod snapshot;
+#[cfg(test)]
+mod test_utils;
+/// Rate limiting of I/O
+pub mod throttle;
+/// Forensic timeline export
+pub mod timeline;
 pub mod timestamp;
+/// Whole-volume access
+pub mod volume;
+
+pub use probe::{ProbeInfo, probe};
 
 pub const GB: u32 = 1024 * 1024 * 1024;
 pub const MB: u32 = 1024 * 1024;
@@ -92,39 +162,120 @@ pub const DEFAULT_BOUNDARY_ALIGNEMENT: u32 = 1024 * 1024;
 /// First usable cluster index of the cluster heap
 pub(crate) const FIRST_USABLE_CLUSTER_INDEX: u32 = 2;
 
-/// A UTF16 encoded volume label. The length must not exceed 11 characters.
-#[derive(Copy, Clone, Debug, Default)]
-pub struct Label(pub(crate) [u8; 22], pub(crate) u8);
+/// Maximum length of a volume label in UTF-8 bytes: each of its UTF-16 code units takes up to
+/// three bytes.
+const MAX_LABEL_UTF8_LENGTH: usize = 3 * limits::MAX_LABEL_LENGTH;
+
+/// A UTF16 encoded volume label. The length must not exceed 11 UTF-16 code units.
+#[derive(Copy, Clone, Debug)]
+pub struct Label {
+    /// Code units as stored on disk.
+    pub(crate) units: [u8; 22],
+    /// Number of code units.
+    pub(crate) len: u8,
+    utf8: [u8; MAX_LABEL_UTF8_LENGTH],
+    utf8_len: u8,
+}
 
 impl Label {
     pub fn new(label: String) -> Option<Label> {
-        let len = label.len();
-        if len > 11 {
-            None
-        } else {
-            let mut utf16_bytes = [0u8; 22];
-
-            let encoded: Vec<u8> = label.encode_utf16().flat_map(|x| x.to_le_bytes()).collect();
+        Self::from_utf16(&label.encode_utf16().collect::<Vec<_>>())
+    }
 
-            let copy_len = encoded.len();
-            assert!(copy_len <= 22);
-            utf16_bytes[..copy_len].copy_from_slice(&encoded[..copy_len]);
+    /// Creates a label from UTF-16 code units. Returns `None` if they are not valid UTF-16 or
+    /// exceed the length limit.
+    pub fn from_utf16(units: &[u16]) -> Option<Label> {
+        if units.len() > limits::MAX_LABEL_LENGTH
+            || char::decode_utf16(units.iter().copied()).any(|c| c.is_err())
+        {
+            return None;
+        }
+        let mut bytes = [0u8; 22];
+        for (i, unit) in units.iter().enumerate() {
+            bytes[2 * i..2 * i + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        Some(Self::from_entry(bytes, units.len() as u8))
+    }
 
-            Some(Label(utf16_bytes, len as u8))
+    /// Creates a label from the fields of a volume label entry, replacing unpaired surrogates.
+    pub(crate) fn from_entry(units: [u8; 22], len: u8) -> Label {
+        let len = len.min(limits::MAX_LABEL_LENGTH as u8);
+        let decoded = char::decode_utf16(
+            units[..2 * len as usize]
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]])),
+        );
+        let mut utf8 = [0u8; MAX_LABEL_UTF8_LENGTH];
+        let mut utf8_len = 0;
+        for c in decoded {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            utf8_len += c.encode_utf8(&mut utf8[utf8_len..]).len();
+        }
+        Label {
+            units,
+            len,
+            utf8,
+            utf8_len: utf8_len as u8,
         }
     }
+
+    /// The label as a string. Unpaired surrogates of labels read from disk are replaced by
+    /// `U+FFFD`.
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.utf8[..self.utf8_len as usize]).unwrap_or_default()
+    }
+
+    /// Number of UTF-16 code units of the label.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for Label {
+    fn default() -> Self {
+        Self::from_entry([0; 22], 0)
+    }
 }
+
 impl core::fmt::Display for Label {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let mut converted = [0u16; 11];
+        f.write_str(self.as_str())
+    }
+}
 
-        for (i, chunk) in self.0[..self.1 as usize * 2].chunks_exact(2).enumerate() {
-            converted[i] = u16::from_ne_bytes([chunk[0], chunk[1]]);
-        }
+#[cfg(test)]
+#[test]
+fn label_with_surrogate_pairs() {
+    use alloc::string::ToString;
 
-        match String::from_utf16(&converted) {
-            Ok(s) => write!(f, "{}", s),
-            Err(_) => write!(f, "<invalid utf16>"),
-        }
+    let label = Label::new("Fotos 📷".to_string()).unwrap();
+    assert_eq!(label.len(), 8);
+    assert_eq!(label.as_str(), "Fotos 📷");
+    assert_eq!(label.to_string(), "Fotos 📷");
+
+    // 10 units + a surrogate pair exceeds the limit
+    assert!(Label::new("0123456789📷".to_string()).is_none());
+    // 11 non-ASCII characters fit
+    assert!(Label::new("ääääääääääé".to_string()).is_some());
+    // ... as do 11 characters of three UTF-8 bytes each
+    let label: Vec<u16> = "ボリュームラベル日本語".encode_utf16().collect();
+    assert_eq!(
+        Label::from_utf16(&label).unwrap().as_str(),
+        "ボリュームラベル日本語"
+    );
+
+    // unpaired surrogates are only accepted from disk
+    let unpaired = [0x41, 0xD83D, 0x42];
+    assert!(Label::from_utf16(&unpaired).is_none());
+    let mut units = [0u8; 22];
+    for (i, unit) in unpaired.iter().enumerate() {
+        units[2 * i..2 * i + 2].copy_from_slice(&unit.to_le_bytes());
     }
+    let label = Label::from_entry(units, 3);
+    assert_eq!(label.as_str(), "A\u{FFFD}B");
+    assert_eq!(label.len(), 3);
 }
@@ -0,0 +1,82 @@
+use crate::MB;
+
+pub use crate::name::MAX_NAME_LENGTH;
+
+/// Smallest supported sector size (in bytes).
+pub const MIN_BYTES_PER_SECTOR: u16 = 512;
+/// Biggest supported sector size (in bytes).
+pub const MAX_BYTES_PER_SECTOR: u16 = 4096;
+/// Biggest supported cluster size (in bytes). The smallest cluster size is the sector size.
+pub const MAX_CLUSTER_SIZE: u32 = 32 * MB;
+/// Maximum amount of clusters in the cluster heap.
+pub const MAX_CLUSTER_COUNT: u32 = 0xFFFFFFF5;
+/// Smallest supported volume size (in bytes).
+pub const MIN_VOLUME_SIZE: u64 = MB as u64;
+/// Maximum size of a directory (in bytes), limiting the amount of entries it can hold.
+pub const MAX_DIRECTORY_SIZE: u64 = 256 * MB as u64;
+/// Maximum length of a volume label in UTF-16 code units.
+pub const MAX_LABEL_LENGTH: usize = 11;
+
+/// Whether the sector size is supported: a power of two between [`MIN_BYTES_PER_SECTOR`] and
+/// [`MAX_BYTES_PER_SECTOR`].
+pub const fn is_valid_sector_size(bytes_per_sector: u16) -> bool {
+    bytes_per_sector.is_power_of_two()
+        && bytes_per_sector >= MIN_BYTES_PER_SECTOR
+        && bytes_per_sector <= MAX_BYTES_PER_SECTOR
+}
+
+/// Whether the cluster size is supported for the given sector size: a power of two between the
+/// sector size and [`MAX_CLUSTER_SIZE`].
+pub const fn is_valid_cluster_size(bytes_per_sector: u16, bytes_per_cluster: u32) -> bool {
+    is_valid_sector_size(bytes_per_sector)
+        && bytes_per_cluster.is_power_of_two()
+        && bytes_per_cluster >= bytes_per_sector as u32
+        && bytes_per_cluster <= MAX_CLUSTER_SIZE
+}
+
+/// Maximum size of a file (in bytes) on a volume with the given cluster size, i.e. the size of
+/// the biggest possible cluster heap.
+pub const fn max_file_size_with(bytes_per_cluster: u32) -> u64 {
+    MAX_CLUSTER_COUNT as u64 * bytes_per_cluster as u64
+}
+
+/// Maximum size of a file (in bytes) on any volume.
+pub const fn max_file_size() -> u64 {
+    max_file_size_with(MAX_CLUSTER_SIZE)
+}
+
+/// Maximum size of a volume (in bytes) with the given sector size whose space can be used
+/// entirely: the biggest possible cluster heap, preceded by the biggest possible offset of the
+/// cluster heap (`ClusterHeapOffset`, a sector count).
+pub const fn max_volume_size(bytes_per_sector: u16) -> u64 {
+    u32::MAX as u64 * bytes_per_sector as u64 + max_file_size()
+}
+
+#[cfg(test)]
+#[test]
+fn limits() {
+    use crate::format::{Exfat, FormatVolumeOptionsBuilder};
+    use std::time::SystemTime;
+
+    assert!(is_valid_sector_size(4096));
+    assert!(!is_valid_sector_size(1024 + 512));
+    assert!(!is_valid_sector_size(8192));
+    assert!(is_valid_cluster_size(512, 512));
+    assert!(!is_valid_cluster_size(4096, 2048));
+    assert!(!is_valid_cluster_size(512, 2 * MAX_CLUSTER_SIZE));
+    assert_eq!(max_file_size(), 0xFFFFFFF5 << 25);
+    assert!(max_volume_size(4096) > max_volume_size(512));
+
+    // the formatter agrees on the smallest volume size
+    let options = |dev_size| {
+        FormatVolumeOptionsBuilder::default()
+            .pack_bitmap(false)
+            .dev_size(dev_size)
+            .bytes_per_sector(512)
+            .boundary_align(512)
+            .build()
+            .unwrap()
+    };
+    assert!(Exfat::try_from::<SystemTime>(options(MIN_VOLUME_SIZE)).is_ok());
+    assert!(Exfat::try_from::<SystemTime>(options(MIN_VOLUME_SIZE - 512)).is_err());
+}
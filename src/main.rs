@@ -1,35 +1,181 @@
-use exfat_fs::root::Root;
-use std::fs::OpenOptions;
-fn main() {
-    // let size: u64 = 32 * MB as u64;
-    // let hello_label = Label::new("Hello".to_string()).unwrap();
-
-    // let format_options = FormatVolumeOptionsBuilder::default()
-    //     .pack_bitmap(false)
-    //     .full_format(false)
-    //     .dev_size(size)
-    //     .label(hello_label)
-    //     .bytes_per_sector(512)
-    //     .build()
-    //     .unwrap();
-
-    // let formatter = Exfat::try_from(format_options).unwrap();
-
-    let file = OpenOptions::new()
-        .write(true)
-        .read(true)
-        .create(true)
-        .truncate(false)
-        .open("test")
-        .unwrap();
-
-    //    formatter.write(&mut file).unwrap();
-
-    let mut root = Root::open(file).unwrap();
+use exfat_fs::{probe, root::Root};
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+const USAGE: &str = "\
+Usage:
+    exfat-fs info <image>
+    exfat-fs loop <image> [--mount <dir>] [-- <command> [args...]]
+
+`loop` attaches the image to a free loop device and optionally mounts it with the kernel's exFAT
+driver. If a command is given, it is run with the device (and mount point) set up, otherwise the
+helper waits for a line on standard input. The image is unmounted and detached afterwards.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("info") if args.len() == 2 => info(Path::new(&args[1])),
+        Some("loop") if args.len() >= 2 => LoopArgs::parse(&args[1..]).and_then(|args| args.run()),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Prints the label and the amount of items in the root directory of an image.
+fn info(image: &Path) -> Result<ExitCode, String> {
+    let file = File::open(image).map_err(|err| format!("{}: {err}", image.display()))?;
+    let mut root = Root::open(file).map_err(|err| format!("{}: {err}", image.display()))?;
     let len = root.items().len();
-    println!(
-        "Root directory parsed! Volume Label: `{}`, Number of items: `{}`",
-        root.label().unwrap(),
-        len
-    );
+    let label = root.label().map(ToString::to_string).unwrap_or_default();
+    println!("Volume Label: `{label}`, Number of items: `{len}`");
+    Ok(ExitCode::SUCCESS)
+}
+
+struct LoopArgs {
+    image: PathBuf,
+    mount_point: Option<PathBuf>,
+    command: Vec<String>,
+}
+
+impl LoopArgs {
+    fn parse(args: &[String]) -> Result<LoopArgs, String> {
+        let mut loop_args = LoopArgs {
+            image: PathBuf::from(&args[0]),
+            mount_point: None,
+            command: Vec::new(),
+        };
+
+        let mut rest = args[1..].iter();
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "--mount" => {
+                    let dir = rest.next().ok_or(USAGE)?;
+                    loop_args.mount_point = Some(PathBuf::from(dir));
+                }
+                "--" => {
+                    loop_args.command = rest.cloned().collect();
+                    break;
+                }
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        Ok(loop_args)
+    }
+
+    /// Sets up the loop device and mount, runs the command and tears everything down again. The
+    /// exit code of the command is passed on.
+    fn run(self) -> Result<ExitCode, String> {
+        // refuse to hand anything but an exFAT volume to the kernel
+        let file =
+            File::open(&self.image).map_err(|err| format!("{}: {err}", self.image.display()))?;
+        if probe(&file).is_none() {
+            return Err(format!("{}: not an exFAT volume", self.image.display()));
+        }
+        drop(file);
+
+        let device = LoopDevice::attach(&self.image)?;
+        let mount = match &self.mount_point {
+            Some(dir) => Some(Mount::new(&device, dir)?),
+            None => None,
+        };
+        println!("{}", device.path);
+        if let Some(mount) = &mount {
+            println!("mounted at {}", mount.dir.display());
+        }
+
+        let Some((program, args)) = self.command.split_first() else {
+            println!("press enter to tear down");
+            let mut line = String::new();
+            io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|err| err.to_string())?;
+            return Ok(ExitCode::SUCCESS);
+        };
+
+        let mut command = Command::new(program);
+        command.args(args).env("EXFAT_LOOP_DEVICE", &device.path);
+        if let Some(mount) = &mount {
+            command.env("EXFAT_MOUNT_POINT", &mount.dir);
+        }
+        let status = command
+            .status()
+            .map_err(|err| format!("{program}: {err}"))?;
+        Ok(status
+            .code()
+            .map(|code| ExitCode::from(u8::try_from(code).unwrap_or(1)))
+            .unwrap_or(ExitCode::FAILURE))
+    }
+}
+
+/// Runs a setup or teardown utility, failing unless it exits successfully.
+fn run_tool(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|err| format!("{program}: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{program}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// A loop device backed by an image, detached when dropped.
+struct LoopDevice {
+    path: String,
+}
+
+impl LoopDevice {
+    fn attach(image: &Path) -> Result<LoopDevice, String> {
+        let image = image.to_str().ok_or("image path is not valid UTF-8")?;
+        let path = run_tool("losetup", &["--find", "--show", image])?;
+        Ok(LoopDevice { path })
+    }
+}
+
+impl Drop for LoopDevice {
+    fn drop(&mut self) {
+        if let Err(err) = run_tool("losetup", &["--detach", &self.path]) {
+            eprintln!("{err}");
+        }
+    }
+}
+
+/// A mount of a loop device with the kernel's exFAT driver, unmounted when dropped.
+struct Mount {
+    dir: PathBuf,
+}
+
+impl Mount {
+    fn new(device: &LoopDevice, dir: &Path) -> Result<Mount, String> {
+        let target = dir.to_str().ok_or("mount point is not valid UTF-8")?;
+        run_tool("mount", &["-t", "exfat", &device.path, target])?;
+        Ok(Mount {
+            dir: dir.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for Mount {
+    fn drop(&mut self) {
+        // the mount is torn down before the loop device it refers to
+        if let Err(err) = run_tool("umount", &[&self.dir.to_string_lossy()]) {
+            eprintln!("{err}");
+        }
+    }
 }
@@ -0,0 +1,204 @@
+use alloc::{string::String, vec::Vec};
+
+use decomposition_table::{CANONICAL_DECOMPOSITIONS, COMBINING_CLASSES};
+
+use crate::error::NameError;
+
+/// Canonical decomposition data.
+mod decomposition_table;
+mod upcase;
+
+pub use upcase::UpcaseTable;
+
+/// Maximum length of a file name in UTF-16 code units.
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Characters which must not appear in file names, besides the control characters `U+0000` to
+/// `U+001F`.
+pub const INVALID_NAME_CHARACTERS: [char; 9] = ['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+/// Whether the character must not appear in file names.
+pub fn is_invalid_name_character(c: char) -> bool {
+    c < '\u{20}' || INVALID_NAME_CHARACTERS.contains(&c)
+}
+
+/// Checks that a name can be stored in a file entry set and returns its UTF-16 encoding.
+pub fn validate_name(name: &str) -> Result<Vec<u16>, NameError> {
+    let units: Vec<u16> = name.encode_utf16().collect();
+    if units.is_empty() {
+        Err(NameError::Empty)
+    } else if units.len() > MAX_NAME_LENGTH {
+        Err(NameError::TooLong(units.len()))
+    } else if let Some(c) = name.chars().find(|c| is_invalid_name_character(*c)) {
+        Err(NameError::InvalidCharacter(c))
+    } else {
+        Ok(units)
+    }
+}
+
+/// Unicode normalization applied to file names before they are compared.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Normalization {
+    /// Names are compared exactly as stored, as mandated by the exFAT specification.
+    #[default]
+    None,
+    /// Names are brought into canonical decomposition (NFD) before being compared, so composed
+    /// (`é`) and decomposed (`e` followed by U+0301) spellings match.
+    Canonical,
+}
+
+/// Decides whether a stored name matches the name searched for by lookups such as
+/// [`Directory::find`](crate::fs::directory::Directory::find), e.g. to implement locale-aware or
+/// accent-insensitive searches. Names are always stored as given.
+///
+/// Implemented for [`Normalization`], for the case-insensitive comparison of an [`UpcaseTable`]
+/// and for closures taking the stored and the searched name.
+pub trait NameMatcher {
+    fn matches(&self, stored: &str, searched: &str) -> bool;
+}
+
+impl NameMatcher for Normalization {
+    fn matches(&self, stored: &str, searched: &str) -> bool {
+        names_match(stored, searched, *self)
+    }
+}
+
+impl NameMatcher for &UpcaseTable {
+    fn matches(&self, stored: &str, searched: &str) -> bool {
+        self.name_eq(stored, searched)
+    }
+}
+
+impl<F: Fn(&str, &str) -> bool> NameMatcher for F {
+    fn matches(&self, stored: &str, searched: &str) -> bool {
+        self(stored, searched)
+    }
+}
+
+/// Applies the given normalization to a name.
+pub fn normalize(name: &str, normalization: Normalization) -> String {
+    match normalization {
+        Normalization::None => String::from(name),
+        Normalization::Canonical => decompose(name),
+    }
+}
+
+/// Whether two names are equal under the given normalization.
+pub fn names_match(a: &str, b: &str, normalization: Normalization) -> bool {
+    match normalization {
+        Normalization::None => a == b,
+        Normalization::Canonical => a == b || decompose(a) == decompose(b),
+    }
+}
+
+// Hangul syllable decomposition constants (Unicode, chapter 3.12)
+const HANGUL_S_BASE: u32 = 0xAC00;
+const HANGUL_L_BASE: u32 = 0x1100;
+const HANGUL_V_BASE: u32 = 0x1161;
+const HANGUL_T_BASE: u32 = 0x11A7;
+const HANGUL_T_COUNT: u32 = 28;
+const HANGUL_N_COUNT: u32 = 588;
+const HANGUL_S_COUNT: u32 = 11172;
+
+/// Canonical decomposition (NFD) of a string.
+fn decompose(name: &str) -> String {
+    let mut chars: Vec<char> = Vec::with_capacity(name.len());
+
+    for c in name.chars() {
+        let code = c as u32;
+        if (HANGUL_S_BASE..HANGUL_S_BASE + HANGUL_S_COUNT).contains(&code) {
+            let index = code - HANGUL_S_BASE;
+            let l = HANGUL_L_BASE + index / HANGUL_N_COUNT;
+            let v = HANGUL_V_BASE + (index % HANGUL_N_COUNT) / HANGUL_T_COUNT;
+            let t = HANGUL_T_BASE + index % HANGUL_T_COUNT;
+
+            chars.extend([l, v].into_iter().filter_map(char::from_u32));
+            if t != HANGUL_T_BASE {
+                chars.extend(char::from_u32(t));
+            }
+        } else if let Ok(i) = CANONICAL_DECOMPOSITIONS.binary_search_by_key(&code, |(c, _)| *c) {
+            chars.extend(
+                CANONICAL_DECOMPOSITIONS[i]
+                    .1
+                    .iter()
+                    .filter_map(|c| char::from_u32(*c)),
+            );
+        } else {
+            chars.push(c);
+        }
+    }
+
+    // canonical ordering: sort each run of combining marks by their combining class
+    let mut start = 0;
+    while start < chars.len() {
+        if combining_class(chars[start]) == 0 {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < chars.len() && combining_class(chars[end]) != 0 {
+            end += 1;
+        }
+        // stable sort, marks of the same class keep their order
+        chars[start..end].sort_by_key(|c| combining_class(*c));
+        start = end;
+    }
+
+    chars.into_iter().collect()
+}
+
+/// Canonical combining class of a character.
+fn combining_class(c: char) -> u8 {
+    let code = c as u32;
+    match COMBINING_CLASSES.binary_search_by(|(first, last, _)| {
+        if *last < code {
+            core::cmp::Ordering::Less
+        } else if *first > code {
+            core::cmp::Ordering::Greater
+        } else {
+            core::cmp::Ordering::Equal
+        }
+    }) {
+        Ok(i) => COMBINING_CLASSES[i].2,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn name_length_limits() {
+    assert!(matches!(validate_name(""), Err(NameError::Empty)));
+    assert_eq!(validate_name(&"a".repeat(255)).unwrap().len(), 255);
+    assert!(matches!(
+        validate_name(&"a".repeat(256)),
+        Err(NameError::TooLong(256))
+    ));
+    // code units are counted, not characters
+    assert!(matches!(
+        validate_name(&"😀".repeat(128)),
+        Err(NameError::TooLong(256))
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn canonical_matching() {
+    // composed vs. decomposed
+    assert!(names_match("café", "cafe\u{301}", Normalization::Canonical));
+    assert!(!names_match("café", "cafe\u{301}", Normalization::None));
+
+    // order of combining marks with different classes is irrelevant
+    assert!(names_match(
+        "q\u{323}\u{307}",
+        "q\u{307}\u{323}",
+        Normalization::Canonical
+    ));
+
+    // hangul syllables
+    assert_eq!(
+        normalize("\u{D4DB}", Normalization::Canonical),
+        "\u{1111}\u{1171}\u{11B6}"
+    );
+
+    assert!(!names_match("cafe", "café", Normalization::Canonical));
+}
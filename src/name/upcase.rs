@@ -0,0 +1,106 @@
+use alloc::vec::Vec;
+
+use crate::format::upcase_table::DEFAULT_UPCASE_TABLE;
+
+/// Decompressed up-case table, mapping every UTF-16 code unit to its up-cased counterpart.
+#[derive(Clone, Debug)]
+pub struct UpcaseTable {
+    mapping: Vec<u16>,
+}
+
+impl UpcaseTable {
+    /// Decodes an up-case table as stored on disk. Both the compressed form (where `0xFFFF`
+    /// followed by a length marks a run of identity mappings) and the uncompressed form are
+    /// accepted. Code units not covered by the table map to themselves.
+    pub fn from_bytes(bytes: &[u8]) -> UpcaseTable {
+        let mut mapping: Vec<u16> = (0..=u16::MAX).collect();
+
+        let mut units = bytes
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]));
+        let mut index = 0usize;
+
+        while index < mapping.len() {
+            let Some(unit) = units.next() else {
+                break;
+            };
+
+            if unit == 0xFFFF
+                && let Some(run) = units.next()
+            {
+                // identity run: the mappings are already in place
+                index += run as usize;
+            } else {
+                mapping[index] = unit;
+                index += 1;
+            }
+        }
+
+        UpcaseTable { mapping }
+    }
+
+    /// Up-cases a single UTF-16 code unit.
+    pub fn upcase(&self, unit: u16) -> u16 {
+        self.mapping[unit as usize]
+    }
+
+    /// Up-cases a name given in UTF-16 code units.
+    pub fn upcase_name(&self, name: &[u16]) -> Vec<u16> {
+        name.iter().map(|unit| self.upcase(*unit)).collect()
+    }
+
+    /// Whether two names are equal when compared case-insensitively with this table, as done by
+    /// Windows and required by the exFAT specification.
+    pub fn name_eq(&self, a: &str, b: &str) -> bool {
+        a.encode_utf16()
+            .map(|unit| self.upcase(unit))
+            .eq(b.encode_utf16().map(|unit| self.upcase(unit)))
+    }
+
+    /// Computes the name hash stored in the stream extension entry of a file entry set.
+    pub fn name_hash(&self, name: &[u16]) -> u16 {
+        let mut hash = 0u16;
+        for unit in name {
+            for byte in self.upcase(*unit).to_le_bytes() {
+                hash = hash.rotate_right(1).wrapping_add(byte as u16);
+            }
+        }
+        hash
+    }
+}
+
+impl Default for UpcaseTable {
+    /// The up-case table written by the formatter.
+    fn default() -> UpcaseTable {
+        UpcaseTable::from_bytes(&DEFAULT_UPCASE_TABLE)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn default_table() {
+    let table = UpcaseTable::default();
+
+    assert_eq!(table.upcase(b'a' as u16), b'A' as u16);
+    assert_eq!(table.upcase(b'Z' as u16), b'Z' as u16);
+    assert_eq!(table.upcase(0x00E9), 0x00C9); // é
+    assert_eq!(table.upcase(0x03B1), 0x0391); // α
+    assert_eq!(table.upcase(0xFFFF), 0xFFFF);
+
+    let name: Vec<u16> = "hello.txt".encode_utf16().collect();
+    let upper: Vec<u16> = "HELLO.TXT".encode_utf16().collect();
+    assert_eq!(table.name_hash(&name), table.name_hash(&upper));
+    assert!(table.name_eq("Hello.txt", "HELLO.TXT"));
+    assert!(table.name_eq("é", "É"));
+    assert!(!table.name_eq("hello.txt", "hello.txt "));
+}
+
+#[cfg(test)]
+#[test]
+fn name_hash_wraps() {
+    let table = UpcaseTable::default();
+
+    // the sum exceeds 16 bits along the way
+    let name: Vec<u16> = "2ZRi_FLc0tYIwjbSH0M".encode_utf16().collect();
+    assert_eq!(table.name_hash(&name), 0x9BF2);
+}
@@ -0,0 +1,351 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    disk::{ReadOffset, Slice},
+    error::PartitionTableError,
+    probe,
+    root::BOOT_SIGNATURE,
+};
+
+/// Size of the sectors addressed by MBR partition tables.
+const MBR_SECTOR_SIZE: u64 = 512;
+/// Offset of the first partition entry within the MBR.
+const MBR_ENTRIES: usize = 446;
+/// MBR partition type of a protective MBR, which precedes a GPT.
+const GPT_PROTECTIVE: u8 = 0xEE;
+/// MBR partition types of extended partitions, which hold a chain of logical partitions.
+const EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+/// Upper limit of logical partitions followed in an extended partition, guarding against loops.
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+/// Sector sizes at which a GPT header is searched, in this order.
+const GPT_SECTOR_SIZES: [u64; 2] = [512, 4096];
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// Type of a [`Partition`], as stored in its partition table entry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PartitionKind {
+    /// Partition type byte of an MBR entry, e.g. `0x07` for exFAT and NTFS.
+    Mbr(u8),
+    /// Partition type and unique GUIDs of a GPT entry.
+    Gpt { type_guid: u128, unique_guid: u128 },
+}
+
+/// A partition found in the partition table of a whole-disk image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Partition {
+    /// Position of the entry in the partition table. Logical partitions of an MBR extended
+    /// partition are numbered starting at 4.
+    pub index: usize,
+    /// Offset of the partition on the disk in bytes.
+    pub offset: u64,
+    /// Length of the partition in bytes.
+    pub len: u64,
+    pub kind: PartitionKind,
+}
+
+impl Partition {
+    /// Wraps the disk holding the partition, so that the partition can be opened like an image
+    /// of the partition alone, e.g. with [`Root::open`](crate::root::Root::open).
+    pub fn device<T>(&self, disk: T) -> Slice<T> {
+        Slice::new(disk, self.offset, self.len)
+    }
+}
+
+/// Reads the MBR or GPT partition table of a whole-disk image. MBR partition tables are assumed
+/// to address 512 byte sectors; GPTs are searched with 512 and 4096 byte sectors.
+pub fn read_partitions<O: ReadOffset>(
+    device: &O,
+) -> Result<Vec<Partition>, PartitionTableError<O>> {
+    let mut mbr = [0u8; MBR_SECTOR_SIZE as usize];
+    device
+        .read_exact(0, &mut mbr)
+        .map_err(PartitionTableError::Io)?;
+    if u16::from_le_bytes([mbr[510], mbr[511]]) != BOOT_SIGNATURE
+        || &mbr[3..11] == b"EXFAT   "
+        || (0..4).any(|index| !matches!(mbr_entry(&mbr, index).status, 0x00 | 0x80))
+    {
+        return Err(PartitionTableError::NoPartitionTable);
+    }
+
+    if (0..4).any(|index| mbr_entry(&mbr, index).kind == GPT_PROTECTIVE) {
+        return read_gpt(device);
+    }
+
+    let mut partitions = Vec::new();
+    for index in 0..4 {
+        let entry = mbr_entry(&mbr, index);
+        if entry.kind == 0 || entry.sectors == 0 {
+            continue;
+        }
+        if EXTENDED.contains(&entry.kind) {
+            read_logical_partitions(device, entry.start, &mut partitions)?;
+        } else {
+            partitions.push(entry.partition(index, 0));
+        }
+    }
+    Ok(partitions)
+}
+
+/// The partitions of a whole-disk image holding an exFAT volume (see [`probe`]).
+/// The partition type is not taken into account, as exFAT shares it with other file systems.
+pub fn find_exfat_partitions<O: ReadOffset>(
+    device: &O,
+) -> Result<Vec<Partition>, PartitionTableError<O>> {
+    let mut partitions = read_partitions(device)?;
+    partitions.retain(|partition| {
+        probe(&partition.device(device)).is_some_and(|info| info.volume_length <= partition.len)
+    });
+    Ok(partitions)
+}
+
+/// A partition table entry of an MBR or extended boot record.
+struct MbrEntry {
+    status: u8,
+    kind: u8,
+    start: u32,
+    sectors: u32,
+}
+
+impl MbrEntry {
+    /// The partition described by the entry, whose start is relative to the sector `base`.
+    fn partition(&self, index: usize, base: u64) -> Partition {
+        Partition {
+            index,
+            offset: (base + self.start as u64) * MBR_SECTOR_SIZE,
+            len: self.sectors as u64 * MBR_SECTOR_SIZE,
+            kind: PartitionKind::Mbr(self.kind),
+        }
+    }
+}
+
+fn mbr_entry(sector: &[u8], index: usize) -> MbrEntry {
+    let entry = &sector[MBR_ENTRIES + index * 16..MBR_ENTRIES + (index + 1) * 16];
+    let u32_at = |at: usize| u32::from_le_bytes(entry[at..at + 4].try_into().unwrap());
+    MbrEntry {
+        status: entry[0],
+        kind: entry[4],
+        start: u32_at(8),
+        sectors: u32_at(12),
+    }
+}
+
+/// Follows the chain of extended boot records of the extended partition starting at sector
+/// `extended`. Each record describes a logical partition relative to itself and the next record
+/// relative to the extended partition.
+fn read_logical_partitions<O: ReadOffset>(
+    device: &O,
+    extended: u32,
+    partitions: &mut Vec<Partition>,
+) -> Result<(), PartitionTableError<O>> {
+    let mut record = extended as u64;
+    for index in 4..4 + MAX_LOGICAL_PARTITIONS {
+        let mut ebr = [0u8; MBR_SECTOR_SIZE as usize];
+        device
+            .read_exact(record * MBR_SECTOR_SIZE, &mut ebr)
+            .map_err(PartitionTableError::Io)?;
+        if u16::from_le_bytes([ebr[510], ebr[511]]) != BOOT_SIGNATURE {
+            return Err(PartitionTableError::InvalidExtendedBootRecord(
+                record * MBR_SECTOR_SIZE,
+            ));
+        }
+
+        let logical = mbr_entry(&ebr, 0);
+        if logical.kind != 0 && logical.sectors != 0 {
+            partitions.push(logical.partition(index, record));
+        }
+        let next = mbr_entry(&ebr, 1);
+        if next.kind == 0 || next.start == 0 {
+            return Ok(());
+        }
+        record = extended as u64 + next.start as u64;
+    }
+    Ok(())
+}
+
+fn read_gpt<O: ReadOffset>(device: &O) -> Result<Vec<Partition>, PartitionTableError<O>> {
+    for sector_size in GPT_SECTOR_SIZES {
+        let mut header = vec![0u8; sector_size as usize];
+        device
+            .read_exact(sector_size, &mut header)
+            .map_err(PartitionTableError::Io)?;
+        if &header[..8] == GPT_SIGNATURE {
+            return read_gpt_entries(device, sector_size, &header);
+        }
+    }
+    Err(PartitionTableError::MissingGptHeader)
+}
+
+fn read_gpt_entries<O: ReadOffset>(
+    device: &O,
+    sector_size: u64,
+    header: &[u8],
+) -> Result<Vec<Partition>, PartitionTableError<O>> {
+    let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+
+    let header_size = u32_at(12) as usize;
+    if !(92..=header.len()).contains(&header_size) {
+        return Err(PartitionTableError::InvalidGptHeader);
+    }
+    let mut checksummed = header[..header_size].to_vec();
+    checksummed[16..20].fill(0);
+    if crc32(&checksummed) != u32_at(16) {
+        return Err(PartitionTableError::GptChecksumMismatch);
+    }
+
+    let entries_lba = u64_at(72);
+    let entry_count = u32_at(80) as usize;
+    let entry_size = u32_at(84) as usize;
+    if !(128..=4096).contains(&entry_size) || entry_count > 1024 {
+        return Err(PartitionTableError::InvalidGptHeader);
+    }
+    let entries_offset = entries_lba
+        .checked_mul(sector_size)
+        .ok_or(PartitionTableError::InvalidGptHeader)?;
+    let mut entries = vec![0u8; entry_count * entry_size];
+    device
+        .read_exact(entries_offset, &mut entries)
+        .map_err(PartitionTableError::Io)?;
+    if crc32(&entries) != u32_at(88) {
+        return Err(PartitionTableError::GptChecksumMismatch);
+    }
+
+    let mut partitions = Vec::new();
+    for (index, entry) in entries.chunks_exact(entry_size).enumerate() {
+        let type_guid = u128::from_le_bytes(entry[..16].try_into().unwrap());
+        if type_guid == 0 {
+            continue;
+        }
+        let first = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+        let last = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+        let offset = first
+            .checked_mul(sector_size)
+            .ok_or(PartitionTableError::InvalidPartition(index))?;
+        let len = last
+            .checked_sub(first)
+            .and_then(|sectors| sectors.checked_add(1))
+            .and_then(|sectors| sectors.checked_mul(sector_size))
+            .filter(|len| offset.checked_add(*len).is_some())
+            .ok_or(PartitionTableError::InvalidPartition(index))?;
+        partitions.push(Partition {
+            index,
+            offset,
+            len,
+            kind: PartitionKind::Gpt {
+                type_guid,
+                unique_guid: u128::from_le_bytes(entry[16..32].try_into().unwrap()),
+            },
+        });
+    }
+    Ok(partitions)
+}
+
+/// CRC-32 (IEEE 802.3) as used by GPTs.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn partition_tables() {
+    use crate::{root::Root, test_utils::TestImage};
+    use std::io::Cursor;
+
+    const START: u64 = 2048 * 512;
+
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+    // a disk with the test image as its second partition, preceded by an unformatted one
+    let image = TestImage::new();
+    let volume = image.image.clone();
+    let mut disk = vec![0u8; START as usize + volume.len() + 64 * 1024];
+    disk[START as usize..START as usize + volume.len()].copy_from_slice(&volume);
+    let sectors = (volume.len() / 512) as u32;
+
+    let mut mbr = disk.clone();
+    for (index, (kind, start, len)) in [(0x83u8, 64u32, 64u32), (0x07, 2048, sectors)]
+        .into_iter()
+        .enumerate()
+    {
+        let entry = &mut mbr[MBR_ENTRIES + index * 16..MBR_ENTRIES + (index + 1) * 16];
+        entry[4] = kind;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&len.to_le_bytes());
+    }
+    mbr[510..512].copy_from_slice(&BOOT_SIGNATURE.to_le_bytes());
+    let mbr = Cursor::new(mbr);
+
+    let partitions = read_partitions(&mbr).unwrap();
+    assert_eq!(partitions.len(), 2);
+    assert_eq!(partitions[1].kind, PartitionKind::Mbr(0x07));
+    let exfat = find_exfat_partitions(&mbr).unwrap();
+    assert_eq!(exfat, [partitions[1]]);
+    let root = Root::open(exfat[0].device(mbr)).unwrap();
+    assert_eq!(
+        root.volume_serial_number(),
+        image.boot.volume_serial_number.get()
+    );
+
+    // the same disk with a GPT
+    let with_gpt = |entries_lba: u64, first: u64, last: u64| {
+        let mut gpt = disk.clone();
+        gpt[MBR_ENTRIES + 4] = GPT_PROTECTIVE;
+        gpt[510..512].copy_from_slice(&BOOT_SIGNATURE.to_le_bytes());
+        let mut entries = vec![0u8; 128 * 128];
+        entries[..16].copy_from_slice(&0x1234u128.to_le_bytes());
+        entries[16..32].copy_from_slice(&0x5678u128.to_le_bytes());
+        entries[32..40].copy_from_slice(&first.to_le_bytes());
+        entries[40..48].copy_from_slice(&last.to_le_bytes());
+        let mut header = [0u8; 92];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&crc32(&entries).to_le_bytes());
+        let checksum = crc32(&header);
+        header[16..20].copy_from_slice(&checksum.to_le_bytes());
+        gpt[512..512 + 92].copy_from_slice(&header);
+        gpt[1024..1024 + entries.len()].copy_from_slice(&entries);
+        Cursor::new(gpt)
+    };
+    let gpt = with_gpt(2, START / 512, START / 512 + sectors as u64 - 1);
+
+    let exfat = find_exfat_partitions(&gpt).unwrap();
+    assert_eq!(exfat.len(), 1);
+    assert_eq!(exfat[0].offset, START);
+    assert_eq!(
+        exfat[0].kind,
+        PartitionKind::Gpt {
+            type_guid: 0x1234,
+            unique_guid: 0x5678
+        }
+    );
+    assert!(Root::open(exfat[0].device(gpt)).is_ok());
+
+    // offsets and lengths that do not fit into 64 bits
+    assert!(matches!(
+        read_partitions(&with_gpt(u64::MAX / 256, 2048, 4095)),
+        Err(PartitionTableError::InvalidGptHeader)
+    ));
+    for (first, last) in [
+        (u64::MAX / 256, u64::MAX / 256),
+        (0, u64::MAX),
+        (2048, u64::MAX / 512),
+    ] {
+        assert!(matches!(
+            read_partitions(&with_gpt(2, first, last)),
+            Err(PartitionTableError::InvalidPartition(0))
+        ));
+    }
+
+    // partition images have no partition table
+    assert!(matches!(
+        read_partitions(&Cursor::new(volume)),
+        Err(PartitionTableError::NoPartitionTable)
+    ));
+}
@@ -0,0 +1,84 @@
+use bytemuck::pod_read_unaligned;
+use endify::Endify;
+
+use crate::{boot_sector::BootSector, disk::ReadOffset, root::BOOT_SIGNATURE};
+
+/// Basic facts about an exFAT volume, as reported by [`probe`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProbeInfo {
+    pub bytes_per_sector: u16,
+    pub bytes_per_cluster: u32,
+    /// Size of the volume in bytes.
+    pub volume_length: u64,
+    pub cluster_count: u32,
+    pub volume_serial_number: u32,
+    /// File system revision as `(major, minor)`.
+    pub file_system_revision: (u8, u8),
+}
+
+/// Checks whether `device` holds an exFAT volume by reading its main boot sector only: the
+/// filesystem name, the boot signature and the geometry must be valid. Unlike opening the volume,
+/// neither the FAT nor the root directory is read, so many devices can be identified quickly.
+/// Returns `None` if the device cannot be read or does not hold an exFAT volume.
+pub fn probe<O: ReadOffset>(device: &O) -> Option<ProbeInfo> {
+    let mut sector = [0u8; 512];
+    device.read_exact(0, &mut sector).ok()?;
+    let boot = Endify::from_le(pod_read_unaligned::<BootSector>(&sector));
+
+    if boot.filesystem_name != *b"EXFAT   "
+        || boot.boot_signature != BOOT_SIGNATURE
+        || !(9..=12).contains(&boot.bytes_per_sector_shift)
+        || boot.sectors_per_cluster_shift > 25 - boot.bytes_per_sector_shift
+        || !(1..=2).contains(&boot.number_of_fats)
+        || boot.fat_offset < 24
+        || boot.cluster_heap_offset < boot.fat_offset
+        || boot.cluster_count == 0
+    {
+        return None;
+    }
+
+    // the cluster heap must fit onto the volume
+    let heap_sectors = (boot.cluster_count as u64) << boot.sectors_per_cluster_shift;
+    if boot.cluster_heap_offset as u64 + heap_sectors > boot.volume_length {
+        return None;
+    }
+
+    Some(ProbeInfo {
+        bytes_per_sector: boot.bytes_per_sector(),
+        bytes_per_cluster: boot.bytes_per_cluster(),
+        volume_length: boot
+            .volume_length
+            .checked_mul(boot.bytes_per_sector() as u64)?,
+        cluster_count: boot.cluster_count,
+        volume_serial_number: boot.volume_serial_number.get(),
+        file_system_revision: (
+            boot.file_system_revision.major(),
+            boot.file_system_revision.minor(),
+        ),
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn probe_volumes() {
+    use crate::test_utils::{IMAGE_SIZE, TestImage};
+    use std::io::Cursor;
+
+    let image = TestImage::sample();
+    let info = probe(&Cursor::new(image.image.clone())).unwrap();
+    assert_eq!(info.bytes_per_sector, 512);
+    assert_eq!(info.bytes_per_cluster, 4096);
+    assert_eq!(info.volume_length, IMAGE_SIZE);
+    assert_eq!(info.cluster_count, image.boot.cluster_count);
+    assert_eq!(info.file_system_revision, (1, 0));
+
+    // not formatted
+    assert_eq!(probe(&Cursor::new(vec![0u8; 4096])), None);
+    // too short to hold a boot sector
+    assert_eq!(probe(&Cursor::new(vec![0u8; 100])), None);
+
+    // cluster heap exceeding the volume
+    let mut corrupt = image.image;
+    corrupt[72..80].copy_from_slice(&4096u64.to_le_bytes());
+    assert_eq!(probe(&Cursor::new(corrupt)), None);
+}
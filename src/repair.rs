@@ -0,0 +1,351 @@
+use alloc::{collections::BTreeSet, format, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    FIRST_USABLE_CLUSTER_INDEX,
+    disk::WriteOffset,
+    entry::{
+        FileAttributes,
+        set::{Allocation, EntrySet},
+    },
+    error::WriteError,
+    fs::FsElement,
+    name::{UpcaseTable, validate_name},
+    timestamp::{Clock, Timestamps},
+    volume::{DirectoryChange, Volume, write::DirectoryClusters},
+};
+
+/// Name of the directory recovered data is placed in.
+pub const LOST_DIR_NAME: &str = "LOST.DIR";
+
+/// Data recovered by a repair pass, such as an orphaned cluster chain or the extent of a deleted
+/// file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RecoveredData {
+    /// First cluster of the data.
+    pub first_cluster: u32,
+    /// Length of the data (in bytes).
+    pub data_len: u64,
+    /// Whether the data is stored in consecutive clusters (`NoFatChain`) rather than being
+    /// described by a FAT chain.
+    pub contiguous: bool,
+}
+
+/// An existing lost and found directory.
+struct LostDir {
+    /// Name as stored on disk.
+    name: String,
+    clusters: DirectoryClusters,
+    /// Up-cased names of all items in the directory.
+    taken: Vec<Vec<u16>>,
+}
+
+impl<O: WriteOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Makes recovered data reachable again by linking it into [`LOST_DIR_NAME`] in the root
+    /// directory, which is created if needed. Every item receives a generated name
+    /// (`FILE0000.CHK`, `FILE0001.CHK`, ...) not colliding with existing entries, and its clusters
+    /// are marked as allocated. Returns the generated names in order. Every created entry is
+    /// reported to the [`Volume::on_change`] callback.
+    ///
+    /// Entries are timestamped using `T`; if the clock is unavailable, the exFAT epoch is used.
+    pub fn materialize_lost<T: Clock>(
+        &mut self,
+        recovered: &[RecoveredData],
+    ) -> Result<Vec<String>, WriteError<O>> {
+        self.ensure_writable()?;
+        self.refresh_if_stale()?;
+
+        // nothing is modified unless all of the data can be linked
+        let mut claimed = BTreeSet::new();
+        let mut clusters = Vec::with_capacity(recovered.len());
+        for data in recovered {
+            clusters.push(self.recovered_clusters(data, &mut claimed)?);
+        }
+
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let timestamps = Timestamps::now::<T>();
+        let bytes_per_cluster = self.bytes_per_cluster() as u64;
+
+        let lost_dir = validate_name(LOST_DIR_NAME)?;
+        let mut volume = self.usage_scope(LOST_DIR_NAME);
+        let mut changes = Vec::new();
+        let (dir_name, mut directory, mut taken) = match volume
+            .lost_dir(&upcase_table, &lost_dir)?
+        {
+            Some(LostDir {
+                name,
+                clusters,
+                taken,
+            }) => (name, clusters, taken),
+            None => {
+                let cluster = volume.writer().allocate_contiguous(1)?;
+                volume.writer().zero_cluster(cluster)?;
+
+                let set = EntrySet::new(
+                    &lost_dir,
+                    FileAttributes::DIRECTORY,
+                    &timestamps,
+                    Allocation {
+                        first_cluster: cluster,
+                        data_len: bytes_per_cluster,
+                        no_fat_chain: true,
+                    },
+                    &upcase_table,
+                );
+                let mut root = DirectoryClusters::root(&volume.root)?;
+                let location = volume
+                    .writer()
+                    .insert_entry_set(&mut root, &set.raw_entries())?;
+                changes.push(DirectoryChange::Created(String::from(LOST_DIR_NAME)));
+                (
+                    String::from(LOST_DIR_NAME),
+                    volume
+                        .writer()
+                        .new_directory_clusters(&root, location, cluster, set.len())?,
+                    Vec::default(),
+                )
+            }
+        };
+
+        let mut names = Vec::with_capacity(recovered.len());
+        let mut counter = 0u32;
+        for (data, clusters) in recovered.iter().zip(clusters) {
+            for cluster in clusters {
+                volume.writer().mark_clusters(cluster, 1, true)?;
+            }
+
+            let name = loop {
+                let name = format!("FILE{counter:04}.CHK");
+                counter += 1;
+
+                let units = validate_name(&name)?;
+                if !taken.contains(&upcase_table.upcase_name(&units)) {
+                    break units;
+                }
+            };
+
+            let set = EntrySet::new(
+                &name,
+                FileAttributes::ARCHIVE,
+                &timestamps,
+                Allocation {
+                    first_cluster: data.first_cluster,
+                    data_len: data.data_len,
+                    no_fat_chain: data.contiguous,
+                },
+                &upcase_table,
+            );
+            volume
+                .writer()
+                .insert_entry_set(&mut directory, &set.raw_entries())?;
+
+            taken.push(upcase_table.upcase_name(&name));
+            let name = String::from_utf16_lossy(&name);
+            changes.push(DirectoryChange::Created(format!("{dir_name}/{name}")));
+            names.push(name);
+        }
+
+        volume.root.reload()?;
+        volume.notify(changes);
+        Ok(names)
+    }
+
+    /// The existing lost and found directory, if any.
+    fn lost_dir(
+        &self,
+        upcase_table: &UpcaseTable,
+        name: &[u16],
+    ) -> Result<Option<LostDir>, WriteError<O>> {
+        let name = upcase_table.upcase_name(name);
+        let directory = self.root.items.iter().find_map(|item| match item {
+            FsElement::D(dir)
+                if upcase_table.upcase_name(&dir.name().encode_utf16().collect::<Vec<_>>())
+                    == name =>
+            {
+                Some(dir)
+            }
+            _ => None,
+        });
+        let Some(directory) = directory else {
+            return Ok(None);
+        };
+
+        let taken = directory
+            .open()?
+            .iter()
+            .map(|item| upcase_table.upcase_name(&item.name().encode_utf16().collect::<Vec<_>>()))
+            .collect();
+        let clusters = DirectoryClusters::of(directory)?;
+        Ok(Some(LostDir {
+            name: String::from(directory.name()),
+            clusters,
+            taken,
+        }))
+    }
+
+    /// The clusters of recovered data. Fails if any of them lies outside of the cluster heap, is
+    /// allocated already or belongs to other recovered data, whose clusters are collected in
+    /// `claimed`.
+    fn recovered_clusters(
+        &mut self,
+        data: &RecoveredData,
+        claimed: &mut BTreeSet<u32>,
+    ) -> Result<Vec<u32>, WriteError<O>> {
+        let count = data.data_len.div_ceil(self.bytes_per_cluster() as u64);
+        let heap = FIRST_USABLE_CLUSTER_INDEX as u64
+            ..FIRST_USABLE_CLUSTER_INDEX as u64 + self.cluster_count() as u64;
+        if !heap.contains(&(data.first_cluster as u64)) {
+            return Err(WriteError::InvalidCluster(data.first_cluster));
+        }
+
+        let clusters: Vec<u32> = if data.contiguous {
+            if data.first_cluster as u64 + count > heap.end {
+                return Err(WriteError::InvalidCluster(heap.end as u32));
+            }
+            (data.first_cluster..data.first_cluster + count as u32).collect()
+        } else {
+            self.root
+                .fat
+                .read_chain_prefix(&self.root.disk, data.first_cluster, count as usize)
+                .map_err(WriteError::Io)?
+        };
+
+        let mut writer = self.writer();
+        let bitmap = writer.bitmap()?;
+        for cluster in &clusters {
+            if !heap.contains(&(*cluster as u64)) {
+                return Err(WriteError::InvalidCluster(*cluster));
+            }
+            if bitmap.is_allocated(*cluster) || !claimed.insert(*cluster) {
+                return Err(WriteError::ClusterInUse(*cluster));
+            }
+        }
+        Ok(clusters)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn lost_and_found() {
+    use crate::{name::Normalization, test_utils::TestImage};
+    use std::{io::Read, sync::Mutex, time::SystemTime};
+
+    let mut image = TestImage::sample();
+    // orphaned data without any directory entry
+    image.write_clusters(200, b"orphaned");
+    image.write_clusters(201, b"deleted");
+
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+    let names = volume
+        .materialize_lost::<SystemTime>(&[RecoveredData {
+            first_cluster: 200,
+            data_len: 8,
+            contiguous: true,
+        }])
+        .unwrap();
+    assert_eq!(names, vec!["FILE0000.CHK"]);
+
+    // a second pass reuses the directory and picks fresh names
+    let names = volume
+        .materialize_lost::<SystemTime>(&[RecoveredData {
+            first_cluster: 201,
+            data_len: 7,
+            contiguous: true,
+        }])
+        .unwrap();
+    assert_eq!(names, vec!["FILE0001.CHK"]);
+
+    let root = volume.root();
+    assert_eq!(root.items().len(), 3);
+    let Some(FsElement::D(lost)) = root.find(LOST_DIR_NAME, Normalization::None) else {
+        panic!("lost and found directory missing");
+    };
+    let items = lost.open().unwrap();
+    assert_eq!(items.len(), 2);
+
+    let Some(FsElement::F(mut file)) = lost.find("FILE0001.CHK", Normalization::None).unwrap()
+    else {
+        panic!("recovered file missing");
+    };
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "deleted");
+
+    let mut writer = volume.writer();
+    let bitmap = writer.bitmap().unwrap();
+    assert!(bitmap.is_allocated(200) && bitmap.is_allocated(201));
+}
+
+#[cfg(test)]
+#[test]
+fn lost_dir_grows() {
+    use crate::test_utils::TestImage;
+    use std::{sync::Mutex, time::SystemTime};
+
+    let image = TestImage::sample();
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+
+    // more entry sets than fit into a single cluster
+    let entries_per_cluster = volume.bytes_per_cluster() as usize / 32;
+    let recovered: Vec<RecoveredData> = (0..entries_per_cluster / 3 + 1)
+        .map(|i| RecoveredData {
+            first_cluster: 300 + i as u32,
+            data_len: 1,
+            contiguous: true,
+        })
+        .collect();
+    let names = volume.materialize_lost::<SystemTime>(&recovered).unwrap();
+
+    let root = volume.root();
+    let FsElement::D(lost) = &root.items()[2] else {
+        panic!("lost and found directory missing");
+    };
+    assert_eq!(lost.open().unwrap().len(), names.len());
+}
+
+#[cfg(test)]
+#[test]
+fn reject_invalid_recovered_data() {
+    use crate::test_utils::TestImage;
+    use std::{sync::Mutex, time::SystemTime};
+
+    let mut image = TestImage::sample();
+    // clusters 100 to 102 are in use by the sample tree
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100;
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+    let cluster_count = volume.cluster_count();
+    let recovered = |first_cluster, data_len| RecoveredData {
+        first_cluster,
+        data_len,
+        contiguous: true,
+    };
+
+    for (data, cluster) in [
+        (recovered(0, 0), 0),
+        (recovered(cluster_count + 2, 1), cluster_count + 2),
+        (recovered(cluster_count, 8 * 1024 * 1024), cluster_count + 2),
+    ] {
+        assert!(
+            matches!(
+                volume.materialize_lost::<SystemTime>(&[data]),
+                Err(WriteError::InvalidCluster(invalid)) if invalid == cluster
+            ),
+            "{data:?}"
+        );
+    }
+    // clusters of live files and of other recovered data
+    assert!(matches!(
+        volume.materialize_lost::<SystemTime>(&[recovered(100, 1)]),
+        Err(WriteError::ClusterInUse(100))
+    ));
+    assert!(matches!(
+        volume.materialize_lost::<SystemTime>(&[recovered(200, 1), recovered(200, 1)]),
+        Err(WriteError::ClusterInUse(200))
+    ));
+    // nothing was linked
+    assert_eq!(volume.root().items().len(), 2);
+    assert!(!volume.writer().bitmap().unwrap().is_allocated(200));
+}
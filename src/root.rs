@@ -1,54 +1,259 @@
 use alloc::sync::Arc;
 
+use bitflags::bitflags;
 use bytemuck::from_bytes_mut;
+use derive_builder::Builder;
 use endify::Endify;
 
 use crate::{
     Label,
+    allocator::AllocationStrategy,
     boot_sector::{BootSector, VolumeFlags},
+    cache::Lock,
+    check::Finding,
+    checksum,
     cluster::{ClusterChainOptions, reader::ClusterChainReader},
+    diagnostics::RawEntry,
     disk::ReadOffset,
     entry::{
         BitmapEntry, ClusterAllocation, DirEntry, UpcaseTableEntry, VOLUME_GUID_ENTRY_TYPE,
         VolumeGuidEntry, VolumeLabelEntry, parsed::ParsedFileEntry, reader::DirEntryReader,
     },
-    error::RootError,
+    error::{
+        DirectoryError, EntryReaderError, FatLoadError, FileParserError, MountPhase, RootError,
+    },
     fat::Fat,
-    fs::{FsElement, directory::Directory, file::File},
+    fs::{EntryLocation, FsElement, directory::Directory, file::File},
+    name::{NameMatcher, UpcaseTable},
+    volume::{ActivityStats, CHECKSUMMED_SECTORS, activity::ActivityEntry, write::WriteState},
 };
 
 /// Buffer used to read the boot sector.
 #[repr(align(8))]
 struct AlignedBootSector([u8; 512]);
 
+/// Jump instruction mandated by the specification.
+const JUMP_BOOT: [u8; 3] = [0xEB, 0x76, 0x90];
+/// Signature of a boot sector.
+pub(crate) const BOOT_SIGNATURE: u16 = 0xAA55;
+/// Size of an uncompressed up-case table, covering every UTF-16 code unit.
+const MAX_UPCASE_TABLE_LENGTH: u64 = 2 << 16;
+
+bitflags! {
+    /// Boot sector checks that may be relaxed when opening a volume. Volumes which only open
+    /// because of a relaxed check are treated as read-only. All but [`Self::ACTIVE_FAT`] relax
+    /// checks that are only performed for [`OpenOptionsBuilder::strict_boot_sector`].
+    #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+    pub struct BootSectorRelaxations: u16 {
+        /// Accept any jump instruction instead of `0xEB 0x76 0x90`.
+        const JUMP_BOOT = 1 << 0;
+        /// Accept non-zero bytes in the `MustBeZero` field of the boot sector.
+        const RESERVED = 1 << 1;
+        /// Accept a boot signature other than `0xAA55`.
+        const BOOT_SIGNATURE = 1 << 2;
+        /// Accept file system revisions other than `1.x`.
+        const FILE_SYSTEM_REVISION = 1 << 3;
+        /// Accept an active FAT that is inconsistent with the number of FATs. The first FAT is
+        /// used in that case.
+        const ACTIVE_FAT = 1 << 4;
+    }
+}
+
+/// How files with a `DataLength` of zero but a nonzero first cluster, which the specification
+/// does not allow but some devices write, are interpreted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ZeroLengthPolicy {
+    /// The file is empty and its first cluster is ignored.
+    #[default]
+    Empty,
+    /// The length of the file is taken from its cluster chain: all clusters of its FAT chain, or
+    /// the first cluster if the file is stored without a FAT chain.
+    TrustChain,
+    /// The file is rejected with
+    /// [`ClusterChainError::ZeroLengthWithCluster`](crate::error::ClusterChainError::ZeroLengthWithCluster),
+    /// or reported as [`Finding::DamagedEntrySet`] if damaged entry sets are skipped.
+    Report,
+}
+
+/// Which of the FATs of a volume is used, see [`OpenOptionsBuilder::fat`]. Only TexFAT volumes
+/// have a second FAT.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FatSelection {
+    /// The FAT selected by the `ActiveFat` volume flag.
+    #[default]
+    Active,
+    /// The first FAT, e.g. to recover data if the second one is damaged.
+    First,
+    /// The second FAT, e.g. to recover data if the first one is damaged.
+    Second,
+}
+
+/// An entry that differs between the two FATs of a volume, see [`Root::compare_fats`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FatMismatch {
+    pub cluster: u32,
+    /// Entry of the cluster in the first FAT.
+    pub first: u32,
+    /// Entry of the cluster in the second FAT.
+    pub second: u32,
+}
+
+/// How reads of file contents and directories are issued to the device. The best settings
+/// differ by orders of magnitude between e.g. SD cards attached to a microcontroller over SPI
+/// and image files on fast storage.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReadProfile {
+    /// Every device read covers at most one sector and never crosses a cluster boundary. Small
+    /// reads, like those of directory entries, are served from a cache of a single sector.
+    LowMemory,
+    /// Device reads span runs of contiguous clusters and are not cached.
+    #[default]
+    Balanced,
+    /// Like [`ReadProfile::Balanced`], but small reads are served from a cache holding up to
+    /// [`ReadProfile::HIGH_THROUGHPUT_READAHEAD`] bytes read ahead.
+    HighThroughput,
+}
+
+impl ReadProfile {
+    /// Maximum amount of bytes read ahead by [`ReadProfile::HighThroughput`].
+    pub const HIGH_THROUGHPUT_READAHEAD: usize = 128 * 1024;
+}
+
+/// A struct of options applied when opening a volume. It implements the
+/// [`derive_builder::Builder`] pattern.
+#[derive(Builder, Copy, Clone, Debug)]
+#[builder(no_std)]
+pub struct OpenOptions {
+    /// Boot sector checks which are relaxed, e.g. to recover data from media written by slightly
+    /// nonconforming devices. Defaults to none.
+    #[builder(default)]
+    relaxations: BootSectorRelaxations,
+    /// Whether to check the jump instruction, the `MustBeZero` field, the boot signature and the
+    /// file system revision of the boot sector, which slightly nonconforming devices may get
+    /// wrong. Failing checks can be relaxed individually with
+    /// [`OpenOptionsBuilder::relaxations`]. Defaults to `false`.
+    #[builder(default)]
+    strict_boot_sector: bool,
+    /// Whether to tolerate duplicate or invalid volume label, allocation bitmap and up-case table
+    /// entries in the root directory. The first valid entry of each kind is used and all others
+    /// are reported as [`Finding`]s. Defaults to `false`.
+    #[builder(default)]
+    recover_duplicates: bool,
+    /// Whether to skip damaged file entry sets (e.g. with an invalid stream extension or file
+    /// name entry) while listing the root and other directories, instead of failing. Skipped sets
+    /// are reported as [`Finding::DamagedEntrySet`]. Defaults to `false`.
+    #[builder(default)]
+    pub(crate) skip_damaged_entry_sets: bool,
+    /// Whether to read FAT entries from the device only as cluster chains are followed, instead of
+    /// loading the entire FAT when opening the volume. Speeds up opening huge volumes, e.g. to
+    /// query the label or read files stored without a FAT chain. [`Volume`](crate::volume::Volume)s
+    /// always load the entire FAT. Defaults to `false`.
+    #[builder(default)]
+    pub(crate) defer_fat: bool,
+    /// Amount of FAT sectors kept in memory for volumes opened with
+    /// [`OpenOptionsBuilder::defer_fat`]. Once full, the least recently used sector is replaced.
+    /// Defaults to `0`, in which case every FAT entry is read from the device on its own.
+    #[builder(default)]
+    fat_cache_sectors: usize,
+    /// How files with a `DataLength` of zero but a nonzero first cluster are interpreted.
+    /// Defaults to [`ZeroLengthPolicy::Empty`].
+    #[builder(default)]
+    pub(crate) zero_length_files: ZeroLengthPolicy,
+    /// How reads are sized and cached, which should match the device. Defaults to
+    /// [`ReadProfile::Balanced`].
+    #[builder(default)]
+    pub(crate) read_profile: ReadProfile,
+    /// Which free clusters are used when files and directories grow. Defaults to
+    /// [`AllocationStrategy::ContiguousPreferred`].
+    #[builder(default)]
+    pub(crate) allocation: AllocationStrategy,
+    /// Which FAT cluster chains are read from. Volumes opened with another FAT than the active
+    /// one are read-only. Defaults to [`FatSelection::Active`].
+    #[builder(default)]
+    fat: FatSelection,
+    /// Whether to reject volumes whose main boot region or up-case table does not match its
+    /// checksum, instead of ignoring the boot region checksum and falling back to the default
+    /// up-case table. Defaults to `false`.
+    #[builder(default)]
+    verify_checksums: bool,
+    /// Whether the volume is opened read-only, so that [`Volume`](crate::volume::Volume)s refuse
+    /// all modifications. Defaults to `false`.
+    #[builder(default)]
+    read_only: bool,
+    /// Whether volumes with the `VolumeDirty` flag set (see [`Root::is_dirty`]) may be opened.
+    /// If not, opening such volumes fails with [`RootError::VolumeDirty`]. Defaults to `true`.
+    #[builder(default = "true")]
+    allow_dirty: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions {
+            relaxations: BootSectorRelaxations::default(),
+            strict_boot_sector: false,
+            recover_duplicates: false,
+            skip_damaged_entry_sets: false,
+            defer_fat: false,
+            fat_cache_sectors: 0,
+            zero_length_files: ZeroLengthPolicy::default(),
+            read_profile: ReadProfile::default(),
+            allocation: AllocationStrategy::default(),
+            fat: FatSelection::default(),
+            verify_checksums: false,
+            read_only: false,
+            allow_dirty: true,
+        }
+    }
+}
+
+/// The parsed boot sector, FAT and up-case table of a volume, which can be reused to open further
+/// handles to the same volume without parsing them again (see [`Root::from_parts`]).
+#[derive(Clone, Debug)]
+pub struct VolumeParts {
+    pub(crate) boot: Arc<BootSector>,
+    pub(crate) fat: Arc<Fat>,
+    /// `None` until the root directory has been read.
+    upcase: Option<Arc<UpcaseTable>>,
+    options: OpenOptions,
+    read_only: bool,
+}
+
 /// Root directory entry.
 pub(crate) struct RawRoot {
     vol_label: DirEntry,
-    vol_guid: DirEntry,
-    bitmap: DirEntry,
+    vol_guid: Option<DirEntry>,
+    bitmaps: Vec<DirEntry>,
     uptable: DirEntry,
     items: Vec<DirEntry>,
+    conservative: bool,
 }
 
 impl RawRoot {
+    /// Maximum number of entries describing the volume itself (label, GUID, bitmaps and up-case
+    /// table), which precede all file entry sets.
+    pub(crate) const METADATA_ENTRIES: u32 = 5;
+
+    /// Creates the root directory entries. Conservative roots only contain the label, bitmap and
+    /// up-case table entries in this order, followed by the GUID entry if there is a GUID.
     pub(crate) fn new(
         volume_label: Label,
         volume_guid: Option<u128>,
-        bitmap_length_bytes: u64,
+        bitmaps: Vec<BitmapEntry>,
         uptable_start_cluster: u32,
+        conservative: bool,
     ) -> RawRoot {
         // create volume label entry
         let vol_label = DirEntry::VolumeLabel(VolumeLabelEntry::new(volume_label));
 
         // create volume GUID entry
-        let vol_guid = if let Some(guid) = volume_guid {
-            DirEntry::VolumeGuid(VolumeGuidEntry::new(guid))
-        } else {
-            DirEntry::new_unused(VOLUME_GUID_ENTRY_TYPE)
+        let vol_guid = match volume_guid {
+            Some(guid) => Some(DirEntry::VolumeGuid(VolumeGuidEntry::new(guid))),
+            None if conservative => None,
+            None => Some(DirEntry::new_unused(VOLUME_GUID_ENTRY_TYPE)),
         };
 
-        // create bitmap entry
-        let bitmap = DirEntry::Bitmap(BitmapEntry::new(bitmap_length_bytes));
+        // create bitmap entries
+        let bitmaps = bitmaps.into_iter().map(DirEntry::Bitmap).collect();
 
         // create upcase table entry
         let uptable = DirEntry::UpcaseTable(UpcaseTableEntry::new(uptable_start_cluster));
@@ -56,14 +261,29 @@ impl RawRoot {
         RawRoot {
             vol_label,
             vol_guid,
-            bitmap,
+            bitmaps,
             uptable,
             items: Vec::default(),
+            conservative,
         }
     }
 
+    /// Number of entries describing the volume itself.
+    pub(crate) fn metadata_entries(&self) -> u32 {
+        2 + self.bitmaps.len() as u32 + self.vol_guid.is_some() as u32
+    }
+
     pub(crate) fn bytes(self) -> Vec<u8> {
-        let mut all_items = vec![self.vol_label, self.vol_guid, self.bitmap, self.uptable];
+        let mut all_items = vec![self.vol_label];
+        if self.conservative {
+            all_items.extend(self.bitmaps.iter().copied());
+            all_items.push(self.uptable);
+        }
+        all_items.extend(self.vol_guid);
+        if !self.conservative {
+            all_items.extend(self.bitmaps);
+            all_items.push(self.uptable);
+        }
         all_items.extend(self.items);
         all_items
             .into_iter()
@@ -73,26 +293,209 @@ impl RawRoot {
 }
 
 pub struct Root<O: ReadOffset> {
+    pub(crate) disk: Arc<O>,
+    pub(crate) boot: Arc<BootSector>,
+    pub(crate) fat: Arc<Fat>,
     volume_label: Option<Label>,
-    items: Vec<FsElement<O>>,
+    volume_guid: Option<u128>,
+    pub(crate) allocation_bitmaps: Vec<BitmapEntry>,
+    pub(crate) upcase_table: UpcaseTableEntry,
+    /// The decoded up-case table, shared with the directories.
+    pub(crate) upcase: Arc<UpcaseTable>,
+    pub(crate) items: Vec<FsElement<O>>,
+    read_only: bool,
+    pub(crate) findings: Vec<Finding>,
+    /// Statistics stored by [`Volume::record_mount`](crate::volume::Volume::record_mount).
+    pub(crate) activity: Option<ActivityEntry>,
+    pub(crate) options: OpenOptions,
+    /// Generation of the device this view was read from.
+    pub(crate) generation: Option<u64>,
+    /// Allocation state shared with all handles read from this view.
+    pub(crate) writes: Arc<Lock<WriteState>>,
 }
 
 impl<O: ReadOffset> Root<O> {
     pub fn label(&self) -> Option<&Label> {
         self.volume_label.as_ref()
     }
+    /// Serial number stored in the boot sector.
+    pub fn volume_serial_number(&self) -> u32 {
+        self.boot.volume_serial_number.get()
+    }
+    /// Volume GUID, if the root directory contains a volume GUID entry.
+    pub fn volume_guid(&self) -> Option<u128> {
+        self.volume_guid
+    }
     pub fn items(&mut self) -> &mut [FsElement<O>] {
         &mut self.items
     }
+    /// Up-case table of the volume, which defines which names are equal (see
+    /// [`UpcaseTable::name_eq`]).
+    pub fn upcase_table(&self) -> &UpcaseTable {
+        &self.upcase
+    }
+
+    /// Looks up an element of the root directory by name, comparing names with `matcher` (e.g. a
+    /// [`Normalization`](crate::name::Normalization)).
+    pub fn find(&mut self, name: &str, matcher: impl NameMatcher) -> Option<&mut FsElement<O>> {
+        self.items
+            .iter_mut()
+            .find(|item| matcher.matches(item.name(), name))
+    }
+
+    /// Looks up the file or directory at `path` (separated by `/`, e.g. `photos/2024/img.jpg`),
+    /// opening the directories along the way. Names are compared case-insensitively using the
+    /// up-case table of the volume, as by the exFAT specification. `None` if the path does not
+    /// exist, or is empty.
+    pub fn open_path(&self, path: &str) -> Result<Option<FsElement<O>>, DirectoryError<O>>
+    where
+        O::Err: core::fmt::Debug,
+    {
+        let upcase_table = self.upcase_table();
+        let mut components = path.split('/').filter(|c| !c.is_empty());
+        let Some(first) = components.next() else {
+            return Ok(None);
+        };
+        let Some(mut element) = self
+            .items
+            .iter()
+            .find(|item| upcase_table.matches(item.name(), first))
+            .cloned()
+        else {
+            return Ok(None);
+        };
+
+        for component in components {
+            let FsElement::D(directory) = element else {
+                return Ok(None);
+            };
+            match directory.find(component, upcase_table)? {
+                Some(next) => element = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(element))
+    }
 }
 
 impl<O: ReadOffset> Root<O> {
+    /// Whether the volume must not be modified, because it could only be opened by relaxing some
+    /// of the boot sector checks (see [`OpenOptions`]).
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether the `VolumeDirty` flag is set, i.e. the volume is in use by a writer or was not
+    /// unmounted cleanly, in which case a repair pass may be needed.
+    pub fn is_dirty(&self) -> bool {
+        VolumeFlags::from_bits_retain(self.boot.volume_flags).contains(VolumeFlags::VOLUME_DIRTY)
+    }
+
+    /// Whether the device has been modified since the volume was opened (see
+    /// [`ReadOffset::generation`]). The elements of a stale root no longer reflect the device.
+    pub fn is_stale(&self) -> bool {
+        self.disk.generation() != self.generation
+    }
+
+    /// Usage statistics of the volume, if any have been recorded (see [`ActivityStats`]).
+    pub fn activity(&self) -> Option<ActivityStats> {
+        self.activity.map(|activity| activity.stats)
+    }
+
+    /// Problems that were tolerated while opening the volume.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
     pub fn open(device: O) -> Result<Self, RootError<O>> {
-        let device = Arc::new(device);
+        Self::open_with(device, OpenOptions::default())
+    }
+
+    pub fn open_with(device: O, options: OpenOptions) -> Result<Self, RootError<O>> {
+        let writes = Arc::new(Lock::new(WriteState::new(options.allocation.into())));
+        Self::open_shared(Arc::new(device), options, writes)
+    }
+
+    /// Opens the volume stored on `device` using the boot sector, FAT and up-case table parsed by another
+    /// handle (see [`Root::parts`]), so that only the root directory is read. The device must
+    /// hold the same volume, unmodified since the parts were taken.
+    pub fn from_parts(device: O, parts: VolumeParts) -> Result<Self, RootError<O>> {
+        let writes = Arc::new(Lock::new(WriteState::new(parts.options.allocation.into())));
+        Self::open_root_directory(Arc::new(device), parts, writes)
+    }
+
+    /// The parsed boot sector, FAT and up-case table, for opening further handles with
+    /// [`Root::from_parts`].
+    pub fn parts(&self) -> VolumeParts {
+        VolumeParts {
+            boot: Arc::clone(&self.boot),
+            fat: Arc::clone(&self.fat),
+            upcase: Some(Arc::clone(&self.upcase)),
+            options: self.options,
+            read_only: self.read_only,
+        }
+    }
+
+    /// Whether the FAT has been loaded entirely, see
+    /// [`OpenOptionsBuilder::defer_fat`].
+    pub fn is_fat_loaded(&self) -> bool {
+        !self.fat.is_deferred()
+    }
+
+    /// Loads the entire FAT of a volume opened with [`OpenOptionsBuilder::defer_fat`], parsing
+    /// the volume again.
+    pub fn load_fat(&mut self) -> Result<(), RootError<O>> {
+        if self.is_fat_loaded() {
+            return Ok(());
+        }
+        self.options.defer_fat = false;
+        self.reload()
+    }
+
+    /// Reads both FATs of a TexFAT volume and returns the entries in which they differ. Both
+    /// FATs are kept identical by this crate, so differences indicate damage or an interrupted
+    /// write by another implementation. Empty for volumes with a single FAT.
+    pub fn compare_fats(&self) -> Result<Vec<FatMismatch>, FatLoadError<Arc<O>>> {
+        if self.boot.number_of_fats < 2 {
+            return Ok(Vec::new());
+        }
+        let first = Fat::load(&self.disk, &self.boot, 0)?;
+        let second = Fat::load(&self.disk, &self.boot, 1)?;
+
+        Ok(first
+            .differences(&second)
+            .map(|(cluster, a, b)| FatMismatch {
+                cluster,
+                first: a.0,
+                second: b.0,
+            })
+            .collect())
+    }
+
+    /// Parses the volume again, e.g. after its structures have been modified.
+    pub(crate) fn reload(&mut self) -> Result<(), RootError<O>> {
+        // writers mark the volume dirty, which must not prevent reloading it
+        let options = self.options;
+        let reopen = OpenOptions {
+            allow_dirty: true,
+            ..options
+        };
+        // handles read before keep allocating from the same state
+        let writes = Arc::clone(&self.writes);
+        *self = Self::open_shared(Arc::clone(&self.disk), reopen, writes)?;
+        self.options = options;
+        Ok(())
+    }
+
+    fn open_shared(
+        device: Arc<O>,
+        options: OpenOptions,
+        writes: Arc<Lock<WriteState>>,
+    ) -> Result<Self, RootError<O>> {
         let mut aligned = Box::new(AlignedBootSector([0u8; 512]));
         device
             .read_exact(0, &mut aligned.0[..])
-            .map_err(RootError::Io)?;
+            .map_err(|err| RootError::Io(MountPhase::ReadingBootSector, err))?;
 
         let boot_sector = from_bytes_mut::<BootSector>(&mut aligned.0);
 
@@ -104,6 +507,43 @@ impl<O: ReadOffset> Root<O> {
             return Err(RootError::WrongFs);
         }
 
+        let mut read_only = options.read_only;
+        let mut relax = |relaxation: BootSectorRelaxations, valid: bool, err: RootError<O>| {
+            if valid {
+                Ok(())
+            } else if options.relaxations.contains(relaxation) {
+                read_only = true;
+                Ok(())
+            } else {
+                Err(err)
+            }
+        };
+
+        let strict = options.strict_boot_sector;
+        relax(
+            BootSectorRelaxations::JUMP_BOOT,
+            !strict || boot_sector.jump_boot == JUMP_BOOT,
+            RootError::InvalidJumpBoot(boot_sector.jump_boot),
+        )?;
+        relax(
+            BootSectorRelaxations::RESERVED,
+            !strict || boot_sector._reserved.iter().all(|b| *b == 0),
+            RootError::ReservedNotZero,
+        )?;
+        relax(
+            BootSectorRelaxations::BOOT_SIGNATURE,
+            !strict || boot_sector.boot_signature == BOOT_SIGNATURE,
+            RootError::InvalidBootSignature(boot_sector.boot_signature),
+        )?;
+        relax(
+            BootSectorRelaxations::FILE_SYSTEM_REVISION,
+            !strict || boot_sector.file_system_revision.major() == 1,
+            RootError::UnsupportedRevision(
+                boot_sector.file_system_revision.major(),
+                boot_sector.file_system_revision.minor(),
+            ),
+        )?;
+
         // check for bytes per sector shift
         if !(9..=12).contains(&boot_sector.bytes_per_sector_shift) {
             return Err(RootError::InvalidBytesPerSectorShift(
@@ -126,15 +566,64 @@ impl<O: ReadOffset> Root<O> {
         }?;
         let volume_flags = VolumeFlags::from_bits_truncate(boot_sector.volume_flags);
 
-        // check for correct active fat
-        if volume_flags.contains(VolumeFlags::ACTIVE_FAT) && fat_num == 1
-            || !volume_flags.contains(VolumeFlags::ACTIVE_FAT) && fat_num == 2
+        if !options.allow_dirty && volume_flags.contains(VolumeFlags::VOLUME_DIRTY) {
+            return Err(RootError::VolumeDirty);
+        }
+        if options.verify_checksums
+            && !boot_checksum_valid(&*device, boot_sector.bytes_per_sector(), 0)
+                .map_err(|err| RootError::Io(MountPhase::ReadingBootSector, err))?
         {
-            return Err(RootError::InvalidNumberOfFats(fat_num));
+            return Err(RootError::BootChecksumMismatch);
         }
 
+        // check for correct active fat: only TexFAT volumes have a second FAT to switch to
+        relax(
+            BootSectorRelaxations::ACTIVE_FAT,
+            !(volume_flags.contains(VolumeFlags::ACTIVE_FAT) && fat_num == 1),
+            RootError::InvalidNumberOfFats(fat_num),
+        )?;
+
         // parse FAT
-        let fat = Arc::new(Fat::load(&device, &boot_sector)?);
+        let active = Fat::active_index(&boot_sector);
+        let index = match options.fat {
+            FatSelection::Active => active,
+            FatSelection::First => 0,
+            FatSelection::Second => 1,
+        };
+        // the allocation bitmap only matches the active FAT
+        read_only |= index != active;
+        let fat = if options.defer_fat {
+            Fat::deferred(&boot_sector, index, options.fat_cache_sectors)?
+        } else {
+            Fat::load(&device, &boot_sector, index)?
+        };
+        let fat = Arc::new(fat);
+
+        let parts = VolumeParts {
+            boot: boot_sector,
+            fat,
+            upcase: None,
+            options,
+            read_only,
+        };
+        Self::open_root_directory(device, parts, writes)
+    }
+
+    /// Parses the root directory of a volume whose boot sector and FAT are already parsed.
+    fn open_root_directory(
+        device: Arc<O>,
+        parts: VolumeParts,
+        writes: Arc<Lock<WriteState>>,
+    ) -> Result<Self, RootError<O>> {
+        let generation = device.generation();
+        let VolumeParts {
+            boot: boot_sector,
+            fat,
+            upcase,
+            options,
+            read_only,
+        } = parts;
+        let fat_num = boot_sector.number_of_fats;
 
         let first_cluster = boot_sector.first_cluster_of_root_directory;
         // check for correct index of root cluster
@@ -154,10 +643,22 @@ impl<O: ReadOffset> Root<O> {
         let mut allocation_bitmaps: [Option<BitmapEntry>; 2] = [None, None];
         let mut upcase_table: Option<UpcaseTableEntry> = None;
         let mut volume_label: Option<Label> = None;
-        let mut items: Vec<FsElement<O>> = Vec::new();
+        let mut volume_guid: Option<u128> = None;
+        let mut elements: Vec<(ParsedFileEntry, bool, EntryLocation)> = Vec::new();
+        let mut activity: Option<ActivityEntry> = None;
+
+        let mut findings: Vec<Finding> = Vec::new();
+        let tolerate = |findings: &mut Vec<Finding>, finding: Finding, err: RootError<O>| {
+            if options.recover_duplicates {
+                findings.push(finding);
+                Ok(())
+            } else {
+                Err(err)
+            }
+        };
 
         loop {
-            let entry = reader.read()?;
+            let entry = reader.read().map_err(root_entry_error)?;
 
             // unused entries are ignored
             if entry.unused() {
@@ -167,71 +668,142 @@ impl<O: ReadOffset> Root<O> {
             if !entry.regular() {
                 break;
             } else if !entry.primary() {
-                return Err(RootError::RootEntryNotPrimary(entry.entry_type()));
+                return Err(RootError::RootEntryNotPrimary(
+                    RawEntry(entry.bytes()),
+                    reader.last_location(),
+                ));
             }
 
+            let location = reader.last_location();
+            let duplicate = Finding::DuplicateMetadataEntry {
+                entry_type: entry.entry_type(),
+                location,
+            };
+            let invalid = Finding::InvalidMetadataEntry {
+                entry_type: entry.entry_type(),
+                location,
+            };
+
             match entry {
                 DirEntry::Bitmap(bitmap_entry) => {
                     let index = if allocation_bitmaps[1].is_some() {
-                        return Err(RootError::InvalidNumberOfAllocationBitmaps);
+                        2
                     } else if allocation_bitmaps[0].is_some() {
                         1
                     } else {
                         0
                     };
+                    // in recovery mode, only as many bitmaps as there are FATs are expected
+                    if index == 2 || options.recover_duplicates && index >= fat_num {
+                        tolerate(
+                            &mut findings,
+                            duplicate,
+                            RootError::InvalidNumberOfAllocationBitmaps,
+                        )?;
+                        continue;
+                    }
                     if index != bitmap_entry.index() || !bitmap_entry.valid() {
-                        return Err(RootError::InvalidAllocationBitmap);
+                        tolerate(
+                            &mut findings,
+                            invalid,
+                            RootError::InvalidAllocationBitmap(location),
+                        )?;
+                        continue;
                     }
 
                     allocation_bitmaps[index as usize] = Some(bitmap_entry);
                 }
                 DirEntry::UpcaseTable(upcase_table_entry) => {
                     if upcase_table.is_some() {
-                        return Err(RootError::InvalidNumberOfUpcaseTables);
+                        tolerate(
+                            &mut findings,
+                            duplicate,
+                            RootError::InvalidNumberOfUpcaseTables,
+                        )?;
+                        continue;
                     }
                     if !upcase_table_entry.valid() {
-                        return Err(RootError::InvalidUpcaseTable);
+                        tolerate(
+                            &mut findings,
+                            invalid,
+                            RootError::InvalidUpcaseTable(location),
+                        )?;
+                        continue;
                     }
                     upcase_table = Some(upcase_table_entry);
                 }
                 DirEntry::VolumeLabel(volume_label_entry) => {
                     if volume_label.is_some() {
-                        return Err(RootError::InvalidNumberOfVolumeLabels);
+                        tolerate(
+                            &mut findings,
+                            duplicate,
+                            RootError::InvalidNumberOfVolumeLabels,
+                        )?;
+                        continue;
                     }
                     if volume_label_entry.character_count > 11 {
-                        return Err(RootError::InvalidVolumeLabel);
+                        tolerate(
+                            &mut findings,
+                            invalid,
+                            RootError::InvalidVolumeLabel(location),
+                        )?;
+                        continue;
                     }
 
-                    volume_label = Some(Label(
+                    volume_label = Some(Label::from_entry(
                         volume_label_entry.volume_label,
                         volume_label_entry.character_count,
                     ));
                 }
+                DirEntry::VolumeGuid(volume_guid_entry) => {
+                    if volume_guid.is_some() {
+                        tolerate(
+                            &mut findings,
+                            duplicate,
+                            RootError::InvalidNumberOfVolumeGuids,
+                        )?;
+                        continue;
+                    }
+                    let checksum = { volume_guid_entry.set_checksum };
+                    if volume_guid_entry.secondary_count != 0
+                        || entry.checksum(0) != u16::from_le(checksum)
+                    {
+                        tolerate(
+                            &mut findings,
+                            invalid,
+                            RootError::InvalidVolumeGuid(location),
+                        )?;
+                        continue;
+                    }
+                    volume_guid = Some(u128::from_le(volume_guid_entry.volume_guid));
+                }
                 DirEntry::File(file_entry) => {
-                    let parsed = ParsedFileEntry::try_new(&file_entry, &mut reader)?;
-                    let item = if file_entry.file_attributes.is_directory() {
-                        FsElement::D(Directory::new(
-                            Arc::clone(&device),
-                            Arc::clone(&boot_sector),
-                            Arc::clone(&fat),
-                            parsed.name,
-                            parsed.stream_extension_entry,
-                            parsed.timestamps,
-                        ))
-                    } else {
-                        FsElement::F(File::try_new(
-                            &device,
-                            &boot_sector,
-                            &fat,
-                            parsed.name,
-                            parsed.stream_extension_entry,
-                            parsed.timestamps,
-                        )?)
+                    let parsed = match ParsedFileEntry::try_new(&file_entry, &mut reader) {
+                        Ok(parsed) => parsed,
+                        Err(err) if options.skip_damaged_entry_sets && err.is_damage() => {
+                            findings.push(Finding::DamagedEntrySet { location });
+                            reader.skip_entry_set(location).map_err(root_entry_error)?;
+                            continue;
+                        }
+                        Err(FileParserError::ReadFailed(
+                            err @ EntryReaderError::ReadFailed(..),
+                        )) => return Err(root_entry_error(err)),
+                        Err(err) => return Err(err.into()),
                     };
-
-                    items.push(item);
+                    // the entry set carrying the statistics is not part of the tree
+                    if let Some(entry) = ActivityEntry::find(location, &parsed.vendor_extensions) {
+                        activity.get_or_insert(entry);
+                        continue;
+                    }
+                    // elements are created once the up-case table is known
+                    elements.push((parsed, file_entry.file_attributes.is_directory(), location));
+                }
+                _ => {
+                    return Err(RootError::UnexpectedRootEntry(
+                        RawEntry(entry.bytes()),
+                        reader.last_location(),
+                    ));
                 }
-                _ => return Err(RootError::UnexpectedRootEntry(entry.entry_type())),
             }
         }
 
@@ -243,12 +815,792 @@ impl<O: ReadOffset> Root<O> {
         }
 
         // check upcase table
-        if upcase_table.is_none() {
+        let Some(upcase_table) = upcase_table else {
             return Err(RootError::InvalidNumberOfUpcaseTables);
+        };
+        let upcase = match upcase {
+            Some(upcase) => upcase,
+            None => Arc::new(read_upcase_table(
+                &device,
+                &boot_sector,
+                &fat,
+                &upcase_table,
+                options.verify_checksums,
+            )?),
+        };
+
+        let mut items: Vec<FsElement<O>> = Vec::with_capacity(elements.len());
+        for (parsed, directory, location) in elements {
+            let path = parsed.name.clone();
+            let item = if directory {
+                FsElement::D(Directory::new(
+                    Arc::clone(&device),
+                    Arc::clone(&boot_sector),
+                    Arc::clone(&fat),
+                    Arc::clone(&upcase),
+                    parsed,
+                    generation,
+                    options,
+                    read_only,
+                    Arc::clone(&writes),
+                    path,
+                ))
+            } else {
+                match File::try_new(
+                    &device,
+                    &boot_sector,
+                    &fat,
+                    parsed,
+                    generation,
+                    &options,
+                    read_only,
+                    &writes,
+                    path,
+                ) {
+                    Ok(file) => FsElement::F(file),
+                    Err(_) if options.skip_damaged_entry_sets => {
+                        findings.push(Finding::DamagedEntrySet { location });
+                        continue;
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            };
+            items.push(item);
         }
+
+        let allocation_bitmaps: Vec<BitmapEntry> =
+            allocation_bitmaps.into_iter().flatten().collect();
+        writes.lock().bitmap_entries = allocation_bitmaps.clone();
+
         Ok(Root {
+            disk: device,
+            boot: boot_sector,
+            fat,
             volume_label,
+            volume_guid,
+            allocation_bitmaps,
+            upcase_table,
+            upcase,
             items,
+            read_only,
+            findings,
+            activity,
+            options,
+            generation,
+            writes,
         })
     }
 }
+
+/// Reads the up-case table of a volume. Falls back to the table written by the formatter if the
+/// stored one is too big or does not match its checksum (which is reported by
+/// [`Volume::scrub`](crate::volume::Volume::scrub)).
+fn read_upcase_table<O: ReadOffset>(
+    device: &Arc<O>,
+    boot: &Arc<BootSector>,
+    fat: &Fat,
+    entry: &UpcaseTableEntry,
+    verify_checksum: bool,
+) -> Result<UpcaseTable, RootError<O>> {
+    if entry.data_len > MAX_UPCASE_TABLE_LENGTH {
+        return Ok(UpcaseTable::default());
+    }
+    let mut reader = ClusterChainReader::try_new(
+        Arc::clone(boot),
+        fat,
+        entry.first_cluster,
+        ClusterChainOptions::Fat {
+            data_length: Some(entry.data_len),
+        },
+        Arc::clone(device),
+    )?;
+    let mut table = vec![0u8; entry.data_len as usize];
+    reader
+        .read_exact(&mut table)
+        .map_err(|err| RootError::Io(MountPhase::ReadingUpcaseTable, err))?;
+
+    let (stored, computed) = ({ entry.table_checksum }, checksum::upcase_table(&table));
+    match stored == computed {
+        true => Ok(UpcaseTable::from_bytes(&table)),
+        false if verify_checksum => Err(RootError::UpcaseTableChecksumMismatch(stored, computed)),
+        false => Ok(UpcaseTable::default()),
+    }
+}
+
+/// Whether the checksum sector of the boot region at the given sector offset matches.
+pub(crate) fn boot_checksum_valid<O: ReadOffset>(
+    device: &O,
+    bytes_per_sector: u16,
+    offset_sectors: u64,
+) -> Result<bool, O::Err> {
+    let mut region = vec![0u8; (CHECKSUMMED_SECTORS + 1) as usize * bytes_per_sector as usize];
+    device.read_exact(offset_sectors * bytes_per_sector as u64, &mut region)?;
+
+    let (sectors, checksum_sector) =
+        region.split_at(CHECKSUMMED_SECTORS as usize * bytes_per_sector as usize);
+    let Some(expected) = checksum::boot_region(sectors, bytes_per_sector) else {
+        return Ok(false);
+    };
+    Ok(checksum_sector
+        .chunks_exact(4)
+        .all(|chunk| chunk == expected.to_le_bytes()))
+}
+
+/// Attributes a failed read of the root directory to the entry that could not be read.
+fn root_entry_error<O: ReadOffset>(err: EntryReaderError<Arc<O>>) -> RootError<O> {
+    match err {
+        EntryReaderError::ReadFailed(index, cluster, err) => RootError::Io(
+            MountPhase::ParsingRootEntry {
+                cluster,
+                index: index as u32,
+            },
+            err,
+        ),
+        err => RootError::DirEntry(err),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parse_error_location() {
+    use crate::{error::FileParserError, test_utils::TestImage};
+
+    let mut image = TestImage::sample();
+    let root_offset = image.cluster_offset(image.root_cluster());
+
+    // stream extension of `hello.txt` without a name
+    let stream_offset = root_offset + 5 * 32;
+    image.image[stream_offset + 3] = 0;
+
+    match Root::open(image.cursor()) {
+        Err(RootError::InvalidFileEntry(FileParserError::InvalidStreamExtension(location))) => {
+            assert_eq!(location.offset, stream_offset as u64);
+            assert_eq!(location.index, 5);
+        }
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("corrupt stream extension must be detected"),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn skip_damaged_entry_sets() {
+    use crate::{name::Normalization, test_utils::TestImage};
+
+    let mut image = TestImage::sample();
+    let root_offset = image.cluster_offset(image.root_cluster());
+    image.add_entry_set(101, "other.txt", false, 0, 0);
+    // stream extension of `hello.txt` without a name
+    image.image[root_offset + 5 * 32 + 3] = 0;
+    // `nested.txt` claims the file entry of `other.txt` as its second name entry
+    let nested_offset = image.cluster_offset(101);
+    image.image[nested_offset + 1] += 1;
+
+    assert!(Root::open(std::io::Cursor::new(image.image.clone())).is_err());
+
+    let options = OpenOptionsBuilder::default()
+        .skip_damaged_entry_sets(true)
+        .build()
+        .unwrap();
+    let mut root = Root::open_with(image.cursor(), options).unwrap();
+    let names: Vec<&str> = root.items().iter().map(|item| item.name()).collect();
+    assert_eq!(names, ["dir"]);
+    assert!(matches!(
+        root.findings(),
+        [Finding::DamagedEntrySet { location }] if location.offset == root_offset as u64 + 4 * 32
+    ));
+
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    assert!(dir.open().is_ok());
+    let (items, findings) = dir.open_with_findings().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name(), "other.txt");
+    assert!(matches!(
+        findings[..],
+        [Finding::DamagedEntrySet { location }] if location.offset == nested_offset as u64
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn surrogate_pair_across_name_entries() {
+    use crate::test_utils::TestImage;
+
+    let mut image = TestImage::new();
+    let root = image.root_cluster();
+    // the emoji occupies code units 14 and 15, i.e. it is split across two entries
+    let name = "holiday-photo-😀.jpg";
+    image.add_entry_set(root, name, false, 100, 0);
+
+    let mut root = Root::open(image.cursor()).unwrap();
+    assert_eq!(root.items()[0].name(), name);
+}
+
+#[cfg(test)]
+#[test]
+fn overlong_name_is_rejected() {
+    use crate::{error::FileParserError, test_utils::TestImage};
+
+    let mut image = TestImage::new();
+    let root = image.root_cluster();
+    image.add_entry_set(root, &"a".repeat(255), false, 100, 0);
+
+    // claim one more file name entry than any valid name can have
+    let offset = image.cluster_offset(root) + 4 * 32;
+    image.image[offset + 1] += 1;
+
+    match Root::open(image.cursor()) {
+        Err(RootError::InvalidFileEntry(FileParserError::NameTooLong(18, _))) => {}
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("overlong name must be rejected"),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn relaxed_boot_sector_checks() {
+    use crate::{name::Normalization, test_utils::TestImage};
+    use std::io::Cursor;
+
+    let mut image = TestImage::sample();
+    // a byte of `MustBeZero` as written by some cameras
+    image.image[0x20] = 0x01;
+
+    // the field is only checked on request
+    let root = Root::open(Cursor::new(image.image.clone())).unwrap();
+    assert!(!root.is_read_only());
+    let strict = OpenOptionsBuilder::default()
+        .strict_boot_sector(true)
+        .build()
+        .unwrap();
+    let root = Root::open_with(Cursor::new(image.image.clone()), strict);
+    assert!(matches!(root, Err(RootError::ReservedNotZero)));
+
+    let options = OpenOptionsBuilder::default()
+        .strict_boot_sector(true)
+        .relaxations(BootSectorRelaxations::RESERVED)
+        .build()
+        .unwrap();
+    let mut root = Root::open_with(image.cursor(), options).unwrap();
+    assert!(root.is_read_only());
+    assert!(root.find("hello.txt", Normalization::None).is_some());
+
+    // drive select and the reserved bytes after it are not checked
+    let mut image = TestImage::sample();
+    image.image[0x6F] = 0x00;
+    image.image[0x71] = 0x01;
+    let root = Root::open_with(image.cursor(), strict).unwrap();
+    assert!(!root.is_read_only());
+
+    // relaxations only matter for nonconforming volumes
+    let root = Root::open_with(TestImage::sample().cursor(), options).unwrap();
+    assert!(!root.is_read_only());
+}
+
+#[cfg(test)]
+#[test]
+fn reopen_from_parts() {
+    use crate::test_utils::TestImage;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use std::io::Cursor;
+
+    /// Device counting the bytes read from it.
+    #[derive(Debug)]
+    struct Counting<'a>(Cursor<Vec<u8>>, &'a AtomicU64);
+    impl ReadOffset for Counting<'_> {
+        type Err = std::io::Error;
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            self.1.fetch_add(buf.len() as u64, Ordering::SeqCst);
+            self.0.read_at(offset, buf)
+        }
+    }
+
+    let image = TestImage::sample().image;
+    let read = AtomicU64::new(0);
+    let root = Root::open(Counting(Cursor::new(image.clone()), &read)).unwrap();
+    let full = read.swap(0, Ordering::SeqCst);
+
+    let mut reopened = Root::from_parts(Counting(Cursor::new(image), &read), root.parts()).unwrap();
+    // only the root directory is read, not the boot sector, the FAT and the up-case table
+    let partial = read.load(Ordering::SeqCst);
+    assert!(partial <= root.boot.bytes_per_cluster() as u64);
+    assert!(partial < full);
+
+    assert_eq!(reopened.volume_serial_number(), root.volume_serial_number());
+    let names: Vec<&str> = reopened.items().iter().map(|item| item.name()).collect();
+    assert_eq!(names, ["hello.txt", "dir"]);
+}
+
+#[cfg(test)]
+#[test]
+fn failing_read_phase() {
+    use crate::test_utils::TestImage;
+    use core::ops::Range;
+    use std::{io::Cursor, vec::Vec};
+
+    /// Device failing all reads of a byte range.
+    struct Failing(Cursor<Vec<u8>>, Range<u64>);
+    impl ReadOffset for Failing {
+        type Err = std::io::Error;
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            if offset < self.1.end && self.1.start < offset + buf.len() as u64 {
+                return Err(std::io::Error::other("bad sector"));
+            }
+            self.0.read_at(offset, buf)
+        }
+    }
+
+    let image = TestImage::sample();
+    let root_cluster = image.root_cluster();
+    let stream_offset = image.cluster_offset(root_cluster) as u64 + 5 * 32;
+    let fail = |range: Range<u64>| {
+        let device = Failing(Cursor::new(image.image.clone()), range);
+        match Root::open(device) {
+            Err(RootError::Io(phase, _)) => phase,
+            Err(err) => panic!("unexpected error: {err}"),
+            Ok(_) => panic!("the read must fail"),
+        }
+    };
+
+    assert_eq!(fail(0..1), MountPhase::ReadingBootSector);
+    // stream extension of `hello.txt`
+    assert_eq!(
+        fail(stream_offset..stream_offset + 1),
+        MountPhase::ParsingRootEntry {
+            cluster: root_cluster,
+            index: 5
+        }
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn deferred_fat() {
+    use crate::{name::Normalization, test_utils::TestImage};
+    use core::{cell::Cell, ops::Range};
+    use std::{
+        io::{Cursor, Read},
+        string::String,
+        vec::Vec,
+    };
+
+    /// Device recording the longest read of a byte range.
+    #[derive(Debug)]
+    struct Recording(Cursor<Vec<u8>>, Range<u64>, Cell<usize>);
+    impl ReadOffset for Recording {
+        type Err = std::io::Error;
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            if offset < self.1.end && self.1.start < offset + buf.len() as u64 {
+                self.2.set(self.2.get().max(buf.len()));
+            }
+            self.0.read_at(offset, buf)
+        }
+    }
+
+    let mut image = TestImage::sample();
+    let root = image.root_cluster();
+    let bytes_per_cluster = image.boot.bytes_per_cluster() as usize;
+    let mut contents = vec![b'a'; bytes_per_cluster];
+    contents.extend_from_slice(b"bc");
+    image.write_clusters(200, &contents);
+    image.add_entry_set(root, "chained.txt", false, 200, contents.len() as u64);
+    // stream extension of `chained.txt` with a FAT chain
+    let stream_offset = image.cluster_offset(root) + 11 * 32;
+    image.image[stream_offset + 1] = 0b01;
+    let fat_offset = image.boot.fat_offset as usize * 512;
+    image.image[fat_offset + 200 * 4..fat_offset + 201 * 4].copy_from_slice(&201u32.to_le_bytes());
+    image.image[fat_offset + 201 * 4..fat_offset + 202 * 4]
+        .copy_from_slice(&u32::MAX.to_le_bytes());
+
+    let fat = fat_offset as u64..(fat_offset + image.boot.fat_length as usize * 512) as u64;
+    let device = Recording(image.cursor(), fat, Cell::new(0));
+    let options = OpenOptionsBuilder::default()
+        .defer_fat(true)
+        .build()
+        .unwrap();
+    let mut root = Root::open_with(device, options).unwrap();
+    assert!(!root.is_fat_loaded());
+
+    let Some(FsElement::F(file)) = root.find("chained.txt", Normalization::None) else {
+        panic!("chained.txt not found");
+    };
+    let mut read = Vec::new();
+    file.read_to_end(&mut read).unwrap();
+    assert_eq!(read, contents);
+    let Some(FsElement::F(file)) = root.find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    let mut read = String::new();
+    file.read_to_string(&mut read).unwrap();
+    assert_eq!(read, "Hello, World!");
+    // only single entries were read
+    assert_eq!(root.disk.2.get(), 4);
+
+    root.load_fat().unwrap();
+    assert!(root.is_fat_loaded());
+    assert!(root.disk.2.get() > 4);
+}
+
+#[cfg(test)]
+#[test]
+fn read_profiles() {
+    use crate::{name::Normalization, test_utils::TestImage};
+    use core::{cell::RefCell, ops::Range};
+    use std::{
+        io::{Cursor, Read, Seek},
+        vec::Vec,
+    };
+
+    /// Device recording the sizes of the reads of a byte range.
+    #[derive(Debug)]
+    struct Recording(Cursor<Vec<u8>>, Range<u64>, RefCell<Vec<usize>>);
+    impl ReadOffset for Recording {
+        type Err = std::io::Error;
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            if offset < self.1.end && self.1.start < offset + buf.len() as u64 {
+                self.2.borrow_mut().push(buf.len());
+            }
+            self.0.read_at(offset, buf)
+        }
+    }
+
+    let mut image = TestImage::sample();
+    let root = image.root_cluster();
+    let bytes_per_cluster = image.boot.bytes_per_cluster() as usize;
+    let contents: Vec<u8> = (0..3 * bytes_per_cluster)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    image.write_clusters(200, &contents);
+    image.add_entry_set(root, "data.bin", false, 200, contents.len() as u64);
+    let heap = image.cluster_offset(200) as u64..image.cluster_offset(203) as u64;
+
+    let reads = |profile| {
+        let device = Recording(
+            Cursor::new(image.image.clone()),
+            heap.clone(),
+            RefCell::default(),
+        );
+        let options = OpenOptionsBuilder::default()
+            .read_profile(profile)
+            .build()
+            .unwrap();
+        let mut root = Root::open_with(device, options).unwrap();
+        let Some(FsElement::F(file)) = root.find("data.bin", Normalization::None) else {
+            panic!("data.bin not found");
+        };
+        let mut read = vec![0u8; contents.len()];
+        file.read_exact(&mut read).unwrap();
+        assert_eq!(read, contents);
+        // small reads
+        file.rewind().unwrap();
+        let mut byte = [0u8; 1];
+        for _ in 0..8 {
+            file.read_exact(&mut byte).unwrap();
+        }
+        root.disk.2.take()
+    };
+
+    let balanced = reads(ReadProfile::Balanced);
+    assert_eq!(balanced[..1], [3 * bytes_per_cluster]);
+    assert_eq!(balanced[1..], [1; 8]);
+    let low_memory = reads(ReadProfile::LowMemory);
+    assert_eq!(low_memory.len(), 3 * bytes_per_cluster / 512 + 1);
+    assert!(low_memory.iter().all(|len| *len == 512));
+    assert_eq!(
+        reads(ReadProfile::HighThroughput),
+        [3 * bytes_per_cluster, 3 * bytes_per_cluster]
+    );
+
+    // adjacent clusters of a FAT chain are read at once
+    let stream_offset = image.cluster_offset(root) + 11 * 32;
+    image.image[stream_offset + 1] = 0b01;
+    let fat_offset = image.boot.fat_offset as usize * 512;
+    for (cluster, next) in [(200u32, 201u32), (201, 205), (205, u32::MAX)] {
+        let at = fat_offset + cluster as usize * 4;
+        image.image[at..at + 4].copy_from_slice(&next.to_le_bytes());
+    }
+    let contents = &image.image[image.cluster_offset(200)..image.cluster_offset(202)];
+    let mut contents = contents.to_vec();
+    contents.extend_from_slice(&image.image[image.cluster_offset(205)..image.cluster_offset(206)]);
+    let heap = image.cluster_offset(200) as u64..image.cluster_offset(206) as u64;
+    let device = Recording(Cursor::new(image.image.clone()), heap, RefCell::default());
+    let mut root = Root::open(device).unwrap();
+    let Some(FsElement::F(file)) = root.find("data.bin", Normalization::None) else {
+        panic!("data.bin not found");
+    };
+    let mut read = vec![0u8; contents.len()];
+    file.read_exact(&mut read).unwrap();
+    assert_eq!(read, contents);
+    assert_eq!(
+        root.disk.2.take(),
+        [2 * bytes_per_cluster, bytes_per_cluster]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn full_directory_cluster() {
+    use crate::{name::Normalization, test_utils::TestImage};
+
+    let mut image = TestImage::sample();
+    let cluster_size = image.boot.bytes_per_cluster() as usize;
+    // fill the root directory and `dir` entirely, leaving no room for an end of directory entry
+    let mut counts = Vec::new();
+    for parent in [image.root_cluster(), 101] {
+        let start = image.cluster_offset(parent);
+        let mut free = (start + cluster_size - image.free_slot(parent)) / 32;
+        let mut count = 0;
+        while free >= 3 {
+            image.add_entry_set(parent, &format!("file{count}"), false, 0, 0);
+            count += 1;
+            free -= 3;
+        }
+        for _ in 0..free {
+            image.add_raw_entry(parent, &[0x05; 32]);
+        }
+        counts.push(count);
+    }
+
+    let mut root = Root::open(image.cursor()).unwrap();
+    assert_eq!(root.items().len(), 2 + counts[0]);
+    let Some(FsElement::D(dir)) = root.find("dir", Normalization::None) else {
+        panic!("directory not found");
+    };
+    assert_eq!(dir.open().unwrap().len(), 1 + counts[1]);
+}
+
+#[cfg(test)]
+#[test]
+fn open_nested_paths() {
+    use crate::test_utils::TestImage;
+    use std::io::{Cursor, Read};
+
+    let mut image = TestImage::sample();
+    image.add_entry_set(101, "photos", true, 103, 4096);
+    image.write_clusters(104, b"jpeg");
+    image.add_entry_set(103, "img.jpg", false, 104, 4);
+    let root = Root::open(Cursor::new(image.image)).unwrap();
+
+    let Some(FsElement::F(mut img)) = root.open_path("/DIR/Photos/img.JPG").unwrap() else {
+        panic!("img.jpg not found");
+    };
+    let mut read = Vec::new();
+    img.read_to_end(&mut read).unwrap();
+    assert_eq!(read, b"jpeg");
+
+    assert!(matches!(
+        root.open_path("dir/photos/").unwrap(),
+        Some(FsElement::D(photos)) if photos.name() == "photos"
+    ));
+    assert!(matches!(
+        root.open_path("hello.txt").unwrap(),
+        Some(FsElement::F(_))
+    ));
+    assert!(root.open_path("dir/missing.txt").unwrap().is_none());
+    assert!(root.open_path("hello.txt/nested.txt").unwrap().is_none());
+    assert!(root.open_path("/").unwrap().is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn volume_upcase_table() {
+    use crate::test_utils::TestImage;
+    use std::io::Cursor;
+
+    let mut image = TestImage::sample();
+    let root_offset = image.cluster_offset(image.root_cluster());
+    let entry_offset = (root_offset..root_offset + 4096)
+        .step_by(32)
+        .find(|offset| image.image[*offset] == 0x82)
+        .unwrap();
+    let first_cluster = u32::from_le_bytes(
+        image.image[entry_offset + 20..entry_offset + 24]
+            .try_into()
+            .unwrap(),
+    );
+
+    // compressed table mapping every code unit to itself, so names are case-sensitive
+    let identity = [0xFF; 4];
+    image.write_clusters(first_cluster, &identity);
+    let entry = &mut image.image[entry_offset..entry_offset + 32];
+    entry[4..8].copy_from_slice(&checksum::upcase_table(&identity).to_le_bytes());
+    entry[24..32].copy_from_slice(&4u64.to_le_bytes());
+
+    let root = Root::open(Cursor::new(image.image.clone())).unwrap();
+    assert!(!root.upcase_table().name_eq("hello.txt", "HELLO.TXT"));
+    assert!(root.open_path("DIR/nested.txt").unwrap().is_none());
+    let Some(FsElement::D(dir)) = root.open_path("dir").unwrap() else {
+        panic!("dir not found");
+    };
+    assert!(
+        dir.find("NESTED.txt", dir.upcase_table())
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        dir.find("nested.txt", dir.upcase_table())
+            .unwrap()
+            .is_some()
+    );
+
+    // tables not matching their checksum are replaced by the default one
+    image.image[entry_offset + 4] ^= 1;
+    let root = Root::open(Cursor::new(image.image)).unwrap();
+    assert!(root.upcase_table().name_eq("hello.txt", "HELLO.TXT"));
+}
+
+#[cfg(test)]
+#[test]
+fn volume_guid_entry() {
+    use crate::test_utils::TestImage;
+    use std::io::Cursor;
+
+    let mut image = TestImage::sample();
+    let root_cluster = image.root_cluster();
+    let entry_offset = image.free_slot(root_cluster);
+    let guid = DirEntry::VolumeGuid(VolumeGuidEntry::new(0x0123_4567_89ab_cdef));
+    image.add_raw_entry(root_cluster, &guid.bytes());
+
+    let root = Root::open(Cursor::new(image.image.clone())).unwrap();
+    assert_eq!(root.volume_guid(), Some(0x0123_4567_89ab_cdef));
+
+    // the GUID no longer matches the set checksum
+    image.image[entry_offset + 6] ^= 0xff;
+    assert!(matches!(
+        Root::open(Cursor::new(image.image.clone())),
+        Err(RootError::InvalidVolumeGuid(location)) if location.offset == entry_offset as u64
+    ));
+
+    let options = OpenOptionsBuilder::default()
+        .recover_duplicates(true)
+        .build()
+        .unwrap();
+    let root = Root::open_with(Cursor::new(image.image), options).unwrap();
+    assert!(root.volume_guid().is_none());
+    assert!(matches!(
+        root.findings(),
+        [Finding::InvalidMetadataEntry { location, .. }] if location.offset == entry_offset as u64
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn fat_selection() {
+    use crate::{fat::FatEntry, test_utils::TestImage};
+    use std::io::Cursor;
+
+    // TexFAT volume using the first FAT and bitmap, the second bitmap is stored at cluster 60
+    let mut image = TestImage::sample();
+    let boot = image.boot;
+    let fat_offset = boot.fat_offset as usize * 512;
+    let fat_length = boot.fat_length as usize * 512;
+    let root = image.cluster_offset(image.root_cluster());
+    image.image[110] = 2;
+    let mut entry: Vec<u8> = image.image[root + 64..root + 96].to_vec();
+    entry[1] = 1;
+    entry[20..24].copy_from_slice(&60u32.to_le_bytes());
+    image.add_raw_entry(image.root_cluster(), &entry);
+    image.image[fat_offset + 60 * 4..fat_offset + 61 * 4].fill(0xFF);
+    image
+        .image
+        .copy_within(fat_offset..fat_offset + fat_length, fat_offset + fat_length);
+
+    let root = Root::open(Cursor::new(image.image.clone())).unwrap();
+    assert!(!root.is_read_only());
+    assert!(root.compare_fats().unwrap().is_empty());
+
+    // cluster 70 is only allocated in the second FAT
+    let second = fat_offset + fat_length;
+    image.image[second + 70 * 4..second + 71 * 4].fill(0xFF);
+    let root = Root::open(Cursor::new(image.image.clone())).unwrap();
+    assert_eq!(
+        root.compare_fats().unwrap(),
+        [FatMismatch {
+            cluster: 70,
+            first: 0,
+            second: FatEntry::eof().0
+        }]
+    );
+
+    let options = OpenOptionsBuilder::default()
+        .fat(FatSelection::Second)
+        .build()
+        .unwrap();
+    let root = Root::open_with(Cursor::new(image.image), options).unwrap();
+    assert!(root.is_read_only());
+    assert_eq!(
+        root.fat.read_entry(&root.disk, 70).unwrap(),
+        Some(FatEntry::eof())
+    );
+
+    // volumes with a single FAT have no second one
+    assert!(matches!(
+        Root::open_with(TestImage::sample().cursor(), options),
+        Err(RootError::Fat(FatLoadError::MissingFat(1)))
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn open_policies() {
+    use crate::{entry::FileAttributes, test_utils::TestImage, volume::Volume};
+    use std::{io::Cursor, sync::Mutex};
+
+    let sample = TestImage::sample().image;
+    let strict = OpenOptionsBuilder::default()
+        .verify_checksums(true)
+        .allow_dirty(false)
+        .build()
+        .unwrap();
+    assert!(Root::open_with(Cursor::new(sample.clone()), strict).is_ok());
+
+    // volumes in use by a writer
+    let mut image = sample.clone();
+    image[106] |= VolumeFlags::VOLUME_DIRTY.bits() as u8;
+    assert!(matches!(
+        Root::open_with(Cursor::new(image.clone()), strict),
+        Err(RootError::VolumeDirty)
+    ));
+    assert!(Root::open(Cursor::new(image)).unwrap().is_dirty());
+
+    // corrupt boot region
+    let mut image = sample.clone();
+    image[512] ^= 0xFF;
+    assert!(matches!(
+        Root::open_with(Cursor::new(image.clone()), strict),
+        Err(RootError::BootChecksumMismatch)
+    ));
+    assert!(Root::open(Cursor::new(image)).is_ok());
+
+    // corrupt up-case table
+    let root = Root::open(Cursor::new(sample.clone())).unwrap();
+    let offset = root
+        .boot
+        .cluster_offset(root.upcase_table.first_cluster)
+        .unwrap() as usize;
+    let mut image = sample.clone();
+    image[offset + 0x100] ^= 0xFF;
+    assert!(matches!(
+        Root::open_with(Cursor::new(image.clone()), strict),
+        Err(RootError::UpcaseTableChecksumMismatch(..))
+    ));
+    assert!(Root::open(Cursor::new(image)).is_ok());
+
+    let read_only = OpenOptionsBuilder::default()
+        .read_only(true)
+        .build()
+        .unwrap();
+    let mut volume = Volume::open_with(Mutex::new(Cursor::new(sample)), read_only).unwrap();
+    assert!(volume.root().is_read_only());
+    assert!(
+        volume
+            .set_attributes("hello.txt", FileAttributes::HIDDEN)
+            .is_err()
+    );
+}
@@ -0,0 +1,284 @@
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use derive_builder::Builder;
+
+use crate::{
+    FIRST_USABLE_CLUSTER_INDEX,
+    bitmap::AllocationBitmap,
+    check::Finding,
+    checksum,
+    cluster::{ClusterChainOptions, reader::ClusterChainReader},
+    disk::ReadOffset,
+    error::ScrubError,
+    fs::FsElement,
+    root::{self, Root},
+    throttle::TokenBucket,
+    volume::{BOOT_REGIONS, Volume},
+};
+
+pub use crate::throttle::MonotonicClock;
+
+/// A struct of options applied while scrubbing a volume. It implements the
+/// [`derive_builder::Builder`] pattern.
+#[derive(Builder, Copy, Clone, Debug, Default)]
+#[builder(no_std)]
+pub struct ScrubOptions {
+    /// Maximum throughput (in bytes per second) of cluster reads. Unlimited if `None`.
+    #[builder(default)]
+    max_bytes_per_sec: Option<u64>,
+}
+
+/// Result of a scrub.
+#[derive(Clone, Debug, Default)]
+pub struct ScrubReport {
+    /// Amount of allocated clusters that were read.
+    pub clusters_read: u64,
+    /// Ranges of allocated clusters that could not be read.
+    pub unreadable: Vec<Range<u32>>,
+    /// Checksum mismatches of the reachable metadata.
+    pub findings: Vec<Finding>,
+}
+
+impl<O: ReadOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Reads every allocated cluster and verifies the checksums of the boot regions, the up-case
+    /// table and all reachable entry sets. Clusters are read no faster than configured in
+    /// `options`, so the scrub can run alongside regular use of the device. Unreadable clusters,
+    /// entry sets and directories are reported instead of aborting the scrub.
+    pub fn scrub<C: MonotonicClock>(
+        &self,
+        options: ScrubOptions,
+    ) -> Result<ScrubReport, ScrubError<O>> {
+        let root = &self.root;
+        let mut report = ScrubReport::default();
+
+        for (i, offset) in BOOT_REGIONS.into_iter().enumerate() {
+            if !root.boot_checksum_valid(offset)? {
+                report
+                    .findings
+                    .push(Finding::BootChecksumMismatch { backup: i != 0 });
+            }
+        }
+
+        let stored = { root.upcase_table.table_checksum };
+        let computed = root.upcase_table_checksum()?;
+        if stored != computed {
+            report
+                .findings
+                .push(Finding::UpcaseTableChecksumMismatch { stored, computed });
+        }
+
+        root.verify_entry_sets(&root.items, &mut report.findings);
+
+        let bitmap = AllocationBitmap::load(
+            &root.disk,
+            &root.boot,
+            &root.fat,
+            &root.allocation_bitmaps[0],
+        )
+        .map_err(ScrubError::Bitmap)?;
+
+        // reads are paced strictly, without any credit for time spent in between
+        let bucket = TokenBucket::<C>::new(options.max_bytes_per_sec.unwrap_or(0), 0);
+        let mut buffer = vec![0u8; self.bytes_per_cluster() as usize];
+        let clusters =
+            FIRST_USABLE_CLUSTER_INDEX..FIRST_USABLE_CLUSTER_INDEX + self.cluster_count();
+
+        for cluster in clusters.filter(|cluster| bitmap.is_allocated(*cluster)) {
+            if self.read_cluster(cluster, &mut buffer).is_err() {
+                match report.unreadable.last_mut() {
+                    Some(range) if range.end == cluster => range.end += 1,
+                    _ => report.unreadable.push(cluster..cluster + 1),
+                }
+            }
+            report.clusters_read += 1;
+            bucket.take(buffer.len() as u64);
+        }
+
+        Ok(report)
+    }
+}
+
+impl<O: ReadOffset> Root<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Whether the checksum sector of the boot region at the given sector offset matches.
+    pub(crate) fn boot_checksum_valid(&self, offset_sectors: u64) -> Result<bool, ScrubError<O>> {
+        let bytes_per_sector = self.boot.bytes_per_sector();
+        root::boot_checksum_valid(&*self.disk, bytes_per_sector, offset_sectors)
+            .map_err(|err| ScrubError::ReadFailed(offset_sectors * bytes_per_sector as u64, err))
+    }
+
+    /// Computes the checksum of the up-case table as stored on disk.
+    pub(crate) fn upcase_table_checksum(&self) -> Result<u32, ScrubError<O>> {
+        let mut reader = ClusterChainReader::try_new(
+            alloc::sync::Arc::clone(&self.boot),
+            &self.fat,
+            self.upcase_table.first_cluster,
+            ClusterChainOptions::Fat {
+                data_length: Some(self.upcase_table.data_len),
+            },
+            alloc::sync::Arc::clone(&self.disk),
+        )?;
+
+        let mut table = vec![0u8; self.upcase_table.data_len as usize];
+        let offset = self.boot.cluster_offset(reader.chain()[0]).unwrap_or(0);
+        reader
+            .read_exact(&mut table)
+            .map_err(|err| ScrubError::ReadFailed(offset, err))?;
+
+        Ok(checksum::upcase_table(&table))
+    }
+
+    /// Recursively compares the set checksums of all entry sets in `items`. Entry sets and
+    /// directories that cannot be read are reported and skipped.
+    fn verify_entry_sets(&self, items: &[FsElement<O>], findings: &mut Vec<Finding>) {
+        for item in items {
+            let location = match item {
+                FsElement::F(file) => file.location(),
+                FsElement::D(dir) => dir.location(),
+            };
+            let Ok(entries) = self.read_entry_set(location) else {
+                findings.push(Finding::UnreadableEntrySet { location });
+                continue;
+            };
+
+            let stored = u16::from_le_bytes([entries[0][2], entries[0][3]]);
+            if checksum::entry_set(&entries) != stored {
+                findings.push(Finding::EntrySetChecksumMismatch { location });
+            }
+
+            if let FsElement::D(dir) = item {
+                match dir.open() {
+                    Ok(items) => self.verify_entry_sets(&items, findings),
+                    Err(_) => findings.push(Finding::UnreadableDirectory { location }),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn scrub_reports_corruption() {
+    use crate::{
+        disk::PartitionError,
+        format::ImageBuilder,
+        test_utils::{TestImage, format_options},
+    };
+    use core::{
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    };
+    use std::io::Cursor;
+
+    /// Device failing all reads of a byte range.
+    #[derive(Debug)]
+    struct Failing(Cursor<Vec<u8>>, Range<u64>);
+    impl ReadOffset for Failing {
+        type Err = std::io::Error;
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            if self.1.contains(&offset) {
+                return Err(std::io::Error::unexpected_eop());
+            }
+            self.0.read_at(offset, buf)
+        }
+    }
+
+    /// Clock that only advances while sleeping.
+    static NOW: AtomicU64 = AtomicU64::new(0);
+    struct Simulated;
+    impl MonotonicClock for Simulated {
+        fn now() -> Duration {
+            Duration::from_nanos(NOW.load(Ordering::SeqCst))
+        }
+        fn sleep(duration: Duration) {
+            NOW.fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+        }
+    }
+
+    let mut builder = ImageBuilder::new(format_options().build().unwrap());
+    builder
+        .add_file("a.txt", Cursor::new(vec![1u8; 3 * 4096]), 3 * 4096)
+        .unwrap()
+        .add_file("dir/b.txt", Cursor::new(b"b".to_vec()), 1)
+        .unwrap();
+    let image = TestImage::build(builder).image;
+    let volume = Volume::open(Cursor::new(image.clone())).unwrap();
+    let options = ScrubOptionsBuilder::default()
+        .max_bytes_per_sec(Some(4096))
+        .build()
+        .unwrap();
+    let report = volume.scrub::<Simulated>(options).unwrap();
+    assert!(report.findings.is_empty(), "{:?}", report.findings);
+    assert!(report.unreadable.is_empty());
+    // one cluster per second
+    assert_eq!(
+        Simulated::now().as_secs(),
+        report.clusters_read,
+        "reads must be paced"
+    );
+
+    // corrupt the name of `a.txt` and make its data unreadable
+    let mut volume = Volume::open(Cursor::new(image.clone())).unwrap();
+    let Some(FsElement::F(file)) = volume
+        .root()
+        .find("a.txt", crate::name::Normalization::None)
+    else {
+        panic!("expected a file");
+    };
+    let location = file.location();
+    let data = volume
+        .cluster_offset(file_cluster(&volume, location))
+        .unwrap();
+
+    let mut corrupted = image.clone();
+    corrupted[location.offset as usize + 2 * 32 + 2] ^= 0x20;
+    corrupted[0x100] ^= 0xFF;
+    let volume = Volume::open(Failing(Cursor::new(corrupted), data..data + 2 * 4096)).unwrap();
+
+    let report = volume
+        .scrub::<std::time::Instant>(ScrubOptions::default())
+        .unwrap();
+    let cluster = file_cluster(&volume, location);
+    assert_eq!(report.unreadable, vec![cluster..cluster + 2]);
+    assert_eq!(
+        report.findings,
+        vec![
+            Finding::BootChecksumMismatch { backup: false },
+            Finding::EntrySetChecksumMismatch { location },
+        ]
+    );
+
+    // an unreadable directory does not abort the scrub
+    let mut volume = Volume::open(Cursor::new(image.clone())).unwrap();
+    let Some(FsElement::D(dir)) = volume.root().find("dir", crate::name::Normalization::None)
+    else {
+        panic!("expected a directory");
+    };
+    let location = dir.location();
+    let dir_cluster = file_cluster(&volume, location);
+    let offset = volume.cluster_offset(dir_cluster).unwrap();
+    let volume = Volume::open(Failing(Cursor::new(image), offset..offset + 1)).unwrap();
+
+    let report = volume
+        .scrub::<std::time::Instant>(ScrubOptions::default())
+        .unwrap();
+    assert_eq!(
+        report.findings,
+        vec![Finding::UnreadableDirectory { location }]
+    );
+    assert_eq!(report.unreadable, vec![dir_cluster..dir_cluster + 1]);
+    assert!(report.clusters_read > 0);
+
+    /// First cluster stored in the stream extension entry of the set at `location`.
+    fn file_cluster<O: ReadOffset>(volume: &Volume<O>, location: crate::fs::EntryLocation) -> u32 {
+        let entries = volume.root.read_entry_set(location).unwrap();
+        u32::from_le_bytes(entries[1][20..24].try_into().unwrap())
+    }
+}
@@ -0,0 +1,159 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::{
+    cluster::{ClusterChainOptions, reader::ClusterChainReader},
+    disk::{ReadOffset, SeekFrom, WriteSeek},
+    error::SnapshotError,
+    fs::FsElement,
+    root::Root,
+};
+
+/// Number of sectors occupied by the main and backup boot regions.
+const BOOT_REGIONS_SECTORS: u64 = 24;
+
+impl<O: ReadOffset> Root<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Copies all filesystem metadata (boot regions, FATs, allocation bitmaps, up-case table and
+    /// every directory cluster) into `out`, each at its original offset. File data is never
+    /// written, so seeking past it leaves holes in sparse image files. The resulting image
+    /// preserves the complete structure of the volume while being small enough to attach to
+    /// corruption reports.
+    pub fn metadata_snapshot<W: WriteSeek>(&self, out: &mut W) -> Result<(), SnapshotError<O, W>> {
+        let bytes_per_sector = self.boot.bytes_per_sector() as u64;
+
+        // main and backup boot regions
+        self.copy_range(out, 0, BOOT_REGIONS_SECTORS * bytes_per_sector)?;
+
+        // all FATs
+        self.copy_range(
+            out,
+            self.boot.fat_offset as u64 * bytes_per_sector,
+            self.boot.fat_length as u64 * self.boot.number_of_fats as u64 * bytes_per_sector,
+        )?;
+
+        // allocation bitmaps and up-case table
+        for bitmap in &self.allocation_bitmaps {
+            self.copy_chain(out, bitmap.first_cluster, Some(bitmap.data_len))?;
+        }
+        self.copy_chain(
+            out,
+            self.upcase_table.first_cluster,
+            Some(self.upcase_table.data_len),
+        )?;
+
+        // directory tree
+        self.copy_chain(out, self.boot.first_cluster_of_root_directory, None)?;
+        self.copy_directories(out, &self.items)?;
+
+        // make sure the image covers the entire volume, without copying the (file) data stored
+        // in its last byte
+        let volume_bytes = self.boot.volume_length * bytes_per_sector;
+        if volume_bytes
+            > out
+                .seek(SeekFrom::End(0))
+                .map_err(|err| SnapshotError::Io(err))?
+        {
+            out.seek(SeekFrom::Start(volume_bytes - 1))
+                .map_err(|err| SnapshotError::Io(err))?;
+            out.write_all(&[0]).map_err(|err| SnapshotError::Io(err))?;
+        }
+        Ok(())
+    }
+
+    /// Recursively copies the clusters of all (sub)directories in `items`.
+    fn copy_directories<W: WriteSeek>(
+        &self,
+        out: &mut W,
+        items: &[FsElement<O>],
+    ) -> Result<(), SnapshotError<O, W>> {
+        for item in items {
+            if let FsElement::D(dir) = item {
+                let reader = dir.cluster_reader()?;
+                self.copy_clusters(out, reader.chain())?;
+                self.copy_directories(out, &dir.open()?)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_chain<W: WriteSeek>(
+        &self,
+        out: &mut W,
+        first_cluster: u32,
+        data_length: Option<u64>,
+    ) -> Result<(), SnapshotError<O, W>> {
+        let reader = ClusterChainReader::try_new(
+            Arc::clone(&self.boot),
+            &self.fat,
+            first_cluster,
+            ClusterChainOptions::Fat { data_length },
+            Arc::clone(&self.disk),
+        )?;
+        self.copy_clusters(out, reader.chain())
+    }
+
+    fn copy_clusters<W: WriteSeek>(
+        &self,
+        out: &mut W,
+        clusters: &[u32],
+    ) -> Result<(), SnapshotError<O, W>> {
+        let cluster_size = self.boot.bytes_per_cluster() as u64;
+        for cluster in clusters {
+            if let Some(offset) = self.boot.cluster_offset(*cluster) {
+                self.copy_range(out, offset, cluster_size)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `len` bytes from the volume at the given byte offset into `out`.
+    fn copy_range<W: WriteSeek>(
+        &self,
+        out: &mut W,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), SnapshotError<O, W>> {
+        let mut buffer: Vec<u8> = vec![0u8; len.min(self.boot.bytes_per_cluster() as u64) as usize];
+
+        out.seek(SeekFrom::Start(offset))
+            .map_err(|err| SnapshotError::Io(err))?;
+
+        let mut done = 0;
+        while done < len {
+            let amount = (len - done).min(buffer.len() as u64) as usize;
+            self.disk
+                .read_exact(offset + done, &mut buffer[..amount])
+                .map_err(|err| SnapshotError::ReadFailed(offset + done, err))?;
+            out.write_all(&buffer[..amount])
+                .map_err(|err| SnapshotError::Io(err))?;
+            done += amount as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn snapshot_skips_file_data() {
+    use crate::test_utils::{IMAGE_SIZE, TestImage};
+    use std::io::Cursor;
+
+    let mut image = TestImage::new().image;
+    let metadata = image.clone();
+
+    // garbage outside of any metadata structure
+    let data_offset = 16 * crate::MB as usize;
+    image[data_offset..data_offset + 4].copy_from_slice(b"DATA");
+    // and in the very last byte of the volume
+    *image.last_mut().unwrap() = 0xAB;
+
+    let root = Root::open(Cursor::new(image)).unwrap();
+    let mut snapshot = Cursor::new(Vec::new());
+    root.metadata_snapshot(&mut snapshot).unwrap();
+
+    let snapshot = snapshot.into_inner();
+    assert_eq!(snapshot.len() as u64, IMAGE_SIZE);
+    assert!(snapshot == metadata, "snapshot must only contain metadata");
+}
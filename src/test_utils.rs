@@ -0,0 +1,191 @@
+//! Fixtures shared by the unit tests.
+use std::{io::Cursor, vec::Vec};
+
+use bytemuck::pod_read_unaligned;
+use endify::Endify;
+
+use crate::{
+    boot_sector::BootSector,
+    disk::ReadOffset,
+    format::{Exfat, FormatVolumeOptions, FormatVolumeOptionsBuilder, ImageBuilder},
+};
+
+pub(crate) const IMAGE_SIZE: u64 = 32 * crate::MB as u64;
+
+/// Options of a quickly formatted volume of [`IMAGE_SIZE`] bytes with 512-byte sectors, which
+/// tests may extend with the options they depend on.
+pub(crate) fn format_options() -> FormatVolumeOptionsBuilder {
+    let mut options = FormatVolumeOptionsBuilder::default();
+    options
+        .pack_bitmap(false)
+        .full_format(false)
+        .dev_size(IMAGE_SIZE)
+        .bytes_per_sector(512);
+    options
+}
+
+/// A formatted in-memory image into which raw entry sets can be injected.
+pub(crate) struct TestImage {
+    pub(crate) image: Vec<u8>,
+    pub(crate) boot: BootSector,
+}
+
+impl TestImage {
+    pub(crate) fn new() -> TestImage {
+        TestImage::with_options(format_options().build().unwrap())
+    }
+
+    /// Image formatted with the given options, whose device size must be [`IMAGE_SIZE`].
+    pub(crate) fn with_options(format_options: FormatVolumeOptions) -> TestImage {
+        let mut f = Cursor::new(vec![0u8; IMAGE_SIZE as usize]);
+
+        let mut formatter = Exfat::try_from::<std::time::SystemTime>(format_options).unwrap();
+        formatter
+            .write::<std::time::SystemTime, Cursor<Vec<u8>>>(&mut f)
+            .unwrap();
+        TestImage::from_image(f.into_inner())
+    }
+
+    /// Image built by the given [`ImageBuilder`], whose device size must be [`IMAGE_SIZE`].
+    pub(crate) fn build<S: ReadOffset + core::fmt::Debug>(builder: ImageBuilder<S>) -> TestImage
+    where
+        S::Err: core::fmt::Debug,
+    {
+        let mut f = Cursor::new(vec![0u8; IMAGE_SIZE as usize]);
+        builder
+            .write::<std::time::SystemTime, Cursor<Vec<u8>>>(&mut f)
+            .unwrap();
+        TestImage::from_image(f.into_inner())
+    }
+
+    fn from_image(image: Vec<u8>) -> TestImage {
+        let boot: BootSector = pod_read_unaligned(&image[..512]);
+        TestImage {
+            image,
+            boot: Endify::from_le(boot),
+        }
+    }
+
+    pub(crate) fn root_cluster(&self) -> u32 {
+        self.boot.first_cluster_of_root_directory
+    }
+
+    pub(crate) fn cluster_offset(&self, cluster: u32) -> usize {
+        self.boot.cluster_offset(cluster).unwrap() as usize
+    }
+
+    /// Writes data into consecutive clusters, starting at `cluster`.
+    pub(crate) fn write_clusters(&mut self, cluster: u32, data: &[u8]) {
+        let offset = self.cluster_offset(cluster);
+        self.image[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// Appends a raw entry set at the first free slot of the given (single cluster) directory.
+    /// The data of the element is expected to be stored contiguously (`NoFatChain`).
+    pub(crate) fn add_entry_set(
+        &mut self,
+        parent: u32,
+        name: &str,
+        directory: bool,
+        first_cluster: u32,
+        data_len: u64,
+    ) {
+        let entries = entry_set(name, directory, first_cluster, data_len);
+
+        let mut offset = self.free_slot(parent);
+        for entry in entries {
+            self.image[offset..offset + 32].copy_from_slice(&entry);
+            offset += 32;
+        }
+    }
+
+    /// Appends a single raw entry at the first free slot of the given (single cluster) directory.
+    pub(crate) fn add_raw_entry(&mut self, parent: u32, entry: &[u8]) {
+        let start = self.cluster_offset(parent);
+        let offset = self.free_slot(parent);
+        assert!(offset + 32 <= start + self.boot.bytes_per_cluster() as usize);
+        self.image[offset..offset + 32].copy_from_slice(entry);
+    }
+
+    /// Offset of the first free entry in the given (single cluster) directory.
+    pub(crate) fn free_slot(&self, parent: u32) -> usize {
+        let start = self.cluster_offset(parent);
+        let cluster_size = self.boot.bytes_per_cluster() as usize;
+        let mut offset = start;
+        while self.image[offset] != 0 {
+            offset += 32;
+            assert!(offset < start + cluster_size, "directory is full");
+        }
+        offset
+    }
+
+    /// Image with the following tree:
+    /// - `hello.txt` containing `Hello, World!`
+    /// - `dir/`
+    ///   - `nested.txt` containing `nested`
+    pub(crate) fn sample() -> TestImage {
+        let mut image = TestImage::new();
+        let root = image.root_cluster();
+
+        image.write_clusters(100, b"Hello, World!");
+        image.add_entry_set(root, "hello.txt", false, 100, 13);
+
+        image.add_entry_set(
+            root,
+            "dir",
+            true,
+            101,
+            image.boot.bytes_per_cluster() as u64,
+        );
+
+        image.write_clusters(102, b"nested");
+        image.add_entry_set(101, "nested.txt", false, 102, 6);
+
+        image
+    }
+
+    pub(crate) fn cursor(self) -> Cursor<Vec<u8>> {
+        Cursor::new(self.image)
+    }
+}
+
+/// Builds a raw file entry set (file, stream extension and file name entries).
+pub(crate) fn entry_set(
+    name: &str,
+    directory: bool,
+    first_cluster: u32,
+    data_len: u64,
+) -> Vec<[u8; 32]> {
+    let name: Vec<u16> = name.encode_utf16().collect();
+    let name_entries = name.len().div_ceil(15);
+
+    let mut file = [0u8; 32];
+    file[0] = 0x85;
+    file[1] = 1 + name_entries as u8;
+    let attributes: u16 = if directory { 0x10 } else { 0x20 };
+    file[4..6].copy_from_slice(&attributes.to_le_bytes());
+    // 2024-05-17 12:30:20
+    let timestamp: u32 = (44 << 25) | (5 << 21) | (17 << 16) | (12 << 11) | (30 << 5) | 10;
+    file[8..12].copy_from_slice(&timestamp.to_le_bytes());
+    file[12..16].copy_from_slice(&timestamp.to_le_bytes());
+    file[16..20].copy_from_slice(&timestamp.to_le_bytes());
+
+    let mut stream = [0u8; 32];
+    stream[0] = 0xC0;
+    stream[1] = 0b11; // allocation possible, no FAT chain
+    stream[3] = name.len() as u8;
+    stream[8..16].copy_from_slice(&data_len.to_le_bytes());
+    stream[20..24].copy_from_slice(&first_cluster.to_le_bytes());
+    stream[24..32].copy_from_slice(&data_len.to_le_bytes());
+
+    let mut entries = vec![file, stream];
+    for chunk in name.chunks(15) {
+        let mut file_name = [0u8; 32];
+        file_name[0] = 0xC1;
+        for (i, c) in chunk.iter().enumerate() {
+            file_name[2 + 2 * i..4 + 2 * i].copy_from_slice(&c.to_le_bytes());
+        }
+        entries.push(file_name);
+    }
+    entries
+}
@@ -0,0 +1,79 @@
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// A monotonic time source, used to pace I/O.
+pub trait MonotonicClock {
+    /// Time elapsed since an arbitrary but fixed point in time.
+    fn now() -> Duration;
+    /// Blocks the caller for the given duration.
+    fn sleep(duration: Duration);
+}
+
+#[cfg(feature = "std")]
+impl MonotonicClock for std::time::Instant {
+    fn now() -> Duration {
+        static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+        START.get_or_init(std::time::Instant::now).elapsed()
+    }
+
+    fn sleep(duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A token bucket refilled with a fixed amount of bytes per second. The bucket holds at most
+/// `burst` bytes, so time spent idle does not build up unlimited credit for later bursts.
+pub(crate) struct TokenBucket<C> {
+    /// Amount of bytes the bucket is refilled with per second.
+    bytes_per_sec: u64,
+    /// Time (in nanoseconds) it takes to refill a whole bucket.
+    burst: u64,
+    /// Point in time (in nanoseconds of `C`) at which the bucket is full again.
+    full_at: AtomicU64,
+    clock: PhantomData<fn() -> C>,
+}
+
+impl<C: MonotonicClock> TokenBucket<C> {
+    /// Creates a full bucket. A rate of zero bytes per second is unlimited.
+    pub(crate) fn new(bytes_per_sec: u64, burst: u64) -> Self {
+        TokenBucket {
+            bytes_per_sec,
+            burst: nanos(burst, bytes_per_sec),
+            full_at: AtomicU64::new(C::now().as_nanos() as u64),
+            clock: PhantomData,
+        }
+    }
+
+    /// Takes `bytes` out of the bucket and blocks until it no longer holds a deficit of more than
+    /// its capacity.
+    pub(crate) fn take(&self, bytes: u64) {
+        if self.bytes_per_sec == 0 {
+            return;
+        }
+
+        let cost = nanos(bytes, self.bytes_per_sec);
+        let now = C::now().as_nanos() as u64;
+        let refill = |full_at: u64| full_at.max(now).saturating_add(cost);
+        let previous = self
+            .full_at
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |full_at| {
+                Some(refill(full_at))
+            })
+            .unwrap_or_else(|full_at| full_at);
+        let ready_at = refill(previous).saturating_sub(self.burst);
+        if ready_at > now {
+            C::sleep(Duration::from_nanos(ready_at - now));
+        }
+    }
+}
+
+/// Time (in nanoseconds) it takes to transfer `bytes` at the given rate.
+fn nanos(bytes: u64, bytes_per_sec: u64) -> u64 {
+    if bytes_per_sec == 0 {
+        return 0;
+    }
+    (bytes as u128 * 1_000_000_000 / bytes_per_sec as u128).min(u64::MAX as u128) as u64
+}
@@ -0,0 +1,91 @@
+use alloc::{format, string::String, vec::Vec};
+
+use crate::{
+    disk::ReadOffset,
+    error::DirectoryError,
+    fs::{EntryLocation, FsElement},
+    root::Root,
+    timestamp::Timestamps,
+};
+
+/// Kind of the element described by a [`TimelineRecord`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecordKind {
+    File,
+    Directory,
+}
+
+/// Timestamps of a single file or directory, together with its path and on-disk location.
+#[derive(Clone, Debug)]
+pub struct TimelineRecord {
+    /// Absolute path of the element, using `/` as separator.
+    pub path: String,
+    pub kind: RecordKind,
+    pub location: EntryLocation,
+    pub timestamps: Timestamps,
+}
+
+impl<O: ReadOffset> Root<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Walks the entire directory tree (depth-first) and returns one [`TimelineRecord`] per file
+    /// and directory, for timeline analysis.
+    pub fn timeline(&self) -> Result<Vec<TimelineRecord>, DirectoryError<O>> {
+        let mut records = Vec::new();
+        collect("", &self.items, &mut records)?;
+        Ok(records)
+    }
+}
+
+fn collect<O: ReadOffset>(
+    parent: &str,
+    items: &[FsElement<O>],
+    records: &mut Vec<TimelineRecord>,
+) -> Result<(), DirectoryError<O>>
+where
+    O::Err: core::fmt::Debug,
+{
+    for item in items {
+        match item {
+            FsElement::F(file) => records.push(TimelineRecord {
+                path: format!("{parent}/{}", file.name()),
+                kind: RecordKind::File,
+                location: file.location(),
+                timestamps: *file.timestamps(),
+            }),
+            FsElement::D(dir) => {
+                let path = format!("{parent}/{}", dir.name());
+                records.push(TimelineRecord {
+                    path: path.clone(),
+                    kind: RecordKind::Directory,
+                    location: dir.location(),
+                    timestamps: *dir.timestamps(),
+                });
+                collect(&path, &dir.open()?, records)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn timeline_of_sample_image() {
+    use crate::test_utils::TestImage;
+
+    let image = TestImage::sample();
+    let root_offset = image.cluster_offset(image.root_cluster()) as u64;
+    let root = Root::open(image.cursor()).unwrap();
+
+    let records = root.timeline().unwrap();
+    let paths: Vec<&str> = records.iter().map(|r| r.path.as_str()).collect();
+    assert_eq!(paths, ["/hello.txt", "/dir", "/dir/nested.txt"]);
+
+    assert_eq!(records[1].kind, RecordKind::Directory);
+    // `hello.txt` follows the four entries written by the formatter
+    assert_eq!(records[0].location.index, 4);
+    assert_eq!(records[0].location.offset, root_offset + 4 * 32);
+    assert_eq!(records[2].location.cluster, 101);
+    assert_eq!(records[2].timestamps.modified().date().year, 2024);
+}
@@ -1,3 +1,33 @@
+/// Source of the current time, used for the volume serial number and the boot sector at the time
+/// of formatting as well as for the timestamps of new or modified entries. Implement it to supply
+/// e.g. a real-time clock on `no-std` targets; with `std`, [`std::time::SystemTime`] can be used.
+pub trait Clock {
+    type Err;
+
+    /// Seconds since the unix epoch.
+    fn as_secs() -> Result<u64, Self::Err>;
+
+    /// The current point in time. Defaults to a UTC timestamp from [`Clock::as_secs`]; clocks
+    /// knowing fractions of a second or their UTC offset may provide them here.
+    fn now() -> Result<Timestamp, Self::Err> {
+        Self::as_secs().map(Timestamp::from_unix_secs)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for std::time::SystemTime {
+    type Err = std::time::SystemTimeError;
+
+    fn as_secs() -> Result<u64, Self::Err> {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+        Ok(now.as_secs())
+    }
+
+    fn now() -> Result<Timestamp, Self::Err> {
+        Ok(Timestamp::from_system_time(std::time::SystemTime::now()))
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Timestamps {
     created: Timestamp,
@@ -25,14 +55,73 @@ impl Timestamps {
     pub fn accessed(&self) -> &Timestamp {
         &self.accessed
     }
+
+    /// All three timestamps set to [`Timestamp::now`].
+    pub fn now<T: Clock>() -> Self {
+        let now = Timestamp::now::<T>();
+        Timestamps::new(now, now, now)
+    }
+
+    pub fn with_created(mut self, created: Timestamp) -> Self {
+        self.created = created;
+        self
+    }
+
+    pub fn with_modified(mut self, modified: Timestamp) -> Self {
+        self.modified = modified;
+        self
+    }
+
+    pub fn with_accessed(mut self, accessed: Timestamp) -> Self {
+        self.accessed = accessed;
+        self
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct Timestamp {
     timestamp: u32,
     ms_increment: u8,
-    // Offset from UTC in 15 minute intervals
-    utc_offset: i8,
+    utc_offset: UtcOffset,
+}
+
+/// Offset of the local time of a timestamp from UTC, in 15 minute intervals between -16 hours
+/// and +15:45 hours. Timestamps of other implementations may leave it unspecified, in which case
+/// their local time is unknown.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UtcOffset(Option<i8>);
+
+impl UtcOffset {
+    pub const UTC: UtcOffset = UtcOffset(Some(0));
+    /// No offset recorded; the timestamp is in an unknown local time.
+    pub const UNKNOWN: UtcOffset = UtcOffset(None);
+
+    /// Creates an offset of `minutes`. Returns `None` unless it is a multiple of 15 minutes
+    /// within the representable range.
+    pub fn from_minutes(minutes: i16) -> Option<Self> {
+        (minutes % 15 == 0 && (-64 * 15..=63 * 15).contains(&minutes))
+            .then_some(UtcOffset(Some((minutes / 15) as i8)))
+    }
+
+    /// The offset in minutes, if known.
+    pub fn minutes(&self) -> Option<i16> {
+        self.0.map(|quarters| quarters as i16 * 15)
+    }
+
+    /// Decodes the on-disk field: a signed 7-bit offset in its lower bits and a valid flag in
+    /// bit 7.
+    pub(crate) fn from_raw(raw: u8) -> Self {
+        match raw & 0x80 {
+            // sign-extend the 7-bit offset
+            0x80 => UtcOffset(Some(((raw << 1) as i8) >> 1)),
+            _ => UtcOffset::UNKNOWN,
+        }
+    }
+
+    /// Encodes the offset as stored on disk, see [`UtcOffset::from_raw`].
+    pub(crate) fn raw(&self) -> u8 {
+        self.0.map_or(0, |quarters| 0x80 | (quarters as u8 & 0x7F))
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -49,8 +138,22 @@ pub struct Time {
     pub second: u8,
 }
 
+/// A timestamp decoded into calendar fields, in the local time given by its UTC offset.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Hundredths of a second.
+    pub centiseconds: u8,
+    pub utc_offset: UtcOffset,
+}
+
 impl Timestamp {
-    pub fn new(timestamp: u32, ms_increment: u8, utc_offset: i8) -> Self {
+    pub fn new(timestamp: u32, ms_increment: u8, utc_offset: UtcOffset) -> Self {
         Timestamp {
             timestamp,
             ms_increment,
@@ -68,13 +171,438 @@ impl Timestamp {
 
     pub fn time(&self) -> Time {
         Time {
-            second: (self.ms_increment as u16 / 1000) as u8 + (self.timestamp & 0x1F) as u8 * 2,
+            second: self.ms_increment / 100 + (self.timestamp & 0x1F) as u8 * 2,
             minute: ((self.timestamp >> 5) & 0x3f) as u8,
             hour: ((self.timestamp >> 11) & 0x1F) as u8,
         }
     }
 
-    pub fn utc_offset(&self) -> i8 {
+    pub fn utc_offset(&self) -> UtcOffset {
         self.utc_offset
     }
+
+    /// Decodes all fields of the timestamp.
+    pub fn date_time(&self) -> DateTime {
+        let (date, time) = (self.date(), self.time());
+        DateTime {
+            year: date.year,
+            month: date.month,
+            day: date.day,
+            hour: time.hour,
+            minute: time.minute,
+            second: time.second,
+            centiseconds: self.ms_increment % 100,
+            utc_offset: self.utc_offset,
+        }
+    }
+
+    /// Encodes calendar fields into a timestamp. Returns `None` if a field is out of range: the
+    /// year must be between 1980 and 2107.
+    pub fn from_date_time(date_time: DateTime) -> Option<Self> {
+        let DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            centiseconds,
+            utc_offset,
+        } = date_time;
+        let days_in_month = match month {
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        if !(1980..=2107).contains(&year)
+            || !(1..=12).contains(&month)
+            || !(1..=days_in_month).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 59
+            || centiseconds > 99
+        {
+            return None;
+        }
+
+        let timestamp = ((year as u32 - 1980) << 25)
+            | ((month as u32) << 21)
+            | ((day as u32) << 16)
+            | ((hour as u32) << 11)
+            | ((minute as u32) << 5)
+            | (second / 2) as u32;
+        Some(Timestamp {
+            timestamp,
+            ms_increment: (second % 2) * 100 + centiseconds,
+            utc_offset,
+        })
+    }
+
+    /// The current time according to `T`. If the clock is unavailable, the exFAT epoch is used.
+    pub fn now<T: Clock>() -> Self {
+        T::now().unwrap_or_else(|_| Self::from_unix_secs(0))
+    }
+
+    /// Creates a UTC timestamp from seconds since the unix epoch. Points in time before the exFAT
+    /// epoch (1980-01-01) are clamped to it.
+    pub fn from_unix_secs(secs: u64) -> Self {
+        // 1980-01-01 00:00:00
+        const EXFAT_EPOCH: u64 = 315_532_800;
+        let secs = secs.max(EXFAT_EPOCH);
+
+        let days = secs / 86_400;
+        let seconds_of_day = secs % 86_400;
+        let (year, month, day) = civil_from_days(days);
+
+        let year = (year - 1980).min(127);
+        let timestamp = (year << 25)
+            | ((month as u32) << 21)
+            | ((day as u32) << 16)
+            | (((seconds_of_day / 3600) as u32) << 11)
+            | ((((seconds_of_day / 60) % 60) as u32) << 5)
+            | ((seconds_of_day % 60) / 2) as u32;
+
+        Timestamp {
+            timestamp,
+            ms_increment: ((seconds_of_day % 2) * 100) as u8,
+            utc_offset: UtcOffset::UTC,
+        }
+    }
+
+    /// Seconds since the unix epoch of this timestamp, taking its UTC offset into account; an
+    /// unknown offset is treated as UTC. Fractions of a second are truncated.
+    pub fn to_unix_secs(&self) -> u64 {
+        let date = self.date();
+        let days = days_from_civil(date.year as u32, date.month, date.day);
+        let seconds_of_day = ((self.timestamp >> 11) & 0x1F) as u64 * 3600
+            + ((self.timestamp >> 5) & 0x3F) as u64 * 60
+            + (self.timestamp & 0x1F) as u64 * 2
+            + self.ms_increment as u64 / 100;
+        let utc_offset = self.utc_offset.minutes().unwrap_or_default() as i64 * 60;
+        (days * 86_400 + seconds_of_day).saturating_add_signed(-utc_offset)
+    }
+
+    /// Time elapsed from `earlier` to this timestamp, both normalized to UTC. Returns `None` if
+    /// `earlier` is later. Useful to compare with a tolerance, e.g. for the two second precision
+    /// of last access timestamps.
+    pub fn duration_since(&self, earlier: &Timestamp) -> Option<core::time::Duration> {
+        let centiseconds = self
+            .utc_centiseconds()
+            .checked_sub(earlier.utc_centiseconds())?;
+        Some(core::time::Duration::from_millis(centiseconds * 10))
+    }
+
+    /// Hundredths of a second since the unix epoch, normalized to UTC.
+    fn utc_centiseconds(&self) -> u64 {
+        self.to_unix_secs() * 100 + (self.ms_increment % 100) as u64
+    }
+
+    /// Creates a UTC timestamp from a point in time, with a precision of 10 milliseconds. Points
+    /// in time before the exFAT epoch (1980-01-01) are clamped to it.
+    #[cfg(feature = "std")]
+    pub fn from_system_time(time: std::time::SystemTime) -> Self {
+        let since_epoch = time
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut timestamp = Self::from_unix_secs(since_epoch.as_secs());
+        if timestamp.to_unix_secs() == since_epoch.as_secs() {
+            timestamp.ms_increment += (since_epoch.subsec_millis() / 10) as u8;
+        }
+        timestamp
+    }
+
+    /// Creates a timestamp from its on-disk fields, see [`UtcOffset::from_raw`].
+    pub(crate) fn from_raw(timestamp: u32, ms_increment: u8, utc_offset: u8) -> Self {
+        Self::new(timestamp, ms_increment, UtcOffset::from_raw(utc_offset))
+    }
+
+    pub(crate) fn raw(&self) -> u32 {
+        self.timestamp
+    }
+
+    pub(crate) fn raw_increment(&self) -> u8 {
+        self.ms_increment
+    }
+
+    /// UTC offset as stored on disk, see [`UtcOffset::raw`].
+    pub(crate) fn raw_utc_offset(&self) -> u8 {
+        self.utc_offset.raw()
+    }
+}
+
+/// Timestamps are compared as points in time, normalized to UTC using their offsets; an unknown
+/// offset is treated as UTC. `12:00 UTC+01:00` thus equals `11:00 UTC`.
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.utc_centiseconds() == other.utc_centiseconds()
+    }
+}
+
+impl Eq for Timestamp {}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.utc_centiseconds().cmp(&other.utc_centiseconds())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Timestamp> for std::time::SystemTime {
+    fn from(timestamp: Timestamp) -> Self {
+        let centiseconds = timestamp.ms_increment % 100;
+        std::time::UNIX_EPOCH
+            + std::time::Duration::from_secs(timestamp.to_unix_secs())
+            + std::time::Duration::from_millis(centiseconds as u64 * 10)
+    }
+}
+
+/// Converts days since the unix epoch into a (year, month, day) civil date.
+fn civil_from_days(days: u64) -> (u32, u8, u8) {
+    // shift the epoch to 0000-03-01, so leap days are at the end of a year
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (year_of_era + era * 400 + (month <= 2) as u64) as u32;
+
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) civil date into days since the unix epoch, the inverse of
+/// [`civil_from_days`]. Invalid days of zero, which corrupted timestamps may contain, are treated
+/// as the first day of the month.
+fn days_from_civil(year: u32, month: u8, day: u8) -> u64 {
+    let day = day.max(1);
+    let year = year as u64 - (month <= 2) as u64;
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let mp = (month as u64 + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era).saturating_sub(719_468)
+}
+
+#[cfg(test)]
+#[test]
+fn timestamp_from_unix_secs() {
+    // 2024-02-29 13:37:42
+    let timestamp = Timestamp::from_unix_secs(1_709_213_862);
+    let date = timestamp.date();
+    assert_eq!((date.year, date.month, date.day), (2024, 2, 29));
+    let time = timestamp.time();
+    assert_eq!((time.hour, time.minute, time.second), (13, 37, 42));
+
+    // before the exFAT epoch
+    let date = Timestamp::from_unix_secs(0).date();
+    assert_eq!((date.year, date.month, date.day), (1980, 1, 1));
+
+    // ... and back, including odd seconds and UTC offsets
+    for secs in [315_532_800, 1_709_213_862, 1_709_213_863, 4_354_819_199] {
+        assert_eq!(Timestamp::from_unix_secs(secs).to_unix_secs(), secs);
+    }
+    let timestamp = Timestamp::from_unix_secs(1_709_213_862);
+    let local = Timestamp::new(
+        timestamp.raw(),
+        timestamp.raw_increment(),
+        UtcOffset::from_minutes(60).unwrap(),
+    );
+    assert_eq!(local.to_unix_secs(), 1_709_213_862 - 3600);
+}
+
+#[cfg(test)]
+#[test]
+fn timestamps_builder() {
+    /// Clock stopped at 2024-02-29 13:37:42.
+    struct Stopped;
+    impl Clock for Stopped {
+        type Err = ();
+
+        fn as_secs() -> Result<u64, Self::Err> {
+            Ok(1_709_213_862)
+        }
+    }
+    /// Real-time clock keeping local time, stopped at 2024-02-29 14:37:42.50 (UTC+1).
+    struct Rtc;
+    impl Clock for Rtc {
+        type Err = ();
+
+        fn as_secs() -> Result<u64, Self::Err> {
+            Ok(1_709_213_862)
+        }
+
+        fn now() -> Result<Timestamp, Self::Err> {
+            let utc = Timestamp::from_unix_secs(1_709_213_862 + 3600);
+            let utc_offset = UtcOffset::from_minutes(60).unwrap();
+            Ok(Timestamp::new(
+                utc.raw(),
+                utc.raw_increment() + 50,
+                utc_offset,
+            ))
+        }
+    }
+    /// Clock that is unavailable.
+    struct Broken;
+    impl Clock for Broken {
+        type Err = ();
+
+        fn as_secs() -> Result<u64, Self::Err> {
+            Err(())
+        }
+    }
+
+    let created = Timestamp::from_unix_secs(0);
+    let timestamps = Timestamps::now::<Stopped>().with_created(created);
+    assert_eq!(timestamps.created().raw(), created.raw());
+    assert_eq!(timestamps.modified().date().year, 2024);
+    assert_eq!(timestamps.accessed().raw(), timestamps.modified().raw());
+    assert_eq!(timestamps.modified().raw_utc_offset(), 0x80);
+
+    let local = Timestamps::now::<Rtc>().modified().date_time();
+    assert_eq!((local.hour, local.centiseconds), (14, 50));
+    assert_eq!(local.utc_offset.minutes(), Some(60));
+    assert_eq!(
+        Timestamp::now::<Rtc>().to_unix_secs(),
+        Stopped::as_secs().unwrap()
+    );
+
+    let date = Timestamps::now::<Broken>().modified().date();
+    assert_eq!((date.year, date.month, date.day), (1980, 1, 1));
+}
+
+#[cfg(test)]
+#[test]
+fn calendar_time() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    // 2024-02-29 13:37:43.57 at UTC+01:00
+    let date_time = DateTime {
+        year: 2024,
+        month: 2,
+        day: 29,
+        hour: 13,
+        minute: 37,
+        second: 43,
+        centiseconds: 57,
+        utc_offset: UtcOffset::from_minutes(60).unwrap(),
+    };
+    let timestamp = Timestamp::from_date_time(date_time).unwrap();
+    assert_eq!(timestamp.date_time(), date_time);
+    assert_eq!(timestamp.time().second, 43);
+    assert_eq!(timestamp.to_unix_secs(), 1_709_213_863 - 3600);
+    assert_eq!(
+        SystemTime::from(timestamp),
+        UNIX_EPOCH + Duration::from_millis((1_709_213_863 - 3600) * 1000 + 570)
+    );
+
+    // negative offsets are encoded in two's complement
+    let west = DateTime {
+        utc_offset: UtcOffset::from_minutes(-5 * 60 - 30).unwrap(),
+        ..date_time
+    };
+    let timestamp = Timestamp::from_date_time(west).unwrap();
+    assert_eq!(timestamp.raw_utc_offset(), 0x80 | (-22i8 as u8 & 0x7F));
+    let decoded = Timestamp::from_raw(
+        timestamp.raw(),
+        timestamp.raw_increment(),
+        timestamp.raw_utc_offset(),
+    );
+    assert_eq!(decoded.date_time(), west);
+    assert_eq!(decoded.to_unix_secs(), 1_709_213_863 + 5 * 3600 + 1800);
+
+    for invalid in [
+        DateTime {
+            year: 1979,
+            ..date_time
+        },
+        DateTime {
+            year: 2023,
+            ..date_time
+        },
+        DateTime {
+            second: 60,
+            ..date_time
+        },
+    ] {
+        assert!(Timestamp::from_date_time(invalid).is_none());
+    }
+    assert!(UtcOffset::from_minutes(10).is_none());
+    assert!(UtcOffset::from_minutes(16 * 60).is_none());
+    assert_eq!(UtcOffset::from_minutes(-16 * 60).unwrap().raw(), 0xC0);
+
+    // without the valid flag, the offset is unknown
+    let unknown = Timestamp::from_raw(timestamp.raw(), 0, 0x04);
+    assert_eq!(unknown.utc_offset(), UtcOffset::UNKNOWN);
+    assert_eq!(unknown.raw_utc_offset(), 0);
+
+    let time = UNIX_EPOCH + Duration::from_millis(1_709_213_863_576);
+    let timestamp = Timestamp::from_system_time(time);
+    assert_eq!(timestamp.date_time().centiseconds, 57);
+    assert_eq!(timestamp.date_time().second, 43);
+    assert_eq!(SystemTime::from(timestamp), time - Duration::from_millis(6));
+}
+
+#[cfg(test)]
+#[test]
+fn compare_timestamps() {
+    use core::time::Duration;
+
+    let utc = Timestamp::from_unix_secs(1_709_213_862);
+    let date_time = utc.date_time();
+    // the same instant in local time at UTC+01:00 and UTC-05:30
+    let east = Timestamp::from_date_time(DateTime {
+        hour: date_time.hour + 1,
+        utc_offset: UtcOffset::from_minutes(60).unwrap(),
+        ..date_time
+    })
+    .unwrap();
+    let west = Timestamp::from_date_time(DateTime {
+        hour: date_time.hour - 5,
+        minute: date_time.minute - 30,
+        utc_offset: UtcOffset::from_minutes(-330).unwrap(),
+        ..date_time
+    })
+    .unwrap();
+    assert_eq!(east, utc);
+    assert_eq!(west, east);
+    // a later local hour is not necessarily newer
+    assert!(east.date_time().hour > utc.date_time().hour);
+    assert_eq!(east.cmp(&utc), core::cmp::Ordering::Equal);
+
+    let later = Timestamp::from_date_time(DateTime {
+        centiseconds: 1,
+        ..west.date_time()
+    })
+    .unwrap();
+    assert!(later > east);
+    assert_eq!(
+        [east, later, utc]
+            .iter()
+            .max()
+            .unwrap()
+            .duration_since(&utc),
+        Some(Duration::from_millis(10))
+    );
+    assert_eq!(utc.duration_since(&later), None);
+}
+
+#[cfg(test)]
+#[test]
+fn zero_day_timestamp() {
+    // 2024-03-00 and 2024-03-01, both at midnight
+    let zero_day = Timestamp::new((44 << 25) | (3 << 21), 0, UtcOffset::UTC);
+    let first_day = Timestamp::new((44 << 25) | (3 << 21) | (1 << 16), 0, UtcOffset::UTC);
+    assert_eq!(zero_day.to_unix_secs(), first_day.to_unix_secs());
+    assert_eq!(zero_day, first_day);
 }
@@ -0,0 +1,211 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    checksum,
+    disk::WriteOffset,
+    entry::{
+        DirEntry, FileAttributes, VendorExtensionEntry,
+        set::{Allocation, EntrySet},
+    },
+    error::WriteError,
+    fs::EntryLocation,
+    timestamp::{Clock, Timestamp, Timestamps},
+};
+
+use super::{Volume, write::DirectoryClusters};
+
+/// Vendor GUID identifying the vendor extension entry holding the [`ActivityStats`].
+pub(crate) const ACTIVITY_VENDOR_GUID: u128 = 0x5f0c_1f43_8e2a_4b7d_9c61_2d3e_8a74_b951;
+/// Name of the hidden root directory entry set carrying the statistics.
+const ACTIVITY_NAME: &str = ".exfat-fs";
+
+/// Usage counters of a volume, stored by this crate in a vendor extension entry of a hidden
+/// system entry set in the root directory. Other implementations ignore them.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ActivityStats {
+    /// Number of mounts recorded with [`Volume::record_mount`].
+    pub mount_count: u32,
+    /// Number of mounts recorded since the last [`Volume::record_check`].
+    pub mounts_since_check: u32,
+    /// Time of the last recorded check.
+    pub last_check: Option<Timestamp>,
+}
+
+impl ActivityStats {
+    /// Whether a check is due under a policy of checking every `max_mounts` mounts.
+    pub fn check_due(&self, max_mounts: u32) -> bool {
+        self.mounts_since_check >= max_mounts
+    }
+
+    /// Decodes the statistics from the vendor defined bytes of their entry.
+    fn from_bytes(bytes: &[u8; 14]) -> ActivityStats {
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let last_check = u32_at(8);
+        ActivityStats {
+            mount_count: u32_at(0),
+            mounts_since_check: u32_at(4),
+            last_check: (last_check != 0)
+                .then(|| Timestamp::from_raw(last_check, bytes[12], bytes[13])),
+        }
+    }
+
+    fn bytes(&self) -> [u8; 14] {
+        let mut bytes = [0u8; 14];
+        bytes[0..4].copy_from_slice(&self.mount_count.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.mounts_since_check.to_le_bytes());
+        if let Some(last_check) = self.last_check {
+            bytes[8..12].copy_from_slice(&last_check.raw().to_le_bytes());
+            bytes[12] = last_check.raw_increment();
+            bytes[13] = last_check.raw_utc_offset();
+        }
+        bytes
+    }
+}
+
+/// The statistics found in the root directory and where their vendor extension entry is stored.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct ActivityEntry {
+    pub(crate) stats: ActivityStats,
+    /// Location of the entry set.
+    pub(crate) location: EntryLocation,
+    /// Index of the vendor extension entry within the set.
+    pub(crate) index: usize,
+}
+
+impl ActivityEntry {
+    /// Finds the statistics among the vendor extension entries of an entry set.
+    pub(crate) fn find(
+        location: EntryLocation,
+        vendor_extensions: &[(usize, VendorExtensionEntry)],
+    ) -> Option<ActivityEntry> {
+        vendor_extensions
+            .iter()
+            .find(|(_, entry)| u128::from_le(entry.vendor_guid) == ACTIVITY_VENDOR_GUID)
+            .map(|(index, entry)| ActivityEntry {
+                stats: ActivityStats::from_bytes(&entry.vendor_defined),
+                location,
+                index: *index,
+            })
+    }
+}
+
+impl<O: WriteOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Records a mount of the volume, incrementing both mount counters. The statistics are
+    /// created on the first call; entries are timestamped using `T`.
+    pub fn record_mount<T: Clock>(&mut self) -> Result<ActivityStats, WriteError<O>> {
+        self.update_activity::<T>(|stats| {
+            stats.mount_count = stats.mount_count.saturating_add(1);
+            stats.mounts_since_check = stats.mounts_since_check.saturating_add(1);
+        })
+    }
+
+    /// Records a completed check of the volume at the current time (according to `T`), resetting
+    /// the mounts since the last check.
+    pub fn record_check<T: Clock>(&mut self) -> Result<ActivityStats, WriteError<O>> {
+        let now = Timestamp::now::<T>();
+        self.update_activity::<T>(|stats| {
+            stats.mounts_since_check = 0;
+            stats.last_check = Some(now);
+        })
+    }
+
+    fn update_activity<T: Clock>(
+        &mut self,
+        update: impl FnOnce(&mut ActivityStats),
+    ) -> Result<ActivityStats, WriteError<O>> {
+        self.ensure_writable()?;
+        self.refresh_if_stale()?;
+
+        let mut clusters = DirectoryClusters::root(&self.root)?;
+        let activity = self.root.activity;
+        let mut stats = activity.map(|activity| activity.stats).unwrap_or_default();
+        update(&mut stats);
+
+        match activity {
+            Some(activity) => {
+                let mut file_entry = [0u8; 2];
+                self.root
+                    .disk
+                    .read_exact(activity.location.offset, &mut file_entry)
+                    .map_err(WriteError::Io)?;
+                let offsets = self
+                    .writer()
+                    .entry_offsets(&clusters, activity.location, 1 + file_entry[1] as usize)
+                    .ok_or(WriteError::CorruptEntrySet(activity.location))?;
+                self.writer().update_entry_set(&offsets, |entries| {
+                    entries[activity.index][18..].copy_from_slice(&stats.bytes());
+                })?;
+            }
+            None => {
+                let upcase_table = Arc::clone(&self.root.upcase);
+                let (_, items) = self.resolve_directory("", &upcase_table)?;
+                let units: Vec<u16> = ACTIVITY_NAME.encode_utf16().collect();
+                if items
+                    .iter()
+                    .any(|item| item.name == upcase_table.upcase_name(&units))
+                {
+                    return Err(WriteError::AlreadyExists(String::from(ACTIVITY_NAME)));
+                }
+
+                let set = EntrySet::new(
+                    &units,
+                    FileAttributes::HIDDEN | FileAttributes::SYSTEM,
+                    &Timestamps::now::<T>(),
+                    Allocation::default(),
+                    &upcase_table,
+                );
+                let mut entries = set.raw_entries();
+                let vendor = VendorExtensionEntry::new(ACTIVITY_VENDOR_GUID, stats.bytes());
+                entries.push(DirEntry::VendorExtension(vendor).bytes());
+                entries[0][1] += 1;
+                let checksum = checksum::entry_set(&entries);
+                entries[0][2..4].copy_from_slice(&checksum.to_le_bytes());
+
+                self.writer().insert_entry_set(&mut clusters, &entries)?;
+            }
+        }
+
+        self.root.reload()?;
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn mount_statistics() {
+    use crate::{name::Normalization, test_utils::TestImage};
+    use std::{sync::Mutex, time::SystemTime};
+
+    let mut volume = Volume::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    assert!(volume.root().activity().is_none());
+
+    for _ in 0..3 {
+        volume.record_mount::<SystemTime>().unwrap();
+    }
+    let stats = volume.root().activity().unwrap();
+    assert_eq!(stats.mount_count, 3);
+    assert!(stats.check_due(3));
+    assert!(stats.last_check.is_none());
+
+    volume.record_check::<SystemTime>().unwrap();
+    volume.record_mount::<SystemTime>().unwrap();
+
+    // the statistics persist and are not listed as a file
+    let image = volume.root.disk.lock().unwrap().clone();
+    let mut volume = Volume::open(Mutex::new(image)).unwrap();
+    let stats = volume.root().activity().unwrap();
+    assert_eq!(stats.mount_count, 4);
+    assert_eq!(stats.mounts_since_check, 1);
+    assert!(!stats.check_due(2));
+    assert!(stats.last_check.unwrap().date().year >= 2024);
+    assert!(
+        volume
+            .root()
+            .find(ACTIVITY_NAME, Normalization::None)
+            .is_none()
+    );
+    assert_eq!(volume.root().items().len(), 2);
+}
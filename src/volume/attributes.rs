@@ -0,0 +1,92 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{disk::WriteOffset, entry::FileAttributes, error::WriteError};
+
+use super::{Volume, write::FILE_ATTRIBUTES};
+
+impl<O: WriteOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Replaces the attributes of the file or directory at `path` (separated by `/`). Whether
+    /// the element is a directory cannot be changed, so [`FileAttributes::DIRECTORY`] is taken
+    /// from the existing entry.
+    pub fn set_attributes(
+        &mut self,
+        path: &str,
+        attributes: FileAttributes,
+    ) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+        self.refresh_if_stale()?;
+
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let path = path.trim_matches('/');
+        let (parent_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let (parent, items) = self.resolve_directory(parent_path, &upcase_table)?;
+        let name = upcase_table.upcase_name(&name.encode_utf16().collect::<Vec<_>>());
+        let target = items
+            .iter()
+            .find(|item| item.name == name)
+            .ok_or_else(|| WriteError::NotFound(String::from(path)))?;
+
+        let entries = self
+            .root
+            .read_entry_set(target.location)
+            .map_err(WriteError::Io)?;
+        let offsets = self
+            .writer()
+            .entry_offsets(&parent, target.location, entries.len())
+            .ok_or(WriteError::CorruptEntrySet(target.location))?;
+        self.writer().update_entry_set(&offsets, |entries| {
+            let field = &mut entries[0][FILE_ATTRIBUTES..FILE_ATTRIBUTES + 2];
+            let existing =
+                FileAttributes::from_bits_retain(u16::from_le_bytes([field[0], field[1]]));
+            let attributes = attributes.difference(FileAttributes::DIRECTORY)
+                | existing.intersection(FileAttributes::DIRECTORY);
+            field.copy_from_slice(&attributes.bits().to_le_bytes());
+        })?;
+
+        self.root.reload()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn set_attributes() {
+    use crate::{fs::FsElement, name::Normalization, test_utils::TestImage};
+    use std::sync::Mutex;
+
+    let mut volume = Volume::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    volume
+        .set_attributes(
+            "hello.txt",
+            FileAttributes::ARCHIVE | FileAttributes::HIDDEN,
+        )
+        .unwrap();
+    volume
+        .set_attributes("dir", FileAttributes::READ_ONLY)
+        .unwrap();
+    assert!(matches!(
+        volume.set_attributes("missing.txt", FileAttributes::empty()),
+        Err(WriteError::NotFound(_))
+    ));
+
+    let image = volume.root.disk.lock().unwrap().clone();
+    let mut volume = Volume::open(Mutex::new(image)).unwrap();
+    let Some(FsElement::F(hello)) = volume.root().find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    assert_eq!(
+        hello.attributes(),
+        FileAttributes::ARCHIVE | FileAttributes::HIDDEN
+    );
+    // the directory attribute is kept
+    let Some(FsElement::D(dir)) = volume.root().find("dir", Normalization::None) else {
+        panic!("dir not found");
+    };
+    assert_eq!(
+        dir.attributes(),
+        FileAttributes::DIRECTORY | FileAttributes::READ_ONLY
+    );
+}
@@ -0,0 +1,162 @@
+use std::{
+    fs::{File, FileType, OpenOptions},
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use crate::{
+    disk::{AlignedDevice, ReadOffset, WriteOffset},
+    error::{MountPhase, RootError},
+    root::OpenOptionsBuilder,
+};
+
+use super::Volume;
+
+/// Block size devices are accessed with. A multiple of every sector size supported by exFAT.
+const DEVICE_BLOCK_SIZE: u64 = 4096;
+
+/// Device opened by [`Volume::open_auto`], depending on the kind of the path.
+#[derive(Debug)]
+pub enum AutoDevice {
+    /// A regular file, e.g. an image.
+    File(File),
+    /// A block device (e.g. a disk on Linux or Windows), accessed block-aligned so that it also
+    /// works if the device is not buffered by the kernel.
+    BlockDevice(AlignedDevice<File>),
+    /// A character device (e.g. a raw disk on BSD or macOS), which only supports block-aligned
+    /// access.
+    CharDevice(AlignedDevice<File>),
+}
+
+impl ReadOffset for AutoDevice {
+    type Err = std::io::Error;
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+        match self {
+            AutoDevice::File(file) => file.read_at(offset, buf),
+            AutoDevice::BlockDevice(device) | AutoDevice::CharDevice(device) => {
+                device.read_at(offset, buf)
+            }
+        }
+    }
+}
+
+impl WriteOffset for AutoDevice {
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+        match self {
+            AutoDevice::File(file) => file.write_at(offset, buf),
+            AutoDevice::BlockDevice(device) | AutoDevice::CharDevice(device) => {
+                device.write_at(offset, buf)
+            }
+        }
+    }
+    fn flush(&self) -> Result<(), Self::Err> {
+        match self {
+            AutoDevice::File(file) => WriteOffset::flush(file),
+            AutoDevice::BlockDevice(device) | AutoDevice::CharDevice(device) => device.flush(),
+        }
+    }
+}
+
+impl Volume<AutoDevice> {
+    /// Opens the volume stored in the image file or device at `path` read-only, accessing it as
+    /// needed for its kind (see [`AutoDevice`]). Use [`Volume::open_auto_rw`] to modify it.
+    pub fn open_auto(path: impl AsRef<Path>) -> Result<Self, RootError<AutoDevice>> {
+        let file = File::open(path).map_err(|err| RootError::Io(MountPhase::OpeningDevice, err))?;
+        Self::open_file(file, true)
+    }
+
+    /// Opens the volume stored in the image file or device at `path` like [`Volume::open_auto`],
+    /// but for reading and writing. Fails if the path cannot be opened for writing (e.g. with
+    /// [`ErrorKind::PermissionDenied`]) instead of falling back to read-only access.
+    pub fn open_auto_rw(path: impl AsRef<Path>) -> Result<Self, RootError<AutoDevice>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|err| RootError::Io(MountPhase::OpeningDevice, err))?;
+        Self::open_file(file, false)
+    }
+
+    fn open_file(file: File, read_only: bool) -> Result<Self, RootError<AutoDevice>> {
+        let file_type = file
+            .metadata()
+            .map_err(|err| RootError::Io(MountPhase::OpeningDevice, err))?
+            .file_type();
+        let device = if file_type.is_file() {
+            AutoDevice::File(file)
+        } else if is_block_device(&file_type) {
+            AutoDevice::BlockDevice(AlignedDevice::new(file, DEVICE_BLOCK_SIZE))
+        } else if is_char_device(&file_type) {
+            AutoDevice::CharDevice(AlignedDevice::new(file, DEVICE_BLOCK_SIZE))
+        } else {
+            let err = Error::new(ErrorKind::InvalidInput, "neither a file nor a device");
+            return Err(RootError::Io(MountPhase::OpeningDevice, err));
+        };
+        let options = OpenOptionsBuilder::default()
+            .read_only(read_only)
+            .build()
+            .unwrap();
+        Volume::open_with(device, options)
+    }
+}
+
+#[cfg(unix)]
+fn is_block_device(file_type: &FileType) -> bool {
+    std::os::unix::fs::FileTypeExt::is_block_device(file_type)
+}
+
+#[cfg(unix)]
+fn is_char_device(file_type: &FileType) -> bool {
+    std::os::unix::fs::FileTypeExt::is_char_device(file_type)
+}
+
+/// Devices have no file type of their own, so anything opened by path that is neither a file nor
+/// a directory (e.g. `\\.\PhysicalDrive0`) is taken to be a disk.
+#[cfg(not(unix))]
+fn is_block_device(file_type: &FileType) -> bool {
+    !file_type.is_dir() && !file_type.is_symlink()
+}
+
+#[cfg(not(unix))]
+fn is_char_device(_: &FileType) -> bool {
+    false
+}
+
+#[cfg(test)]
+#[test]
+fn open_image_by_path() {
+    use crate::{fs::FsElement, name::Normalization, test_utils::TestImage};
+    use std::time::SystemTime;
+
+    let path = std::env::temp_dir().join(format!("exfat-open-auto-{}.img", std::process::id()));
+    std::fs::write(&path, TestImage::sample().image).unwrap();
+
+    let mut volume = Volume::open_auto(&path).unwrap();
+    assert!(matches!(volume.root.disk.as_ref(), AutoDevice::File(_)));
+    assert!(matches!(
+        volume.root().find("hello.txt", Normalization::None),
+        Some(FsElement::F(_))
+    ));
+    assert!(volume.root.is_read_only());
+    assert!(volume.write_atomic::<SystemTime>("a.txt", b"a").is_err());
+
+    let mut volume = Volume::open_auto_rw(&path).unwrap();
+    assert!(!volume.root.is_read_only());
+    volume.write_atomic::<SystemTime>("a.txt", b"a").unwrap();
+
+    let mut volume = Volume::open_auto(&path).unwrap();
+    assert!(volume.root().find("a.txt", Normalization::None).is_some());
+
+    // directories are rejected
+    assert!(matches!(
+        Volume::open_auto(std::env::temp_dir()),
+        Err(RootError::Io(MountPhase::OpeningDevice, err)) if err.kind() == ErrorKind::InvalidInput
+    ));
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        Volume::open_auto(&path),
+        Err(RootError::Io(MountPhase::OpeningDevice, err)) if err.kind() == ErrorKind::NotFound
+    ));
+}
@@ -0,0 +1,167 @@
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    disk::WriteOffset,
+    error::WriteError,
+    name::{UpcaseTable, validate_name},
+    timestamp::{Clock, Timestamps},
+};
+
+use super::{DirectoryChange, Volume};
+
+impl<O: WriteOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Creates many files in the directory at `path` (separated by `/`) at once, like
+    /// [`Directory::create_files`](crate::fs::directory::Directory::create_files) does for
+    /// directory handles. This also works for the root directory.
+    pub fn create_files<T: Clock, N: AsRef<str>, D: AsRef<[u8]>>(
+        &mut self,
+        path: &str,
+        files: impl IntoIterator<Item = (N, D)>,
+    ) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+        self.refresh_if_stale()?;
+
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let path = path.trim_matches('/');
+        let (mut directory, items) = self.resolve_directory(path, &upcase_table)?;
+        let files = sorted_batch(files, &upcase_table, |upcased| {
+            items.iter().any(|item| item.name == upcased)
+        })?;
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let mut volume = self.usage_scope(path);
+        volume.writer().create_files(
+            &mut directory,
+            &files,
+            &Timestamps::now::<T>(),
+            &upcase_table,
+        )?;
+
+        volume.root.reload()?;
+        volume.notify(files.iter().map(|(_, name, _)| {
+            DirectoryChange::Created(match path {
+                "" => String::from(name.as_ref()),
+                _ => format!("{path}/{}", name.as_ref()),
+            })
+        }));
+        Ok(())
+    }
+}
+
+/// A file of a batch: its validated name, the name as given and its contents.
+pub(crate) type BatchFile<N, D> = (Vec<u16>, N, D);
+
+/// Validates the names of a batch of files and sorts the files by their up-cased names. Fails if
+/// a name appears twice or is `taken` in the directory, before anything is written.
+pub(crate) fn sorted_batch<O: WriteOffset, N: AsRef<str>, D>(
+    files: impl IntoIterator<Item = (N, D)>,
+    upcase_table: &UpcaseTable,
+    taken: impl Fn(&[u16]) -> bool,
+) -> Result<Vec<BatchFile<N, D>>, WriteError<O>> {
+    let mut files = files
+        .into_iter()
+        .map(|(name, data)| {
+            let units = validate_name(name.as_ref())?;
+            Ok((upcase_table.upcase_name(&units), units, name, data))
+        })
+        .collect::<Result<Vec<_>, WriteError<O>>>()?;
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (i, (upcased, _, name, _)) in files.iter().enumerate() {
+        let duplicate = i > 0 && files[i - 1].0 == *upcased;
+        if duplicate || taken(upcased) {
+            return Err(WriteError::AlreadyExists(String::from(name.as_ref())));
+        }
+    }
+    Ok(files
+        .into_iter()
+        .map(|(_, units, name, data)| (units, name, data))
+        .collect())
+}
+
+#[cfg(test)]
+#[test]
+fn batch_of_small_files() {
+    use crate::{
+        disk::{AuditDevice, WriteRecord},
+        fs::FsElement,
+        name::Normalization,
+        test_utils::TestImage,
+    };
+    use std::{io::Read, sync::Mutex, time::SystemTime};
+
+    let writes = core::cell::RefCell::new(Vec::<WriteRecord>::new());
+    let device = AuditDevice::new(Mutex::new(TestImage::sample().cursor()), |record| {
+        writes.borrow_mut().push(record)
+    });
+    let mut volume = Volume::open(device).unwrap();
+    // the clusters of the sample tree are not marked in its bitmap
+    volume.writer().mark_clusters(100, 3, true).unwrap();
+
+    let files: Vec<(String, Vec<u8>)> = (0..200)
+        .rev()
+        .map(|i| {
+            (
+                format!("file{i:03}.txt"),
+                format!("contents {i}").into_bytes(),
+            )
+        })
+        .chain([(String::from("empty"), Vec::new())])
+        .collect();
+    writes.borrow_mut().clear();
+    volume
+        .create_files::<SystemTime, _, _>("dir", files)
+        .unwrap();
+
+    let Some(FsElement::D(dir)) = volume.root().find("dir", Normalization::None) else {
+        panic!("directory not found");
+    };
+    let items = dir.open().unwrap();
+    assert_eq!(items.len(), 202);
+    // sorted by name, after the existing file
+    assert_eq!(items[1].name(), "empty");
+    assert_eq!(items[2].name(), "file000.txt");
+
+    let FsElement::F(first) = &items[2] else {
+        panic!("file000.txt is not a file");
+    };
+    let location = first.location();
+
+    let Some(FsElement::F(mut file)) = dir.find("file123.txt", Normalization::None).unwrap() else {
+        panic!("file123.txt not found");
+    };
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "contents 123");
+
+    // one write per file with data, the rest (bitmap, directory growth and entry sets) is
+    // independent of the amount of files
+    let entries = volume.root.read_entry_set(location).unwrap();
+    let first_cluster = u32::from_le_bytes(entries[1][20..24].try_into().unwrap());
+    let data_start = volume.cluster_offset(first_cluster).unwrap();
+    let data_end = data_start + 200 * volume.bytes_per_cluster() as u64;
+    let (data, metadata): (Vec<WriteRecord>, Vec<WriteRecord>) = writes
+        .borrow()
+        .iter()
+        .copied()
+        .partition(|write| (data_start..data_end).contains(&write.offset));
+    assert_eq!(data.len(), 200);
+    assert!(metadata.len() < 40, "{} writes", metadata.len());
+
+    // nothing is written if a name is taken
+    writes.borrow_mut().clear();
+    assert!(matches!(
+        volume.create_files::<SystemTime, _, _>("dir", [("new", b"a"), ("NESTED.txt", b"b")]),
+        Err(WriteError::AlreadyExists(name)) if name == "NESTED.txt"
+    ));
+    assert!(matches!(
+        volume.create_files::<SystemTime, _, _>("", [("a", b"a"), ("A", b"b")]),
+        Err(WriteError::AlreadyExists(_))
+    ));
+    assert!(writes.borrow().is_empty());
+}
@@ -0,0 +1,135 @@
+use alloc::collections::BTreeSet;
+use core::ops::Range;
+
+use crate::{
+    FIRST_USABLE_CLUSTER_INDEX,
+    disk::ReadOffset,
+    fat::{Fat, FatEntry},
+};
+
+use super::Volume;
+
+/// The reason a [`Chain`] ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChainEnd {
+    /// The FAT entry of the last cluster marks the end of the chain.
+    EndOfChain,
+    /// The FAT entry of the given cluster, the last one of the chain, is free.
+    Free(u32),
+    /// The given cluster is marked as bad in the FAT and is not part of the chain.
+    Bad(u32),
+    /// The given cluster is not part of the cluster heap.
+    OutOfRange(u32),
+    /// The given cluster is already part of the chain.
+    Cycle(u32),
+    /// The FAT entry of the given cluster could not be read from the device.
+    Unreadable(u32),
+}
+
+/// Iterator over the clusters of a FAT chain, created by [`Volume::chain`]. Stops at the
+/// end-of-chain marker and before the first cluster that is invalid or would be visited twice;
+/// [`Chain::end`] tells which of these ended the chain.
+pub struct Chain<'a, O> {
+    fat: &'a Fat,
+    disk: &'a O,
+    heap: Range<u32>,
+    next: u32,
+    visited: BTreeSet<u32>,
+    end: Option<ChainEnd>,
+}
+
+impl<O> Chain<'_, O> {
+    /// Why the chain ended, or `None` if it has not been followed to its end yet.
+    pub fn end(&self) -> Option<ChainEnd> {
+        self.end
+    }
+}
+
+impl<O: ReadOffset> Iterator for Chain<'_, O> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.end.is_some() {
+            return None;
+        }
+
+        let cluster = self.next;
+        let end = if !self.heap.contains(&cluster) {
+            ChainEnd::OutOfRange(cluster)
+        } else if self.visited.contains(&cluster) {
+            ChainEnd::Cycle(cluster)
+        } else {
+            // entries of a deferred FAT are read as the chain is followed
+            match self.fat.read_entry(self.disk, cluster) {
+                Err(_) => ChainEnd::Unreadable(cluster),
+                Ok(Some(entry)) if entry == FatEntry::bad() => ChainEnd::Bad(cluster),
+                Ok(Some(entry)) => {
+                    match entry {
+                        FatEntry(0) => self.end = Some(ChainEnd::Free(cluster)),
+                        entry if entry == FatEntry::eof() => self.end = Some(ChainEnd::EndOfChain),
+                        FatEntry(next) => self.next = next,
+                    }
+                    self.visited.insert(cluster);
+                    return Some(cluster);
+                }
+                Ok(None) => ChainEnd::OutOfRange(cluster),
+            }
+        };
+        self.end = Some(end);
+        None
+    }
+}
+
+impl<O: ReadOffset> Volume<O> {
+    /// Follows the FAT chain starting at `first_cluster`, e.g. to recover a file whose first
+    /// cluster was found by carving. The chain is protected against cycles and entries
+    /// pointing outside of the cluster heap (see [`ChainEnd`]).
+    pub fn chain(&self, first_cluster: u32) -> Chain<'_, O> {
+        Chain {
+            fat: &self.root.fat,
+            disk: &self.root.disk,
+            heap: FIRST_USABLE_CLUSTER_INDEX..self.cluster_count() + FIRST_USABLE_CLUSTER_INDEX,
+            next: first_cluster,
+            visited: BTreeSet::new(),
+            end: None,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn follow_chains() {
+    use crate::test_utils::TestImage;
+    use alloc::vec::Vec;
+
+    let mut image = TestImage::sample();
+    let fat_offset = image.boot.fat_offset as usize * 512;
+    let mut link = |cluster: usize, next: u32| {
+        image.image[fat_offset + cluster * 4..fat_offset + cluster * 4 + 4]
+            .copy_from_slice(&next.to_le_bytes())
+    };
+    link(200, 201);
+    link(201, 0xFFFF_FFFF);
+    // cycle
+    link(300, 301);
+    link(301, 300);
+    // free and bad clusters
+    link(400, 401);
+    link(402, 403);
+    link(403, 0xFFFF_FFF7);
+    // entry pointing outside of the heap
+    link(500, 0x0FFF_FFFF);
+    let volume = Volume::open(image.cursor()).unwrap();
+
+    let follow = |first_cluster| {
+        let mut chain = volume.chain(first_cluster);
+        let clusters: Vec<u32> = chain.by_ref().collect();
+        (clusters, chain.end().unwrap())
+    };
+    assert_eq!(follow(200), (vec![200, 201], ChainEnd::EndOfChain));
+    assert_eq!(follow(300), (vec![300, 301], ChainEnd::Cycle(300)));
+    assert_eq!(follow(400), (vec![400, 401], ChainEnd::Free(401)));
+    assert_eq!(follow(402), (vec![402], ChainEnd::Bad(403)));
+    assert_eq!(follow(500), (vec![500], ChainEnd::OutOfRange(0x0FFF_FFFF)));
+    assert_eq!(follow(1), (vec![], ChainEnd::OutOfRange(1)));
+}
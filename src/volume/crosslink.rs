@@ -0,0 +1,326 @@
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc, vec, vec::Vec};
+
+use crate::{
+    disk::{ReadOffset, WriteOffset},
+    error::WriteError,
+    fat::FatEntry,
+    fs::{EntryLocation, FsElement},
+};
+
+use super::{
+    DirectoryChange, Volume,
+    write::{NO_FAT_CHAIN, STREAM_FIRST_CLUSTER, STREAM_FLAGS},
+};
+
+/// A cluster chain referencing a cluster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClusterOwner {
+    /// The allocation bitmap, the up-case table or the root directory.
+    Metadata,
+    /// The file at the given path.
+    File {
+        path: String,
+        location: EntryLocation,
+    },
+    /// The directory at the given path.
+    Directory {
+        path: String,
+        location: EntryLocation,
+    },
+}
+
+/// A cluster referenced by more than one cluster chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrossLink {
+    pub cluster: u32,
+    /// All chains referencing the cluster: volume metadata first, followed by files and
+    /// directories in the order they are found in the directory tree.
+    pub owners: Vec<ClusterOwner>,
+}
+
+impl<O: ReadOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Follows the cluster chains of the volume metadata and of every reachable file and
+    /// directory, reporting all clusters referenced by more than one of them (cross-linked
+    /// clusters), in ascending order.
+    pub fn find_cross_links(&self) -> Result<Vec<CrossLink>, WriteError<O>> {
+        Ok(self
+            .cluster_owners()?
+            .into_iter()
+            .filter(|(_, owners)| owners.len() > 1)
+            .map(|(cluster, owners)| CrossLink { cluster, owners })
+            .collect())
+    }
+
+    /// Chains referencing each cluster of the volume.
+    fn cluster_owners(&self) -> Result<BTreeMap<u32, Vec<ClusterOwner>>, WriteError<O>> {
+        let root = &self.root;
+        let bytes_per_cluster = self.bytes_per_cluster() as u64;
+        let mut owners = BTreeMap::<u32, Vec<ClusterOwner>>::new();
+
+        let metadata = root
+            .allocation_bitmaps
+            .iter()
+            .map(|bitmap| (bitmap.first_cluster, bitmap.data_len))
+            .chain([(root.upcase_table.first_cluster, root.upcase_table.data_len)]);
+        for (first_cluster, data_len) in metadata {
+            let clusters = data_len.div_ceil(bytes_per_cluster) as usize;
+            let chain = root
+                .fat
+                .read_chain_prefix(&root.disk, first_cluster, clusters)
+                .map_err(WriteError::Io)?;
+            for cluster in chain {
+                owners
+                    .entry(cluster)
+                    .or_default()
+                    .push(ClusterOwner::Metadata);
+            }
+        }
+        // the root directory has no length; bound its chain by the size of the heap
+        let root_chain = root
+            .fat
+            .read_chain(&root.disk, root.boot.first_cluster_of_root_directory)
+            .map_err(WriteError::Io)?;
+        for cluster in root_chain {
+            owners
+                .entry(cluster)
+                .or_default()
+                .push(ClusterOwner::Metadata);
+        }
+
+        self.collect_owners(&root.items, "", &mut owners)?;
+        Ok(owners)
+    }
+
+    /// Recursively records the chains of `items`, located in the directory at `parent`.
+    fn collect_owners(
+        &self,
+        items: &[FsElement<O>],
+        parent: &str,
+        owners: &mut BTreeMap<u32, Vec<ClusterOwner>>,
+    ) -> Result<(), WriteError<O>> {
+        for item in items {
+            let path = match parent {
+                "" => String::from(item.name()),
+                _ => format!("{parent}/{}", item.name()),
+            };
+            match item {
+                FsElement::F(file) => {
+                    let owner = ClusterOwner::File {
+                        path,
+                        location: file.location(),
+                    };
+                    for cluster in file.cluster_chain().0 {
+                        owners.entry(cluster).or_default().push(owner.clone());
+                    }
+                }
+                FsElement::D(dir) => {
+                    let owner = ClusterOwner::Directory {
+                        path: path.clone(),
+                        location: dir.location(),
+                    };
+                    for cluster in dir.cluster_reader()?.chain() {
+                        owners.entry(*cluster).or_default().push(owner.clone());
+                    }
+                    self.collect_owners(&dir.open()?, &path, owners)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<O: WriteOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Resolves all cross-links (see [`Volume::find_cross_links`]) by giving every file except
+    /// the first owner of a shared cluster its own copy of its data. Copies are stored in fresh
+    /// contiguous clusters; clusters no longer referenced afterwards are released. Cross-links
+    /// between directories or volume metadata only cannot be resolved and are left as they are.
+    ///
+    /// Returns the paths of the duplicated files, each of which is also reported to the
+    /// [`Volume::on_change`] callback as modified.
+    pub fn repair_cross_links(&mut self) -> Result<Vec<String>, WriteError<O>> {
+        self.ensure_writable()?;
+        self.refresh_if_stale()?;
+
+        let mut owners = self.cluster_owners()?;
+        let mut duplicated: Vec<(String, EntryLocation)> = Vec::new();
+        for chains in owners.values().filter(|owners| owners.len() > 1) {
+            for owner in &chains[1..] {
+                if let ClusterOwner::File { path, location } = owner
+                    && !duplicated.iter().any(|(_, l)| l == location)
+                {
+                    duplicated.push((path.clone(), *location));
+                }
+            }
+        }
+
+        let upcase_table = Arc::clone(&self.root.upcase);
+        for (path, location) in &duplicated {
+            let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+            let (clusters, _) = self.resolve_directory(parent, &upcase_table)?;
+            let chain = self.file_chain(parent, name, *location)?;
+
+            // copy the data into fresh clusters
+            let mut buffer = vec![0u8; self.bytes_per_cluster() as usize];
+            let mut first_cluster = 0;
+            if !chain.is_empty() {
+                first_cluster = self.writer().allocate_contiguous(chain.len() as u32)?;
+                for (i, cluster) in chain.iter().enumerate() {
+                    self.read_cluster(*cluster, &mut buffer)
+                        .map_err(WriteError::Io)?;
+                    self.write_cluster(first_cluster + i as u32, &buffer)
+                        .map_err(WriteError::Io)?;
+                }
+            }
+
+            let mut file_entry = [0u8; 2];
+            self.root
+                .disk
+                .read_exact(location.offset, &mut file_entry)
+                .map_err(WriteError::Io)?;
+            let offsets = self
+                .writer()
+                .entry_offsets(&clusters, *location, 1 + file_entry[1] as usize)
+                .ok_or(WriteError::CorruptEntrySet(*location))?;
+            self.writer().update_entry_set(&offsets, |entries| {
+                let stream = &mut entries[1];
+                stream[STREAM_FIRST_CLUSTER..STREAM_FIRST_CLUSTER + 4]
+                    .copy_from_slice(&first_cluster.to_le_bytes());
+                stream[STREAM_FLAGS] |= NO_FAT_CHAIN;
+            })?;
+
+            // release the clusters only this file referenced
+            for cluster in chain {
+                let Some(chains) = owners.get_mut(&cluster) else {
+                    continue;
+                };
+                chains.retain(|owner| !matches!(owner, ClusterOwner::File { location: l, .. } if l == location));
+                if chains.is_empty() {
+                    owners.remove(&cluster);
+                    self.writer().mark_clusters(cluster, 1, false)?;
+                    self.writer().set_fat_entry(cluster, FatEntry(0))?;
+                }
+            }
+        }
+
+        self.root.reload()?;
+        let paths: Vec<String> = duplicated.into_iter().map(|(path, _)| path).collect();
+        self.notify(paths.iter().cloned().map(DirectoryChange::Modified));
+        Ok(paths)
+    }
+
+    /// Clusters of the file with the given name and location in the directory at `parent`.
+    fn file_chain(
+        &self,
+        parent: &str,
+        name: &str,
+        location: EntryLocation,
+    ) -> Result<Vec<u32>, WriteError<O>> {
+        let mut items = None;
+        for component in parent.split('/').filter(|c| !c.is_empty()) {
+            let directory = items
+                .as_deref()
+                .unwrap_or(&self.root.items[..])
+                .iter()
+                .find_map(|item| match item {
+                    FsElement::D(dir) if dir.name() == component => Some(dir),
+                    _ => None,
+                })
+                .ok_or_else(|| WriteError::NotFound(String::from(component)))?;
+            items = Some(directory.open()?);
+        }
+
+        items
+            .as_deref()
+            .unwrap_or(&self.root.items[..])
+            .iter()
+            .find_map(|item| match item {
+                FsElement::F(file) if file.location() == location => Some(file.cluster_chain().0),
+                _ => None,
+            })
+            .ok_or_else(|| WriteError::NotFound(String::from(name)))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn cross_linked_files() {
+    use crate::{name::Normalization, test_utils::TestImage};
+    use std::{io::Read, sync::Mutex};
+
+    let mut image = TestImage::sample();
+    let root = image.root_cluster();
+    image.add_entry_set(root, "copy.txt", false, 100, 13);
+    image.add_entry_set(101, "again.txt", false, 102, 6);
+    // shares the first cluster of the allocation bitmap
+    image.add_entry_set(root, "bitmap.bin", false, 2, 4);
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+    // the clusters of the sample tree are not marked in its bitmap
+    volume.writer().mark_clusters(100, 3, true).unwrap();
+
+    let location = |volume: &mut Volume<_>, path: &str| match path.split_once('/') {
+        None => match volume.root().find(path, Normalization::None) {
+            Some(FsElement::F(file)) => file.location(),
+            _ => panic!("{path} not found"),
+        },
+        Some((parent, name)) => match volume.root().find(parent, Normalization::None) {
+            Some(FsElement::D(dir)) => match dir.find(name, Normalization::None).unwrap() {
+                Some(FsElement::F(file)) => file.location(),
+                _ => panic!("{path} not found"),
+            },
+            _ => panic!("{parent} not found"),
+        },
+    };
+    let file = |volume: &mut Volume<_>, path: &str| ClusterOwner::File {
+        path: String::from(path),
+        location: location(volume, path),
+    };
+
+    assert_eq!(
+        volume.find_cross_links().unwrap(),
+        [
+            CrossLink {
+                cluster: 2,
+                owners: vec![ClusterOwner::Metadata, file(&mut volume, "bitmap.bin")],
+            },
+            CrossLink {
+                cluster: 100,
+                owners: vec![
+                    file(&mut volume, "hello.txt"),
+                    file(&mut volume, "copy.txt")
+                ],
+            },
+            CrossLink {
+                cluster: 102,
+                owners: vec![
+                    file(&mut volume, "dir/nested.txt"),
+                    file(&mut volume, "dir/again.txt"),
+                ],
+            },
+        ]
+    );
+
+    assert_eq!(
+        volume.repair_cross_links().unwrap(),
+        ["bitmap.bin", "copy.txt", "dir/again.txt"]
+    );
+    assert_eq!(volume.find_cross_links().unwrap(), []);
+
+    // the copies keep the contents, the originals keep their clusters
+    let Some(FsElement::F(copy)) = volume.root().find("copy.txt", Normalization::None) else {
+        panic!("copy.txt not found");
+    };
+    let mut contents = String::new();
+    copy.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "Hello, World!");
+    let Some(FsElement::F(hello)) = volume.root().find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    assert_eq!(hello.cluster_chain().0, [100]);
+    assert!(volume.writer().bitmap().unwrap().is_allocated(100));
+}
@@ -0,0 +1,101 @@
+use std::{
+    format,
+    fs::FileTimes,
+    path::{Path, PathBuf},
+    time::SystemTime,
+    vec::Vec,
+};
+
+use crate::{
+    disk::ReadOffset,
+    error::ExtractError,
+    fs::{FsElement, walk::WalkOrder},
+    timestamp::Timestamps,
+};
+
+use super::Volume;
+
+impl<O: ReadOffset> Volume<O>
+where
+    O::Err: Into<std::io::Error>,
+{
+    /// Copies all files and directories of the volume into the host directory `target`, which
+    /// is created if it does not exist. Modification and access times are preserved; existing
+    /// files are overwritten. The volume itself is only read.
+    pub fn extract_to(&self, target: impl AsRef<Path>) -> Result<(), ExtractError> {
+        let target = target.as_ref();
+        std::fs::create_dir_all(target)?;
+
+        // directories are modified by extracting their contents, so their times are set last
+        let mut directories: Vec<(PathBuf, Timestamps)> = Vec::new();
+        for item in self.root.walk(WalkOrder::DepthFirst) {
+            let (path, element) = item.map_err(|err| ExtractError::Directory(format!("{err}")))?;
+            if path.split('/').any(|name| name == "." || name == "..") {
+                return Err(ExtractError::UnsafeName(path));
+            }
+            let host = target.join(&path);
+            match element {
+                FsElement::D(directory) => {
+                    std::fs::create_dir_all(&host)?;
+                    directories.push((host, *directory.timestamps()));
+                }
+                FsElement::F(mut file) => {
+                    let mut out = std::fs::File::create(&host)?;
+                    std::io::copy(&mut file, &mut out)?;
+                    out.set_times(file_times(file.timestamps()))?;
+                }
+            }
+        }
+
+        // directories cannot be opened as files on every platform
+        #[cfg(unix)]
+        for (host, timestamps) in directories.into_iter().rev() {
+            std::fs::File::open(host)?.set_times(file_times(&timestamps))?;
+        }
+        #[cfg(not(unix))]
+        drop(directories);
+        Ok(())
+    }
+}
+
+/// Modification and access times of the host file system.
+fn file_times(timestamps: &Timestamps) -> FileTimes {
+    FileTimes::new()
+        .set_modified(SystemTime::from(*timestamps.modified()))
+        .set_accessed(SystemTime::from(*timestamps.accessed()))
+}
+
+#[cfg(test)]
+#[test]
+fn extract_to() {
+    use crate::test_utils::TestImage;
+    use std::sync::Mutex;
+
+    let volume = Volume::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let target = std::env::temp_dir().join(format!("exfat-extract-{}", std::process::id()));
+    volume.extract_to(&target).unwrap();
+
+    assert_eq!(
+        std::fs::read(target.join("hello.txt")).unwrap(),
+        b"Hello, World!"
+    );
+    assert_eq!(
+        std::fs::read(target.join("dir/nested.txt")).unwrap(),
+        b"nested"
+    );
+
+    let Some(FsElement::F(hello)) = volume
+        .root
+        .walk(WalkOrder::DepthFirst)
+        .map(Result::unwrap)
+        .find_map(|(path, element)| (path == "hello.txt").then_some(element))
+    else {
+        panic!("file not found");
+    };
+    let modified = std::fs::metadata(target.join("hello.txt"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert_eq!(modified, SystemTime::from(*hello.timestamps().modified()));
+    std::fs::remove_dir_all(&target).unwrap();
+}
@@ -0,0 +1,324 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    Label,
+    boot_sector::VolumeFlags,
+    disk::WriteOffset,
+    entry::{DirEntry, VOLUME_GUID_ENTRY_TYPE, VolumeGuidEntry, VolumeLabelEntry},
+    error::WriteError,
+};
+
+use super::{BOOT_REGIONS, Volume, write::DirectoryClusters};
+
+/// Entry type of in-use volume label entries.
+const VOLUME_LABEL_ENTRY_TYPE: u8 = 0x83;
+
+/// Staged changes to the metadata of a volume: its label, GUID and volume flags. Nothing is
+/// written until [`VolumeMetadataEdit::commit`] is called. Created by
+/// [`Volume::edit_metadata`].
+pub struct VolumeMetadataEdit<'a, O: WriteOffset> {
+    volume: &'a mut Volume<O>,
+    label: Option<Option<Label>>,
+    guid: Option<Option<u128>>,
+    set_flags: VolumeFlags,
+    clear_flags: VolumeFlags,
+}
+
+impl<O: WriteOffset> Volume<O> {
+    /// Stages changes to the label, GUID and flags of the volume, which are written together by
+    /// [`VolumeMetadataEdit::commit`].
+    pub fn edit_metadata(&mut self) -> VolumeMetadataEdit<'_, O> {
+        VolumeMetadataEdit {
+            volume: self,
+            label: None,
+            guid: None,
+            set_flags: VolumeFlags::empty(),
+            clear_flags: VolumeFlags::empty(),
+        }
+    }
+
+    /// Changes the volume label, rewriting the label entry of the root directory in place or
+    /// creating it if the volume has none.
+    pub fn set_label(&mut self, label: Label) -> Result<(), WriteError<O>> {
+        self.edit_metadata().label(Some(label)).commit()
+    }
+}
+
+impl<O: WriteOffset> VolumeMetadataEdit<'_, O> {
+    /// Sets (or removes) the volume label.
+    pub fn label(&mut self, label: Option<Label>) -> &mut Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Sets (or removes) the volume GUID.
+    pub fn guid(&mut self, guid: Option<u128>) -> &mut Self {
+        self.guid = Some(guid);
+        self
+    }
+
+    /// Sets the given volume flags. `ActiveFat` cannot be changed this way.
+    pub fn set_flags(&mut self, flags: VolumeFlags) -> &mut Self {
+        self.set_flags |= flags - VolumeFlags::ACTIVE_FAT;
+        self.clear_flags -= flags;
+        self
+    }
+
+    /// Clears the given volume flags. `ActiveFat` cannot be changed this way.
+    pub fn clear_flags(&mut self, flags: VolumeFlags) -> &mut Self {
+        self.clear_flags |= flags - VolumeFlags::ACTIVE_FAT;
+        self.set_flags -= flags;
+        self
+    }
+
+    /// Writes all staged changes. Every structure stays valid on disk while doing so:
+    ///
+    /// 1. Slots for entries the volume does not have yet are reserved, growing the root
+    ///    directory if needed.
+    /// 2. If the flags change, the backup boot region is rewritten before the main one, so that
+    ///    an interrupted update of the main boot region can be recovered from the backup.
+    /// 3. The label and GUID entries are written, with a single write if they are located in the
+    ///    same cluster of the root directory (as laid out by the formatter).
+    ///
+    /// The commit is not atomic as a whole: if it is interrupted, the flags may be changed
+    /// without the entries, or entries located in different clusters only in part.
+    pub fn commit(&mut self) -> Result<(), WriteError<O>> {
+        let volume = &mut *self.volume;
+        volume.ensure_writable()?;
+        volume.refresh_if_stale()?;
+
+        let mut staged: Vec<(u8, [u8; 32])> = Vec::new();
+        if let Some(label) = self.label {
+            let entry = match label {
+                Some(label) => DirEntry::VolumeLabel(VolumeLabelEntry::new(label)),
+                None => DirEntry::new_unused(VOLUME_LABEL_ENTRY_TYPE),
+            };
+            staged.push((VOLUME_LABEL_ENTRY_TYPE, entry.bytes()));
+        }
+        if let Some(guid) = self.guid {
+            let entry = match guid {
+                Some(guid) => DirEntry::VolumeGuid(VolumeGuidEntry::new(guid)),
+                None => DirEntry::new_unused(VOLUME_GUID_ENTRY_TYPE),
+            };
+            staged.push((VOLUME_GUID_ENTRY_TYPE, entry.bytes()));
+        }
+
+        let mut writes: Vec<(u64, [u8; 32])> = Vec::new();
+        let mut missing = Vec::new();
+        for (entry_type, entry) in staged {
+            match volume.metadata_slot(entry_type)? {
+                Some(offset) => writes.push((offset, entry)),
+                // nothing to remove
+                None if entry[0] & 0x80 == 0 => {}
+                None => missing.push(entry),
+            }
+        }
+        if !missing.is_empty() {
+            // adjacent slots, so that the new entries are written at once
+            let mut root = DirectoryClusters::root(&volume.root)?;
+            let mut writer = volume.writer();
+            let start = writer.reserve_slots(&mut root, missing.len())?;
+            for (slot, entry) in (start..).zip(missing) {
+                let offset = writer
+                    .slot_offset(&root, slot)
+                    .ok_or(WriteError::NoSpace(1))?;
+                writes.push((offset, entry));
+            }
+        }
+
+        let boot = *volume.root.boot;
+        let flags =
+            (VolumeFlags::from_bits_retain(boot.volume_flags) | self.set_flags) - self.clear_flags;
+        if flags.bits() != boot.volume_flags {
+            let mut boot = boot;
+            boot.volume_flags = flags.bits();
+            // the backup first, so that one of both regions is valid at any time
+            for offset in BOOT_REGIONS.into_iter().rev() {
+                volume
+                    .write_boot_region(&boot, offset)
+                    .map_err(WriteError::Io)?;
+            }
+        }
+        volume.write_entries(&writes)?;
+
+        volume.root.reload()?;
+        Ok(())
+    }
+}
+
+impl<O: WriteOffset> Volume<O> {
+    /// Offset of the root directory entry holding the given kind of volume metadata. An unused
+    /// entry of the same type (as left by the formatter) is used if there is no entry in use.
+    fn metadata_slot(&mut self, entry_type: u8) -> Result<Option<u64>, WriteError<O>> {
+        let root = DirectoryClusters::root(&self.root)?;
+        let mut placeholder = None;
+
+        for slot in 0.. {
+            let Some(offset) = self.writer().slot_offset(&root, slot) else {
+                break;
+            };
+            let mut current = [0u8; 1];
+            self.root
+                .disk
+                .read_exact(offset, &mut current)
+                .map_err(WriteError::Io)?;
+
+            match current[0] {
+                0 => break,
+                t if t == entry_type => return Ok(Some(offset)),
+                t if t == entry_type & 0x7F && placeholder.is_none() => placeholder = Some(offset),
+                _ => {}
+            }
+        }
+        Ok(placeholder)
+    }
+
+    /// Writes raw entries at the given offsets. Entries within the same cluster are combined
+    /// into one write.
+    fn write_entries(&self, writes: &[(u64, [u8; 32])]) -> Result<(), WriteError<O>> {
+        let Some(start) = writes.iter().map(|(offset, _)| *offset).min() else {
+            return Ok(());
+        };
+        let end = writes
+            .iter()
+            .map(|(offset, _)| *offset + 32)
+            .max()
+            .unwrap_or(start);
+        let cluster_size = self.root.boot.bytes_per_cluster() as u64;
+        let heap_offset = self.root.boot.cluster_offset(2).unwrap_or_default();
+        let same_cluster =
+            (start - heap_offset) / cluster_size == (end - 1 - heap_offset) / cluster_size;
+
+        let disk = &self.root.disk;
+        if !same_cluster {
+            for (offset, entry) in writes {
+                disk.write_all_at(*offset, entry).map_err(WriteError::Io)?;
+            }
+            return Ok(());
+        }
+
+        let mut span = vec![0u8; (end - start) as usize];
+        disk.read_exact(start, &mut span).map_err(WriteError::Io)?;
+        for (offset, entry) in writes {
+            let at = (offset - start) as usize;
+            span[at..at + 32].copy_from_slice(entry);
+        }
+        disk.write_all_at(start, &span).map_err(WriteError::Io)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn metadata_edit() {
+    use crate::{
+        checksum,
+        disk::{AuditDevice, ReadOffset, WriteRecord},
+        test_utils::TestImage,
+    };
+    use alloc::string::ToString;
+    use std::sync::Mutex;
+
+    let image = TestImage::new();
+    let root_offset = image.cluster_offset(image.root_cluster()) as u64;
+    let cluster_size = image.boot.bytes_per_cluster() as u64;
+
+    let writes = core::cell::RefCell::new(Vec::<WriteRecord>::new());
+    let device = AuditDevice::new(Mutex::new(image.cursor()), |record| {
+        writes.borrow_mut().push(record)
+    });
+    let mut volume = Volume::open(device).unwrap();
+    writes.borrow_mut().clear();
+
+    volume
+        .edit_metadata()
+        .label(Label::new("NAS".to_string()))
+        .guid(Some(0x1234_5678))
+        .set_flags(VolumeFlags::MEDIA_FAILURE | VolumeFlags::ACTIVE_FAT)
+        .commit()
+        .unwrap();
+
+    assert_eq!(volume.root().label().unwrap().to_string(), "NAS");
+    assert_eq!(volume.root().volume_guid(), Some(0x1234_5678));
+
+    // label and GUID are written at once
+    let root_writes: Vec<_> = writes
+        .borrow()
+        .iter()
+        .filter(|r| (root_offset..root_offset + cluster_size).contains(&r.offset))
+        .copied()
+        .collect();
+    assert_eq!(root_writes.len(), 1);
+    assert_eq!(root_writes[0].len, 64);
+
+    // the backup boot region is written before the main one, and the entries last
+    let regions: Vec<_> = writes
+        .borrow()
+        .iter()
+        .map(|r| match r.offset / (12 * 512) {
+            0 => "main",
+            1 => "backup",
+            _ => "root",
+        })
+        .collect();
+    assert_eq!(regions, ["backup", "backup", "main", "main", "root"]);
+
+    volume
+        .edit_metadata()
+        .label(None)
+        .clear_flags(VolumeFlags::MEDIA_FAILURE)
+        .set_flags(VolumeFlags::MEDIA_FAILURE)
+        .commit()
+        .unwrap();
+    assert!(volume.root().label().is_none());
+    assert_eq!(volume.root().volume_guid(), Some(0x1234_5678));
+
+    for region in BOOT_REGIONS {
+        let mut sectors = vec![0u8; 12 * 512];
+        volume
+            .root
+            .disk
+            .read_exact(region * 512, &mut sectors)
+            .unwrap();
+        assert_eq!(
+            sectors[106] as u16,
+            VolumeFlags::MEDIA_FAILURE.bits(),
+            "`ActiveFat` must not be changed"
+        );
+        let stored = u32::from_le_bytes(sectors[11 * 512..11 * 512 + 4].try_into().unwrap());
+        assert_eq!(
+            checksum::boot_region(&sectors[..11 * 512], 512),
+            Some(stored)
+        );
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn set_label() {
+    use crate::test_utils::TestImage;
+    use alloc::string::ToString;
+    use std::sync::Mutex;
+
+    let image = TestImage::sample();
+    let root_offset = image.cluster_offset(image.root_cluster());
+    let cluster_size = image.boot.bytes_per_cluster() as usize;
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+
+    volume
+        .set_label(Label::new("BACKUP".to_string()).unwrap())
+        .unwrap();
+    volume
+        .set_label(Label::new("DATA".to_string()).unwrap())
+        .unwrap();
+    assert_eq!(volume.root().label().unwrap().to_string(), "DATA");
+
+    // the entry is rewritten instead of being added again
+    let image = volume.root.disk.lock().unwrap().clone().into_inner();
+    let labels = image[root_offset..root_offset + cluster_size]
+        .chunks_exact(32)
+        .filter(|entry| entry[0] == VOLUME_LABEL_ENTRY_TYPE)
+        .count();
+    assert_eq!(labels, 1);
+    let mut volume = Volume::open(std::io::Cursor::new(image)).unwrap();
+    assert_eq!(volume.root().label().unwrap().to_string(), "DATA");
+}
@@ -0,0 +1,602 @@
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+use bytemuck::bytes_of;
+use endify::Endify;
+
+use crate::{
+    boot_sector::{BootSector, Checksum},
+    check::Finding,
+    disk::{PartitionError, ReadOffset, WriteOffset},
+    error::{RootError, WriteError},
+    root::{OpenOptions, Root, VolumeParts},
+};
+
+pub(crate) mod activity;
+mod attributes;
+#[cfg(feature = "std")]
+mod auto;
+pub(crate) mod batch;
+mod chain;
+mod crosslink;
+#[cfg(feature = "std")]
+mod extract;
+mod metadata;
+pub(crate) mod notify;
+mod path;
+mod replace;
+pub(crate) mod usage;
+#[cfg(feature = "std")]
+mod walk;
+pub(crate) mod write;
+
+pub use crate::boot_sector::VolumeFlags;
+pub use activity::ActivityStats;
+#[cfg(feature = "std")]
+pub use auto::AutoDevice;
+pub use chain::{Chain, ChainEnd};
+pub use crosslink::{ClusterOwner, CrossLink};
+pub use metadata::VolumeMetadataEdit;
+pub use notify::DirectoryChange;
+
+/// Offsets of the main and backup boot regions (in sectors).
+pub(crate) const BOOT_REGIONS: [u64; 2] = [0, 12];
+/// Number of sectors covered by the boot region checksum.
+pub(crate) const CHECKSUMMED_SECTORS: u64 = 11;
+/// Offset of the `VolumeFlags` field within the main boot sector.
+const VOLUME_FLAGS_OFFSET: u64 = 106;
+
+/// Handle to an entire exFAT volume.
+pub struct Volume<O: ReadOffset> {
+    pub(crate) root: Root<O>,
+    /// Whether the `VolumeDirty` flag was set when the volume was opened.
+    was_dirty: bool,
+    /// Whether modifications set the `VolumeDirty` flag until the next [`Volume::flush`], for
+    /// volumes opened with [`Volume::open_rw`].
+    track_dirty: bool,
+    /// Top-level directory the clusters allocated by the current operation are accounted to,
+    /// see [`Volume::usage_scope`].
+    usage_scope: Option<String>,
+}
+
+impl<O: ReadOffset> Volume<O> {
+    /// Opens the volume stored on the given device.
+    pub fn open(device: O) -> Result<Self, RootError<O>> {
+        Self::open_with(device, OpenOptions::default())
+    }
+
+    /// Opens the volume stored on the given device with the given options.
+    pub fn open_with(device: O, options: OpenOptions) -> Result<Self, RootError<O>> {
+        Self::from_root(Root::open_with(device, options)?)
+    }
+
+    /// Opens the volume stored on the given device, reusing the boot sector and FAT parsed by
+    /// another handle to the same volume (see [`Root::from_parts`]).
+    pub fn from_parts(device: O, parts: VolumeParts) -> Result<Self, RootError<O>> {
+        Self::from_root(Root::from_parts(device, parts)?)
+    }
+
+    fn from_root(mut root: Root<O>) -> Result<Self, RootError<O>> {
+        // allocating and freeing clusters requires the entire FAT
+        root.load_fat()?;
+        Ok(Volume {
+            was_dirty: root.is_dirty(),
+            track_dirty: false,
+            root,
+            usage_scope: None,
+        })
+    }
+
+    /// The parsed boot sector and FAT, for opening further handles with [`Volume::from_parts`].
+    pub fn parts(&self) -> VolumeParts {
+        self.root.parts()
+    }
+
+    /// Whether the volume was marked dirty (see [`Root::is_dirty`]) when it was opened, i.e. it
+    /// was not cleanly unmounted and may need a repair pass.
+    pub fn was_dirty(&self) -> bool {
+        self.was_dirty
+    }
+
+    /// The root directory of the volume.
+    pub fn root(&mut self) -> &mut Root<O> {
+        &mut self.root
+    }
+
+    /// Amount of bytes per cluster.
+    pub fn bytes_per_cluster(&self) -> u32 {
+        self.root.boot.bytes_per_cluster()
+    }
+
+    /// Amount of clusters in the cluster heap.
+    pub fn cluster_count(&self) -> u32 {
+        self.root.boot.cluster_count
+    }
+
+    /// Absolute byte offset of the given cluster on the device, or `None` if the cluster is not
+    /// part of the cluster heap.
+    pub fn cluster_offset(&self, cluster: u32) -> Option<u64> {
+        self.root.boot.cluster_offset(cluster)
+    }
+
+    /// Reads the raw contents of a cluster into `buf`. At most one cluster is read; the amount of
+    /// bytes read is returned.
+    pub fn read_cluster(&self, cluster: u32, buf: &mut [u8]) -> Result<usize, O::Err> {
+        let offset = self
+            .cluster_offset(cluster)
+            .ok_or(O::Err::cluster_not_found(cluster))?;
+        let amount = buf.len().min(self.bytes_per_cluster() as usize);
+
+        self.root.disk.read_exact(offset, &mut buf[..amount])?;
+        Ok(amount)
+    }
+}
+
+impl<O: WriteOffset> Volume<O> {
+    /// Overwrites the raw contents of a cluster with `buf`, bypassing all filesystem structures.
+    /// At most one cluster is written; the amount of bytes written is returned. Fails for
+    /// read-only volumes (see [`Root::is_read_only`]).
+    pub fn write_cluster(&self, cluster: u32, buf: &[u8]) -> Result<usize, O::Err> {
+        if self.root.is_read_only() {
+            return Err(O::Err::read_only());
+        }
+        let offset = self
+            .cluster_offset(cluster)
+            .ok_or(O::Err::cluster_not_found(cluster))?;
+        let amount = buf.len().min(self.bytes_per_cluster() as usize);
+
+        self.root.disk.write_all_at(offset, &buf[..amount])?;
+        Ok(amount)
+    }
+}
+
+impl<O: WriteOffset> Volume<O> {
+    /// Recomputes `PercentInUse` from the allocation bitmap and clears the `VolumeDirty` flag.
+    /// Both boot regions are rewritten with the updated boot sector and checksum. Returns the new
+    /// percentage of allocated clusters.
+    pub fn refresh_flags(&mut self) -> Result<u8, WriteError<O>> {
+        if self.root.is_read_only() {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        self.refresh_if_stale()?;
+
+        let mut boot = *self.root.boot;
+        let percent_in_use = self.percent_in_use()?;
+        boot.percent_in_use = percent_in_use;
+        boot.volume_flags &= !VolumeFlags::VOLUME_DIRTY.bits();
+
+        for offset in BOOT_REGIONS {
+            self.write_boot_region(&boot, offset)
+                .map_err(WriteError::Io)?;
+        }
+
+        self.root.boot = Arc::new(boot);
+        Ok(percent_in_use)
+    }
+
+    /// Recomputes `PercentInUse` from the allocation bitmap, e.g. after writing files, and stores
+    /// it in both boot regions. Unlike [`Volume::refresh_flags`], the volume flags are left
+    /// untouched. Returns the new percentage of allocated clusters.
+    pub fn update_percent_in_use(&mut self) -> Result<u8, WriteError<O>> {
+        if self.root.is_read_only() {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        self.refresh_if_stale()?;
+
+        let percent_in_use = self.percent_in_use()?;
+        if percent_in_use == self.root.boot.percent_in_use {
+            return Ok(percent_in_use);
+        }
+        let mut boot = *self.root.boot;
+        boot.percent_in_use = percent_in_use;
+
+        for offset in BOOT_REGIONS {
+            self.write_boot_region(&boot, offset)
+                .map_err(WriteError::Io)?;
+        }
+
+        self.root.boot = Arc::new(boot);
+        Ok(percent_in_use)
+    }
+
+    /// Percentage of allocated clusters according to the allocation bitmap, rounded down.
+    fn percent_in_use(&mut self) -> Result<u8, WriteError<O>> {
+        let allocated = self.writer().bitmap()?.allocated_count();
+        Ok((allocated * 100 / self.cluster_count().max(1) as u64) as u8)
+    }
+
+    /// Makes sure all previous writes reached the device. For volumes opened with
+    /// [`Volume::open_rw`], the `VolumeDirty` flag is cleared afterwards; it is set again by the
+    /// next modification through the volume.
+    pub fn flush(&mut self) -> Result<(), WriteError<O>> {
+        self.root.disk.flush().map_err(WriteError::Io)?;
+        if self.track_dirty {
+            self.set_dirty(false)?;
+            self.root.disk.flush().map_err(WriteError::Io)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the volume (see [`Volume::flush`]) and closes it cleanly.
+    pub fn close(mut self) -> Result<(), WriteError<O>> {
+        self.flush()
+    }
+
+    /// Sets or clears the `VolumeDirty` flag. Only the flags of the main boot sector are written,
+    /// as the specification demands; they are not covered by the boot region checksum.
+    pub(crate) fn set_dirty(&mut self, dirty: bool) -> Result<(), WriteError<O>> {
+        let mut boot = *self.root.boot;
+        let mut flags = VolumeFlags::from_bits_retain(boot.volume_flags);
+        if flags.contains(VolumeFlags::VOLUME_DIRTY) == dirty {
+            return Ok(());
+        }
+        flags.set(VolumeFlags::VOLUME_DIRTY, dirty);
+        boot.volume_flags = flags.bits();
+
+        self.root
+            .disk
+            .write_all_at(VOLUME_FLAGS_OFFSET, &boot.volume_flags.to_le_bytes())
+            .map_err(WriteError::Io)?;
+        self.root.boot = Arc::new(boot);
+        Ok(())
+    }
+
+    /// Marks all root directory entries reported as [`Finding::DuplicateMetadataEntry`] or
+    /// [`Finding::InvalidMetadataEntry`] as unused, so other implementations no longer trip over
+    /// them. Both kinds of entries are ignored when opening the volume, so nothing in use is lost.
+    /// Returns the amount of removed entries.
+    pub fn remove_duplicate_entries(&mut self) -> Result<usize, O::Err> {
+        if self.root.is_read_only() {
+            return Err(O::Err::read_only());
+        }
+
+        let mut removed = 0;
+        let mut result = Ok(());
+        let disk = &self.root.disk;
+        self.root.findings.retain(|finding| {
+            let (Finding::DuplicateMetadataEntry {
+                entry_type,
+                location,
+            }
+            | Finding::InvalidMetadataEntry {
+                entry_type,
+                location,
+            }) = finding
+            else {
+                return true;
+            };
+            if result.is_err() {
+                return true;
+            }
+
+            // clearing the `InUse` bit marks the entry as unused
+            result = disk.write_all_at(location.offset, &[entry_type & 0x7F]);
+            removed += result.is_ok() as usize;
+            result.is_err()
+        });
+
+        result.map(|_| removed)
+    }
+
+    /// Writes the boot sector at the given sector offset and recomputes the checksum of the
+    /// boot region from the sectors following it.
+    fn write_boot_region(&self, boot: &BootSector, offset_sectors: u64) -> Result<(), O::Err> {
+        let bytes_per_sector = boot.bytes_per_sector();
+        let disk = &self.root.disk;
+        let mut checksum = Checksum::new(bytes_per_sector);
+
+        let mut sector = vec![0u8; bytes_per_sector as usize];
+        let raw = Endify::to_le(*boot);
+        sector[..size_of::<BootSector>()].copy_from_slice(bytes_of(&raw));
+        disk.write_all_at(offset_sectors * bytes_per_sector as u64, &sector)?;
+        checksum.boot_sector(&sector);
+
+        for i in 1..CHECKSUMMED_SECTORS {
+            disk.read_exact((offset_sectors + i) * bytes_per_sector as u64, &mut sector)?;
+            checksum.extended_boot_sector(&sector, 1);
+        }
+
+        let checksum_sector: Vec<u8> = checksum
+            .get()
+            .to_ne_bytes()
+            .into_iter()
+            .cycle()
+            .take(bytes_per_sector as usize)
+            .collect();
+        disk.write_all_at(
+            (offset_sectors + CHECKSUMMED_SECTORS) * bytes_per_sector as u64,
+            &checksum_sector,
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn raw_cluster_access() {
+    use crate::{fs::FsElement, test_utils::TestImage};
+    use std::{io::Read, sync::Mutex};
+
+    let image = TestImage::sample();
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+
+    let mut buffer = vec![0u8; volume.bytes_per_cluster() as usize + 1];
+    assert_eq!(
+        volume.read_cluster(100, &mut buffer).unwrap(),
+        volume.bytes_per_cluster() as usize
+    );
+    assert_eq!(&buffer[..13], b"Hello, World!");
+
+    volume.write_cluster(100, b"Howdy").unwrap();
+
+    let FsElement::F(file) = &mut volume.root().items()[0] else {
+        panic!("expected a file");
+    };
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "Howdy, World!");
+
+    assert!(volume.read_cluster(1, &mut buffer).is_err());
+    assert!(
+        volume
+            .read_cluster(volume.cluster_count() + 2, &mut buffer)
+            .is_err()
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn relaxed_volume_is_read_only() {
+    use crate::{
+        root::{BootSectorRelaxations, OpenOptionsBuilder},
+        test_utils::TestImage,
+    };
+    use std::sync::Mutex;
+
+    let mut image = TestImage::sample();
+    image.image[..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+
+    let options = OpenOptionsBuilder::default()
+        .strict_boot_sector(true)
+        .relaxations(BootSectorRelaxations::JUMP_BOOT)
+        .build()
+        .unwrap();
+    let volume = Volume::open_with(Mutex::new(image.cursor()), options).unwrap();
+
+    let err = volume.write_cluster(100, b"Howdy").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::ReadOnlyFilesystem);
+}
+
+#[cfg(test)]
+#[test]
+fn refresh_flags() {
+    use crate::test_utils::TestImage;
+    use std::sync::Mutex;
+
+    let mut image = TestImage::sample();
+    image.image[106] |= VolumeFlags::VOLUME_DIRTY.bits() as u8;
+    let expected: Vec<u8> = image.image[11 * 512..12 * 512].to_vec();
+
+    // mark the clusters of the sample files as allocated
+    let bitmap_offset = image.cluster_offset(2);
+    image.image[bitmap_offset + 12] |= 0b0001_1100; // clusters 100 to 102
+
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+    let used = 4 + 3;
+    let percent = volume.refresh_flags().unwrap();
+    assert_eq!(percent as u64, used * 100 / volume.cluster_count() as u64);
+
+    let image = volume.root.disk.lock().unwrap().get_ref().clone();
+    for region in BOOT_REGIONS {
+        let offset = region as usize * 512;
+        assert_eq!(image[offset + 112], percent);
+        assert_eq!(
+            image[offset + 106] & VolumeFlags::VOLUME_DIRTY.bits() as u8,
+            0
+        );
+        // flags and percentage are not covered by the checksum
+        assert_eq!(image[offset + 11 * 512..offset + 12 * 512], expected);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn update_percent_in_use() {
+    use crate::format::{Exfat, FormatVolumeOptionsBuilder};
+    use std::{io::Cursor, sync::Mutex, time::SystemTime};
+
+    let size: u64 = 4 * crate::MB as u64;
+    let format_options = FormatVolumeOptionsBuilder::default()
+        .dev_size(size)
+        .bytes_per_sector(512)
+        .bytes_per_cluster(Some(4 * crate::KB as u32))
+        .build()
+        .unwrap();
+    let mut f = Cursor::new(vec![0u8; size as usize]);
+    let mut formatter = Exfat::try_from::<SystemTime>(format_options).unwrap();
+    formatter.write::<SystemTime, _>(&mut f).unwrap();
+
+    // formatting already records the clusters used by the volume metadata
+    let mut volume = Volume::open(Mutex::new(f)).unwrap();
+    let formatted = volume.root.boot.percent_in_use;
+    assert_eq!(formatted, volume.percent_in_use().unwrap());
+    assert_eq!(volume.update_percent_in_use().unwrap(), formatted);
+
+    let data = vec![7u8; size as usize / 4];
+    volume
+        .create_files::<SystemTime, _, _>("", [("quarter.bin", &data[..])])
+        .unwrap();
+    let percent = volume.update_percent_in_use().unwrap();
+    assert!(percent >= formatted + 25);
+    assert_eq!(volume.root.boot.percent_in_use, percent);
+
+    let image = volume.root.disk.lock().unwrap().get_ref().clone();
+    for region in BOOT_REGIONS {
+        assert_eq!(image[region as usize * 512 + 112], percent);
+    }
+    assert!(Volume::open(Mutex::new(Cursor::new(image))).is_ok());
+}
+
+#[cfg(test)]
+#[test]
+fn duplicate_metadata_entries() {
+    use crate::{
+        entry::{DirEntry, VolumeGuidEntry},
+        error::RootError,
+        root::{OpenOptionsBuilder, Root},
+        test_utils::TestImage,
+    };
+    use std::sync::Mutex;
+
+    // duplicate the volume label and the allocation bitmap
+    let mut image = TestImage::sample();
+    let root = image.cluster_offset(image.root_cluster());
+    let label: Vec<u8> = image.image[root..root + 32].to_vec();
+    let bitmap: Vec<u8> = image.image[root + 64..root + 96].to_vec();
+    image.add_raw_entry(image.root_cluster(), &label);
+    image.add_raw_entry(image.root_cluster(), &bitmap);
+
+    assert!(matches!(
+        Root::open(image.cursor()),
+        Err(RootError::InvalidNumberOfVolumeLabels)
+    ));
+
+    let mut image = TestImage::sample();
+    image.add_raw_entry(image.root_cluster(), &label);
+    image.add_raw_entry(image.root_cluster(), &bitmap);
+    // and a volume GUID entry that does not match its checksum
+    let mut guid = DirEntry::VolumeGuid(VolumeGuidEntry::new(0x0123_4567_89ab_cdef)).bytes();
+    guid[6] ^= 0xff;
+    image.add_raw_entry(image.root_cluster(), &guid);
+
+    let options = OpenOptionsBuilder::default()
+        .recover_duplicates(true)
+        .build()
+        .unwrap();
+    let mut volume = Volume::open_with(Mutex::new(image.cursor()), options).unwrap();
+    let findings = volume.root().findings();
+    assert_eq!(findings.len(), 3);
+    assert!(matches!(
+        findings,
+        [
+            Finding::DuplicateMetadataEntry { .. },
+            Finding::DuplicateMetadataEntry { .. },
+            Finding::InvalidMetadataEntry { .. }
+        ]
+    ));
+
+    assert_eq!(volume.remove_duplicate_entries().unwrap(), 3);
+    assert!(volume.root().findings().is_empty());
+
+    // the cleaned volume opens in strict mode again
+    let image = volume.root.disk.lock().unwrap().clone();
+    let mut root = Root::open(image).unwrap();
+    assert_eq!(root.items().len(), 2);
+}
+
+#[cfg(test)]
+#[test]
+fn mirrored_allocation_bitmap() {
+    use crate::{error::ClusterChainError, test_utils::TestImage};
+    use std::{io::Cursor, sync::Mutex, time::SystemTime};
+
+    // turn the image into a TexFAT volume using the second FAT and bitmap
+    let mut image = TestImage::sample();
+    let boot = image.boot;
+    let fat_offset = boot.fat_offset as usize * 512;
+    let fat_length = boot.fat_length as usize * 512;
+    let bitmap_length = boot.cluster_count.div_ceil(8) as usize;
+    let root = image.cluster_offset(image.root_cluster());
+    image.image[110] = 2;
+    image.image[106] |= VolumeFlags::ACTIVE_FAT.bits() as u8;
+
+    let mut entry: Vec<u8> = image.image[root + 64..root + 96].to_vec();
+    entry[1] = 1;
+    entry[20..24].copy_from_slice(&60u32.to_le_bytes());
+    image.add_raw_entry(image.root_cluster(), &entry);
+    image.image[fat_offset + 60 * 4..fat_offset + 61 * 4].fill(0xFF);
+    let bitmap = image.cluster_offset(2);
+    let second = image.cluster_offset(60);
+    // the clusters of the sample tree and the second bitmap
+    image.image[bitmap + 12] |= 0b0001_1100;
+    image.image[bitmap + 7] |= 0b0100_0000;
+    image
+        .image
+        .copy_within(bitmap..bitmap + bitmap_length, second);
+    image
+        .image
+        .copy_within(fat_offset..fat_offset + fat_length, fat_offset + fat_length);
+
+    // a mirror too short for the bitmap is rejected instead of being written past its end
+    let mut short = image.image.clone();
+    short[root + 64 + 24..root + 64 + 32].copy_from_slice(&1u64.to_le_bytes());
+    let mut volume = Volume::open(Mutex::new(Cursor::new(short))).unwrap();
+    assert!(matches!(
+        volume.write_atomic::<SystemTime>("dir/new.txt", b"data"),
+        Err(WriteError::ClusterChain(
+            ClusterChainError::InvalidDataLength
+        ))
+    ));
+
+    // volumes are checked against the bitmap of the active FAT
+    let mut inactive = image.image.clone();
+    inactive[bitmap + 20] = 0xFF;
+    let map = Root::open(Cursor::new(inactive))
+        .unwrap()
+        .cluster_map()
+        .unwrap();
+    assert!(!map.bitmap.is_allocated(2 + 20 * 8));
+
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+    assert_eq!(volume.root.allocation_bitmaps.len(), 2);
+    volume
+        .write_atomic::<SystemTime>("dir/new.txt", &[1u8; 10000])
+        .unwrap();
+    // replacing the contents frees the old clusters
+    volume
+        .write_atomic::<SystemTime>("hello.txt", b"Hello again!")
+        .unwrap();
+
+    let image = volume.root.disk.lock().unwrap().get_ref().clone();
+    assert_eq!(
+        image[bitmap..bitmap + bitmap_length],
+        image[second..second + bitmap_length]
+    );
+    assert_eq!(
+        image[fat_offset..fat_offset + fat_length],
+        image[fat_offset + fat_length..fat_offset + 2 * fat_length]
+    );
+    // the old cluster of `hello.txt` is free again
+    assert_eq!(image[second + 12] & 0b0000_0100, 0);
+}
+
+#[cfg(test)]
+#[test]
+fn dirty_flag() {
+    use crate::test_utils::TestImage;
+    use std::{io::Cursor, sync::Mutex, time::SystemTime};
+
+    let device = Mutex::new(TestImage::sample().cursor());
+    let flags = |device: &Mutex<Cursor<Vec<u8>>>, sector: usize| {
+        let image = device.lock().unwrap();
+        VolumeFlags::from_bits_retain(image.get_ref()[sector * 512 + 106] as u16)
+    };
+
+    let mut volume = Volume::open_rw(&device).unwrap();
+    assert!(!volume.was_dirty());
+    assert!(volume.root().is_dirty());
+    assert!(flags(&device, 0).contains(VolumeFlags::VOLUME_DIRTY));
+    // the backup boot sector is left untouched
+    assert!(!flags(&device, 12).contains(VolumeFlags::VOLUME_DIRTY));
+
+    volume.flush().unwrap();
+    assert!(!flags(&device, 0).contains(VolumeFlags::VOLUME_DIRTY));
+    // modifications mark the volume again
+    volume.create_dir::<SystemTime>("photos").unwrap();
+    assert!(flags(&device, 0).contains(VolumeFlags::VOLUME_DIRTY));
+
+    // volumes which were not closed are reported
+    let volume = Volume::open_rw(&device).unwrap();
+    assert!(volume.was_dirty());
+    volume.close().unwrap();
+    assert!(!flags(&device, 0).contains(VolumeFlags::VOLUME_DIRTY));
+    assert!(!Volume::open(&device).unwrap().root().is_dirty());
+}
@@ -0,0 +1,148 @@
+use alloc::{boxed::Box, string::String};
+
+use crate::disk::ReadOffset;
+
+use super::{Volume, write::WriteState};
+
+/// A change of the directory tree, reported once it has been written to the device. Paths are
+/// relative to the root directory and separated by `/`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DirectoryChange {
+    /// A file or directory was created.
+    Created(String),
+    /// A file or directory was removed.
+    Removed(String),
+    /// A file or directory was moved to another path.
+    Renamed { from: String, to: String },
+    /// The contents of an existing file were replaced.
+    Modified(String),
+}
+
+/// Callback receiving [`DirectoryChange`]s.
+pub(crate) type ChangeHook = Box<dyn FnMut(&DirectoryChange) + Send>;
+
+impl<O: ReadOffset> Volume<O> {
+    /// Registers a callback which is invoked for every change of the directory tree made through
+    /// this volume or the file and directory handles read from it, right after the change has
+    /// been committed. Replaces any previous callback. The callback runs while the volume is
+    /// locked for writing, so it must not modify the volume itself.
+    pub fn on_change(&mut self, hook: impl FnMut(&DirectoryChange) + Send + 'static) {
+        self.root.writes.lock().change_hook = Some(Box::new(hook));
+    }
+
+    /// Removes the callback registered with [`Volume::on_change`].
+    pub fn remove_change_hook(&mut self) {
+        self.root.writes.lock().change_hook = None;
+    }
+
+    /// Reports committed changes to the registered callback, if any.
+    pub(crate) fn notify(&mut self, changes: impl IntoIterator<Item = DirectoryChange>) {
+        self.root.writes.lock().notify(changes);
+    }
+}
+
+impl WriteState {
+    /// Reports committed changes to the registered callback, if any.
+    pub(crate) fn notify(&mut self, changes: impl IntoIterator<Item = DirectoryChange>) {
+        if let Some(hook) = &mut self.change_hook {
+            changes.into_iter().for_each(|change| hook(&change));
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn change_notifications() {
+    use crate::{repair::RecoveredData, test_utils::TestImage};
+    use alloc::{string::ToString, vec::Vec};
+    use std::{
+        sync::{Arc, Mutex},
+        time::SystemTime,
+    };
+
+    let mut image = TestImage::sample();
+    image.write_clusters(200, b"orphaned");
+    let mut volume = Volume::open(Mutex::new(image.cursor())).unwrap();
+
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&changes);
+    volume.on_change(move |change| recorded.lock().unwrap().push(change.clone()));
+
+    volume
+        .write_atomic::<SystemTime>("dir/a.txt", b"a")
+        .unwrap();
+    volume
+        .write_atomic::<SystemTime>("/dir/a.txt", b"b")
+        .unwrap();
+    volume
+        .materialize_lost::<SystemTime>(&[RecoveredData {
+            first_cluster: 200,
+            data_len: 8,
+            contiguous: true,
+        }])
+        .unwrap();
+
+    assert_eq!(
+        *changes.lock().unwrap(),
+        [
+            DirectoryChange::Created("dir/a.txt".to_string()),
+            DirectoryChange::Modified("dir/a.txt".to_string()),
+            DirectoryChange::Created("LOST.DIR".to_string()),
+            DirectoryChange::Created("LOST.DIR/FILE0000.CHK".to_string()),
+        ]
+    );
+
+    // failed writes are not reported
+    changes.lock().unwrap().clear();
+    assert!(volume.write_atomic::<SystemTime>("dir", b"").is_err());
+    volume.remove_change_hook();
+    volume.write_atomic::<SystemTime>("b.txt", b"b").unwrap();
+    assert!(changes.lock().unwrap().is_empty());
+}
+
+#[cfg(test)]
+#[test]
+fn handle_change_notifications() {
+    use crate::{fs::FsElement, test_utils::TestImage};
+    use alloc::{string::ToString, vec::Vec};
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+        time::SystemTime,
+    };
+
+    let mut volume = Volume::open_rw(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&changes);
+    volume.on_change(move |change| recorded.lock().unwrap().push(change.clone()));
+
+    let Some(FsElement::D(mut dir)) = volume.root().open_path("dir").unwrap() else {
+        panic!("dir not found");
+    };
+    let mut sub = dir.create_dir::<SystemTime>("sub").unwrap();
+    let mut file = sub.create_file::<SystemTime>("a.txt").unwrap();
+    file.write_all(b"contents").unwrap();
+    file.set_len(3).unwrap();
+    sub.rename("a.txt", "a much longer name.txt").unwrap();
+    sub.move_to(&mut dir, "a much longer name.txt").unwrap();
+    volume.root().create_file::<SystemTime>("top.txt").unwrap();
+
+    assert_eq!(
+        *changes.lock().unwrap(),
+        [
+            DirectoryChange::Created("dir/sub".to_string()),
+            DirectoryChange::Created("dir/sub/a.txt".to_string()),
+            DirectoryChange::Modified("dir/sub/a.txt".to_string()),
+            DirectoryChange::Modified("dir/sub/a.txt".to_string()),
+            DirectoryChange::Renamed {
+                from: "dir/sub/a.txt".to_string(),
+                to: "dir/sub/a much longer name.txt".to_string()
+            },
+            DirectoryChange::Renamed {
+                from: "dir/sub/a much longer name.txt".to_string(),
+                to: "dir/a much longer name.txt".to_string()
+            },
+            DirectoryChange::Created("top.txt".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,201 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    disk::{PartitionError, ReadOffset, WriteOffset},
+    entry::{
+        FileAttributes,
+        set::{Allocation, EntrySet},
+    },
+    error::WriteError,
+    fs::FsElement,
+    name::validate_name,
+    timestamp::{Clock, Timestamps},
+};
+
+use super::{DirectoryChange, Volume, replace::stream_allocation};
+
+impl<O: ReadOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Reads the entire contents of the file at `path` (separated by `/`).
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, WriteError<O>> {
+        if self.root.is_stale() {
+            return Err(WriteError::Io(O::Err::stale_handle()));
+        }
+
+        match self.root.open_path(path)? {
+            Some(FsElement::F(file)) => file.contents().map_err(WriteError::Io),
+            Some(FsElement::D(_)) => Err(WriteError::IsADirectory(String::from(path))),
+            None => Err(WriteError::NotFound(String::from(path))),
+        }
+    }
+}
+
+impl<O: WriteOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Opens the volume stored on the given device for writing. Unlike [`Volume::open`], this
+    /// fails for volumes that must not be modified (see
+    /// [`Root::is_read_only`](crate::root::Root::is_read_only)), and the allocation bitmap is
+    /// loaded right away. The volume is marked dirty until it is flushed or closed (see
+    /// [`Volume::flush`]); [`Volume::was_dirty`] tells whether it was marked before.
+    ///
+    /// Files and directories can then be accessed by path: [`Volume::read`],
+    /// [`Volume::write_atomic`], [`Volume::create_dir`] and [`Volume::remove`].
+    pub fn open_rw(device: O) -> Result<Self, WriteError<O>> {
+        let mut volume = Volume::open(device)?;
+        volume.track_dirty = true;
+        volume.ensure_writable()?;
+        volume.writer().bitmap()?;
+        Ok(volume)
+    }
+
+    /// Creates an empty directory at `path` (separated by `/`), whose parent must exist.
+    /// Entries are timestamped using `T`.
+    pub fn create_dir<T: Clock>(&mut self, path: &str) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+        self.refresh_if_stale()?;
+
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let path = path.trim_matches('/');
+        let (parent_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let units = validate_name(name)?;
+        let (mut parent, items) = self.resolve_directory(parent_path, &upcase_table)?;
+        if items
+            .iter()
+            .any(|item| item.name == upcase_table.upcase_name(&units))
+        {
+            return Err(WriteError::AlreadyExists(String::from(path)));
+        }
+
+        let mut volume = self.usage_scope(path);
+        let cluster = volume.writer().allocate_contiguous(1)?;
+        volume.writer().zero_cluster(cluster)?;
+        let set = EntrySet::new(
+            &units,
+            FileAttributes::DIRECTORY,
+            &Timestamps::now::<T>(),
+            Allocation {
+                first_cluster: cluster,
+                data_len: volume.bytes_per_cluster() as u64,
+                no_fat_chain: true,
+            },
+            &upcase_table,
+        );
+        volume
+            .writer()
+            .insert_entry_set(&mut parent, &set.raw_entries())?;
+
+        volume.root.reload()?;
+        volume.notify([DirectoryChange::Created(String::from(path))]);
+        Ok(())
+    }
+
+    /// Removes the file or empty directory at `path` (separated by `/`) and releases its
+    /// clusters.
+    pub fn remove(&mut self, path: &str) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+        self.refresh_if_stale()?;
+
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let path = path.trim_matches('/');
+        let (parent_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let (parent, items) = self.resolve_directory(parent_path, &upcase_table)?;
+        let name = upcase_table.upcase_name(&name.encode_utf16().collect::<Vec<_>>());
+        let target = items
+            .iter()
+            .find(|item| item.name == name)
+            .ok_or_else(|| WriteError::NotFound(String::from(path)))?;
+        if target.directory && !self.resolve_directory(path, &upcase_table)?.1.is_empty() {
+            return Err(WriteError::DirectoryNotEmpty(String::from(path)));
+        }
+
+        let entries = self
+            .root
+            .read_entry_set(target.location)
+            .map_err(WriteError::Io)?;
+        if entries.len() < 2 {
+            return Err(WriteError::CorruptEntrySet(target.location));
+        }
+        let offsets = self
+            .writer()
+            .entry_offsets(&parent, target.location, entries.len())
+            .ok_or(WriteError::CorruptEntrySet(target.location))?;
+        let removed: Vec<[u8; 32]> = entries
+            .iter()
+            .map(|entry| {
+                let mut entry = *entry;
+                entry[0] &= 0x7F;
+                entry
+            })
+            .collect();
+
+        let top_level = parent_path.is_empty() && target.directory;
+        {
+            let mut volume = self.usage_scope(if target.directory { path } else { parent_path });
+            volume.writer().write_slots(&offsets, &removed)?;
+            volume
+                .writer()
+                .free_allocation(stream_allocation(&entries[1]))?;
+        }
+        if top_level {
+            self.forget_usage(path);
+        }
+
+        self.root.reload()?;
+        self.notify([DirectoryChange::Removed(String::from(path))]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn path_access() {
+    use crate::test_utils::TestImage;
+    use std::{io::Cursor, sync::Mutex, time::SystemTime};
+
+    let mut volume = Volume::open_rw(Mutex::new(TestImage::sample().cursor())).unwrap();
+    assert_eq!(volume.read("dir/nested.txt").unwrap(), b"nested");
+    assert!(matches!(
+        volume.read("dir"),
+        Err(WriteError::IsADirectory(_))
+    ));
+    assert!(matches!(
+        volume.read("hello.txt/inner"),
+        Err(WriteError::NotFound(_))
+    ));
+
+    volume.create_dir::<SystemTime>("dir/sub").unwrap();
+    volume
+        .write_atomic::<SystemTime>("dir/sub/new.txt", b"new contents")
+        .unwrap();
+    assert_eq!(volume.read("DIR/SUB/NEW.TXT").unwrap(), b"new contents");
+    assert!(matches!(
+        volume.create_dir::<SystemTime>("dir/sub"),
+        Err(WriteError::AlreadyExists(_))
+    ));
+    assert!(matches!(
+        volume.remove("dir/sub"),
+        Err(WriteError::DirectoryNotEmpty(_))
+    ));
+
+    let allocated_before = volume.writer().bitmap().unwrap().allocated_count();
+    volume.remove("dir/sub/new.txt").unwrap();
+    volume.remove("dir/sub").unwrap();
+    assert_eq!(
+        volume.writer().bitmap().unwrap().allocated_count(),
+        allocated_before - 2
+    );
+    assert!(matches!(
+        volume.read("dir/sub/new.txt"),
+        Err(WriteError::NotFound(_))
+    ));
+
+    // the changes persist
+    let image = volume.root.disk.lock().unwrap().get_ref().clone();
+    let volume = Volume::open(Cursor::new(image)).unwrap();
+    assert_eq!(volume.read("hello.txt").unwrap(), b"Hello, World!");
+    assert!(volume.read("dir/sub").is_err());
+}
@@ -0,0 +1,369 @@
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    disk::{PartitionError, WriteOffset},
+    entry::{
+        FileAttributes,
+        set::{Allocation, EntrySet},
+    },
+    error::WriteError,
+    fs::{EntryLocation, FsElement},
+    name::{UpcaseTable, validate_name},
+    timestamp::{Clock, Timestamps},
+};
+
+use super::{DirectoryChange, Volume, write::DirectoryClusters};
+
+/// Suffix appended to the name of the temporary file written by [`Volume::write_atomic`].
+const TEMP_SUFFIX: &str = ".tmp~";
+
+/// An element of a directory, as needed to replace it.
+pub(super) struct Existing {
+    /// Up-cased name.
+    pub(super) name: Vec<u16>,
+    pub(super) location: EntryLocation,
+    pub(super) directory: bool,
+}
+
+impl<O: WriteOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Replaces the contents of the file at `path` (separated by `/`) with `data`, creating it if
+    /// it does not exist yet. The parent directory must exist.
+    ///
+    /// The data is first written to fresh clusters referenced by a temporary file next to the
+    /// target. Only then is the target switched over to the new clusters, by renaming the
+    /// temporary file, or by removing it and then rewriting the entry set of the target. The
+    /// device is flushed between these steps, so after a crash the target holds either its old or
+    /// its new contents, and no two entry sets ever reference the same clusters. A crash right
+    /// after removing the temporary file leaves its clusters allocated without being referenced.
+    ///
+    /// Entries are timestamped using `T`; if the clock is unavailable, the exFAT epoch is used.
+    /// The change is reported to the [`Volume::on_change`] callback with `path` as given.
+    pub fn write_atomic<T: Clock>(&mut self, path: &str, data: &[u8]) -> Result<(), WriteError<O>> {
+        self.ensure_writable()?;
+        self.refresh_if_stale()?;
+
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let path = path.trim_matches('/');
+        let (parent_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let name_units = validate_name(name)?;
+
+        let (mut parent, items) = self.resolve_directory(parent_path, &upcase_table)?;
+        let mut volume = self.usage_scope(parent_path);
+        let target = items
+            .iter()
+            .find(|item| item.name == upcase_table.upcase_name(&name_units));
+        if target.is_some_and(|target| target.directory) {
+            return Err(WriteError::IsADirectory(String::from(path)));
+        }
+
+        let timestamps = Timestamps::now::<T>();
+
+        // new contents
+        let bytes_per_cluster = volume.bytes_per_cluster() as u64;
+        let clusters = (data.len() as u64).div_ceil(bytes_per_cluster) as u32;
+        let first_cluster = if clusters == 0 {
+            0
+        } else {
+            let first = volume.writer().allocate_contiguous(clusters)?;
+            let offset = volume
+                .cluster_offset(first)
+                .ok_or(WriteError::Io(O::Err::cluster_not_found(first)))?;
+            volume
+                .root
+                .disk
+                .write_all_at(offset, data)
+                .map_err(WriteError::Io)?;
+            first
+        };
+        let allocation = Allocation {
+            first_cluster,
+            data_len: data.len() as u64,
+            no_fat_chain: true,
+        };
+        volume.barrier()?;
+
+        // temporary file referencing the new contents
+        let mut counter = 0u32;
+        let temp_name = loop {
+            let candidate = match counter {
+                0 => format!("{name}{TEMP_SUFFIX}"),
+                _ => format!("{name}{TEMP_SUFFIX}{counter}"),
+            };
+            counter += 1;
+
+            let units = validate_name(&candidate)?;
+            let upcased = upcase_table.upcase_name(&units);
+            if !items.iter().any(|item| item.name == upcased) {
+                break units;
+            }
+        };
+        let temp = EntrySet::new(
+            &temp_name,
+            FileAttributes::ARCHIVE,
+            &timestamps,
+            allocation,
+            &upcase_table,
+        );
+        let temp_location = volume
+            .writer()
+            .insert_entry_set(&mut parent, &temp.raw_entries())?;
+        let temp_offsets = volume
+            .writer()
+            .entry_offsets(&parent, temp_location, temp.len())
+            .ok_or(WriteError::CorruptEntrySet(temp_location))?;
+        volume.barrier()?;
+
+        // switch over
+        let new = EntrySet::new(
+            &name_units,
+            FileAttributes::ARCHIVE,
+            &timestamps,
+            allocation,
+            &upcase_table,
+        )
+        .raw_entries();
+
+        match target {
+            Some(target) => {
+                let entries = volume
+                    .root
+                    .read_entry_set(target.location)
+                    .map_err(WriteError::Io)?;
+                let offsets = volume
+                    .writer()
+                    .entry_offsets(&parent, target.location, entries.len())
+                    .ok_or(WriteError::CorruptEntrySet(target.location))?;
+                if entries.len() < 2 {
+                    return Err(WriteError::CorruptEntrySet(target.location));
+                }
+                let old = stream_allocation(&entries[1]);
+
+                // the clusters must never be referenced by both entry sets
+                let removed: Vec<[u8; 32]> = temp
+                    .raw_entries()
+                    .into_iter()
+                    .map(|mut entry| {
+                        entry[0] &= 0x7F;
+                        entry
+                    })
+                    .collect();
+                volume.writer().write_slots(&temp_offsets, &removed)?;
+                volume.barrier()?;
+
+                volume.writer().update_entry_set(&offsets, |entries| {
+                    // last modified timestamp, 10ms increment and UTC offset
+                    entries[0][12..16].copy_from_slice(&new[0][12..16]);
+                    entries[0][21] = new[0][21];
+                    entries[0][23] = new[0][23];
+                    // flags, lengths and first cluster; name length and hash stay the same
+                    entries[1][1] = new[1][1];
+                    entries[1][8..32].copy_from_slice(&new[1][8..32]);
+                })?;
+                volume.barrier()?;
+                volume.writer().free_allocation(old)?;
+            }
+            None => {
+                // rename the temporary file in place, the name never needs more entries
+                let renamed: Vec<[u8; 32]> = temp
+                    .raw_entries()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, mut entry)| {
+                        if let Some(new) = new.get(i) {
+                            *new
+                        } else {
+                            entry[0] &= 0x7F;
+                            entry
+                        }
+                    })
+                    .collect();
+                volume.writer().write_slots(&temp_offsets, &renamed)?;
+            }
+        }
+        volume.barrier()?;
+
+        volume.root.reload()?;
+        volume.notify([match target {
+            Some(_) => DirectoryChange::Modified(String::from(path)),
+            None => DirectoryChange::Created(String::from(path)),
+        }]);
+        Ok(())
+    }
+
+    /// Clusters and elements of the directory at the given path.
+    pub(super) fn resolve_directory(
+        &self,
+        path: &str,
+        upcase_table: &UpcaseTable,
+    ) -> Result<(DirectoryClusters, Vec<Existing>), WriteError<O>> {
+        let upcased =
+            |name: &str| upcase_table.upcase_name(&name.encode_utf16().collect::<Vec<_>>());
+
+        let mut clusters = DirectoryClusters::root(&self.root)?;
+        let mut opened: Option<Vec<FsElement<O>>> = None;
+
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            let items = opened.as_deref().unwrap_or(&self.root.items);
+            let item = items
+                .iter()
+                .find(|item| upcased(item.name()) == upcased(component))
+                .ok_or_else(|| WriteError::NotFound(String::from(component)))?;
+            let FsElement::D(directory) = item else {
+                return Err(WriteError::NotADirectory(String::from(component)));
+            };
+
+            let next = DirectoryClusters::of(directory)?;
+            let items = directory.open()?;
+            clusters = next;
+            opened = Some(items);
+        }
+
+        let items = opened.as_deref().unwrap_or(&self.root.items);
+        let existing = items
+            .iter()
+            .map(|item| match item {
+                FsElement::F(file) => Existing {
+                    name: upcased(file.name()),
+                    location: file.location(),
+                    directory: false,
+                },
+                FsElement::D(dir) => Existing {
+                    name: upcased(dir.name()),
+                    location: dir.location(),
+                    directory: true,
+                },
+            })
+            .collect();
+        Ok((clusters, existing))
+    }
+
+    /// Makes sure the previous writes reached the device before continuing.
+    fn barrier(&self) -> Result<(), WriteError<O>> {
+        self.root.disk.flush().map_err(WriteError::Io)
+    }
+}
+
+/// Allocation described by a raw stream extension entry.
+pub(super) fn stream_allocation(stream: &[u8; 32]) -> Allocation {
+    Allocation {
+        first_cluster: u32::from_le_bytes([stream[20], stream[21], stream[22], stream[23]]),
+        data_len: u64::from_le_bytes(stream[24..32].try_into().unwrap_or_default()),
+        no_fat_chain: stream[1] & 0x2 != 0,
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn atomic_replace() {
+    use crate::{name::Normalization, test_utils::TestImage};
+    use std::{io::Read, sync::Mutex, time::SystemTime};
+
+    let mut volume = Volume::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let read = |volume: &mut Volume<_>, name: &str| {
+        let Some(FsElement::D(dir)) = volume.root().find("dir", Normalization::None) else {
+            panic!("directory not found");
+        };
+        let Some(FsElement::F(mut file)) = dir.find(name, Normalization::None).unwrap() else {
+            panic!("{name} not found");
+        };
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        (contents, dir.open().unwrap().len())
+    };
+    let first_cluster = |volume: &mut Volume<_>| {
+        let Some(FsElement::D(dir)) = volume.root().find("dir", Normalization::None) else {
+            panic!("directory not found");
+        };
+        let Some(FsElement::F(file)) = dir.find("config.txt", Normalization::None).unwrap() else {
+            panic!("config.txt not found");
+        };
+        let entries = volume.root.read_entry_set(file.location()).unwrap();
+        stream_allocation(&entries[1]).first_cluster
+    };
+
+    volume
+        .write_atomic::<SystemTime>("dir/config.txt", b"v1")
+        .unwrap();
+    assert_eq!(read(&mut volume, "config.txt"), (b"v1".to_vec(), 2));
+    let old = first_cluster(&mut volume);
+
+    let contents = vec![7u8; 5000];
+    volume
+        .write_atomic::<SystemTime>("/dir/CONFIG.TXT", &contents)
+        .unwrap();
+    // the existing entry keeps its name, the temporary file is gone
+    assert_eq!(read(&mut volume, "config.txt"), (contents, 2));
+
+    // the clusters of the first version were released
+    assert_ne!(first_cluster(&mut volume), old);
+    assert!(!volume.writer().bitmap().unwrap().is_allocated(old));
+
+    volume.write_atomic::<SystemTime>("hello.txt", b"").unwrap();
+    let Some(FsElement::F(file)) = volume.root().find("hello.txt", Normalization::None) else {
+        panic!("hello.txt not found");
+    };
+    assert!(file.is_empty());
+
+    assert!(matches!(
+        volume.write_atomic::<SystemTime>("missing/a", b""),
+        Err(WriteError::NotFound(_))
+    ));
+    assert!(matches!(
+        volume.write_atomic::<SystemTime>("hello.txt/a", b""),
+        Err(WriteError::NotADirectory(_))
+    ));
+    assert!(matches!(
+        volume.write_atomic::<SystemTime>("dir", b""),
+        Err(WriteError::IsADirectory(_))
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn atomic_replace_never_cross_links() {
+    use crate::{disk::ReadOffset, test_utils::TestImage};
+    use std::{io::Cursor, sync::Mutex, time::SystemTime};
+
+    /// Device keeping a copy of its contents at every flush, the states a crash can leave.
+    #[derive(Debug)]
+    struct Snapshots(Mutex<Cursor<Vec<u8>>>, Mutex<Vec<Vec<u8>>>);
+    impl ReadOffset for Snapshots {
+        type Err = std::io::Error;
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Self::Err> {
+            self.0.read_at(offset, buf)
+        }
+    }
+    impl WriteOffset for Snapshots {
+        fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Self::Err> {
+            self.0.write_at(offset, buf)
+        }
+        fn flush(&self) -> Result<(), Self::Err> {
+            let image = self.0.lock().unwrap().get_ref().clone();
+            self.1.lock().unwrap().push(image);
+            Ok(())
+        }
+    }
+
+    let mut volume = Volume::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    volume
+        .write_atomic::<SystemTime>("dir/config.txt", b"v1")
+        .unwrap();
+    let image = volume.root.disk.lock().unwrap().get_ref().clone();
+
+    let device = Snapshots(Mutex::new(Cursor::new(image)), Mutex::new(Vec::new()));
+    let mut volume = Volume::open(device).unwrap();
+    volume
+        .write_atomic::<SystemTime>("dir/config.txt", &[7u8; 5000])
+        .unwrap();
+
+    let snapshots = core::mem::take(&mut *volume.root.disk.1.lock().unwrap());
+    assert!(snapshots.len() >= 4);
+    for snapshot in snapshots {
+        let volume = Volume::open(Cursor::new(snapshot)).unwrap();
+        assert_eq!(volume.find_cross_links().unwrap(), []);
+    }
+}
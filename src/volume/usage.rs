@@ -0,0 +1,259 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::ops::{Deref, DerefMut};
+
+use crate::{
+    disk::ReadOffset,
+    error::DirectoryError,
+    fs::{FsElement, directory::Directory},
+};
+
+use super::Volume;
+
+/// Bytes allocated below each top-level directory.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UsageTracker {
+    bytes: BTreeMap<String, u64>,
+}
+
+impl UsageTracker {
+    /// Accounts clusters that changed their allocation state to the top-level directory `scope`.
+    pub(crate) fn account(&mut self, scope: &str, bytes: u64, allocated: bool) {
+        let used = self.bytes.entry(String::from(scope)).or_default();
+        *used = if allocated {
+            used.saturating_add(bytes)
+        } else {
+            used.saturating_sub(bytes)
+        };
+    }
+}
+
+/// Accounts the clusters allocated and freed through a volume to a top-level directory for as
+/// long as it lives, see [`Volume::usage_scope`]. Dereferences to the volume.
+pub(crate) struct UsageScope<'a, O: ReadOffset> {
+    volume: &'a mut Volume<O>,
+}
+
+impl<O: ReadOffset> Deref for UsageScope<'_, O> {
+    type Target = Volume<O>;
+
+    fn deref(&self) -> &Volume<O> {
+        self.volume
+    }
+}
+
+impl<O: ReadOffset> DerefMut for UsageScope<'_, O> {
+    fn deref_mut(&mut self) -> &mut Volume<O> {
+        self.volume
+    }
+}
+
+impl<O: ReadOffset> Drop for UsageScope<'_, O> {
+    fn drop(&mut self) {
+        self.volume.usage_scope = None;
+    }
+}
+
+impl<O: ReadOffset> Volume<O>
+where
+    O::Err: core::fmt::Debug,
+{
+    /// Computes the amount of bytes allocated below each top-level directory, including the
+    /// clusters of the directories themselves, and keeps these numbers up to date on every
+    /// following write through this volume or the handles read from it. Meant to be called right
+    /// after opening the volume, so per-directory budgets can be enforced without rescanning the
+    /// tree.
+    pub fn track_usage(&mut self) -> Result<(), DirectoryError<O>> {
+        let bytes_per_cluster = self.bytes_per_cluster() as u64;
+        let mut bytes = BTreeMap::new();
+        for item in &self.root.items {
+            if let FsElement::D(dir) = item {
+                bytes.insert(
+                    String::from(dir.name()),
+                    directory_bytes(dir, bytes_per_cluster)?,
+                );
+            }
+        }
+
+        self.root.writes.lock().usage = Some(UsageTracker { bytes });
+        Ok(())
+    }
+}
+
+/// Allocated bytes of an element, including everything below it for directories.
+pub(crate) fn element_bytes<O: ReadOffset>(
+    element: &FsElement<O>,
+    bytes_per_cluster: u64,
+) -> Result<u64, DirectoryError<O>>
+where
+    O::Err: core::fmt::Debug,
+{
+    match element {
+        FsElement::F(file) => Ok(file.on_disk_clusters() as u64 * bytes_per_cluster),
+        FsElement::D(dir) => directory_bytes(dir, bytes_per_cluster),
+    }
+}
+
+/// Allocated bytes of a directory and everything below it.
+fn directory_bytes<O: ReadOffset>(
+    dir: &Directory<O>,
+    bytes_per_cluster: u64,
+) -> Result<u64, DirectoryError<O>>
+where
+    O::Err: core::fmt::Debug,
+{
+    let mut used = dir.cluster_reader()?.chain().len() as u64 * bytes_per_cluster;
+    for item in dir.open()? {
+        used += element_bytes(&item, bytes_per_cluster)?;
+    }
+    Ok(used)
+}
+
+impl<O: ReadOffset> Volume<O> {
+    /// Bytes allocated below the given top-level directory (compared case-insensitively), if
+    /// usage is tracked (see [`Volume::track_usage`]) and the directory exists.
+    pub fn directory_usage(&self, name: &str) -> Option<u64> {
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let upcased =
+            |name: &str| upcase_table.upcase_name(&name.encode_utf16().collect::<Vec<_>>());
+
+        self.root
+            .writes
+            .lock()
+            .usage
+            .as_ref()?
+            .bytes
+            .iter()
+            .find(|(dir, _)| upcased(dir) == upcased(name))
+            .map(|(_, bytes)| *bytes)
+    }
+
+    /// Bytes allocated below every top-level directory, if usage is tracked.
+    pub fn usage(&self) -> Option<Vec<(String, u64)>> {
+        let writes = self.root.writes.lock();
+        let usage = writes.usage.as_ref()?;
+        Some(usage.bytes.clone().into_iter().collect())
+    }
+
+    /// Accounts the clusters allocated and freed through the returned scope to the top-level
+    /// directory `path` (separated by `/`) lies in, until the scope is dropped. Elements directly
+    /// in the root directory are not accounted, so `path` is the directory allocations are made
+    /// in or for. Names are resolved to their on-disk spelling.
+    pub(crate) fn usage_scope(&mut self, path: &str) -> UsageScope<'_, O> {
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let upcased =
+            |name: &str| upcase_table.upcase_name(&name.encode_utf16().collect::<Vec<_>>());
+        self.usage_scope = path.split('/').find(|c| !c.is_empty()).map(|name| {
+            self.root
+                .items
+                .iter()
+                .map(|item| item.name())
+                .find(|item| upcased(item) == upcased(name))
+                .map_or_else(|| String::from(name), String::from)
+        });
+        UsageScope { volume: self }
+    }
+
+    /// Stops tracking the usage of the removed top-level directory `name` (compared
+    /// case-insensitively).
+    pub(crate) fn forget_usage(&mut self, name: &str) {
+        let upcase_table = Arc::clone(&self.root.upcase);
+        let upcased =
+            |name: &str| upcase_table.upcase_name(&name.encode_utf16().collect::<Vec<_>>());
+        if let Some(usage) = &mut self.root.writes.lock().usage {
+            usage.bytes.retain(|dir, _| upcased(dir) != upcased(name));
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn usage_per_top_level_directory() {
+    use crate::{repair::RecoveredData, test_utils::TestImage};
+    use std::{sync::Mutex, time::SystemTime};
+
+    let mut volume = Volume::open(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let cluster = volume.bytes_per_cluster() as u64;
+    assert_eq!(volume.directory_usage("dir"), None);
+
+    volume.track_usage().unwrap();
+    // the directory itself and `nested.txt`
+    assert_eq!(volume.directory_usage("DIR"), Some(2 * cluster));
+
+    volume
+        .write_atomic::<SystemTime>("dir/recording.bin", &vec![1u8; 2 * cluster as usize])
+        .unwrap();
+    assert_eq!(volume.directory_usage("dir"), Some(4 * cluster));
+
+    volume
+        .write_atomic::<SystemTime>("dir/recording.bin", b"short")
+        .unwrap();
+    assert_eq!(volume.directory_usage("dir"), Some(3 * cluster));
+
+    // root level files are not accounted to any directory
+    volume
+        .write_atomic::<SystemTime>("hello.txt", &vec![1u8; 3 * cluster as usize])
+        .unwrap();
+    assert_eq!(volume.directory_usage("dir"), Some(3 * cluster));
+
+    volume
+        .materialize_lost::<SystemTime>(&[RecoveredData {
+            first_cluster: 1000,
+            data_len: cluster + 1,
+            contiguous: true,
+        }])
+        .unwrap();
+    assert_eq!(
+        volume.usage().unwrap(),
+        [
+            (String::from("LOST.DIR"), 3 * cluster),
+            (String::from("dir"), 3 * cluster)
+        ]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn usage_follows_all_writes() {
+    use crate::{error::WriteError, test_utils::TestImage};
+    use std::{io::Write, sync::Mutex, time::SystemTime};
+
+    let mut volume = Volume::open_rw(Mutex::new(TestImage::sample().cursor())).unwrap();
+    let cluster = volume.bytes_per_cluster() as u64;
+    volume.track_usage().unwrap();
+
+    // top-level directories enter and leave the map
+    volume.create_dir::<SystemTime>("media").unwrap();
+    assert_eq!(volume.directory_usage("media"), Some(cluster));
+    volume.create_dir::<SystemTime>("media/video").unwrap();
+    assert_eq!(volume.directory_usage("media"), Some(2 * cluster));
+    volume.create_dir::<SystemTime>("empty").unwrap();
+    volume.remove("empty").unwrap();
+    assert_eq!(volume.directory_usage("empty"), None);
+
+    // a failed operation does not account later ones to its directory
+    assert!(matches!(
+        volume.write_atomic::<SystemTime>("dir", b"data"),
+        Err(WriteError::IsADirectory(_))
+    ));
+    volume
+        .write_atomic::<SystemTime>("top.bin", &vec![1u8; cluster as usize])
+        .unwrap();
+    assert_eq!(volume.directory_usage("dir"), Some(2 * cluster));
+
+    // handles account to the top-level directory they lie in
+    let Some(FsElement::D(mut media)) = volume.root().open_path("media").unwrap() else {
+        panic!("media not found");
+    };
+    let mut file = media.create_file::<SystemTime>("clip.bin").unwrap();
+    file.write_all(&vec![1u8; 2 * cluster as usize]).unwrap();
+    assert_eq!(volume.directory_usage("media"), Some(4 * cluster));
+    file.set_len(cluster).unwrap();
+    assert_eq!(volume.directory_usage("media"), Some(3 * cluster));
+
+    let Some(FsElement::D(mut dir)) = volume.root().open_path("dir").unwrap() else {
+        panic!("dir not found");
+    };
+    media.move_to(&mut dir, "clip.bin").unwrap();
+    assert_eq!(volume.directory_usage("media"), Some(2 * cluster));
+    assert_eq!(volume.directory_usage("dir"), Some(3 * cluster));
+}
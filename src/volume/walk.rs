@@ -0,0 +1,147 @@
+use std::{
+    boxed::Box,
+    format,
+    string::String,
+    sync::{Mutex, mpsc},
+    thread,
+    vec::Vec,
+};
+
+use crate::{
+    disk::ReadOffset,
+    error::DirectoryError,
+    fs::{FsElement, directory::Directory},
+};
+
+use super::Volume;
+
+/// A directory waiting to be listed by a worker.
+enum Pending<'root, O: ReadOffset> {
+    /// A directory of the root directory, borrowed from the volume.
+    TopLevel(&'root Directory<O>),
+    Nested(Box<Directory<O>>),
+}
+
+impl<O: ReadOffset> Pending<'_, O> {
+    fn directory(&self) -> &Directory<O> {
+        match self {
+            Pending::TopLevel(directory) => directory,
+            Pending::Nested(directory) => directory,
+        }
+    }
+}
+
+impl<O: ReadOffset + Send + Sync> Volume<O>
+where
+    O::Err: core::fmt::Debug + Send,
+{
+    /// Visits every file and directory of the volume, listing directories on `threads` worker
+    /// threads. `visit` is called on the calling thread with the path (separated by `/`) and the
+    /// element; parents are visited before their children, otherwise the order is unspecified.
+    ///
+    /// Stops at the first directory that cannot be read.
+    pub fn par_walk(
+        &self,
+        threads: usize,
+        mut visit: impl FnMut(&str, &FsElement<O>),
+    ) -> Result<(), DirectoryError<O>> {
+        type Listed<O> = (String, Result<Vec<FsElement<O>>, DirectoryError<O>>);
+
+        let (jobs, job_receiver) = mpsc::channel::<(String, Pending<'_, O>)>();
+        let job_receiver = &Mutex::new(job_receiver);
+        let (results, result_receiver) = mpsc::channel::<Listed<O>>();
+
+        // the job sender is moved into the scope, so that returning stops the workers
+        thread::scope(move |scope| {
+            for _ in 0..threads.max(1) {
+                let results = results.clone();
+                scope.spawn(move || {
+                    loop {
+                        // the lock is released before listing
+                        let job = job_receiver.lock().unwrap().recv();
+                        let Ok((path, pending)) = job else {
+                            break;
+                        };
+                        let listed = pending.directory().open();
+                        if results.send((path, listed)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+
+            let mut outstanding = 0usize;
+            for item in &self.root.items {
+                visit(item.name(), item);
+                if let FsElement::D(directory) = item {
+                    let job = (String::from(item.name()), Pending::TopLevel(directory));
+                    jobs.send(job).expect("workers are running");
+                    outstanding += 1;
+                }
+            }
+
+            while outstanding > 0 {
+                let (parent, listed) = result_receiver.recv().expect("workers are running");
+                outstanding -= 1;
+                for item in listed? {
+                    let path = format!("{parent}/{}", item.name());
+                    visit(&path, &item);
+                    if let FsElement::D(directory) = item {
+                        jobs.send((path, Pending::Nested(Box::new(directory))))
+                            .expect("workers are running");
+                        outstanding += 1;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parallel_walk() {
+    use crate::test_utils::TestImage;
+    use std::io::Cursor;
+
+    let mut image = TestImage::sample();
+    let root = image.root_cluster();
+    image.add_entry_set(root, "other", true, 103, 4096);
+    image.add_entry_set(103, "deep", true, 104, 4096);
+    image.add_entry_set(104, "leaf.txt", false, 0, 0);
+    image.add_entry_set(103, "sibling.txt", false, 0, 0);
+    let volume = Volume::open(Cursor::new(image.image.clone())).unwrap();
+
+    let mut paths = Vec::new();
+    volume
+        .par_walk(4, |path, _| paths.push(String::from(path)))
+        .unwrap();
+    // parents first
+    let position = |path: &str| paths.iter().position(|p| p == path).unwrap();
+    assert!(position("other") < position("other/deep"));
+    assert!(position("other/deep") < position("other/deep/leaf.txt"));
+
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            "dir",
+            "dir/nested.txt",
+            "hello.txt",
+            "other",
+            "other/deep",
+            "other/deep/leaf.txt",
+            "other/sibling.txt"
+        ]
+    );
+
+    // unreadable directories end the walk
+    let mut image = TestImage::sample();
+    image.add_entry_set(image.root_cluster(), "broken", true, 105, 4096);
+    // allocation bitmap entries are only allowed in the root directory
+    let mut bitmap = [0u8; 32];
+    bitmap[0] = 0x81;
+    image.add_raw_entry(105, &bitmap);
+    let volume = Volume::open(Cursor::new(image.image)).unwrap();
+    assert!(volume.par_walk(2, |_, _| {}).is_err());
+}
@@ -0,0 +1,685 @@
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+use crate::{
+    FIRST_USABLE_CLUSTER_INDEX,
+    allocator::{Allocator, ClusterAllocator, FreeClusters},
+    bitmap::AllocationBitmap,
+    boot_sector::BootSector,
+    cache::{Lock, LockGuard},
+    checksum,
+    disk::{PartitionError, ReadOffset, WriteOffset},
+    entry::{
+        BitmapEntry, DirEntry, FileAttributes,
+        parsed::ParsedFileEntry,
+        set::{Allocation, EntrySet},
+    },
+    error::WriteError,
+    fat::{Fat, FatEntry},
+    fs::{EntryLocation, directory::Directory},
+    name::{UpcaseTable, validate_name},
+    root::Root,
+    timestamp::Timestamps,
+};
+
+use super::{Volume, batch::BatchFile, notify::ChangeHook, usage::UsageTracker};
+
+/// Offset of the `FileAttributes` field within a file entry.
+pub(crate) const FILE_ATTRIBUTES: usize = 4;
+/// Offset of the `CreateTimestamp` field within a file entry, followed by the
+/// `LastModifiedTimestamp` and `LastAccessedTimestamp` fields.
+pub(crate) const FILE_TIMESTAMPS: usize = 8;
+/// Offset of the `Create10msIncrement` field within a file entry, followed by the
+/// `LastModified10msIncrement` field.
+pub(crate) const FILE_10MS_INCREMENTS: usize = 20;
+/// Offset of the `CreateUtcOffset` field within a file entry, followed by the
+/// `LastModifiedUtcOffset` and `LastAccessedUtcOffset` fields.
+pub(crate) const FILE_UTC_OFFSETS: usize = 22;
+/// Offset of the `GeneralSecondaryFlags` field within a stream extension entry.
+pub(crate) const STREAM_FLAGS: usize = 1;
+/// Offset of the `NameLength` field within a stream extension entry.
+pub(crate) const STREAM_NAME_LENGTH: usize = 3;
+/// Offset of the `NameHash` field within a stream extension entry.
+pub(crate) const STREAM_NAME_HASH: usize = 4;
+/// Offset of the `ValidDataLength` field within a stream extension entry.
+pub(crate) const STREAM_VALID_DATA_LENGTH: usize = 8;
+/// Offset of the `FirstCluster` field within a stream extension entry.
+pub(crate) const STREAM_FIRST_CLUSTER: usize = 20;
+/// Offset of the `DataLength` field within a stream extension entry.
+pub(crate) const STREAM_DATA_LENGTH: usize = 24;
+/// `NoFatChain` bit of the `GeneralSecondaryFlags` field.
+pub(crate) const NO_FAT_CHAIN: u8 = 0x2;
+/// Maximum amount of files created with a single allocation and entry set insert, bounding the
+/// memory used for the entries of large batches.
+pub(crate) const BATCH_FILES: usize = 256;
+
+/// Clusters of a directory into which new entry sets are placed.
+pub(crate) struct DirectoryClusters {
+    chain: Vec<u32>,
+    no_fat_chain: bool,
+    /// Offsets of the entries describing the directory in its parent. `None` for the root
+    /// directory, whose size is only given by its cluster chain.
+    entry_set: Option<Vec<u64>>,
+    /// Index of the first entry marking the end of the directory, once found. The directory is
+    /// only searched for free entries up to there, as all following ones are free as well.
+    end: Option<usize>,
+}
+
+impl DirectoryClusters {
+    /// Clusters of the root directory.
+    pub(crate) fn root<O: ReadOffset>(root: &Root<O>) -> Result<Self, WriteError<O>> {
+        let chain = root
+            .fat
+            .read_chain(&root.disk, root.boot.first_cluster_of_root_directory)
+            .map_err(WriteError::Io)?;
+        Ok(DirectoryClusters {
+            chain,
+            no_fat_chain: false,
+            entry_set: None,
+            end: None,
+        })
+    }
+
+    /// Clusters of the directory read by the given handle.
+    pub(crate) fn of<O: ReadOffset>(directory: &Directory<O>) -> Result<Self, WriteError<O>>
+    where
+        O::Err: core::fmt::Debug,
+    {
+        Ok(DirectoryClusters {
+            chain: directory.cluster_reader()?.chain().to_vec(),
+            no_fat_chain: directory.no_fat_chain(),
+            entry_set: Some(directory.entry_offsets.clone()),
+            end: None,
+        })
+    }
+}
+
+/// State all writers of a volume have to agree on. It is shared by a [`Volume`] and every file
+/// and directory handle read through it, so that all of them allocate from the same bitmap.
+pub(crate) struct WriteState {
+    /// Allocation bitmap, loaded once the volume is first modified.
+    bitmap: Option<AllocationBitmap>,
+    /// Allocation bitmap entries of the root directory, as validated when the volume was read.
+    pub(crate) bitmap_entries: Vec<BitmapEntry>,
+    /// Picks the clusters of growing files and directories.
+    allocator: Allocator,
+    /// Per top-level directory usage, if tracked.
+    pub(super) usage: Option<UsageTracker>,
+    /// Callback notified about changes of the directory tree.
+    pub(super) change_hook: Option<ChangeHook>,
+}
+
+impl WriteState {
+    pub(crate) fn new(allocator: Allocator) -> WriteState {
+        WriteState {
+            bitmap: None,
+            bitmap_entries: Vec::new(),
+            allocator,
+            usage: None,
+            change_hook: None,
+        }
+    }
+}
+
+/// Write access to a volume, either through a [`Volume`] or through a file or directory handle.
+/// The shared [`WriteState`] stays locked until the writer is dropped.
+pub(crate) struct Writer<'a, O> {
+    disk: &'a Arc<O>,
+    boot: &'a Arc<BootSector>,
+    fat: &'a mut Arc<Fat>,
+    state: LockGuard<'a, WriteState>,
+    /// Top-level directory that allocated and freed clusters are accounted to, if usage is
+    /// tracked.
+    scope: Option<&'a str>,
+}
+
+impl<O: WriteOffset> Volume<O> {
+    /// Write access to the volume.
+    pub(crate) fn writer(&mut self) -> Writer<'_, O> {
+        self.root.writer(self.usage_scope.as_deref())
+    }
+
+    /// Fails if the volume must not be modified. Marks volumes opened with
+    /// [`Volume::open_rw`] as dirty until they are flushed.
+    pub(crate) fn ensure_writable(&mut self) -> Result<(), WriteError<O>> {
+        if self.root.is_read_only() {
+            return Err(WriteError::Io(O::Err::read_only()));
+        }
+        if self.track_dirty {
+            self.set_dirty(true)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the volume again if the device has been modified since it was last read (see
+    /// [`Root::is_stale`](crate::root::Root::is_stale)), so writes start from its current state.
+    pub(crate) fn refresh_if_stale(&mut self) -> Result<(), WriteError<O>> {
+        if self.root.is_stale() {
+            self.root.reload()?;
+            self.root.writes.lock().bitmap = None;
+        }
+        Ok(())
+    }
+}
+
+impl<O: WriteOffset> Root<O> {
+    /// Write access to the volume, sharing its allocation state with all handles read from it.
+    /// Clusters are accounted to the top-level directory `scope`.
+    pub(crate) fn writer<'a>(&'a mut self, scope: Option<&'a str>) -> Writer<'a, O> {
+        Writer::new(&self.disk, &self.boot, &mut self.fat, &self.writes, scope)
+    }
+}
+
+impl<'a, O: WriteOffset> Writer<'a, O> {
+    pub(crate) fn new(
+        disk: &'a Arc<O>,
+        boot: &'a Arc<BootSector>,
+        fat: &'a mut Arc<Fat>,
+        state: &'a Lock<WriteState>,
+        scope: Option<&'a str>,
+    ) -> Self {
+        Writer {
+            disk,
+            boot,
+            fat,
+            state: state.lock(),
+            scope,
+        }
+    }
+
+    /// Loads the allocation bitmap, unless it is loaded already. On TexFAT volumes, the bitmap
+    /// of the active FAT is loaded and all updates are mirrored into the other one.
+    pub(crate) fn bitmap(&mut self) -> Result<&mut AllocationBitmap, WriteError<O>> {
+        if self.state.bitmap.is_none() {
+            let bitmap = AllocationBitmap::load_active(
+                self.disk,
+                self.boot,
+                self.fat,
+                &self.state.bitmap_entries,
+            )?;
+            self.state.bitmap = Some(bitmap);
+        }
+        Ok(self.state.bitmap.as_mut().unwrap())
+    }
+
+    /// Marks clusters as allocated (or free) both in memory and on disk.
+    pub(crate) fn mark_clusters(
+        &mut self,
+        first: u32,
+        count: u32,
+        allocated: bool,
+    ) -> Result<(), WriteError<O>> {
+        if count == 0 {
+            return Ok(());
+        }
+        let (disk, boot) = (self.disk, self.boot);
+        let bitmap = self.bitmap()?;
+
+        let changed = (first..first + count)
+            .filter(|cluster| bitmap.is_allocated(*cluster) != allocated)
+            .count() as u64;
+        bitmap.set(first, count, allocated);
+        bitmap
+            .flush(disk, boot, first, count)
+            .map_err(WriteError::Io)?;
+
+        if let (Some(usage), Some(scope)) = (&mut self.state.usage, self.scope) {
+            usage.account(scope, changed * boot.bytes_per_cluster() as u64, allocated);
+        }
+        Ok(())
+    }
+
+    /// Accounts `bytes` of an element moved out of the top-level directory `from` to the scope of
+    /// this writer instead.
+    pub(crate) fn move_usage(&mut self, from: Option<&str>, bytes: u64) {
+        let Some(usage) = &mut self.state.usage else {
+            return;
+        };
+        if let Some(from) = from {
+            usage.account(from, bytes, false);
+        }
+        if let Some(scope) = self.scope {
+            usage.account(scope, bytes, true);
+        }
+    }
+
+    /// Allocates a run of `count` free contiguous clusters and returns the first one.
+    pub(crate) fn allocate_contiguous(&mut self, count: u32) -> Result<u32, WriteError<O>> {
+        self.bitmap()?;
+        let state = &mut *self.state;
+        let free = FreeClusters::new(state.bitmap.as_ref().unwrap());
+        let first = state
+            .allocator
+            .allocate_contiguous(&free, count)
+            .ok_or(WriteError::NoSpace(count))?;
+        self.mark_clusters(first, count, true)?;
+        Ok(first)
+    }
+
+    /// Allocates `count` free clusters picked by the allocator for a chain ending at `after`
+    /// (`None` for new chains), in the order they are appended to the chain.
+    pub(crate) fn allocate(
+        &mut self,
+        count: u32,
+        after: Option<u32>,
+    ) -> Result<Vec<u32>, WriteError<O>> {
+        self.bitmap()?;
+        let state = &mut *self.state;
+        let free = FreeClusters::new(state.bitmap.as_ref().unwrap());
+        let clusters = state
+            .allocator
+            .allocate(&free, count, after)
+            .ok_or(WriteError::NoSpace(count))?;
+        for cluster in &clusters {
+            self.mark_clusters(*cluster, 1, true)?;
+        }
+        Ok(clusters)
+    }
+
+    /// Releases the clusters of a file or directory, including its FAT chain.
+    pub(crate) fn free_allocation(&mut self, allocation: Allocation) -> Result<(), WriteError<O>> {
+        let clusters = allocation
+            .data_len
+            .div_ceil(self.boot.bytes_per_cluster() as u64) as u32;
+        if allocation.first_cluster == 0 || clusters == 0 {
+            return Ok(());
+        }
+        if allocation.no_fat_chain {
+            return self.mark_clusters(allocation.first_cluster, clusters, false);
+        }
+
+        let fat = Arc::make_mut(self.fat);
+        let chain = fat
+            .free_chain(&**self.disk, allocation.first_cluster, clusters as usize)
+            .map_err(WriteError::Io)?;
+        fat.flush(&**self.disk, self.boot).map_err(WriteError::Io)?;
+        for cluster in chain {
+            self.mark_clusters(cluster, 1, false)?;
+        }
+        Ok(())
+    }
+
+    /// Sets a FAT entry in memory and in every FAT on disk.
+    pub(crate) fn set_fat_entry(
+        &mut self,
+        cluster: u32,
+        entry: FatEntry,
+    ) -> Result<(), WriteError<O>> {
+        Fat::write_entry(self.fat, &**self.disk, self.boot, cluster, entry).map_err(WriteError::Io)
+    }
+
+    /// Links `clusters` into a FAT chain terminated by an end of chain marker, continuing the
+    /// chain ending at `last` if given.
+    pub(crate) fn append_chain(
+        &mut self,
+        last: Option<u32>,
+        clusters: &[u32],
+    ) -> Result<(), WriteError<O>> {
+        let fat = Arc::make_mut(self.fat);
+        fat.append_chain(last, clusters);
+        fat.flush(&**self.disk, self.boot).map_err(WriteError::Io)
+    }
+
+    /// Fills the given cluster with zeroes.
+    pub(crate) fn zero_cluster(&self, cluster: u32) -> Result<(), WriteError<O>> {
+        let offset = self
+            .boot
+            .cluster_offset(cluster)
+            .ok_or(WriteError::Io(O::Err::cluster_not_found(cluster)))?;
+        let zeroes = vec![0u8; self.boot.bytes_per_cluster() as usize];
+        self.disk
+            .write_all_at(offset, &zeroes)
+            .map_err(WriteError::Io)
+    }
+
+    /// Clusters of a newly created contiguous directory whose entry set of `entries` entries is
+    /// located at `location` in `parent`.
+    pub(crate) fn new_directory_clusters(
+        &self,
+        parent: &DirectoryClusters,
+        location: EntryLocation,
+        first_cluster: u32,
+        entries: usize,
+    ) -> Result<DirectoryClusters, WriteError<O>> {
+        Ok(DirectoryClusters {
+            chain: vec![first_cluster],
+            no_fat_chain: true,
+            entry_set: Some(
+                self.entry_offsets(parent, location, entries)
+                    .ok_or(WriteError::CorruptEntrySet(location))?,
+            ),
+            end: None,
+        })
+    }
+
+    /// Offsets of `count` consecutive entries of a directory, starting at the given entry.
+    pub(crate) fn entry_offsets(
+        &self,
+        directory: &DirectoryClusters,
+        first: EntryLocation,
+        count: usize,
+    ) -> Option<Vec<u64>> {
+        let entries_per_cluster = (self.boot.bytes_per_cluster() / 32) as usize;
+        let start = directory.chain.iter().position(|c| *c == first.cluster)? * entries_per_cluster
+            + first.index as usize;
+
+        (start..start + count)
+            .map(|slot| self.slot_offset(directory, slot))
+            .collect()
+    }
+
+    /// Absolute offset of the entry with the given index in a directory.
+    pub(crate) fn slot_offset(&self, directory: &DirectoryClusters, slot: usize) -> Option<u64> {
+        let entries_per_cluster = (self.boot.bytes_per_cluster() / 32) as usize;
+        let cluster = *directory.chain.get(slot / entries_per_cluster)?;
+        Some(
+            self.boot.cluster_offset(cluster)?
+                + (slot % entries_per_cluster * size_of::<DirEntry>()) as u64,
+        )
+    }
+
+    /// Allocates a first cluster for a new file or directory named `name` and writes its entry
+    /// set into `directory`. The cluster is zeroed and fully valid for directories, whereas files
+    /// start out empty.
+    pub(crate) fn create_entry_set(
+        &mut self,
+        directory: &mut DirectoryClusters,
+        name: &str,
+        attributes: FileAttributes,
+        timestamps: Timestamps,
+        upcase_table: &UpcaseTable,
+    ) -> Result<ParsedFileEntry, WriteError<O>> {
+        let units = validate_name(name)?;
+        let first_cluster = self.allocate_contiguous(1)?;
+        let is_directory = attributes.contains(FileAttributes::DIRECTORY);
+        if is_directory {
+            self.zero_cluster(first_cluster)?;
+        }
+
+        let allocation = Allocation {
+            first_cluster,
+            data_len: self.boot.bytes_per_cluster() as u64,
+            no_fat_chain: true,
+        };
+        let mut set = EntrySet::new(&units, attributes, &timestamps, allocation, upcase_table);
+        if !is_directory {
+            // nothing has been written yet
+            set.stream.valid_data_length = 0;
+            set.update_checksum();
+        }
+        let entries = set.raw_entries();
+        let location = self.insert_entry_set(directory, &entries)?;
+
+        Ok(ParsedFileEntry {
+            name: String::from(name),
+            attributes,
+            stream_extension_entry: set.stream,
+            timestamps,
+            location,
+            entry_offsets: self
+                .entry_offsets(directory, location, entries.len())
+                .ok_or(WriteError::CorruptEntrySet(location))?,
+            vendor_extensions: Vec::new(),
+        })
+    }
+
+    /// Creates files with the given names and contents in `directory`, in the given order. The
+    /// files are written in chunks of up to [`BATCH_FILES`]: the data of a chunk is stored in one
+    /// run of contiguous clusters (`NoFatChain`) and its entry sets are inserted as one block, so
+    /// the bitmap and the directory are updated once per chunk instead of once per file. Chunks
+    /// written before a failure stay in place.
+    pub(crate) fn create_files<N, D: AsRef<[u8]>>(
+        &mut self,
+        directory: &mut DirectoryClusters,
+        files: &[BatchFile<N, D>],
+        timestamps: &Timestamps,
+        upcase_table: &UpcaseTable,
+    ) -> Result<(), WriteError<O>> {
+        let bytes_per_cluster = self.boot.bytes_per_cluster() as u64;
+        let clusters = |data: &D| {
+            u32::try_from((data.as_ref().len() as u64).div_ceil(bytes_per_cluster))
+                .map_err(|_| WriteError::NoSpace(u32::MAX))
+        };
+
+        let mut rest = files;
+        while !rest.is_empty() {
+            // as many files as fit into a single allocation
+            let mut total = 0u32;
+            let mut len = 0;
+            for (.., data) in rest.iter().take(BATCH_FILES) {
+                let Some(sum) = total.checked_add(clusters(data)?) else {
+                    break;
+                };
+                total = sum;
+                len += 1;
+            }
+            let (chunk, next) = rest.split_at(len);
+            rest = next;
+
+            let mut cluster = match total {
+                0 => 0,
+                total => self.allocate_contiguous(total)?,
+            };
+            let mut entries = Vec::new();
+            for (units, _, data) in chunk {
+                let clusters = clusters(data)?;
+                let data = data.as_ref();
+                if !data.is_empty() {
+                    let offset = self
+                        .boot
+                        .cluster_offset(cluster)
+                        .ok_or(WriteError::Io(O::Err::cluster_not_found(cluster)))?;
+                    self.disk
+                        .write_all_at(offset, data)
+                        .map_err(WriteError::Io)?;
+                }
+
+                let set = EntrySet::new(
+                    units,
+                    FileAttributes::ARCHIVE,
+                    timestamps,
+                    Allocation {
+                        first_cluster: if clusters == 0 { 0 } else { cluster },
+                        data_len: data.len() as u64,
+                        no_fat_chain: true,
+                    },
+                    upcase_table,
+                );
+                entries.extend(set.raw_entries());
+                cluster += clusters;
+            }
+            self.insert_entry_set(directory, &entries)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a raw entry set into the first run of free entries of a directory, growing the
+    /// directory if needed. Returns the location of the primary entry.
+    pub(crate) fn insert_entry_set(
+        &mut self,
+        directory: &mut DirectoryClusters,
+        entries: &[[u8; 32]],
+    ) -> Result<EntryLocation, WriteError<O>> {
+        let entries_per_cluster = (self.boot.bytes_per_cluster() / 32) as usize;
+        let start = self.reserve_slots(directory, entries.len())?;
+
+        let offsets: Vec<u64> = (start..start + entries.len())
+            .map(|slot| self.slot_offset(directory, slot))
+            .collect::<Option<_>>()
+            .ok_or(WriteError::NoSpace(1))?;
+        self.write_slots(&offsets, entries)?;
+
+        let cluster = directory.chain[start / entries_per_cluster];
+        Ok(EntryLocation {
+            cluster,
+            index: (start % entries_per_cluster) as u32,
+            offset: self.slot_offset(directory, start).unwrap_or_default(),
+        })
+    }
+
+    /// Index of the first run of `count` entries that are not in use, growing the directory if
+    /// there is none.
+    pub(crate) fn reserve_slots(
+        &mut self,
+        directory: &mut DirectoryClusters,
+        count: usize,
+    ) -> Result<usize, WriteError<O>> {
+        loop {
+            if let Some(start) = self.find_free_slots(directory, count)? {
+                // the reserved entries are about to be used
+                if directory.end.is_some_and(|end| end < start + count) {
+                    directory.end = Some(start + count);
+                }
+                return Ok(start);
+            }
+            self.grow_directory(directory)?;
+        }
+    }
+
+    /// Reads raw entries at the given offsets.
+    pub(crate) fn read_slots(&self, offsets: &[u64]) -> Result<Vec<[u8; 32]>, WriteError<O>> {
+        let mut entries = vec![[0u8; 32]; offsets.len()];
+        for (entry, offset) in entries.iter_mut().zip(offsets) {
+            self.disk
+                .read_exact(*offset, entry)
+                .map_err(WriteError::Io)?;
+        }
+        Ok(entries)
+    }
+
+    /// Writes raw entries at the given offsets, combining consecutive offsets into one write.
+    pub(crate) fn write_slots(
+        &self,
+        offsets: &[u64],
+        entries: &[[u8; 32]],
+    ) -> Result<(), WriteError<O>> {
+        let mut i = 0;
+        while i < offsets.len() {
+            let mut run = 1;
+            while i + run < offsets.len() && offsets[i + run] == offsets[i] + 32 * run as u64 {
+                run += 1;
+            }
+
+            let bytes: Vec<u8> = entries[i..i + run].iter().flatten().copied().collect();
+            self.disk
+                .write_all_at(offsets[i], &bytes)
+                .map_err(WriteError::Io)?;
+            i += run;
+        }
+        Ok(())
+    }
+
+    /// Index of the first run of `count` entries that are not in use. The directory is read a
+    /// cluster at a time, up to its end.
+    fn find_free_slots(
+        &self,
+        directory: &mut DirectoryClusters,
+        count: usize,
+    ) -> Result<Option<usize>, WriteError<O>> {
+        let entries_per_cluster = (self.boot.bytes_per_cluster() / 32) as usize;
+        let slots = directory.chain.len() * entries_per_cluster;
+        let searched = directory.end.unwrap_or(slots);
+
+        let mut run = 0;
+        let mut entries = vec![0u8; self.boot.bytes_per_cluster() as usize];
+        'clusters: for (index, &cluster) in directory.chain.iter().enumerate() {
+            let first = index * entries_per_cluster;
+            if first >= searched {
+                break;
+            }
+            let offset = self
+                .boot
+                .cluster_offset(cluster)
+                .ok_or(WriteError::Io(O::Err::cluster_not_found(cluster)))?;
+            self.disk
+                .read_exact(offset, &mut entries)
+                .map_err(WriteError::Io)?;
+
+            for (slot, entry) in (first..searched).zip(entries.chunks_exact(32)) {
+                if entry[0] == 0 {
+                    directory.end = Some(slot);
+                    break 'clusters;
+                }
+
+                if entry[0] < 0x80 {
+                    run += 1;
+                    if run == count {
+                        return Ok(Some(slot + 1 - count));
+                    }
+                } else {
+                    run = 0;
+                }
+            }
+        }
+
+        // all entries following the end of the directory are free as well
+        Ok(directory
+            .end
+            .filter(|end| slots - end >= count - run)
+            .map(|end| end - run))
+    }
+
+    /// Appends a zeroed cluster to a directory. Contiguous directories stay contiguous if the
+    /// following cluster is free, otherwise they are converted to a FAT chain.
+    fn grow_directory(&mut self, directory: &mut DirectoryClusters) -> Result<(), WriteError<O>> {
+        let last = *directory.chain.last().ok_or(WriteError::NoSpace(1))?;
+        let next = last + 1;
+
+        let cluster = if directory.no_fat_chain
+            && next < FIRST_USABLE_CLUSTER_INDEX + self.boot.cluster_count
+            && !self.bitmap()?.is_allocated(next)
+        {
+            self.mark_clusters(next, 1, true)?;
+            next
+        } else {
+            if directory.no_fat_chain {
+                // the FAT has not been maintained so far
+                for window in directory.chain.windows(2) {
+                    self.set_fat_entry(window[0], FatEntry(window[1]))?;
+                }
+                directory.no_fat_chain = false;
+            }
+
+            let cluster = self.allocate_contiguous(1)?;
+            self.set_fat_entry(last, FatEntry(cluster))?;
+            cluster
+        };
+        if !directory.no_fat_chain {
+            self.set_fat_entry(cluster, FatEntry::eof())?;
+        }
+
+        self.zero_cluster(cluster)?;
+        directory
+            .end
+            .get_or_insert(directory.chain.len() * (self.boot.bytes_per_cluster() / 32) as usize);
+        directory.chain.push(cluster);
+
+        let data_len = directory.chain.len() as u64 * self.boot.bytes_per_cluster() as u64;
+        let no_fat_chain = directory.no_fat_chain;
+        if let Some(offsets) = &directory.entry_set {
+            self.update_entry_set(offsets, |entries| {
+                let stream = &mut entries[1];
+                stream[STREAM_VALID_DATA_LENGTH..STREAM_VALID_DATA_LENGTH + 8]
+                    .copy_from_slice(&data_len.to_le_bytes());
+                stream[STREAM_DATA_LENGTH..STREAM_DATA_LENGTH + 8]
+                    .copy_from_slice(&data_len.to_le_bytes());
+                if !no_fat_chain {
+                    stream[STREAM_FLAGS] &= !NO_FAT_CHAIN;
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Reads an entry set, applies `update` and writes it back with a recomputed checksum.
+    pub(crate) fn update_entry_set(
+        &self,
+        offsets: &[u64],
+        update: impl FnOnce(&mut [[u8; 32]]),
+    ) -> Result<(), WriteError<O>> {
+        let mut entries = self.read_slots(offsets)?;
+        update(&mut entries);
+        let checksum = checksum::entry_set(&entries);
+        entries[0][2..4].copy_from_slice(&checksum.to_le_bytes());
+        self.write_slots(offsets, &entries)
+    }
+}